@@ -0,0 +1,235 @@
+//! Command line client for the luxo ipc socket
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use luxo::ipc::{socket_path, EventType, Request, Response, SOCKET_ENV};
+
+const DEBUG_OVERLAYS: [&str; 4] = ["damage", "fps", "repaint", "full-redraw"];
+
+const USAGE: &str = "USAGE: luxoctl <command>
+
+Commands:
+    windows                         List all windows
+    outputs                         List all outputs
+    workspaces                      List the workspaces of all outputs
+    action <action>                 Run an action, as written in the [keybindings] config section
+    focus <window id>               Focus a window, switching to its workspace
+    close <window id>               Ask a window to close
+    kill <window id>                Kill the client of a window that doesn't close
+    move <window id> <workspace>    Move a window to another workspace of its output
+    output-mode <output> <mode>     Set the mode of an output, e.g. 1920x1080@60
+    capture <output> <path>         Save an output as a PNG image (headless backend)
+    key-repeat <delay> <rate>       Set the key repeat delay (ms) and rate (per second)
+    reload                          Reload the config file
+    autostart                       End the programs started from exec_once and exec and run
+                                    them again
+    debug-overlay [damage] [fps] [repaint] [full-redraw]
+                                    Show the listed debug overlays and hide all others
+    lock-state                      Show whether caps lock and num lock are on
+    keyboard-layout                 Show the active keyboard layout
+    focused-output                  Print the name of the output under the pointer
+    window-rules                    List the configured window rules
+    input <device> <setting> <value> [--save]
+                                    Change a libinput setting of mouse, touchpad or a device by
+                                    name, e.g. `input mouse accel_speed 0.5`
+    logs [count] [level]            Print recent log events, e.g. `logs 50 warn`
+    opacity <id> <opacity>          Set the opacity of a window, between 0.1 and 1.0
+    scratchpad <window id>          Make a window the one toggled by the toggle-scratchpad action
+    always-on-top <window id> <true|false>
+                                    Keep a window above all others, or stop doing so
+    sticky <window id> <true|false> Show a window on all workspaces of its output, or not
+    leases                          List the connectors of VR headsets and other non-desktop
+                                    displays
+    revoke-lease <connector>        End the drm lease of a connector
+    subscribe [window] [focus] [lease] [switch] [keyboard]
+                                    Print events as JSON lines until luxo exits, all if none are
+                                    listed";
+
+fn parse_request(args: &[String]) -> Option<Request> {
+    let arg = |index: usize| args.get(index).cloned();
+    let number = |index: usize| args.get(index)?.parse().ok();
+    Some(match args.first()?.as_str() {
+        "windows" => Request::Windows,
+        "outputs" => Request::Outputs,
+        "workspaces" => Request::Workspaces,
+        "action" if args.len() > 1 => Request::Action {
+            action: args[1..].join(" "),
+        },
+        "focus" => Request::FocusWindow { id: number(1)? },
+        "close" => Request::CloseWindow { id: number(1)? },
+        "kill" => Request::KillWindow { id: number(1)? },
+        "move" => Request::MoveWindowToWorkspace {
+            id: number(1)?,
+            workspace: number(2)?,
+        },
+        "output-mode" => Request::SetOutputMode {
+            output: arg(1)?,
+            mode: arg(2)?,
+        },
+        "capture" => Request::CaptureOutput {
+            output: arg(1)?,
+            path: PathBuf::from(arg(2)?),
+        },
+        "key-repeat" => Request::SetKeyRepeat {
+            delay: number(1)?,
+            rate: number(2)?,
+        },
+        "reload" => Request::ReloadConfig,
+        "autostart" => Request::RerunAutostart,
+        "debug-overlay" if args[1..].iter().all(|arg| DEBUG_OVERLAYS.contains(&arg.as_str())) => {
+            Request::SetDebugOverlay {
+                damage: args.iter().any(|arg| arg == "damage"),
+                fps: args.iter().any(|arg| arg == "fps"),
+                repaint: args.iter().any(|arg| arg == "repaint"),
+                full_redraw: args.iter().any(|arg| arg == "full-redraw"),
+            }
+        }
+        "lock-state" => Request::LockState,
+        "keyboard-layout" => Request::KeyboardLayout,
+        "focused-output" => Request::FocusedOutput,
+        "window-rules" => Request::WindowRules,
+        "input" if args.len() == 4 || (args.len() == 5 && args[4] == "--save") => Request::ConfigureInput {
+            device: arg(1)?,
+            setting: arg(2)?,
+            value: arg(3)?,
+            persist: args.len() == 5,
+        },
+        "logs" => Request::Logs {
+            limit: args.get(1).map(|arg| arg.parse()).transpose().ok()?,
+            level: arg(2),
+        },
+        "opacity" => Request::SetWindowOpacity {
+            id: number(1)?,
+            opacity: args.get(2)?.parse().ok()?,
+        },
+        "scratchpad" => Request::SetScratchpad { id: number(1)? },
+        "always-on-top" => Request::SetWindowFlags {
+            id: number(1)?,
+            always_on_top: Some(args.get(2)?.parse().ok()?),
+            sticky: None,
+        },
+        "sticky" => Request::SetWindowFlags {
+            id: number(1)?,
+            always_on_top: None,
+            sticky: Some(args.get(2)?.parse().ok()?),
+        },
+        "leases" => Request::LeaseConnectors,
+        "revoke-lease" => Request::RevokeLease { connector: arg(1)? },
+        "subscribe" => Request::Subscribe {
+            events: args[1..]
+                .iter()
+                .map(|arg| match arg.as_str() {
+                    "window" => Some(EventType::Window),
+                    "focus" => Some(EventType::Focus),
+                    "lease" => Some(EventType::Lease),
+                    "switch" => Some(EventType::Switch),
+                    "keyboard" => Some(EventType::Keyboard),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?,
+        },
+        _ => return None,
+    })
+}
+
+fn socket() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(SOCKET_ENV) {
+        return Some(path.into());
+    }
+    let display = std::env::var("WAYLAND_DISPLAY").ok()?;
+    socket_path(&display).ok()
+}
+
+fn send(path: &Path, request: &Request) -> Result<Response, Box<dyn std::error::Error>> {
+    let mut stream = UnixStream::connect(path)?;
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    Ok(serde_json::from_str(&reply)?)
+}
+
+#[allow(clippy::disallowed_macros)]
+fn subscribe(path: &Path, request: &Request) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stream = UnixStream::connect(path)?;
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+
+    let mut lines = BufReader::new(stream).lines();
+    let reply = lines.next().ok_or("Connection closed")??;
+    if let Response::Error { message } = serde_json::from_str(&reply)? {
+        return Err(message.into());
+    }
+    for event in lines {
+        println!("{}", event?);
+    }
+    Ok(())
+}
+
+#[allow(clippy::disallowed_macros)]
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let Some(request) = parse_request(&args) else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    let Some(path) = socket() else {
+        eprintln!(
+            "Unable to find the luxo socket, is {} or WAYLAND_DISPLAY set?",
+            SOCKET_ENV
+        );
+        return ExitCode::FAILURE;
+    };
+
+    if let Request::Subscribe { .. } = request {
+        return match subscribe(&path, &request) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    match send(&path, &request) {
+        Ok(Response::Ok) => ExitCode::SUCCESS,
+        // plain, so it can be used as the output chooser of xdg-desktop-portal-wlr
+        Ok(Response::FocusedOutput { output: Some(output) }) => {
+            println!("{}", output);
+            ExitCode::SUCCESS
+        }
+        Ok(Response::FocusedOutput { output: None }) => {
+            eprintln!("Error: There are no outputs");
+            ExitCode::FAILURE
+        }
+        Ok(Response::Logs { entries }) => {
+            for entry in entries {
+                println!(
+                    "{:.3} {:>5} {}: {}",
+                    entry.time, entry.level, entry.target, entry.message
+                );
+            }
+            ExitCode::SUCCESS
+        }
+        Ok(Response::Error { message }) => {
+            eprintln!("Error: {}", message);
+            ExitCode::FAILURE
+        }
+        Ok(response) => {
+            println!("{}", serde_json::to_string_pretty(&response).unwrap());
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("Failed to talk to {}: {}", path.display(), err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+USAGE: luxo [OPTIONS]
+
+Options:
+    --backend <udev|winit|x11|headless>  Backend to run on
+    --config <path>                      Config file to use instead of $XDG_CONFIG_HOME/luxo/config.toml
+    --socket <name>                      Name of the wayland socket, picked automatically by default
+    --command <program>                  Program to start once the compositor is running
+    --output-size <width>x<height>       Size of the virtual output of the headless backend
+    --debug                              Log at debug level, unless RUST_LOG is set
+
+    --tty, --tty-udev, --winit, --x11, --headless are shorthands for the matching --backend";
+
+/// The backends luxo can run on, independently of which are compiled in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    Udev,
+    Winit,
+    X11,
+    /// Renders offscreen without any display or input devices, for tests
+    Headless,
+}
+
+impl std::str::FromStr for BackendKind {
+    type Err = ArgsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "udev" | "tty" | "tty-udev" => Ok(BackendKind::Udev),
+            "winit" => Ok(BackendKind::Winit),
+            "x11" => Ok(BackendKind::X11),
+            "headless" => Ok(BackendKind::Headless),
+            other => Err(ArgsError::UnknownBackend(other.to_owned())),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ArgsError {
+    #[error("Missing value for {0}")]
+    MissingValue(String),
+    #[error("Unknown backend: {0}")]
+    UnknownBackend(String),
+    #[error("Invalid output size: {0}, expected <width>x<height>")]
+    InvalidSize(String),
+    #[error("Unknown argument: {0}")]
+    UnknownArgument(String),
+}
+
+/// Startup options given on the command line
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    pub backend: Option<BackendKind>,
+    /// Config file overriding the default location
+    pub config: Option<PathBuf>,
+    /// Wayland socket name overriding the automatically picked `wayland-N`
+    pub socket: Option<String>,
+    /// Started through `sh -c` once the backend is up
+    pub command: Option<String>,
+    /// Size of the virtual output of the headless backend
+    pub output_size: Option<(i32, i32)>,
+    pub debug: bool,
+}
+
+impl Options {
+    /// Parse the arguments following the program name.
+    ///
+    /// Values may be given as `--flag value` or `--flag=value`.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Options, ArgsError> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_owned(), Some(value.to_owned())),
+                _ => (arg.clone(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| ArgsError::MissingValue(flag.clone()))
+            };
+
+            match flag.as_str() {
+                "--backend" => options.backend = Some(value()?.parse()?),
+                "--config" => options.config = Some(PathBuf::from(value()?)),
+                "--socket" => options.socket = Some(value()?),
+                "--command" => options.command = Some(value()?),
+                "--output-size" => options.output_size = Some(parse_size(&value()?)?),
+                "--debug" => options.debug = true,
+                "--tty" | "--tty-udev" => options.backend = Some(BackendKind::Udev),
+                "--winit" => options.backend = Some(BackendKind::Winit),
+                "--x11" => options.backend = Some(BackendKind::X11),
+                "--headless" => options.backend = Some(BackendKind::Headless),
+                _ => return Err(ArgsError::UnknownArgument(flag)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Parse a size given as `<width>x<height>`
+fn parse_size(size: &str) -> Result<(i32, i32), ArgsError> {
+    size.split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .filter(|&(w, h)| w > 0 && h > 0)
+        .ok_or_else(|| ArgsError::InvalidSize(size.to_owned()))
+}
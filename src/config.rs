@@ -0,0 +1,1084 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use smithay::{
+    backend::renderer::Color32F,
+    input::keyboard::XkbConfig,
+    output::Mode,
+    reexports::calloop::{
+        timer::{TimeoutAction, Timer},
+        LoopHandle,
+    },
+    utils::Transform,
+};
+use tracing::{info, warn};
+
+/// Parsed contents of `$XDG_CONFIG_HOME/luxo/config.toml`.
+///
+/// Every section is optional, missing values fall back to the built-in defaults.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub input: InputConfig,
+    pub outputs: OutputsConfig,
+    /// Raw keybindings, mapping a key combination (e.g. `"Logo+Return"`) to an action string.
+    pub keybindings: BTreeMap<String, String>,
+    /// Raw touchpad gesture bindings, mapping a swipe (e.g. `"3-finger-swipe-left"`) to an action string.
+    pub gestures: BTreeMap<String, String>,
+    /// Raw mouse button and scroll bindings, mapping e.g. `"Logo+button-left"` to an action string.
+    pub pointer_bindings: BTreeMap<String, String>,
+    pub focus: FocusConfig,
+    pub appearance: AppearanceConfig,
+    pub cursor: CursorConfig,
+    pub night_light: NightLightConfig,
+    pub magnifier: MagnifierConfig,
+    pub drm_lease: DrmLeaseConfig,
+    pub switches: SwitchesConfig,
+    pub primary_selection: PrimarySelectionConfig,
+    pub session: SessionConfig,
+    /// Environment variables set for every program luxo starts, e.g. `QT_QPA_PLATFORM = "wayland"`
+    pub environment: BTreeMap<String, String>,
+    /// Clients allowed to see the globals of privileged protocols. Protocols missing here are
+    /// available to every client not connected through a security context.
+    pub protocol_access: BTreeMap<PrivilegedProtocol, Vec<ClientRule>>,
+    pub protocols: ProtocolsConfig,
+    pub window_rules: Vec<WindowRule>,
+    pub debug_overlay: DebugOverlayConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    /// Delay in milliseconds before a held key starts repeating
+    pub repeat_delay: i32,
+    /// Key repeats per second
+    pub repeat_rate: i32,
+    pub keyboard: KeyboardConfig,
+    /// Turn num lock on at startup
+    pub numlock: bool,
+    /// libinput settings for touchpads
+    pub touchpad: PointerDeviceConfig,
+    /// libinput settings for mice and other pointer devices that are no touchpads
+    pub mouse: PointerDeviceConfig,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig {
+            repeat_delay: 200,
+            repeat_rate: 25,
+            keyboard: KeyboardConfig::default(),
+            numlock: false,
+            touchpad: PointerDeviceConfig::default(),
+            mouse: PointerDeviceConfig::default(),
+        }
+    }
+}
+
+/// XKB keymap settings, empty values use the XKB defaults (or the `XKB_DEFAULT_*` variables)
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyboardConfig {
+    pub rules: String,
+    pub model: String,
+    /// Comma separated layouts (e.g. `"us,de"`), the `next-layout` action cycles through them
+    pub layout: String,
+    /// Comma separated variants, one per layout
+    pub variant: String,
+    pub options: Option<String>,
+}
+
+impl KeyboardConfig {
+    pub fn layout_count(&self) -> usize {
+        self.layout.split(',').count()
+    }
+
+    /// Name of layout `index`, as written in the config
+    pub fn layout_name(&self, index: usize) -> &str {
+        self.layout.split(',').nth(index).unwrap_or_default().trim()
+    }
+
+    /// XKB configuration with only layout `index` out of the configured ones
+    pub fn xkb_config(&self, index: usize) -> XkbConfig<'_> {
+        XkbConfig {
+            rules: &self.rules,
+            model: &self.model,
+            layout: self.layout_name(index),
+            variant: self.variant.split(',').nth(index).unwrap_or_default().trim(),
+            options: self.options.clone(),
+        }
+    }
+}
+
+/// libinput settings of a pointer device, unset values keep the libinput defaults
+///
+/// Tap-to-click is the exception, it is enabled on touchpads unless disabled here.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PointerDeviceConfig {
+    pub tap: Option<bool>,
+    pub natural_scroll: Option<bool>,
+    pub left_handed: Option<bool>,
+    /// Disable the touchpad while typing
+    pub disable_while_typing: Option<bool>,
+    pub accel_profile: Option<AccelProfileConfig>,
+    /// Pointer acceleration between -1.0 and 1.0
+    pub accel_speed: Option<f64>,
+    pub scroll_method: Option<ScrollMethodConfig>,
+    pub click_method: Option<ClickMethodConfig>,
+    /// Multiplier for scroll distances, 1.0 if unset
+    pub scroll_factor: Option<f64>,
+}
+
+impl PointerDeviceConfig {
+    /// Parse a single setting as written in the config, e.g. `accel_speed` and `0.5`. Values
+    /// that are no valid toml, like `flat`, are read as strings.
+    pub fn parse_setting(setting: &str, value: &str) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(&setting_line(setting, value))?)
+    }
+
+    /// Override the settings with those set in `other`
+    pub fn merge(&mut self, other: &PointerDeviceConfig) {
+        let PointerDeviceConfig {
+            tap,
+            natural_scroll,
+            left_handed,
+            disable_while_typing,
+            accel_profile,
+            accel_speed,
+            scroll_method,
+            click_method,
+            scroll_factor,
+        } = other;
+        self.tap = tap.or(self.tap);
+        self.natural_scroll = natural_scroll.or(self.natural_scroll);
+        self.left_handed = left_handed.or(self.left_handed);
+        self.disable_while_typing = disable_while_typing.or(self.disable_while_typing);
+        self.accel_profile = accel_profile.or(self.accel_profile);
+        self.accel_speed = accel_speed.or(self.accel_speed);
+        self.scroll_method = scroll_method.or(self.scroll_method);
+        self.click_method = click_method.or(self.click_method);
+        self.scroll_factor = scroll_factor.or(self.scroll_factor);
+    }
+}
+
+/// `<setting> = <value>`, quoting `value` unless it is a valid toml value already
+fn setting_line(setting: &str, value: &str) -> String {
+    let line = format!("{} = {}", setting, value);
+    if line.parse::<toml::Table>().is_ok() {
+        line
+    } else {
+        format!("{} = {}", setting, toml::Value::String(value.to_owned()))
+    }
+}
+
+/// Set `setting` of `[input.<section>]` in the config file at `path`, keeping its comments and
+/// formatting. The file is created if it doesn't exist yet.
+pub fn save_input_setting(
+    path: &std::path::Path,
+    section: &str,
+    setting: &str,
+    value: &str,
+) -> Result<(), ConfigError> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let mut document = contents.parse::<toml_edit::DocumentMut>()?;
+    let line = setting_line(setting, value).parse::<toml_edit::DocumentMut>()?;
+
+    let input = document
+        .entry("input")
+        .or_insert_with(|| {
+            let mut table = toml_edit::Table::new();
+            table.set_implicit(true);
+            toml_edit::Item::Table(table)
+        })
+        .as_table_mut()
+        .ok_or_else(|| ConfigError::NotATable(String::from("input")))?;
+    let section_table = input
+        .entry(section)
+        .or_insert_with(toml_edit::table)
+        .as_table_mut()
+        .ok_or_else(|| ConfigError::NotATable(format!("input.{}", section)))?;
+    section_table[setting] = line[setting].clone();
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, document.to_string())?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccelProfileConfig {
+    Flat,
+    Adaptive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScrollMethodConfig {
+    None,
+    TwoFinger,
+    Edge,
+    OnButtonDown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClickMethodConfig {
+    ButtonAreas,
+    Clickfinger,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputsConfig {
+    /// Only allocate 8-bit color formats for drm outputs
+    pub disable_10bit: bool,
+    /// Never scan out client buffers directly
+    pub disable_direct_scanout: bool,
+    /// Render outputs on the gpu they are connected to instead of copying from the primary gpu
+    pub disable_gpu_copy: bool,
+    /// Wait as long after a vblank as recent repaints allow, instead of a fixed `repaint_delay`
+    pub adaptive_repaint_delay: bool,
+    /// Fraction of the frame duration to wait before repainting after a vblank, also used by the
+    /// adaptive delay until the first repaints were timed
+    pub repaint_delay: f64,
+    /// Which content may be scanned out on overlay planes
+    pub overlay_planes: OverlayPlanesConfig,
+    /// Drivers never using overlay planes, matched case-insensitively against the driver name and
+    /// description
+    pub disable_overlay_planes: Vec<String>,
+    /// Rotate the internal panel as the accelerometer of a convertible says, replacing its
+    /// configured transform
+    pub auto_rotate: bool,
+    /// Logical pixels the pointer has to be pushed past the edge of an output before it moves on to
+    /// a neighbouring one, 0 to cross right away
+    pub edge_resistance: f64,
+    /// Per-output settings, from `[[outputs.output]]` tables
+    pub output: Vec<OutputConfig>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverlayPlanesConfig {
+    /// Composite everything not on the primary or cursor plane
+    Off,
+    /// Only use overlay planes while a window on the output shows video
+    Video,
+    /// Let any element be scanned out on an overlay plane
+    All,
+}
+
+impl Default for OutputsConfig {
+    fn default() -> Self {
+        OutputsConfig {
+            disable_10bit: false,
+            disable_direct_scanout: false,
+            disable_gpu_copy: false,
+            adaptive_repaint_delay: true,
+            repaint_delay: 0.6,
+            overlay_planes: OverlayPlanesConfig::Video,
+            disable_overlay_planes: vec!["nvidia".into()],
+            auto_rotate: false,
+            edge_resistance: 0.0,
+            output: Vec::new(),
+        }
+    }
+}
+
+impl OutputsConfig {
+    /// Settings for an output, matched by connector name first and by EDID make and model second
+    pub fn find(&self, connector: &str, make: &str, model: &str) -> Option<&OutputConfig> {
+        self.output
+            .iter()
+            .find(|output| output.name == connector)
+            .or_else(|| {
+                let make_model = format!("{} {}", make, model);
+                self.output.iter().find(|output| output.name == make_model)
+            })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Connector name (e.g. `"DP-1"`) or EDID make and model separated by a space
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub mode: Option<ModeConfig>,
+    /// Position in the global space, outputs without one are placed left-to-right after the others
+    pub position: Option<[i32; 2]>,
+    /// Kept between [`MIN_OUTPUT_SCALE`] and [`MAX_OUTPUT_SCALE`], see [`Self::scale`]
+    pub scale: Option<f64>,
+    pub transform: Option<TransformConfig>,
+    /// Variable refresh rate, off by default and ignored on displays that don't support it
+    pub vrr: Option<VrrConfig>,
+    /// Gpu rendering this output, given as its render or card node (e.g. `/dev/dri/renderD129`)
+    pub render_node: Option<PathBuf>,
+    /// Switch the display to BT.2020 with HDR metadata for the PQ transfer function and advertise
+    /// it to clients. Experimental: frames are still composited in sRGB, so everything else shows
+    /// wrong colors.
+    #[serde(default)]
+    pub experimental_hdr: bool,
+    /// Scale the output is rendered at relative to its mode before being stretched over it, below 1
+    /// to spare weak gpus and above 1 to supersample
+    pub render_scale: Option<f64>,
+    /// How the pointer crosses the edges of the output onto neighbouring ones
+    #[serde(default)]
+    pub edges: BTreeMap<OutputEdge, EdgeConfig>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputEdge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EdgeConfig {
+    /// Resistance of the edge instead of `edge_resistance`
+    pub resistance: Option<f64>,
+    /// Keep the pointer from crossing the edge at all
+    pub barrier: bool,
+}
+
+/// Smallest output scale, smaller ones blow up the logical size
+pub const MIN_OUTPUT_SCALE: f64 = 0.25;
+/// Largest output scale
+pub const MAX_OUTPUT_SCALE: f64 = 10.0;
+
+impl OutputConfig {
+    /// The configured scale kept between [`MIN_OUTPUT_SCALE`] and [`MAX_OUTPUT_SCALE`], unset if it
+    /// isn't a number
+    pub fn scale(&self) -> Option<f64> {
+        self.scale
+            .filter(|scale| !scale.is_nan())
+            .map(|scale| scale.clamp(MIN_OUTPUT_SCALE, MAX_OUTPUT_SCALE))
+    }
+
+    /// The configured render scale kept between a quarter and twice the mode, 1 if it isn't set
+    pub fn render_scale(&self) -> f64 {
+        self.render_scale.map_or(1.0, |scale| scale.clamp(0.25, 2.0))
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Output mode written as `"<width>x<height>"` or `"<width>x<height>@<refresh in Hz>"`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ModeConfig {
+    pub width: i32,
+    pub height: i32,
+    pub refresh: Option<f64>,
+}
+
+impl TryFrom<String> for ModeConfig {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid mode {:?}, expected e.g. \"1920x1080@60\"", value);
+        let (size, refresh) = match value.split_once('@') {
+            Some((size, refresh)) => (size, Some(refresh.parse::<f64>().map_err(|_| invalid())?)),
+            None => (value.as_str(), None),
+        };
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        Ok(ModeConfig {
+            width: width.parse().map_err(|_| invalid())?,
+            height: height.parse().map_err(|_| invalid())?,
+            refresh,
+        })
+    }
+}
+
+impl ModeConfig {
+    /// The mode out of `modes` matching this one best, if any has the right size
+    ///
+    /// Without a refresh rate the fastest mode of that size is picked.
+    pub fn best_match<'a>(&self, modes: impl IntoIterator<Item = &'a Mode>) -> Option<&'a Mode> {
+        let refresh = self.refresh.map(|refresh| (refresh * 1000.0).round() as i32);
+        modes
+            .into_iter()
+            .filter(|mode| mode.size.w == self.width && mode.size.h == self.height)
+            .min_by_key(|mode| match refresh {
+                Some(refresh) => (mode.refresh - refresh).abs(),
+                None => -mode.refresh,
+            })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VrrConfig {
+    #[default]
+    Off,
+    On,
+    /// Only while a window is fullscreen on the output, which avoids flicker on the desktop
+    Fullscreen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum TransformConfig {
+    #[serde(rename = "normal")]
+    Normal,
+    #[serde(rename = "90")]
+    _90,
+    #[serde(rename = "180")]
+    _180,
+    #[serde(rename = "270")]
+    _270,
+    #[serde(rename = "flipped")]
+    Flipped,
+    #[serde(rename = "flipped-90")]
+    Flipped90,
+    #[serde(rename = "flipped-180")]
+    Flipped180,
+    #[serde(rename = "flipped-270")]
+    Flipped270,
+}
+
+impl From<TransformConfig> for Transform {
+    fn from(transform: TransformConfig) -> Self {
+        match transform {
+            TransformConfig::Normal => Transform::Normal,
+            TransformConfig::_90 => Transform::_90,
+            TransformConfig::_180 => Transform::_180,
+            TransformConfig::_270 => Transform::_270,
+            TransformConfig::Flipped => Transform::Flipped,
+            TransformConfig::Flipped90 => Transform::Flipped90,
+            TransformConfig::Flipped180 => Transform::Flipped180,
+            TransformConfig::Flipped270 => Transform::Flipped270,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FocusConfig {
+    pub model: FocusModel,
+    /// Milliseconds the pointer has to rest on a window before it gets focused, ignored for `click`
+    pub follow_delay: u64,
+    /// Raise windows above all others when they get focused
+    pub raise_on_focus: bool,
+    /// Move the pointer to the center of windows that got focused through a keybinding or gesture
+    pub warp_pointer: bool,
+    /// Keep the pointer on the output showing the focused window while it is fullscreen
+    pub confine_pointer: bool,
+}
+
+impl Default for FocusConfig {
+    fn default() -> Self {
+        FocusConfig {
+            model: FocusModel::default(),
+            follow_delay: 0,
+            raise_on_focus: true,
+            warp_pointer: false,
+            confine_pointer: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FocusModel {
+    /// Windows get focused when clicked
+    #[default]
+    Click,
+    /// The window under the pointer is focused, moving the pointer onto the desktop clears the focus
+    FollowsMouse,
+    /// Like `follows-mouse`, but the focus is kept while the pointer is not over a window
+    Sloppy,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppearanceConfig {
+    /// RGBA color used to clear outputs
+    pub background_color: [f32; 4],
+    /// Decoration mode offered to clients supporting xdg-decoration
+    pub decorations: DecorationPolicy,
+    /// Fade windows in and out, and ease window moves and workspace switches
+    pub animations: bool,
+    /// Only draw an outline while resizing windows with the pointer, they get their new size once
+    /// the resize is released
+    pub outline_resize: bool,
+}
+
+impl Default for AppearanceConfig {
+    fn default() -> Self {
+        AppearanceConfig {
+            background_color: [0.8, 0.8, 0.9, 1.0],
+            decorations: DecorationPolicy::default(),
+            animations: true,
+            outline_resize: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DecorationPolicy {
+    /// Clients draw their own decorations
+    #[default]
+    Client,
+    /// The compositor draws a title bar, clients should not draw decorations or shadows
+    Server,
+}
+
+/// Cursor theme drawn by the compositor and passed on to XWayland and started programs
+///
+/// Unset values fall back to `XCURSOR_THEME` and `XCURSOR_SIZE`, setting `LUXO_SOFTWARE_CURSOR`
+/// forces `software`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CursorConfig {
+    pub theme: Option<String>,
+    /// Nominal size in logical pixels
+    pub size: Option<u32>,
+    /// Always composite the pointer instead of showing it on the cursor plane of the display (udev
+    /// backend), for drivers whose hardware cursors glitch
+    pub software: bool,
+}
+
+impl CursorConfig {
+    pub fn theme(&self) -> String {
+        self.theme
+            .clone()
+            .or_else(|| std::env::var("XCURSOR_THEME").ok())
+            .unwrap_or_else(|| "default".into())
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+            .or_else(|| std::env::var("XCURSOR_SIZE").ok().and_then(|s| s.parse().ok()))
+            .unwrap_or(24)
+    }
+
+    pub fn software(&self) -> bool {
+        self.software || std::env::var_os("LUXO_SOFTWARE_CURSOR").is_some()
+    }
+}
+
+/// Zooming into the output under the pointer
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MagnifierConfig {
+    /// Modifiers to hold while scrolling to zoom, e.g. `"Logo+Ctrl"`, empty to only zoom with
+    /// the keyboard actions
+    pub scroll_modifiers: String,
+    /// Highest zoom factor
+    pub max_zoom: f64,
+}
+
+impl Default for MagnifierConfig {
+    fn default() -> Self {
+        MagnifierConfig {
+            scroll_modifiers: "Logo+Ctrl".into(),
+            max_zoom: 8.0,
+        }
+    }
+}
+
+/// Reactions to the switches of laptops and convertibles on the udev backend. Tablet mode changes
+/// are only reported to ipc subscribers.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SwitchesConfig {
+    pub lid_close: LidAction,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LidAction {
+    /// Leave the internal panel on, e.g. when logind suspends anyway
+    None,
+    /// Turn off the internal panel while other outputs are connected, it is turned on again once
+    /// the lid opens
+    #[default]
+    DisableOutput,
+    /// Like `DisableOutput`, but suspend through logind if the internal panel is the only output
+    Suspend,
+}
+
+/// The primary selection, pasted with the middle mouse button
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrimarySelectionConfig {
+    /// Offer it to Wayland clients at all. Clipboard managers using data control only lose access
+    /// to it after a restart.
+    pub enabled: bool,
+    /// Which way it is copied between Wayland and X11 clients
+    pub xwayland_sync: SelectionSync,
+}
+
+impl Default for PrimarySelectionConfig {
+    fn default() -> Self {
+        PrimarySelectionConfig {
+            enabled: true,
+            xwayland_sync: SelectionSync::default(),
+        }
+    }
+}
+
+impl PrimarySelectionConfig {
+    /// Whether X11 clients may paste what Wayland clients selected
+    pub fn to_x11(&self) -> bool {
+        self.enabled && matches!(self.xwayland_sync, SelectionSync::Both | SelectionSync::ToX11)
+    }
+
+    /// Whether Wayland clients may paste what X11 clients selected
+    pub fn from_x11(&self) -> bool {
+        self.enabled && matches!(self.xwayland_sync, SelectionSync::Both | SelectionSync::FromX11)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SelectionSync {
+    #[default]
+    Both,
+    /// Only from Wayland to X11 clients
+    ToX11,
+    /// Only from X11 to Wayland clients
+    FromX11,
+    None,
+}
+
+/// What is started with the compositor once its outputs are set up
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    /// Export `WAYLAND_DISPLAY` and friends to the systemd user manager and dbus activation, and
+    /// start `services`
+    pub systemd: bool,
+    /// systemd user units to start, e.g. `"waybar.service"`
+    pub services: Vec<String>,
+    /// Commands run through `sh -c` at startup, like with the `spawn` action
+    pub exec_once: Vec<String>,
+    /// Commands run through `sh -c` at startup and whenever the config is reloaded
+    pub exec: Vec<String>,
+}
+
+/// Connectors of VR headsets and other non-desktop displays that clients may lease, on the udev
+/// backend. Who may lease them is set with the `drm-lease` entry of `[protocol_access]`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DrmLeaseConfig {
+    /// Names of the connectors offered for leasing, e.g. `"DP-2"`, all non-desktop ones if empty
+    pub connectors: Vec<String>,
+}
+
+impl DrmLeaseConfig {
+    pub fn allows(&self, connector: &str) -> bool {
+        self.connectors.is_empty() || self.connectors.iter().any(|name| name == connector)
+    }
+}
+
+/// Warmer colors during the night, applied through the gamma ramps of the udev backend's outputs
+/// that are not controlled by a client like gammastep
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NightLightConfig {
+    pub enabled: bool,
+    /// Color temperature in Kelvin while the night light is on, 6500 leaves colors unchanged
+    pub temperature: u32,
+    /// Local time the night light turns on
+    pub start: TimeOfDay,
+    /// Local time the night light turns off
+    pub end: TimeOfDay,
+}
+
+impl Default for NightLightConfig {
+    fn default() -> Self {
+        NightLightConfig {
+            enabled: false,
+            temperature: 4500,
+            start: TimeOfDay(20 * 60),
+            end: TimeOfDay(7 * 60),
+        }
+    }
+}
+
+impl NightLightConfig {
+    /// Whether the night light is on at `now`, a schedule may span midnight
+    pub fn is_active(&self, now: TimeOfDay) -> bool {
+        let (start, end) = (self.start, self.end);
+        self.enabled
+            && if start <= end {
+                start <= now && now < end
+            } else {
+                now >= start || now < end
+            }
+    }
+}
+
+/// Minutes since midnight, written as `"<hours>:<minutes>"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeOfDay(pub u32);
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid time {:?}, expected e.g. \"20:30\"", value);
+        let (hours, minutes) = value.split_once(':').ok_or_else(invalid)?;
+        let hours = hours.parse::<u32>().map_err(|_| invalid())?;
+        let minutes = minutes.parse::<u32>().map_err(|_| invalid())?;
+        if hours >= 24 || minutes >= 60 {
+            return Err(invalid());
+        }
+        Ok(TimeOfDay(hours * 60 + minutes))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PrivilegedProtocol {
+    LayerShell,
+    Screencopy,
+    ExportDmabuf,
+    DataControl,
+    OutputManagement,
+    GammaControl,
+    ForeignToplevel,
+    VirtualKeyboard,
+    InputMethod,
+    DrmLease,
+}
+
+/// Protocols every client can use, unless the config turns them off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OptionalProtocol {
+    ContentType,
+    CursorShape,
+    AlphaModifier,
+    IdleInhibit,
+    TextInput,
+    RelativePointer,
+    PointerConstraints,
+    PointerGestures,
+    Tablet,
+    XwaylandKeyboardGrab,
+}
+
+/// Name of a protocol in the `[protocols]` section, its kind tells how the global is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(untagged)]
+pub enum Protocol {
+    /// Shown to the clients `[protocol_access]` allows, turning it off applies after a reload
+    Privileged(PrivilegedProtocol),
+    /// Shown to every client, turning it off only applies after a restart
+    Optional(OptionalProtocol),
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProtocolsConfig {
+    /// Protocols not offered to any client
+    pub disabled: Vec<Protocol>,
+}
+
+/// Client a privileged protocol is exposed to, every given field has to match and at least one
+/// is given
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "ClientRuleFields")]
+pub struct ClientRule {
+    /// Path of the client's executable
+    pub executable: Option<PathBuf>,
+    /// Sandbox engine of the client's security context, e.g. `org.flatpak`
+    pub sandbox_engine: Option<String>,
+    /// App id of the client's security context, sandboxed clients only match rules naming it
+    pub app_id: Option<String>,
+}
+
+/// [`ClientRule`] as written in the config, which may leave out every field
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClientRuleFields {
+    executable: Option<PathBuf>,
+    sandbox_engine: Option<String>,
+    app_id: Option<String>,
+}
+
+impl TryFrom<ClientRuleFields> for ClientRule {
+    type Error = &'static str;
+
+    fn try_from(fields: ClientRuleFields) -> Result<Self, Self::Error> {
+        let ClientRuleFields {
+            executable,
+            sandbox_engine,
+            app_id,
+        } = fields;
+        // `{}` would match every client
+        if executable.is_none() && sandbox_engine.is_none() && app_id.is_none() {
+            return Err("a client rule needs an executable, sandbox_engine or app_id");
+        }
+        Ok(ClientRule {
+            executable,
+            sandbox_engine,
+            app_id,
+        })
+    }
+}
+
+/// Settings for windows matching all of the given patterns, applied when they are first mapped.
+/// Later rules override the settings of earlier ones.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowRule {
+    /// Matched against the xdg app_id, or the class of X11 windows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_id: Option<Pattern>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<Pattern>,
+    /// Workspace of the output the window opens on, starting at 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<usize>,
+    /// Location relative to the usable area of the output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<[i32; 2]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<[i32; 2]>,
+    /// Zone of the output the window is tiled to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snap: Option<SnapConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maximized: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fullscreen: Option<bool>,
+    /// Decoration mode offered to the window, instead of the `[appearance]` one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decorations: Option<DecorationPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub opacity: Option<f32>,
+    /// Set to false to always composite the window, disabling direct scanout on outputs showing it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direct_scanout: Option<bool>,
+    /// Allow or deny keyboard shortcut inhibitors of the window without asking
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inhibit_shortcuts: Option<bool>,
+    /// Make the window the one toggled by `toggle-scratchpad`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scratchpad: Option<bool>,
+    /// Stack the window above all windows without this flag
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub always_on_top: Option<bool>,
+    /// Show the window on all workspaces of its output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sticky: Option<bool>,
+}
+
+impl WindowRule {
+    pub fn matches(&self, app_id: Option<&str>, title: Option<&str>) -> bool {
+        let matches = |pattern: &Option<Pattern>, value: Option<&str>| {
+            pattern
+                .as_ref()
+                .map_or(true, |pattern| value.is_some_and(|value| pattern.matches(value)))
+        };
+        matches(&self.app_id, app_id) && matches(&self.title, title)
+    }
+
+    /// Take over the settings `other` sets
+    pub fn merge(&mut self, other: &WindowRule) {
+        self.workspace = other.workspace.or(self.workspace);
+        self.position = other.position.or(self.position);
+        self.size = other.size.or(self.size);
+        self.snap = other.snap.or(self.snap);
+        self.maximized = other.maximized.or(self.maximized);
+        self.fullscreen = other.fullscreen.or(self.fullscreen);
+        self.decorations = other.decorations.or(self.decorations);
+        self.opacity = other.opacity.or(self.opacity);
+        self.direct_scanout = other.direct_scanout.or(self.direct_scanout);
+        self.inhibit_shortcuts = other.inhibit_shortcuts.or(self.inhibit_shortcuts);
+        self.scratchpad = other.scratchpad.or(self.scratchpad);
+        self.always_on_top = other.always_on_top.or(self.always_on_top);
+        self.sticky = other.sticky.or(self.sticky);
+    }
+}
+
+/// Pattern matching a whole string, where `*` stands for any number of characters
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Pattern(pub String);
+
+impl Pattern {
+    pub fn matches(&self, value: &str) -> bool {
+        let mut parts = self.0.split('*');
+        let Some(mut rest) = value.strip_prefix(parts.next().unwrap_or_default()) else {
+            return false;
+        };
+        let parts = parts.collect::<Vec<_>>();
+        let Some((last, middle)) = parts.split_last() else {
+            // no wildcard
+            return rest.is_empty();
+        };
+        for part in middle {
+            match rest.find(part) {
+                Some(index) => rest = &rest[index + part.len()..],
+                None => return false,
+            }
+        }
+        rest.ends_with(last)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SnapConfig {
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Environment variable enabling debug overlays on top of the configured ones, e.g. `damage,fps`
+pub const DEBUG_OVERLAY_ENV: &str = "LUXO_DEBUG_OVERLAY";
+
+/// Rendering diagnostics drawn on top of the outputs of the udev backend
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DebugOverlayConfig {
+    /// Highlight the regions that got repainted in the last frames
+    pub damage: bool,
+    /// Show the frame rate in the top left corner, only available with the `debug` feature
+    pub fps: bool,
+    /// Compare the repaint delay and the slowest recent repaint to the frame duration
+    pub repaint: bool,
+    /// Repaint every frame completely instead of only its damage, to tell whether stale damage
+    /// causes rendering artifacts
+    pub full_redraw: bool,
+}
+
+impl DebugOverlayConfig {
+    /// Additionally enable the overlays listed in `$LUXO_DEBUG_OVERLAY`
+    pub fn with_env(mut self) -> Self {
+        let Ok(overlays) = std::env::var(DEBUG_OVERLAY_ENV) else {
+            return self;
+        };
+        for overlay in overlays.split(',').map(str::trim) {
+            match overlay {
+                "damage" => self.damage = true,
+                "fps" => self.fps = true,
+                "repaint" => self.repaint = true,
+                "full-redraw" => self.full_redraw = true,
+                "" => {}
+                _ => warn!(overlay, "Unknown debug overlay in ${}", DEBUG_OVERLAY_ENV),
+            }
+        }
+        self
+    }
+}
+
+impl AppearanceConfig {
+    pub fn background_color(&self) -> Color32F {
+        let [r, g, b, a] = self.background_color;
+        Color32F::new(r, g, b, a)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    #[error("Error reading config file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Error parsing config file: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("Unable to determine config directory")]
+    NoConfigDir,
+    #[error("Unable to watch config file: {0}")]
+    Watch(#[from] smithay::reexports::calloop::Error),
+    #[error("Error editing config file: {0}")]
+    Edit(#[from] toml_edit::TomlError),
+    #[error("[{0}] of the config file is no table")]
+    NotATable(String),
+}
+
+/// How often the config file is checked for modifications
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+impl Config {
+    /// Location of the config file, `$XDG_CONFIG_HOME/luxo/config.toml` or `~/.config/luxo/config.toml`
+    pub fn path() -> Option<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|dir| dir.join("luxo").join("config.toml"))
+    }
+
+    pub fn from_path(path: &std::path::Path) -> Result<Config, ConfigError> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    /// Load the configuration at `path`, falling back to the defaults if it is missing or invalid.
+    ///
+    /// `path` is `None` if no location was given and the default one can't be determined.
+    pub fn load(path: Option<&std::path::Path>) -> Config {
+        let Some(path) = path else {
+            warn!("Unable to determine config directory, using default configuration");
+            return Config::default();
+        };
+
+        if !path.exists() {
+            info!(path = %path.display(), "No config file found, using default configuration");
+            return Config::default();
+        }
+
+        match Config::from_path(&path) {
+            Ok(config) => {
+                info!(path = %path.display(), "Loaded configuration");
+                config
+            }
+            Err(err) => {
+                warn!(path = %path.display(), "{}, using default configuration", err);
+                Config::default()
+            }
+        }
+    }
+
+    /// Call `on_change` whenever the config file at `path` is created, modified or removed
+    pub fn watch<D: 'static>(
+        handle: &LoopHandle<'static, D>,
+        path: PathBuf,
+        mut on_change: impl FnMut(&mut D) + 'static,
+    ) -> Result<(), ConfigError> {
+        let modified = |path: &std::path::Path| -> Option<SystemTime> {
+            std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        };
+
+        let mut last_modified = modified(&path);
+        handle
+            .insert_source(Timer::from_duration(WATCH_INTERVAL), move |_, _, data| {
+                let current = modified(&path);
+                if current != last_modified {
+                    last_modified = current;
+                    on_change(data);
+                }
+                TimeoutAction::ToDuration(WATCH_INTERVAL)
+            })
+            .map_err(|err| err.error)?;
+        Ok(())
+    }
+}
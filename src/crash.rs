@@ -0,0 +1,61 @@
+//! Crash reports for panics
+//!
+//! The panic hook writes the message and a backtrace to `$XDG_STATE_HOME/luxo/crash.log`, since the
+//! terminal luxo was started from is usually not visible anymore once it runs on a tty. Restoring
+//! the tty itself is up to the backend, see `run_udev`.
+
+use std::{
+    backtrace::Backtrace,
+    fs::File,
+    io::Write,
+    panic::PanicHookInfo,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tracing::error;
+
+/// Location of the crash log, `$XDG_STATE_HOME/luxo/crash.log` or `~/.local/state/luxo/crash.log`
+pub fn crash_log_path() -> Option<PathBuf> {
+    crate::logging::state_dir().map(|dir| dir.join("crash.log"))
+}
+
+/// Install a panic hook logging panics and writing them to the crash log, before running the
+/// default hook
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let backtrace = Backtrace::force_capture();
+        error!("{}", info);
+        match write_crash_log(info, &backtrace) {
+            Ok(path) => error!("Crash report written to {}", path.display()),
+            Err(err) => error!("Failed to write crash report: {}", err),
+        }
+        default_hook(info);
+    }));
+}
+
+fn write_crash_log(info: &PanicHookInfo<'_>, backtrace: &Backtrace) -> std::io::Result<PathBuf> {
+    let path = crash_log_path()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no home directory"))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    let thread = std::thread::current();
+    let mut file = File::create(&path)?;
+    writeln!(
+        file,
+        "luxo {} crashed at {} (unix time)",
+        env!("CARGO_PKG_VERSION"),
+        timestamp
+    )?;
+    writeln!(file, "thread '{}' {}", thread.name().unwrap_or("<unnamed>"), info)?;
+    writeln!(file)?;
+    writeln!(file, "{}", backtrace)?;
+    Ok(path)
+}
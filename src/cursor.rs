@@ -1,30 +1,30 @@
-use std::{io::Read, time::Duration};
+use std::{collections::HashMap, io::Read, time::Duration};
 
-use tracing::warn;
+use smithay::input::pointer::CursorIcon;
+use tracing::{debug, warn};
 use xcursor::{
     parser::{parse_xcursor, Image},
     CursorTheme,
 };
 
+use crate::config::CursorConfig;
+
 static FALLBACK_CURSOR_DATA: &[u8] = include_bytes!("../resources/cursor.rgba");
 
 pub struct Cursor {
-    icons: Vec<Image>,
+    theme: CursorTheme,
+    /// Images of every shape used so far, loaded on first use
+    icons: HashMap<CursorIcon, Vec<Image>>,
     size: u32,
 }
 
 impl Cursor {
-    pub fn load() -> Cursor {
-        let name = std::env::var("XCURSOR_THEME")
-            .ok()
-            .unwrap_or_else(|| "default".into());
-        let size = std::env::var("XCURSOR_SIZE")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(24);
+    pub fn load(config: &CursorConfig) -> Cursor {
+        let name = config.theme();
+        let size = config.size();
 
         let theme = CursorTheme::load(&name);
-        let icons = load_icon(&theme)
+        let default = load_icon(&theme, CursorIcon::Default)
             .map_err(|err| warn!("Unable to load xcursor: {}, using fallback cursor", err))
             .unwrap_or_else(|_| {
                 vec![Image {
@@ -39,12 +39,35 @@ impl Cursor {
                 }]
             });
 
-        Cursor { icons, size }
+        Cursor {
+            theme,
+            icons: HashMap::from([(CursorIcon::Default, default)]),
+            size,
+        }
+    }
+
+    /// Images of the `icon` shape, the default arrow if the theme doesn't have it
+    fn images(&mut self, icon: CursorIcon) -> &[Image] {
+        if !self.icons.contains_key(&icon) {
+            let images = load_icon(&self.theme, icon).unwrap_or_else(|err| {
+                debug!(?icon, "Unable to load xcursor: {}, using the default cursor", err);
+                self.icons[&CursorIcon::Default].clone()
+            });
+            self.icons.insert(icon, images);
+        }
+        &self.icons[&icon]
     }
 
-    pub fn get_image(&self, scale: u32, time: Duration) -> Image {
+    pub fn get_image(&mut self, icon: CursorIcon, scale: u32, time: Duration) -> Image {
         let size = self.size * scale;
-        frame(time.as_millis() as u32, size, &self.icons)
+        frame(time.as_millis() as u32, size, self.images(icon))
+    }
+
+    /// Buffer scale to draw `image` with, so it shows up at the nominal size.
+    ///
+    /// Themes don't ship every size, a smaller image than asked for gets scaled up instead.
+    pub fn buffer_scale(&self, image: &Image) -> i32 {
+        (image.size as f64 / self.size as f64).round().max(1.0) as i32
     }
 }
 
@@ -79,16 +102,20 @@ fn frame(mut millis: u32, size: u32, images: &[Image]) -> Image {
 
 #[derive(thiserror::Error, Debug)]
 enum Error {
-    #[error("Theme has no default cursor")]
-    NoDefaultCursor,
+    #[error("Theme has no {0} cursor")]
+    MissingIcon(&'static str),
     #[error("Error opening xcursor file: {0}")]
     File(#[from] std::io::Error),
     #[error("Failed to parse XCursor file")]
     Parse,
 }
 
-fn load_icon(theme: &CursorTheme) -> Result<Vec<Image>, Error> {
-    let icon_path = theme.load_icon("default").ok_or(Error::NoDefaultCursor)?;
+fn load_icon(theme: &CursorTheme, icon: CursorIcon) -> Result<Vec<Image>, Error> {
+    // older themes only use the X11 names, e.g. `left_ptr` for the default arrow
+    let icon_path = std::iter::once(icon.name())
+        .chain(icon.alt_names().iter().copied())
+        .find_map(|name| theme.load_icon(name))
+        .ok_or(Error::MissingIcon(icon.name()))?;
     let mut cursor_file = std::fs::File::open(icon_path)?;
     let mut cursor_data = Vec::new();
     cursor_file.read_to_end(&mut cursor_data)?;
@@ -1,29 +1,30 @@
 #![allow(clippy::too_many_arguments)]
 
+use std::time::{Duration, Instant};
+
 use smithay::{
     backend::renderer::{
+        damage::OutputDamageTracker,
         element::{
             memory::{MemoryRenderBuffer, MemoryRenderBufferRenderElement},
+            solid::SolidColorRenderElement,
             surface::WaylandSurfaceRenderElement,
-            AsRenderElements, Kind,
+            AsRenderElements, Id, Kind, RenderElement,
         },
+        utils::CommitCounter,
         Color32F, ImportAll, ImportMem, Renderer, Texture,
     },
     input::pointer::CursorImageStatus,
+    output::Output,
     render_elements,
-    utils::{Physical, Point, Scale},
+    utils::{Physical, Point, Rectangle, Scale},
 };
 #[cfg(feature = "debug")]
 use smithay::{
-    backend::renderer::{
-        element::{Element, Id, RenderElement},
-        utils::CommitCounter,
-        Frame,
-    },
-    utils::{Buffer, Logical, Rectangle, Size, Transform},
+    backend::renderer::{element::Element, Frame},
+    utils::{Buffer, Logical, Size, Transform},
 };
 
-pub static CLEAR_COLOR: Color32F = Color32F::new(0.8, 0.8, 0.9, 1.0);
 pub static CLEAR_COLOR_FULLSCREEN: Color32F = Color32F::new(0.0, 0.0, 0.0, 0.0);
 
 pub struct PointerElement {
@@ -83,7 +84,7 @@ where
     {
         match &self.status {
             CursorImageStatus::Hidden => vec![],
-            // Always render `Default` for a named shape.
+            // the backend sets the buffer to the image of the named shape
             CursorImageStatus::Named(_) => {
                 if let Some(buffer) = self.buffer.as_ref() {
                     vec![PointerRenderElement::<R>::from(
@@ -119,6 +120,139 @@ where
     }
 }
 
+/// How long repainted regions stay highlighted by the damage overlay
+const DAMAGE_HIGHLIGHT_DURATION: Duration = Duration::from_millis(500);
+
+/// Highlights the regions of an output that got repainted in recent frames
+#[derive(Debug)]
+pub struct DamageOverlay {
+    damage_tracker: OutputDamageTracker,
+    damage: Vec<(Instant, Rectangle<i32, Physical>)>,
+    /// Damaged pixels of the frames of the last [`DAMAGE_HIGHLIGHT_DURATION`] that changed anything
+    frames: Vec<(Instant, u64)>,
+    frame_damage: Option<u64>,
+    /// The damage bar only changes along with the damage, so it doesn't cause repaints itself
+    bar_id: Id,
+    bar_geometry: Rectangle<i32, Physical>,
+    bar_commit: CommitCounter,
+}
+
+impl DamageOverlay {
+    pub fn new(output: &Output) -> Self {
+        DamageOverlay {
+            damage_tracker: OutputDamageTracker::from_output(output),
+            damage: Vec::new(),
+            frames: Vec::new(),
+            frame_damage: None,
+            bar_id: Id::new(),
+            bar_geometry: Rectangle::default(),
+            bar_commit: CommitCounter::default(),
+        }
+    }
+
+    /// Damaged pixels of the current frame, `None` if nothing changed since the previous one
+    pub fn frame_damage(&self) -> Option<u64> {
+        self.frame_damage
+    }
+
+    /// Average damaged pixels of the recent frames that changed anything
+    pub fn recent_damage(&self) -> f64 {
+        if self.frames.is_empty() {
+            return 0.0;
+        }
+        self.frames.iter().map(|(_, pixels)| *pixels as f64).sum::<f64>() / self.frames.len() as f64
+    }
+
+    /// Record the damage `elements` cause compared to the previous frame, and return elements
+    /// highlighting the damage of the last [`DAMAGE_HIGHLIGHT_DURATION`]
+    pub fn update<R, E>(&mut self, elements: &[E]) -> Vec<SolidColorRenderElement>
+    where
+        R: Renderer,
+        E: RenderElement<R>,
+    {
+        let now = Instant::now();
+        self.frame_damage = match self.damage_tracker.damage_output(1, elements) {
+            Ok((Some(damage), _)) => {
+                self.damage.extend(damage.iter().map(|rect| (now, *rect)));
+                Some(damage.iter().map(|rect| rect.size.w as u64 * rect.size.h as u64).sum())
+            }
+            _ => None,
+        };
+        self.damage
+            .retain(|(time, _)| now.duration_since(*time) < DAMAGE_HIGHLIGHT_DURATION);
+        self.frames
+            .retain(|(time, _)| now.duration_since(*time) < DAMAGE_HIGHLIGHT_DURATION);
+        self.frames.extend(self.frame_damage.map(|pixels| (now, pixels)));
+
+        self.damage
+            .iter()
+            .map(|(time, rect)| {
+                let age = now.duration_since(*time).as_secs_f32() / DAMAGE_HIGHLIGHT_DURATION.as_secs_f32();
+                let alpha = 0.4 * (1.0 - age);
+                SolidColorRenderElement::new(
+                    Id::new(),
+                    *rect,
+                    CommitCounter::default(),
+                    Color32F::new(alpha, 0.0, 0.0, alpha),
+                    Kind::Unspecified,
+                )
+            })
+            .collect()
+    }
+
+    /// Bar below the repaint bars whose full `width` stands for all `frame_pixels` of a frame,
+    /// showing how much of the recent frames got damaged
+    pub fn damage_bar(&mut self, width: i32, frame_pixels: u64) -> SolidColorRenderElement {
+        let fraction = (self.recent_damage() / frame_pixels.max(1) as f64).min(1.0);
+        let geometry = Rectangle::new(
+            (0, 2 * REPAINT_BAR_HEIGHT).into(),
+            ((width as f64 * fraction).round() as i32, REPAINT_BAR_HEIGHT).into(),
+        );
+        if geometry != self.bar_geometry {
+            self.bar_geometry = geometry;
+            self.bar_commit.increment();
+        }
+        SolidColorRenderElement::new(
+            self.bar_id.clone(),
+            geometry,
+            self.bar_commit,
+            Color32F::new(0.0, 0.2, 0.8, 0.8),
+            Kind::Unspecified,
+        )
+    }
+}
+
+/// Height of the bars drawn by the repaint overlay, in physical pixels
+const REPAINT_BAR_HEIGHT: i32 = 6;
+
+/// Bars along the top edge of an output whose full width stands for one frame: the upper one is
+/// the delay between a vblank and the next repaint, the lower one the slowest recent repaint,
+/// which has to fit into the rest of the frame
+pub fn repaint_bars(
+    width: i32,
+    frame_duration: Duration,
+    delay: Duration,
+    render_time: Duration,
+) -> [SolidColorRenderElement; 2] {
+    let bar = |row: i32, duration: Duration, color: Color32F| {
+        let fraction = (duration.as_secs_f64() / frame_duration.as_secs_f64()).min(1.0);
+        SolidColorRenderElement::new(
+            Id::new(),
+            Rectangle::new(
+                (0, row * REPAINT_BAR_HEIGHT).into(),
+                ((width as f64 * fraction).round() as i32, REPAINT_BAR_HEIGHT).into(),
+            ),
+            CommitCounter::default(),
+            color,
+            Kind::Unspecified,
+        )
+    };
+    [
+        bar(0, delay, Color32F::new(0.0, 0.6, 0.0, 0.8)),
+        bar(1, render_time, Color32F::new(0.8, 0.0, 0.0, 0.8)),
+    ]
+}
+
 #[cfg(feature = "debug")]
 pub static FPS_NUMBERS_PNG: &[u8] = include_bytes!("../resources/numbers.png");
 
@@ -0,0 +1,396 @@
+//! Backend without any display or input devices
+//!
+//! Renders a single virtual output into an offscreen buffer, so the compositor can run in CI or
+//! benchmarks and tests can inspect its frames through screencopy and its state through IPC.
+//! Named cursors are drawn as a plain square, so captured frames don't depend on the cursor theme.
+
+use std::{
+    path::Path,
+    sync::{atomic::Ordering, Mutex},
+    time::Duration,
+};
+
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        egl::{EGLContext, EGLDevice, EGLDisplay},
+        renderer::{
+            damage::OutputDamageTracker,
+            element::{memory::MemoryRenderBuffer, AsRenderElements},
+            gles::{GlesRenderbuffer, GlesRenderer},
+            Bind, ExportMem, ImportMemWl, Offscreen, TextureMapping,
+        },
+    },
+    input::{
+        keyboard::LedState,
+        pointer::{CursorImageAttributes, CursorImageStatus},
+    },
+    output::{Mode, Output, PhysicalProperties, Subpixel},
+    reexports::{
+        calloop::{
+            timer::{TimeoutAction, Timer},
+            EventLoop,
+        },
+        wayland_protocols::wp::presentation_time::server::wp_presentation_feedback,
+        wayland_server::{protocol::wl_surface, Display},
+    },
+    utils::{IsAlive, Rectangle, Scale, Size, Transform},
+    wayland::{compositor, presentation::Refresh},
+};
+use tracing::{error, info, trace, warn};
+
+use crate::{
+    cli::Options,
+    drawing::PointerElement,
+    ipc::FrameStats,
+    render::*,
+    state::{take_presentation_feedback, LuxoState, Backend},
+};
+
+pub const OUTPUT_NAME: &str = "headless";
+/// Size of the virtual output unless given with `--output-size`
+pub const DEFAULT_SIZE: (i32, i32) = (1920, 1080);
+const REFRESH: i32 = 60_000;
+/// Edge length of the square drawn for named cursors
+const CURSOR_SIZE: i32 = 16;
+
+pub struct HeadlessData {
+    renderer: GlesRenderer,
+    pointer_element: PointerElement,
+    buffer: GlesRenderbuffer,
+    damage_tracker: OutputDamageTracker,
+    /// Whether `buffer` still holds the last frame
+    buffer_valid: bool,
+    output: Output,
+    frame_stats: FrameStats,
+}
+
+impl Backend for HeadlessData {
+    fn seat_name(&self) -> String {
+        String::from("headless")
+    }
+    fn reset_buffers(&mut self, _output: &Output) {
+        self.buffer_valid = false;
+    }
+    fn early_import(&mut self, _surface: &wl_surface::WlSurface) {}
+    fn update_led_state(&mut self, _led_state: LedState) {}
+    fn frame_stats(&self, output: &Output) -> Option<FrameStats> {
+        (output == &self.output).then_some(self.frame_stats)
+    }
+    fn capture_output(state: &mut LuxoState<Self>, output: &Output, path: &Path) -> Result<(), String> {
+        let mode = output.current_mode().ok_or("Output has no mode")?;
+        let size = Size::from((mode.size.w, mode.size.h));
+        let elements = state.cursor_elements(output);
+
+        let data = &mut state.backend_data;
+        let mut buffer: GlesRenderbuffer = data
+            .renderer
+            .create_buffer(Fourcc::Abgr8888, size)
+            .map_err(|err| err.to_string())?;
+        let mut framebuffer = data.renderer.bind(&mut buffer).map_err(|err| err.to_string())?;
+        let mut damage_tracker = OutputDamageTracker::from_output(output);
+        render_output(
+            output,
+            &state.space,
+            elements,
+            &mut data.renderer,
+            &mut framebuffer,
+            &mut damage_tracker,
+            0,
+            state.show_window_preview,
+            state.config.appearance.background_color(),
+        )
+        .map_err(|err| format!("{:?}", err))?;
+        let mapping = data
+            .renderer
+            .copy_framebuffer(&framebuffer, Rectangle::from_size(size), Fourcc::Abgr8888)
+            .map_err(|err| err.to_string())?;
+        let pixels = data
+            .renderer
+            .map_texture(&mapping)
+            .map_err(|err| err.to_string())?;
+        let row_len = size.w as usize * 4;
+        let pixels = if mapping.flipped() {
+            pixels.chunks_exact(row_len).rev().flatten().copied().collect()
+        } else {
+            pixels.to_vec()
+        };
+
+        // Abgr8888 is laid out as red, green, blue, alpha bytes
+        image::save_buffer(
+            path,
+            &pixels,
+            size.w as u32,
+            size.h as u32,
+            image::ExtendedColorType::Rgba8,
+        )
+        .map_err(|err| err.to_string())
+    }
+}
+
+/// Black square with a white border, drawn for all named cursors
+fn cursor_buffer() -> MemoryRenderBuffer {
+    let pixels = (0..CURSOR_SIZE * CURSOR_SIZE)
+        .flat_map(|i| {
+            let (x, y) = (i % CURSOR_SIZE, i / CURSOR_SIZE);
+            let border = x == 0 || y == 0 || x == CURSOR_SIZE - 1 || y == CURSOR_SIZE - 1;
+            if border {
+                [255, 255, 255, 255]
+            } else {
+                [0, 0, 0, 255]
+            }
+        })
+        .collect::<Vec<u8>>();
+    MemoryRenderBuffer::from_slice(
+        &pixels,
+        Fourcc::Argb8888,
+        (CURSOR_SIZE, CURSOR_SIZE),
+        1,
+        Transform::Normal,
+        None,
+    )
+}
+
+/// Create a renderer on the first EGL device that supports it, which may be a software
+/// rasterizer like llvmpipe
+fn init_renderer() -> Option<GlesRenderer> {
+    let devices = match EGLDevice::enumerate() {
+        Ok(devices) => devices,
+        Err(err) => {
+            error!("Failed to enumerate EGL devices: {}", err);
+            return None;
+        }
+    };
+    devices.into_iter().find_map(|device| {
+        let name = device.drm_device_path().ok();
+        // SAFETY: the display is only used by this renderer
+        let display = match unsafe { EGLDisplay::new(device) } {
+            Ok(display) => display,
+            Err(err) => {
+                warn!(device = ?name, "Failed to create EGL display: {}", err);
+                return None;
+            }
+        };
+        let renderer = EGLContext::new(&display)
+            .map_err(|err| err.to_string())
+            // SAFETY: the context is not current on any other thread
+            .and_then(|context| unsafe { GlesRenderer::new(context) }.map_err(|err| err.to_string()));
+        match renderer {
+            Ok(renderer) => {
+                info!(device = ?name, "Rendering on EGL device");
+                Some(renderer)
+            }
+            Err(err) => {
+                warn!(device = ?name, "Failed to create renderer: {}", err);
+                None
+            }
+        }
+    })
+}
+
+pub fn run_headless(options: &Options) {
+    let mut event_loop = EventLoop::try_new().unwrap();
+    let display = Display::new().unwrap();
+    let mut display_handle = display.handle();
+
+    let Some(mut renderer) = init_renderer() else {
+        error!("No EGL device to render on");
+        return;
+    };
+
+    let (width, height) = options.output_size.unwrap_or(DEFAULT_SIZE);
+    let mode = Mode {
+        size: (width, height).into(),
+        refresh: REFRESH,
+    };
+    let buffer = match Offscreen::<GlesRenderbuffer>::create_buffer(
+        &mut renderer,
+        Fourcc::Abgr8888,
+        Size::from((width, height)),
+    ) {
+        Ok(buffer) => buffer,
+        Err(err) => {
+            error!("Failed to create {}x{} output buffer: {}", width, height, err);
+            return;
+        }
+    };
+
+    let output = Output::new(
+        OUTPUT_NAME.to_string(),
+        PhysicalProperties {
+            size: (0, 0).into(),
+            subpixel: Subpixel::Unknown,
+            make: "Smithay".into(),
+            model: "Headless".into(),
+        },
+    );
+    let _global = output.create_global::<LuxoState<HeadlessData>>(&display.handle());
+    output.change_current_state(Some(mode), Some(Transform::Normal), None, Some((0, 0).into()));
+    output.set_preferred(mode);
+
+    let mut pointer_element = PointerElement::default();
+    pointer_element.set_buffer(cursor_buffer());
+    let data = HeadlessData {
+        damage_tracker: OutputDamageTracker::from_output(&output),
+        renderer,
+        pointer_element,
+        buffer,
+        buffer_valid: false,
+        output: output.clone(),
+        frame_stats: FrameStats::default(),
+    };
+
+    let mut state = LuxoState::init(display, event_loop.handle(), data, true, options);
+    state
+        .shm_state
+        .update_formats(state.backend_data.renderer.shm_formats());
+    state.space.map_output(&output, (0, 0));
+
+    let frame_duration = Duration::from_secs_f64(1_000f64 / REFRESH as f64);
+    event_loop
+        .handle()
+        .insert_source(Timer::immediate(), move |_, _, state| {
+            state.render_headless(frame_duration);
+            TimeoutAction::ToDuration(frame_duration)
+        })
+        .expect("Failed to schedule frame timer");
+
+    #[cfg(feature = "xwayland")]
+    state.start_xwayland();
+
+    if let Some(command) = options.command.as_deref() {
+        state.spawn(command);
+    }
+    state.start_session();
+
+    info!(width, height, "Initialization completed, starting the main loop.");
+
+    while state.running.load(Ordering::SeqCst) {
+        let result = event_loop.dispatch(Some(frame_duration), &mut state);
+        if result.is_err() {
+            state.running.store(false, Ordering::SeqCst);
+        } else {
+            state.refresh_space();
+            state.update_scratchpad();
+            state.restack_windows();
+            state.update_magnifier();
+            state.update_pings();
+            state.popups.cleanup();
+            state.update_foreign_toplevels();
+            state.notify_ipc_subscribers();
+            display_handle.flush_clients().unwrap();
+        }
+    }
+
+    state.shutdown();
+}
+
+impl LuxoState<HeadlessData> {
+    /// Render elements of the cursor, if it is on `output`
+    fn cursor_elements(&mut self, output: &Output) -> Vec<CustomRenderElements<GlesRenderer>> {
+        // reset the cursor if the surface is no longer alive
+        if let CursorImageStatus::Surface(ref surface) = self.cursor_status {
+            if !surface.alive() {
+                self.cursor_status = CursorImageStatus::default_named();
+            }
+        }
+        let Some(output_geometry) = self.space.output_geometry(output) else {
+            return Vec::new();
+        };
+        let cursor_pos = self.pointer.current_location();
+        if !output_geometry.to_f64().contains(cursor_pos) {
+            return Vec::new();
+        }
+
+        let hotspot = if let CursorImageStatus::Surface(ref surface) = self.cursor_status {
+            compositor::with_states(surface, |states| {
+                states
+                    .data_map
+                    .get::<Mutex<CursorImageAttributes>>()
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .hotspot
+            })
+        } else {
+            (0, 0).into()
+        };
+        let scale = Scale::from(output.current_scale().fractional_scale());
+        let location = (cursor_pos - output_geometry.loc.to_f64() - hotspot.to_f64())
+            .to_physical(scale)
+            .to_i32_round();
+
+        let data = &mut self.backend_data;
+        data.pointer_element.set_status(self.cursor_status.clone());
+        data.pointer_element
+            .render_elements(&mut data.renderer, location, scale, 1.0)
+    }
+
+    fn render_headless(&mut self, frame_duration: Duration) {
+        profiling::scope!("render_frame");
+
+        let output = self.backend_data.output.clone();
+        let frame_target = self.clock.now() + frame_duration;
+        self.pre_repaint(&output, frame_target);
+        let elements = self.cursor_elements(&output);
+
+        let backend_data = &mut self.backend_data;
+        let age = if backend_data.buffer_valid { 1 } else { 0 };
+        let mut framebuffer = match backend_data.renderer.bind(&mut backend_data.buffer) {
+            Ok(framebuffer) => framebuffer,
+            Err(err) => {
+                error!("Error while binding buffer: {}", err);
+                return;
+            }
+        };
+        let result = render_output(
+            &output,
+            &self.space,
+            elements,
+            &mut backend_data.renderer,
+            &mut framebuffer,
+            &mut backend_data.damage_tracker,
+            age,
+            self.show_window_preview,
+            self.config.appearance.background_color(),
+        );
+        drop(framebuffer);
+
+        let states = match result {
+            Ok(result) => {
+                backend_data.buffer_valid = true;
+                if result.damage.is_some() {
+                    trace!("Finished rendering");
+                    backend_data.frame_stats.composited += 1;
+                    let mut feedback = take_presentation_feedback(&output, &self.space, &result.states);
+                    feedback.presented(
+                        frame_target,
+                        Refresh::fixed(frame_duration),
+                        0,
+                        wp_presentation_feedback::Kind::empty(),
+                    );
+                }
+                result.states
+            }
+            Err(err) => {
+                backend_data.buffer_valid = false;
+                error!("Rendering error: {}", err);
+                return;
+            }
+        };
+
+        self.post_repaint(&output, frame_target, None, &states);
+
+        render_pending_screencopies::<_, GlesRenderbuffer>(
+            &mut self.backend_data.renderer,
+            &output,
+            &self.space,
+            &mut self.pending_screencopies,
+            self.show_window_preview,
+            self.config.appearance.background_color(),
+            self.clock.now().into(),
+        );
+
+        profiling::finish_frame!();
+    }
+}
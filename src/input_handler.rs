@@ -1,9 +1,21 @@
-use std::{convert::TryInto, process::Command, sync::atomic::Ordering};
-
-use crate::{focus::PointerFocusTarget, shell::FullscreenSurface, LuxoState};
+use std::{convert::TryInto, process::Command, sync::atomic::Ordering, time::Duration};
+
+use crate::{
+    config::{FocusModel, OutputEdge},
+    focus::{KeyboardFocusTarget, PointerFocusTarget},
+    ipc::{self, SOCKET_ENV},
+    keybindings::{KeyAction, PointerAction, PointerTrigger},
+    magnifier::ZOOM_STEP,
+    shell::{minimized_windows, FullscreenSurface, WindowElement},
+    LuxoState,
+};
 
 #[cfg(feature = "udev")]
-use crate::udev::UdevData;
+use crate::{
+    keybindings::{Direction, SwipeGesture},
+    rotation,
+    udev::UdevData,
+};
 #[cfg(feature = "udev")]
 use smithay::backend::renderer::DebugFlags;
 
@@ -14,11 +26,12 @@ use smithay::{
     },
     desktop::{layer_map_for_output, WindowSurfaceType},
     input::{
-        keyboard::{keysyms as xkb, FilterResult, Keysym, ModifiersState},
+        keyboard::FilterResult,
         pointer::{AxisFrame, ButtonEvent, MotionEvent},
     },
     output::Scale,
     reexports::{
+        calloop::timer::{TimeoutAction, Timer},
         wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1,
         wayland_server::protocol::wl_pointer,
     },
@@ -36,18 +49,16 @@ use smithay::backend::input::AbsolutePositionEvent;
 
 #[cfg(any(feature = "winit", feature = "x11"))]
 use smithay::output::Output;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use crate::state::Backend;
 #[cfg(feature = "udev")]
 use smithay::{
-    backend::{
-        input::{
-            Device, DeviceCapability, GestureBeginEvent, GestureEndEvent, GesturePinchUpdateEvent as _,
-            GestureSwipeUpdateEvent as _, PointerMotionEvent, ProximityState, TabletToolButtonEvent,
-            TabletToolEvent, TabletToolProximityEvent, TabletToolTipEvent, TabletToolTipState, TouchEvent,
-        },
-        session::Session,
+    backend::input::{
+        Device, DeviceCapability, GestureBeginEvent, GestureEndEvent, GesturePinchUpdateEvent as _,
+        GestureSwipeUpdateEvent as _, PointerMotionEvent, ProximityState, Switch, SwitchState, SwitchToggleEvent,
+        TabletToolButtonEvent, TabletToolEvent, TabletToolProximityEvent, TabletToolTipEvent, TabletToolTipState,
+        TouchEvent,
     },
     input::{
         pointer::{
@@ -65,8 +76,46 @@ use smithay::{
     },
 };
 
+/// Distance a touchpad swipe has to travel to trigger its binding
+#[cfg(feature = "udev")]
+const SWIPE_THRESHOLD: f64 = 100.0;
+
+/// A touchpad swipe the compositor handles itself, as its finger count has a gesture binding
+#[cfg(feature = "udev")]
+#[derive(Debug)]
+pub struct CompositorSwipe {
+    fingers: u32,
+    delta: Point<f64, Logical>,
+}
+
+#[cfg(feature = "udev")]
+impl CompositorSwipe {
+    /// The gesture performed so far, if the swipe went far enough along one axis
+    fn gesture(&self) -> Option<SwipeGesture> {
+        let Point { x, y, .. } = self.delta;
+        let direction = if x.abs() >= y.abs() {
+            if x < 0.0 {
+                Direction::Left
+            } else {
+                Direction::Right
+            }
+        } else if y < 0.0 {
+            Direction::Up
+        } else {
+            Direction::Down
+        };
+
+        (x.abs().max(y.abs()) >= SWIPE_THRESHOLD).then_some(SwipeGesture {
+            fingers: self.fingers,
+            direction,
+        })
+    }
+}
+
 impl<BackendData: Backend> LuxoState<BackendData> {
-    fn process_common_key_action(&mut self, action: KeyAction) {
+    pub(crate) fn process_common_key_action(&mut self, action: KeyAction) {
+        let focused = self.focused_window();
+
         match action {
             KeyAction::None => (),
 
@@ -75,27 +124,7 @@ impl<BackendData: Backend> LuxoState<BackendData> {
                 self.running.store(false, Ordering::SeqCst);
             }
 
-            KeyAction::Run(cmd) => {
-                info!(cmd, "Starting program");
-
-                if let Err(e) = Command::new(&cmd)
-                    .envs(
-                        self.socket_name
-                            .clone()
-                            .map(|v| ("WAYLAND_DISPLAY", v))
-                            .into_iter()
-                            .chain(
-                                #[cfg(feature = "xwayland")]
-                                self.xdisplay.map(|v| ("DISPLAY", format!(":{}", v))),
-                                #[cfg(not(feature = "xwayland"))]
-                                None,
-                            ),
-                    )
-                    .spawn()
-                {
-                    error!(cmd, err = %e, "Failed to start program");
-                }
-            }
+            KeyAction::Spawn(cmd) => self.spawn(&cmd),
 
             KeyAction::TogglePreview => {
                 self.show_window_preview = !self.show_window_preview;
@@ -127,11 +156,343 @@ impl<BackendData: Backend> LuxoState<BackendData> {
                 }
             }
 
+            KeyAction::CloseWindow => {
+                if let Some(window) = self.focused_window() {
+                    self.close_window(&window);
+                }
+            }
+            KeyAction::KillWindow => {
+                if let Some(window) = self.focused_window() {
+                    self.kill_window(&window);
+                }
+            }
+
+            KeyAction::ToggleFullscreen => {
+                if let Some(window) = self.focused_window() {
+                    self.toggle_fullscreen(&window);
+                }
+            }
+
+            KeyAction::ToggleMaximize => {
+                if let Some(window) = self.focused_window() {
+                    self.toggle_maximize(&window);
+                }
+            }
+
+            KeyAction::FocusDirection(direction) => self.focus_direction(direction),
+            KeyAction::FocusNext => self.focus_next(),
+            KeyAction::FocusPrev => self.focus_prev(),
+            KeyAction::MoveWindow(direction) => self.move_focused_window(direction),
+            KeyAction::GrowWindow(direction) => self.grow_focused_window(direction),
+            KeyAction::ShrinkWindow(direction) => self.shrink_focused_window(direction),
+            KeyAction::CenterWindow => self.center_focused_window(),
+            KeyAction::MoveToOutput(target) => self.move_focused_window_to_output(target),
+
+            KeyAction::ChangeOpacity(delta) => {
+                if let Some(window) = self.focused_window() {
+                    window.set_opacity(window.opacity() + delta);
+                }
+            }
+
+            KeyAction::ToggleShortcutsInhibit => self.toggle_shortcuts_inhibit(),
+            KeyAction::ToggleScratchpad => self.toggle_scratchpad(),
+
+            KeyAction::ToggleAlwaysOnTop => {
+                if let Some(window) = self.focused_window() {
+                    window.set_always_on_top(!window.is_always_on_top());
+                }
+            }
+
+            KeyAction::ToggleSticky => {
+                if let Some(window) = self.focused_window() {
+                    window.set_sticky(!window.is_sticky());
+                }
+            }
+
+            KeyAction::ZoomIn => self.zoom_by(ZOOM_STEP, true),
+            KeyAction::ZoomOut => self.zoom_by(1.0 / ZOOM_STEP, true),
+            KeyAction::ZoomReset => self.reset_zoom(),
+
+            KeyAction::MinimizeWindow => {
+                if let Some(window) = self.focused_window() {
+                    self.minimize_window(&window);
+                }
+            }
+
+            KeyAction::UnminimizeWindow => {
+                if let Some(window) = self
+                    .workspace_output()
+                    .and_then(|output| minimized_windows(&output).pop())
+                {
+                    self.unminimize_window(&window);
+                }
+            }
+
+            KeyAction::ToggleShowDesktop => {
+                if let Some(output) = self.workspace_output() {
+                    self.toggle_show_desktop(&output);
+                }
+            }
+
+            KeyAction::Workspace(index) => {
+                if let Some(output) = self.workspace_output() {
+                    self.switch_workspace(&output, index);
+                }
+            }
+
+            KeyAction::MoveToWorkspace(index) => {
+                if let Some(window) = self.focused_window() {
+                    self.move_to_workspace(&window, index);
+                }
+            }
+
+            KeyAction::NextWorkspace => {
+                if let Some(output) = self.workspace_output() {
+                    let index = self.active_workspace(&output) + 1;
+                    self.switch_workspace(&output, index);
+                }
+            }
+
+            KeyAction::PrevWorkspace => {
+                if let Some(output) = self.workspace_output() {
+                    if let Some(index) = self.active_workspace(&output).checked_sub(1) {
+                        self.switch_workspace(&output, index);
+                    }
+                }
+            }
+
+            KeyAction::NextLayout => {
+                let count = self.config.input.keyboard.layout_count();
+                if count > 1 {
+                    self.set_keyboard_layout((self.keyboard_layout + 1) % count);
+                }
+            }
+
             _ => unreachable!(
                 "Common key action handler encountered backend specific action {:?}",
                 action
             ),
         }
+
+        if self.config.focus.warp_pointer {
+            if let Some(window) = self
+                .focused_window()
+                .filter(|window| Some(window) != focused.as_ref())
+            {
+                self.warp_pointer_to_window(&window);
+            }
+        }
+    }
+
+    /// Move the pointer to `location`, as if it was moved there by an input device
+    pub fn warp_pointer(&mut self, location: Point<f64, Logical>) {
+        let pointer = self.pointer.clone();
+        let under = self.surface_under(location);
+        pointer.motion(
+            self,
+            under,
+            &MotionEvent {
+                location,
+                serial: SCOUNTER.next_serial(),
+                time: self.clock.now().as_millis(),
+            },
+        );
+        pointer.frame(self);
+    }
+
+    /// Move the pointer to the center of an output if it is no longer on any, e.g. after an
+    /// output got removed
+    pub fn warp_pointer_onto_outputs(&mut self) {
+        let location = self.pointer.current_location();
+        if self.space.output_under(location).next().is_some() {
+            return;
+        }
+        let Some(geometry) = self
+            .workspace_output()
+            .and_then(|output| self.space.output_geometry(&output))
+        else {
+            return;
+        };
+
+        let location = geometry.to_f64().loc + geometry.size.to_f64().downscale(2.0).to_point();
+        self.warp_pointer(location);
+    }
+
+    /// Move the pointer to the center of `window`, unless it already is above it
+    fn warp_pointer_to_window(&mut self, window: &WindowElement) {
+        let Some(geometry) = self.space.element_geometry(window) else {
+            return;
+        };
+        if geometry.to_f64().contains(self.pointer.current_location()) {
+            return;
+        }
+        self.warp_pointer(geometry.loc.to_f64() + geometry.size.to_f64().downscale(2.0).to_point());
+    }
+
+    /// Load layout `index` out of the configured keyboard layouts, sending the new keymap to clients
+    /// and the change to ipc subscribers
+    pub fn set_keyboard_layout(&mut self, index: usize) {
+        let keyboard_config = self.config.input.keyboard.clone();
+        let layout = keyboard_config.layout_name(index);
+        let keyboard = self.seat.get_keyboard().unwrap();
+        let modifiers = keyboard.modifier_state();
+        match keyboard.set_xkb_config(self, keyboard_config.xkb_config(index)) {
+            Ok(()) => {
+                info!(layout, "Switched keyboard layout");
+                self.keyboard_layout = index;
+                // the new keymap starts out without any locked modifiers
+                self.set_locks(modifiers.caps_lock, modifiers.num_lock);
+                self.send_ipc_event(ipc::Event::KeyboardLayoutChanged {
+                    index,
+                    layout: layout.to_owned(),
+                });
+            }
+            Err(err) => warn!(layout, error = ?err, "Failed to switch keyboard layout"),
+        }
+    }
+
+    /// Lock or unlock caps lock and num lock of the seat keyboard
+    pub fn set_locks(&mut self, caps_lock: bool, num_lock: bool) {
+        let keyboard = self.seat.get_keyboard().unwrap();
+        let mut modifiers = keyboard.modifier_state();
+        modifiers.caps_lock = caps_lock;
+        modifiers.num_lock = num_lock;
+        keyboard.set_modifier_state(modifiers);
+        // no key was pressed, so the seat handler isn't told about the changed leds
+        self.backend_data.update_led_state(keyboard.led_state());
+    }
+
+    /// Change the key repeat delay (in milliseconds) and rate (per second) of the seat keyboard
+    pub fn set_key_repeat(&mut self, delay: i32, rate: i32) {
+        let keyboard = self.seat.get_keyboard().unwrap();
+        keyboard.change_repeat_info(rate, delay);
+        self.config.input.repeat_delay = delay;
+        self.config.input.repeat_rate = rate;
+        info!(delay, rate, "Changed key repeat");
+    }
+
+    /// Start `cmd` as a client of this compositor.
+    ///
+    /// The command is run through `sh -c` so arguments, quoting and variable expansion work as in a shell.
+    pub fn spawn(&mut self, cmd: &str) {
+        info!(cmd, "Starting program");
+
+        match self.program_command(cmd).spawn() {
+            Ok(mut child) => {
+                // reap the child once it exits, so it doesn't linger as a zombie
+                let cmd = cmd.to_owned();
+                std::thread::spawn(move || match child.wait() {
+                    Ok(status) => debug!(cmd, %status, "Program exited"),
+                    Err(err) => warn!(cmd, %err, "Failed to wait for program"),
+                });
+            }
+            Err(err) => error!(cmd, %err, "Failed to start program"),
+        }
+    }
+
+    /// Command running `cmd` through `sh -c` with the environment clients of this compositor need
+    pub(crate) fn program_command(&mut self, cmd: &str) -> Command {
+        let mut command = Command::new("/bin/sh");
+        command.arg("-c").arg(cmd);
+        command.envs(&self.config.environment);
+        // lets the program take the focus once it shows its first window
+        let (token, _) = self.xdg_activation_state.create_external_token(None);
+        command.env("XDG_ACTIVATION_TOKEN", token.as_str());
+        command.env("DESKTOP_STARTUP_ID", token.as_str());
+        if let Some(socket_name) = self.socket_name.as_ref() {
+            command.env("WAYLAND_DISPLAY", socket_name);
+        }
+        // so toolkits drawing their own cursors match the one of the compositor
+        command.env("XCURSOR_THEME", self.config.cursor.theme());
+        command.env("XCURSOR_SIZE", self.config.cursor.size().to_string());
+        if let Some(ipc_socket) = self.ipc_socket.as_ref() {
+            command.env(SOCKET_ENV, ipc_socket.path());
+        }
+        #[cfg(feature = "xwayland")]
+        if let Some(xdisplay) = self.xdisplay {
+            command.env("DISPLAY", format!(":{}", xdisplay));
+        }
+        command
+    }
+
+    /// The window currently holding the keyboard focus, if any
+    pub(crate) fn focused_window(&self) -> Option<WindowElement> {
+        let focus = self.seat.get_keyboard()?.current_focus()?;
+        let KeyboardFocusTarget::Window(window) = focus else {
+            return None;
+        };
+        self.space.elements().find(|element| element.0 == window).cloned()
+    }
+
+    pub fn toggle_fullscreen(&mut self, window: &WindowElement) {
+        #[allow(irrefutable_let_patterns)]
+        if let Some(toplevel) = window.0.toplevel() {
+            use smithay::{
+                reexports::wayland_protocols::xdg::shell::server::xdg_toplevel,
+                wayland::shell::xdg::XdgShellHandler,
+            };
+
+            let toplevel = toplevel.clone();
+            if toplevel.current_state().states.contains(xdg_toplevel::State::Fullscreen) {
+                self.unfullscreen_request(toplevel);
+            } else {
+                self.fullscreen_request(toplevel, None);
+            }
+        }
+
+        #[cfg(feature = "xwayland")]
+        if let Some(surface) = window.0.x11_surface() {
+            use smithay::xwayland::XwmHandler;
+
+            let Some(xwm) = self.xwm.as_ref().map(|xwm| xwm.id()) else {
+                return;
+            };
+            let surface = surface.clone();
+            if surface.is_fullscreen() {
+                self.unfullscreen_request(xwm, surface);
+            } else {
+                self.fullscreen_request(xwm, surface);
+            }
+        }
+    }
+
+    pub fn toggle_maximize(&mut self, window: &WindowElement) {
+        #[allow(irrefutable_let_patterns)]
+        if let Some(toplevel) = window.0.toplevel() {
+            use smithay::wayland::shell::xdg::XdgShellHandler;
+
+            let toplevel = toplevel.clone();
+            if window.is_maximized() {
+                self.unmaximize_request(toplevel);
+            } else {
+                self.maximize_request(toplevel);
+            }
+        }
+
+        #[cfg(feature = "xwayland")]
+        if let Some(surface) = window.0.x11_surface() {
+            use smithay::xwayland::XwmHandler;
+
+            let surface = surface.clone();
+            if surface.is_maximized() {
+                if let Some(xwm) = self.xwm.as_ref().map(|xwm| xwm.id()) {
+                    self.unmaximize_request(xwm, surface);
+                }
+            } else {
+                self.maximize_request_x11(&surface);
+            }
+        }
+    }
+
+    /// Whether the focused window holds an active keyboard shortcuts inhibitor
+    fn shortcuts_inhibited(&self) -> bool {
+        self.focused_window()
+            .and_then(|window| {
+                let surface = window.wl_surface()?;
+                self.seat.keyboard_shortcuts_inhibitor_for_surface(&surface)
+            })
+            .map(|inhibitor| inhibitor.is_active())
+            .unwrap_or(false)
     }
 
     fn keyboard_key_to_action<B: InputBackend>(&mut self, evt: B::KeyboardKeyEvent) -> KeyAction {
@@ -165,18 +526,10 @@ impl<BackendData: Backend> LuxoState<BackendData> {
             }
         }
 
-        let inhibited = self
-            .space
-            .element_under(self.pointer.current_location())
-            .and_then(|(window, _)| {
-                let surface = window.wl_surface()?;
-                self.seat.keyboard_shortcuts_inhibitor_for_surface(&surface)
-            })
-            .map(|inhibitor| inhibitor.is_active())
-            .unwrap_or(false);
+        let inhibited = self.shortcuts_inhibited();
 
         let action = keyboard
-            .input(self, keycode, state, serial, time, |_, modifiers, handle| {
+            .input(self, keycode, state, serial, time, |data, modifiers, handle| {
                 let keysym = handle.modified_sym();
 
                 debug!(
@@ -192,19 +545,19 @@ impl<BackendData: Backend> LuxoState<BackendData> {
                 // so that we can decide on a release if the key
                 // should be forwarded to the client or not.
                 if let KeyState::Pressed = state {
-                    if !inhibited {
-                        let action = process_keyboard_shortcut(*modifiers, keysym);
-
-                        if action.is_some() {
-                            suppressed_keys.push(keysym);
-                        }
-
-                        action
-                            .map(FilterResult::Intercept)
-                            .unwrap_or(FilterResult::Forward)
-                    } else {
-                        FilterResult::Forward
+                    // the escape hatch reaches us even while shortcuts are inhibited
+                    let action = data
+                        .keybindings
+                        .action(modifiers, &handle)
+                        .filter(|action| !inhibited || *action == KeyAction::ToggleShortcutsInhibit);
+
+                    if action.is_some() {
+                        suppressed_keys.push(keysym);
                     }
+
+                    action
+                        .map(FilterResult::Intercept)
+                        .unwrap_or(FilterResult::Forward)
                 } else {
                     let suppressed = suppressed_keys.contains(&keysym);
                     if suppressed {
@@ -218,6 +571,14 @@ impl<BackendData: Backend> LuxoState<BackendData> {
             .unwrap_or(KeyAction::None);
 
         self.suppressed_keys = suppressed_keys;
+
+        // like alt-tab, cycling through the windows ends with releasing the modifiers
+        if state == KeyState::Released && self.focus_cycle.is_some() {
+            let modifiers = keyboard.modifier_state();
+            if !(modifiers.ctrl || modifiers.alt || modifiers.logo || modifiers.shift) {
+                self.end_focus_cycle();
+            }
+        }
         action
     }
 
@@ -229,7 +590,14 @@ impl<BackendData: Backend> LuxoState<BackendData> {
 
         if wl_pointer::ButtonState::Pressed == state {
             self.update_keyboard_focus(self.pointer.current_location(), serial);
-        };
+            if self.run_pointer_binding(PointerTrigger::Button(button)) {
+                self.suppressed_buttons.push(button);
+                return;
+            }
+        } else if self.suppressed_buttons.contains(&button) {
+            self.suppressed_buttons.retain(|b| *b != button);
+            return;
+        }
         let pointer = self.pointer.clone();
         pointer.button(
             self,
@@ -243,92 +611,232 @@ impl<BackendData: Backend> LuxoState<BackendData> {
         pointer.frame(self);
     }
 
+    /// Run the pointer binding of `trigger` with the held modifiers, if there is one.
+    /// Returns whether the event was used up, a press starting a move or resize still has to
+    /// reach the grab so it ends with the release.
+    fn run_pointer_binding(&mut self, trigger: PointerTrigger) -> bool {
+        let keyboard = self.seat.get_keyboard().unwrap();
+        let Some(action) = self.keybindings.pointer_action(&keyboard.modifier_state(), trigger) else {
+            return false;
+        };
+        if self.shortcuts_inhibited() {
+            return false;
+        }
+
+        let location = self.pointer.current_location();
+        let window = self.space.element_under(location).map(|(window, _)| window.clone());
+        match (action, trigger) {
+            (PointerAction::Move, PointerTrigger::Button(button)) => {
+                let Some(window) = window else {
+                    return true;
+                };
+                self.start_pointer_move(&window, button);
+                false
+            }
+            (PointerAction::Resize, PointerTrigger::Button(button)) => {
+                let Some(window) = window else {
+                    return true;
+                };
+                self.start_pointer_resize(&window, button);
+                false
+            }
+            (PointerAction::Move | PointerAction::Resize, _) => {
+                warn!(?trigger, "Dragging windows needs a button binding");
+                true
+            }
+            (PointerAction::Key(action), _) if action.is_common() => {
+                self.process_common_key_action(action);
+                true
+            }
+            (PointerAction::Key(action), _) => {
+                warn!(?action, "Action unsupported for pointer bindings");
+                true
+            }
+        }
+    }
+
+    /// Run the scroll binding of the held modifiers once per wheel click, or per the same distance
+    /// on a touchpad. Returns whether the scroll event was used up.
+    fn scroll_pointer_binding(&mut self, amount: f64, discrete: Option<f64>) -> bool {
+        let trigger = if amount < 0.0 {
+            PointerTrigger::ScrollUp
+        } else {
+            PointerTrigger::ScrollDown
+        };
+        let keyboard = self.seat.get_keyboard().unwrap();
+        let bound = self
+            .keybindings
+            .pointer_action(&keyboard.modifier_state(), trigger)
+            .is_some();
+        if !bound || self.shortcuts_inhibited() {
+            self.pointer_binding_scroll = 0.0;
+            return false;
+        }
+
+        // scrolling back the other way starts over
+        let step = discrete.unwrap_or(amount * 120.0 / 15.0);
+        if self.pointer_binding_scroll.signum() != step.signum() {
+            self.pointer_binding_scroll = 0.0;
+        }
+        self.pointer_binding_scroll += step;
+        if self.pointer_binding_scroll.abs() < 120.0 {
+            return true;
+        }
+        self.pointer_binding_scroll -= 120.0 * step.signum();
+        self.run_pointer_binding(trigger)
+    }
+
     fn update_keyboard_focus(&mut self, location: Point<f64, Logical>, serial: Serial) {
+        if let Some(target) = self.keyboard_focus_under(location) {
+            self.set_keyboard_focus(Some(target), serial);
+            return;
+        }
+
+        // layer surfaces focused on demand, like the search field of a panel, lose the focus to a
+        // click or tap elsewhere, but not to one on an on-screen keyboard typing into them
+        let keyboard = self.seat.get_keyboard().unwrap();
+        let on_demand = match keyboard.current_focus() {
+            Some(KeyboardFocusTarget::LayerSurface(layer)) => {
+                let data = with_states(layer.wl_surface(), |states| {
+                    *states.cached_state.get::<LayerSurfaceCachedState>().current()
+                });
+                data.keyboard_interactivity == KeyboardInteractivity::OnDemand
+            }
+            _ => false,
+        };
+        if on_demand && !self.unfocusable_layer_under(location) {
+            self.set_keyboard_focus(None, serial);
+        }
+    }
+
+    /// Whether a layer surface above the windows that never takes the keyboard focus, like an
+    /// on-screen keyboard, is at `location`
+    fn unfocusable_layer_under(&self, location: Point<f64, Logical>) -> bool {
+        let Some(output) = self.space.output_under(location).next() else {
+            return false;
+        };
+        let output_geo = self.space.output_geometry(output).unwrap();
+        let map = layer_map_for_output(output);
+        [WlrLayer::Overlay, WlrLayer::Top]
+            .iter()
+            .find_map(|layer| map.layer_under(*layer, location - output_geo.loc.to_f64()))
+            .is_some_and(|layer| !layer.can_receive_keyboard_focus())
+    }
+
+    /// Move the keyboard focus to `target`, raising it if it is a window and the config asks for it
+    fn set_keyboard_focus(&mut self, target: Option<KeyboardFocusTarget>, serial: Serial) {
         let keyboard = self.seat.get_keyboard().unwrap();
         let touch = self.seat.get_touch();
         let input_method = self.seat.input_method();
         // change the keyboard focus unless the pointer or keyboard is grabbed
-        // We test for any matching surface type here but always use the root
-        // (in case of a window the toplevel) surface for the focus.
-        // So for example if a user clicks on a subsurface or popup the toplevel
-        // will receive the keyboard focus. Directly assigning the focus to the
-        // matching surface leads to issues with clients dismissing popups and
-        // subsurface menus (for example firefox-wayland).
-        // see here for a discussion about that issue:
-        // https://gitlab.freedesktop.org/wayland/wayland/-/issues/294
-        if !self.pointer.is_grabbed()
-            && (!keyboard.is_grabbed() || input_method.keyboard_grabbed())
-            && !touch.map(|touch| touch.is_grabbed()).unwrap_or(false)
+        if self.pointer.is_grabbed()
+            || (keyboard.is_grabbed() && !input_method.keyboard_grabbed())
+            || touch.map(|touch| touch.is_grabbed()).unwrap_or(false)
         {
-            let output = self.space.output_under(location).next().cloned();
-            if let Some(output) = output.as_ref() {
-                let output_geo = self.space.output_geometry(output).unwrap();
-                if let Some(window) = output
-                    .user_data()
-                    .get::<FullscreenSurface>()
-                    .and_then(|f| f.get())
-                {
-                    if let Some((_, _)) =
-                        window.surface_under(location - output_geo.loc.to_f64(), WindowSurfaceType::ALL)
-                    {
-                        #[cfg(feature = "xwayland")]
-                        if let Some(surface) = window.0.x11_surface() {
-                            self.xwm.as_mut().unwrap().raise_window(surface).unwrap();
-                        }
-                        keyboard.set_focus(self, Some(window.into()), serial);
-                        return;
-                    }
-                }
+            return;
+        }
 
-                let layers = layer_map_for_output(output);
-                if let Some(layer) = layers
-                    .layer_under(WlrLayer::Overlay, location - output_geo.loc.to_f64())
-                    .or_else(|| layers.layer_under(WlrLayer::Top, location - output_geo.loc.to_f64()))
-                {
-                    if layer.can_receive_keyboard_focus() {
-                        if let Some((_, _)) = layer.surface_under(
-                            location
-                                - output_geo.loc.to_f64()
-                                - layers.layer_geometry(layer).unwrap().loc.to_f64(),
-                            WindowSurfaceType::ALL,
-                        ) {
-                            keyboard.set_focus(self, Some(layer.clone().into()), serial);
-                            return;
-                        }
-                    }
+        if let Some(KeyboardFocusTarget::Window(window)) = target.as_ref() {
+            if self.config.focus.raise_on_focus {
+                if let Some(window) = self.space.elements().find(|w| &w.0 == window).cloned() {
+                    self.space.raise_element(&window, true);
                 }
-            }
-
-            if let Some((window, _)) = self.space.element_under(location).map(|(w, p)| (w.clone(), p)) {
-                self.space.raise_element(&window, true);
                 #[cfg(feature = "xwayland")]
-                if let Some(surface) = window.0.x11_surface() {
+                if let Some(surface) = window.x11_surface() {
                     self.xwm.as_mut().unwrap().raise_window(surface).unwrap();
                 }
-                keyboard.set_focus(self, Some(window.into()), serial);
-                return;
             }
+        }
+        keyboard.set_focus(self, target, serial);
+    }
+
+    /// What should get the keyboard focus when clicking at `location`.
+    ///
+    /// We test for any matching surface type here but always use the root
+    /// (in case of a window the toplevel) surface for the focus.
+    /// So for example if a user clicks on a subsurface or popup the toplevel
+    /// will receive the keyboard focus. Directly assigning the focus to the
+    /// matching surface leads to issues with clients dismissing popups and
+    /// subsurface menus (for example firefox-wayland).
+    /// see here for a discussion about that issue:
+    /// https://gitlab.freedesktop.org/wayland/wayland/-/issues/294
+    fn keyboard_focus_under(&self, location: Point<f64, Logical>) -> Option<KeyboardFocusTarget> {
+        let output = self.space.output_under(location).next();
+        let layer_under = |layers: &[WlrLayer]| {
+            let output = output?;
+            let output_geo = self.space.output_geometry(output).unwrap();
+            let map = layer_map_for_output(output);
+            let layer = layers
+                .iter()
+                .find_map(|layer| map.layer_under(*layer, location - output_geo.loc.to_f64()))?;
+            if !layer.can_receive_keyboard_focus() {
+                return None;
+            }
+            layer.surface_under(
+                location - output_geo.loc.to_f64() - map.layer_geometry(layer).unwrap().loc.to_f64(),
+                WindowSurfaceType::ALL,
+            )?;
+            Some(KeyboardFocusTarget::from(layer.clone()))
+        };
 
-            if let Some(output) = output.as_ref() {
-                let output_geo = self.space.output_geometry(output).unwrap();
-                let layers = layer_map_for_output(output);
-                if let Some(layer) = layers
-                    .layer_under(WlrLayer::Bottom, location - output_geo.loc.to_f64())
-                    .or_else(|| layers.layer_under(WlrLayer::Background, location - output_geo.loc.to_f64()))
+        if let Some(output) = output {
+            let output_geo = self.space.output_geometry(output).unwrap();
+            if let Some(window) = output
+                .user_data()
+                .get::<FullscreenSurface>()
+                .and_then(|f| f.get())
+            {
+                if window
+                    .surface_under(location - output_geo.loc.to_f64(), WindowSurfaceType::ALL)
+                    .is_some()
                 {
-                    if layer.can_receive_keyboard_focus() {
-                        if let Some((_, _)) = layer.surface_under(
-                            location
-                                - output_geo.loc.to_f64()
-                                - layers.layer_geometry(layer).unwrap().loc.to_f64(),
-                            WindowSurfaceType::ALL,
-                        ) {
-                            keyboard.set_focus(self, Some(layer.clone().into()), serial);
-                        }
-                    }
+                    return Some(window.into());
                 }
-            };
+            }
+        }
+
+        layer_under(&[WlrLayer::Overlay, WlrLayer::Top])
+            .or_else(|| {
+                self.space
+                    .element_under(location)
+                    .map(|(window, _)| window.clone().into())
+            })
+            .or_else(|| layer_under(&[WlrLayer::Bottom, WlrLayer::Background]))
+    }
+
+    /// Apply the focus-follows-mouse policy after the pointer moved
+    fn focus_follows_pointer(&mut self) {
+        if self.config.focus.model == FocusModel::Click {
+            return;
+        }
+        if let Some(timer) = self.focus_timer.take() {
+            self.handle.remove(timer);
         }
+
+        let target = self.keyboard_focus_under(self.pointer.current_location());
+        let current = self.seat.get_keyboard().unwrap().current_focus();
+        if target == current || (target.is_none() && self.config.focus.model == FocusModel::Sloppy) {
+            return;
+        }
+
+        if self.config.focus.follow_delay == 0 {
+            self.set_keyboard_focus(target, SCOUNTER.next_serial());
+            return;
+        }
+        let delay = Duration::from_millis(self.config.focus.follow_delay);
+        self.focus_timer = self
+            .handle
+            .insert_source(Timer::from_duration(delay), |_, _, data| {
+                data.focus_timer = None;
+                // the pointer may have moved without any motion events, e.g. by a window moving away
+                let target = data.keyboard_focus_under(data.pointer.current_location());
+                if target.is_some() || data.config.focus.model != FocusModel::Sloppy {
+                    data.set_keyboard_focus(target, SCOUNTER.next_serial());
+                }
+                TimeoutAction::Drop
+            })
+            .map_err(|err| warn!(error = ?err.error, "Failed to schedule focus change"))
+            .ok();
     }
 
     pub fn surface_under(
@@ -398,15 +906,30 @@ impl<BackendData: Backend> LuxoState<BackendData> {
         under.map(|(s, l)| (s, l.to_f64()))
     }
 
-    fn on_pointer_axis<B: InputBackend>(&mut self, evt: B::PointerAxisEvent) {
+    /// Scroll distances are multiplied by `scroll_factor`
+    fn on_pointer_axis<B: InputBackend>(&mut self, evt: B::PointerAxisEvent, scroll_factor: f64) {
         let horizontal_amount = evt
             .amount(input::Axis::Horizontal)
-            .unwrap_or_else(|| evt.amount_v120(input::Axis::Horizontal).unwrap_or(0.0) * 15.0 / 120.);
+            .unwrap_or_else(|| evt.amount_v120(input::Axis::Horizontal).unwrap_or(0.0) * 15.0 / 120.)
+            * scroll_factor;
         let vertical_amount = evt
             .amount(input::Axis::Vertical)
-            .unwrap_or_else(|| evt.amount_v120(input::Axis::Vertical).unwrap_or(0.0) * 15.0 / 120.);
-        let horizontal_amount_discrete = evt.amount_v120(input::Axis::Horizontal);
-        let vertical_amount_discrete = evt.amount_v120(input::Axis::Vertical);
+            .unwrap_or_else(|| evt.amount_v120(input::Axis::Vertical).unwrap_or(0.0) * 15.0 / 120.)
+            * scroll_factor;
+        let horizontal_amount_discrete = evt
+            .amount_v120(input::Axis::Horizontal)
+            .map(|discrete| discrete * scroll_factor);
+        let vertical_amount_discrete = evt
+            .amount_v120(input::Axis::Vertical)
+            .map(|discrete| discrete * scroll_factor);
+
+        if vertical_amount != 0.0 && self.zoom_by_scrolling(vertical_amount) {
+            return;
+        }
+
+        if vertical_amount != 0.0 && self.scroll_pointer_binding(vertical_amount, vertical_amount_discrete) {
+            return;
+        }
 
         {
             let mut frame = AxisFrame::new(evt.time_msec()).source(evt.source());
@@ -442,6 +965,8 @@ impl<BackendData: Backend> LuxoState<BackendData> {
 #[cfg(any(feature = "winit", feature = "x11"))]
 impl<BackendData: Backend> LuxoState<BackendData> {
     pub fn process_input_event_windowed<B: InputBackend>(&mut self, event: InputEvent<B>, output_name: &str) {
+        self.idle_notifier_state.notify_activity(&self.seat);
+
         match event {
             InputEvent::Keyboard { event } => match self.keyboard_key_to_action::<B>(event) {
                 KeyAction::ScaleUp => {
@@ -456,7 +981,7 @@ impl<BackendData: Backend> LuxoState<BackendData> {
                     let new_scale = current_scale + 0.25;
                     output.change_current_state(None, None, Some(Scale::Fractional(new_scale)), None);
 
-                    crate::shell::fixup_positions(&mut self.space, self.pointer.current_location());
+                    self.fixup_positions();
                     self.backend_data.reset_buffers(&output);
                 }
 
@@ -472,7 +997,7 @@ impl<BackendData: Backend> LuxoState<BackendData> {
                     let new_scale = f64::max(1.0, current_scale - 0.25);
                     output.change_current_state(None, None, Some(Scale::Fractional(new_scale)), None);
 
-                    crate::shell::fixup_positions(&mut self.space, self.pointer.current_location());
+                    self.fixup_positions();
                     self.backend_data.reset_buffers(&output);
                 }
 
@@ -497,23 +1022,16 @@ impl<BackendData: Backend> LuxoState<BackendData> {
                     };
                     tracing::info!(?current_transform, ?new_transform, output = ?output.name(), "changing output transform");
                     output.change_current_state(None, Some(new_transform), None, None);
-                    crate::shell::fixup_positions(&mut self.space, self.pointer.current_location());
+                    self.fixup_positions();
                     self.backend_data.reset_buffers(&output);
                 }
 
-                action => match action {
-                    KeyAction::None
-                    | KeyAction::Quit
-                    | KeyAction::Run(_)
-                    | KeyAction::TogglePreview
-                    | KeyAction::ToggleDecorations => self.process_common_key_action(action),
-
-                    _ => tracing::warn!(
-                        ?action,
-                        output_name,
-                        "Key action unsupported on on output backend.",
-                    ),
-                },
+                action if action.is_common() => self.process_common_key_action(action),
+                action => tracing::warn!(
+                    ?action,
+                    output_name,
+                    "Key action unsupported on on output backend.",
+                ),
             },
 
             InputEvent::PointerMotionAbsolute { event } => {
@@ -526,7 +1044,7 @@ impl<BackendData: Backend> LuxoState<BackendData> {
                 self.on_pointer_move_absolute_windowed::<B>(event, &output)
             }
             InputEvent::PointerButton { event } => self.on_pointer_button::<B>(event),
-            InputEvent::PointerAxis { event } => self.on_pointer_axis::<B>(event),
+            InputEvent::PointerAxis { event } => self.on_pointer_axis::<B>(event, 1.0),
             _ => (), // other events are not handled in anvil (yet)
         }
     }
@@ -553,6 +1071,7 @@ impl<BackendData: Backend> LuxoState<BackendData> {
             },
         );
         pointer.frame(self);
+        self.focus_follows_pointer();
     }
 
     pub fn release_all_keys(&mut self) {
@@ -573,15 +1092,12 @@ impl<BackendData: Backend> LuxoState<BackendData> {
 #[cfg(feature = "udev")]
 impl LuxoState<UdevData> {
     pub fn process_input_event<B: InputBackend>(&mut self, dh: &DisplayHandle, event: InputEvent<B>) {
+        self.idle_notifier_state.notify_activity(&self.seat);
+
         match event {
             InputEvent::Keyboard { event, .. } => match self.keyboard_key_to_action::<B>(event) {
                 #[cfg(feature = "udev")]
-                KeyAction::VtSwitch(vt) => {
-                    info!(to = vt, "Trying to switch vt");
-                    if let Err(err) = self.backend_data.session.change_vt(vt) {
-                        error!(vt, "Error switching vt: {}", err);
-                    }
-                }
+                KeyAction::VtSwitch(vt) => self.switch_vt(vt),
                 KeyAction::Screen(num) => {
                     let geometry = self
                         .space
@@ -592,19 +1108,7 @@ impl LuxoState<UdevData> {
                     if let Some(geometry) = geometry {
                         let x = geometry.loc.x as f64 + geometry.size.w as f64 / 2.0;
                         let y = geometry.size.h as f64 / 2.0;
-                        let location = (x, y).into();
-                        let pointer = self.pointer.clone();
-                        let under = self.surface_under(location);
-                        pointer.motion(
-                            self,
-                            under,
-                            &MotionEvent {
-                                location,
-                                serial: SCOUNTER.next_serial(),
-                                time: self.clock.now().as_millis(),
-                            },
-                        );
-                        pointer.frame(self);
+                        self.warp_pointer((x, y).into());
                     }
                 }
                 KeyAction::ScaleUp => {
@@ -630,19 +1134,8 @@ impl LuxoState<UdevData> {
                         pointer_output_location.y *= rescale;
                         let pointer_location = output_location + pointer_output_location;
 
-                        crate::shell::fixup_positions(&mut self.space, pointer_location);
-                        let pointer = self.pointer.clone();
-                        let under = self.surface_under(pointer_location);
-                        pointer.motion(
-                            self,
-                            under,
-                            &MotionEvent {
-                                location: pointer_location,
-                                serial: SCOUNTER.next_serial(),
-                                time: self.clock.now().as_millis(),
-                            },
-                        );
-                        pointer.frame(self);
+                        self.arrange_outputs();
+                        self.warp_pointer(pointer_location);
                         self.backend_data.reset_buffers(&output);
                     }
                 }
@@ -669,19 +1162,8 @@ impl LuxoState<UdevData> {
                         pointer_output_location.y *= rescale;
                         let pointer_location = output_location + pointer_output_location;
 
-                        crate::shell::fixup_positions(&mut self.space, pointer_location);
-                        let pointer = self.pointer.clone();
-                        let under = self.surface_under(pointer_location);
-                        pointer.motion(
-                            self,
-                            under,
-                            &MotionEvent {
-                                location: pointer_location,
-                                serial: SCOUNTER.next_serial(),
-                                time: self.clock.now().as_millis(),
-                            },
-                        );
-                        pointer.frame(self);
+                        self.arrange_outputs();
+                        self.warp_pointer(pointer_location);
                         self.backend_data.reset_buffers(&output);
                     }
                 }
@@ -694,20 +1176,7 @@ impl LuxoState<UdevData> {
                         .cloned();
 
                     if let Some(output) = output {
-                        let current_transform = output.current_transform();
-                        let new_transform = match current_transform {
-                            Transform::Normal => Transform::_90,
-                            Transform::_90 => Transform::_180,
-                            Transform::_180 => Transform::_270,
-                            Transform::_270 => Transform::Flipped,
-                            Transform::Flipped => Transform::Flipped90,
-                            Transform::Flipped90 => Transform::Flipped180,
-                            Transform::Flipped180 => Transform::Flipped270,
-                            Transform::Flipped270 => Transform::Normal,
-                        };
-                        output.change_current_state(None, Some(new_transform), None, None);
-                        crate::shell::fixup_positions(&mut self.space, self.pointer.current_location());
-                        self.backend_data.reset_buffers(&output);
+                        self.set_output_transform(&output, rotation::rotate(output.current_transform()));
                     }
                 }
                 KeyAction::ToggleTint => {
@@ -716,20 +1185,16 @@ impl LuxoState<UdevData> {
                     self.backend_data.set_debug_flags(debug_flags);
                 }
 
-                action => match action {
-                    KeyAction::None
-                    | KeyAction::Quit
-                    | KeyAction::Run(_)
-                    | KeyAction::TogglePreview
-                    | KeyAction::ToggleDecorations => self.process_common_key_action(action),
-
-                    _ => unreachable!(),
-                },
+                action if action.is_common() => self.process_common_key_action(action),
+                _ => unreachable!(),
             },
             InputEvent::PointerMotion { event, .. } => self.on_pointer_move::<B>(dh, event),
             InputEvent::PointerMotionAbsolute { event, .. } => self.on_pointer_move_absolute::<B>(dh, event),
             InputEvent::PointerButton { event, .. } => self.on_pointer_button::<B>(event),
-            InputEvent::PointerAxis { event, .. } => self.on_pointer_axis::<B>(event),
+            InputEvent::PointerAxis { event, .. } => {
+                let scroll_factor = self.scroll_factor(&event.device().id());
+                self.on_pointer_axis::<B>(event, scroll_factor)
+            }
             InputEvent::TabletToolAxis { event, .. } => self.on_tablet_tool_axis::<B>(event),
             InputEvent::TabletToolProximity { event, .. } => self.on_tablet_tool_proximity::<B>(dh, event),
             InputEvent::TabletToolTip { event, .. } => self.on_tablet_tool_tip::<B>(event),
@@ -749,6 +1214,8 @@ impl LuxoState<UdevData> {
             InputEvent::TouchFrame { event } => self.on_touch_frame::<B>(event),
             InputEvent::TouchCancel { event } => self.on_touch_cancel::<B>(event),
 
+            InputEvent::SwitchToggle { event } => self.on_switch_toggle::<B>(event),
+
             InputEvent::DeviceAdded { device } => {
                 if device.has_capability(DeviceCapability::TabletTool) {
                     self.seat
@@ -768,6 +1235,10 @@ impl LuxoState<UdevData> {
                     // If there are no tablets in seat we can remove all tools
                     if tablet_seat.count_tablets() == 0 {
                         tablet_seat.clear_tools();
+                        if let Some((_, pointer_cursor)) = self.tablet_tool_in_proximity.take() {
+                            self.cursor_status = pointer_cursor;
+                        }
+                        self.tablet_tool_cursors.clear();
                     }
                 }
             }
@@ -829,11 +1300,19 @@ impl LuxoState<UdevData> {
             return;
         }
 
+        // the lock ended, continue from where its client asked to leave the pointer
+        if let Some(hint) = self.pointer_lock_hint.take() {
+            pointer_location = hint;
+        }
+
+        let previous_location = pointer_location;
         pointer_location += evt.delta();
+        pointer_location = self.resist_output_edges(previous_location, pointer_location);
 
         // clamp to screen limits
         // this event is never generated by winit
         pointer_location = self.clamp_coords(pointer_location);
+        pointer_location = self.confine_to_fullscreen_output(pointer_location);
 
         let new_under = self.surface_under(pointer_location);
 
@@ -863,6 +1342,7 @@ impl LuxoState<UdevData> {
             },
         );
         pointer.frame(self);
+        self.focus_follows_pointer();
 
         // If pointer is now in a constraint region, activate it
         // TODO Anywhere else pointer is moved needs to do this
@@ -919,6 +1399,7 @@ impl LuxoState<UdevData> {
             },
         );
         pointer.frame(self);
+        self.focus_follows_pointer();
     }
 
     fn on_tablet_tool_axis<B: InputBackend>(&mut self, evt: B::TabletToolAxisEvent) {
@@ -983,6 +1464,10 @@ impl LuxoState<UdevData> {
         if let Some(pointer_location) = self.touch_location_transformed(&evt) {
             let tool = evt.tool();
             tablet_seat.add_tool::<Self>(self, dh, &tool);
+            match evt.state() {
+                ProximityState::In => self.tablet_tool_proximity_in(&tool),
+                ProximityState::Out => self.tablet_tool_proximity_out(&tool),
+            }
 
             let pointer = self.pointer.clone();
             let under = self.surface_under(pointer_location);
@@ -1051,7 +1536,31 @@ impl LuxoState<UdevData> {
         }
     }
 
+    fn on_switch_toggle<B: InputBackend>(&mut self, evt: B::SwitchToggleEvent) {
+        let on = matches!(evt.state(), SwitchState::On);
+        match evt.switch() {
+            Some(Switch::Lid) => {
+                info!(closed = on, "Lid switch toggled");
+                self.lid_switched(on);
+                self.send_ipc_event(ipc::Event::LidSwitched { closed: on });
+            }
+            Some(Switch::TabletMode) => {
+                info!(enabled = on, "Tablet mode switch toggled");
+                self.send_ipc_event(ipc::Event::TabletModeSwitched { enabled: on });
+            }
+            None => {}
+        }
+    }
+
     fn on_gesture_swipe_begin<B: InputBackend>(&mut self, evt: B::GestureSwipeBeginEvent) {
+        if self.keybindings.has_swipe(evt.fingers()) {
+            self.compositor_swipe = Some(CompositorSwipe {
+                fingers: evt.fingers(),
+                delta: Point::default(),
+            });
+            return;
+        }
+
         let serial = SCOUNTER.next_serial();
         let pointer = self.pointer.clone();
         pointer.gesture_swipe_begin(
@@ -1065,6 +1574,11 @@ impl LuxoState<UdevData> {
     }
 
     fn on_gesture_swipe_update<B: InputBackend>(&mut self, evt: B::GestureSwipeUpdateEvent) {
+        if let Some(swipe) = self.compositor_swipe.as_mut() {
+            swipe.delta += evt.delta();
+            return;
+        }
+
         let pointer = self.pointer.clone();
         pointer.gesture_swipe_update(
             self,
@@ -1076,6 +1590,19 @@ impl LuxoState<UdevData> {
     }
 
     fn on_gesture_swipe_end<B: InputBackend>(&mut self, evt: B::GestureSwipeEndEvent) {
+        if let Some(swipe) = self.compositor_swipe.take() {
+            let action = swipe
+                .gesture()
+                .filter(|_| !evt.cancelled())
+                .and_then(|gesture| self.keybindings.swipe_action(gesture));
+            match action {
+                Some(action) if action.is_common() => self.process_common_key_action(action),
+                Some(action) => warn!(?action, "Action unsupported for gestures"),
+                None => {}
+            }
+            return;
+        }
+
         let serial = SCOUNTER.next_serial();
         let pointer = self.pointer.clone();
         pointer.gesture_swipe_end(
@@ -1244,86 +1771,103 @@ impl LuxoState<UdevData> {
         handle.cancel(self);
     }
 
-    fn clamp_coords(&self, pos: Point<f64, Logical>) -> Point<f64, Logical> {
-        if self.space.outputs().next().is_none() {
+    /// Clamp `pos` to the output of the focused window if it is fullscreen and `confine_pointer` is set
+    fn confine_to_fullscreen_output(&self, pos: Point<f64, Logical>) -> Point<f64, Logical> {
+        if !self.config.focus.confine_pointer {
             return pos;
         }
-
-        let (pos_x, pos_y) = pos.into();
-        let max_x = self
-            .space
-            .outputs()
-            .fold(0, |acc, o| acc + self.space.output_geometry(o).unwrap().size.w);
-        let clamped_x = pos_x.clamp(0.0, max_x as f64);
-        let max_y = self
+        let Some(window) = self.focused_window() else {
+            return pos;
+        };
+        let Some(geometry) = self
             .space
             .outputs()
-            .find(|o| {
-                let geo = self.space.output_geometry(o).unwrap();
-                geo.contains((clamped_x as i32, 0))
+            .find(|output| {
+                output
+                    .user_data()
+                    .get::<FullscreenSurface>()
+                    .and_then(|fullscreen| fullscreen.get())
+                    .is_some_and(|fullscreen| fullscreen == window)
             })
-            .map(|o| self.space.output_geometry(o).unwrap().size.h);
+            .and_then(|output| self.space.output_geometry(output))
+        else {
+            return pos;
+        };
+
+        let (min, size) = (geometry.loc.to_f64(), geometry.size.to_f64());
+        let x = pos.x.clamp(min.x, min.x + size.w - 1.0);
+        let y = pos.y.clamp(min.y, min.y + size.h - 1.0);
+        (x, y).into()
+    }
 
-        if let Some(max_y) = max_y {
-            let clamped_y = pos_y.clamp(0.0, max_y as f64);
-            (clamped_x, clamped_y).into()
+    /// Hold the pointer moving from `from` to `to` at the edge of the output it would leave, until
+    /// it was pushed past the edge as far as its resistance or for good if the edge is a barrier
+    fn resist_output_edges(
+        &mut self,
+        from: Point<f64, Logical>,
+        to: Point<f64, Logical>,
+    ) -> Point<f64, Logical> {
+        let Some(output) = self.space.output_under(from).next().cloned() else {
+            return to;
+        };
+        let geometry = self.space.output_geometry(&output).unwrap();
+        let (min, size) = (geometry.loc.to_f64(), geometry.size.to_f64());
+        let (edge, overshoot) = if to.x < min.x {
+            (OutputEdge::Left, min.x - to.x)
+        } else if to.x > min.x + size.w - 1.0 {
+            (OutputEdge::Right, to.x - (min.x + size.w - 1.0))
+        } else if to.y < min.y {
+            (OutputEdge::Top, min.y - to.y)
+        } else if to.y > min.y + size.h - 1.0 {
+            (OutputEdge::Bottom, to.y - (min.y + size.h - 1.0))
         } else {
-            (clamped_x, pos_y).into()
+            self.pointer_edge_push = None;
+            return to;
+        };
+        // edges without an output behind them are left to `clamp_coords`
+        if self.space.output_under(to).next().is_none() {
+            return to;
         }
-    }
-}
 
-/// Possible results of a keyboard action
-#[allow(dead_code)] // some of these are only read if udev is enabled
-#[derive(Debug)]
-enum KeyAction {
-    /// Quit the compositor
-    Quit,
-    /// Trigger a vt-switch
-    VtSwitch(i32),
-    /// run a command
-    Run(String),
-    /// Switch the current screen
-    Screen(usize),
-    ScaleUp,
-    ScaleDown,
-    TogglePreview,
-    RotateOutput,
-    ToggleTint,
-    ToggleDecorations,
-    /// Do nothing more
-    None,
-}
+        let physical = output.physical_properties();
+        let edge_config = self
+            .config
+            .outputs
+            .find(&output.name(), &physical.make, &physical.model)
+            .and_then(|config| config.edges.get(&edge))
+            .cloned()
+            .unwrap_or_default();
+        if !edge_config.barrier {
+            let resistance = edge_config.resistance.unwrap_or(self.config.outputs.edge_resistance);
+            let pushed = match self.pointer_edge_push {
+                Some((pushed_edge, pushed)) if pushed_edge == edge => pushed + overshoot,
+                _ => overshoot,
+            };
+            if pushed >= resistance {
+                self.pointer_edge_push = None;
+                return to;
+            }
+            self.pointer_edge_push = Some((edge, pushed));
+        }
 
-fn process_keyboard_shortcut(modifiers: ModifiersState, keysym: Keysym) -> Option<KeyAction> {
-    if modifiers.ctrl && modifiers.alt && keysym == Keysym::BackSpace || modifiers.logo && keysym == Keysym::q
-    {
-        // ctrl+alt+backspace = quit
-        // logo + q = quit
-        Some(KeyAction::Quit)
-    } else if (xkb::KEY_XF86Switch_VT_1..=xkb::KEY_XF86Switch_VT_12).contains(&keysym.raw()) {
-        // VTSwitch
-        Some(KeyAction::VtSwitch(
-            (keysym.raw() - xkb::KEY_XF86Switch_VT_1 + 1) as i32,
-        ))
-    } else if modifiers.logo && keysym == Keysym::Return {
-        // run terminal
-        Some(KeyAction::Run("weston-terminal".into()))
-    } else if modifiers.logo && (xkb::KEY_1..=xkb::KEY_9).contains(&keysym.raw()) {
-        Some(KeyAction::Screen((keysym.raw() - xkb::KEY_1) as usize))
-    } else if modifiers.logo && modifiers.shift && keysym == Keysym::M {
-        Some(KeyAction::ScaleDown)
-    } else if modifiers.logo && modifiers.shift && keysym == Keysym::P {
-        Some(KeyAction::ScaleUp)
-    } else if modifiers.logo && modifiers.shift && keysym == Keysym::W {
-        Some(KeyAction::TogglePreview)
-    } else if modifiers.logo && modifiers.shift && keysym == Keysym::R {
-        Some(KeyAction::RotateOutput)
-    } else if modifiers.logo && modifiers.shift && keysym == Keysym::T {
-        Some(KeyAction::ToggleTint)
-    } else if modifiers.logo && modifiers.shift && keysym == Keysym::D {
-        Some(KeyAction::ToggleDecorations)
-    } else {
-        None
+        let x = to.x.clamp(min.x, min.x + size.w - 1.0);
+        let y = to.y.clamp(min.y, min.y + size.h - 1.0);
+        (x, y).into()
+    }
+
+    /// The point of the outputs closest to `pos`, which is kept if it is on one of them
+    fn clamp_coords(&self, pos: Point<f64, Logical>) -> Point<f64, Logical> {
+        self.space
+            .outputs()
+            .filter_map(|output| self.space.output_geometry(output))
+            .map(|geometry| {
+                let (min, size) = (geometry.loc.to_f64(), geometry.size.to_f64());
+                let x = pos.x.clamp(min.x, min.x + size.w - 1.0);
+                let y = pos.y.clamp(min.y, min.y + size.h - 1.0);
+                let distance = (x - pos.x).powi(2) + (y - pos.y).powi(2);
+                (Point::from((x, y)), distance)
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map_or(pos, |(clamped, _)| clamped)
     }
 }
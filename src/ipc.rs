@@ -0,0 +1,931 @@
+//! JSON control socket
+//!
+//! Clients connect to the socket in `$LUXO_SOCKET`, write a single [`Request`] as one line of JSON
+//! and read back a single [`Response`] line, after which the compositor closes the connection.
+//! The exception is [`Request::Subscribe`], after its `ok` response the connection stays open and
+//! an [`Event`] line is sent whenever a window opens, closes, changes or gets focused, and on
+//! hardware events like switches toggling.
+//! `luxoctl` is a command line client for it.
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use smithay::{
+    output::Output,
+    reexports::calloop::{self, generic::Generic, Interest, LoopHandle, Mode, PostAction},
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    config::{
+        save_input_setting, ConfigError, DebugOverlayConfig, ModeConfig, PointerDeviceConfig, WindowRule,
+    },
+    keybindings::KeyAction,
+    protocols::output_management::{HeadConfiguration, ModeConfiguration, OutputConfiguration},
+    shell::{hidden_windows, FullscreenSurface, WindowElement},
+    state::Backend,
+    LuxoState,
+};
+
+/// Environment variable pointing clients to the socket
+pub const SOCKET_ENV: &str = "LUXO_SOCKET";
+
+/// Requests larger than this are dropped
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "kebab-case")]
+pub enum Request {
+    Windows,
+    Outputs,
+    Workspaces,
+    /// Run an action written as in the `[keybindings]` config section, e.g. `"workspace 2"`
+    Action {
+        action: String,
+    },
+    FocusWindow {
+        id: u64,
+    },
+    /// Ask a window to close, like the `close-window` action
+    CloseWindow {
+        id: u64,
+    },
+    /// Kill the client of a window that doesn't react to being closed, like the `kill-window` action
+    KillWindow {
+        id: u64,
+    },
+    /// Move a window to workspace `workspace` (starting at 1) of its output
+    MoveWindowToWorkspace {
+        id: u64,
+        workspace: usize,
+    },
+    /// Make a visible window the one toggled by the `toggle-scratchpad` action
+    SetScratchpad {
+        id: u64,
+    },
+    /// Change the stacking flags of a window, unset ones are left alone
+    SetWindowFlags {
+        id: u64,
+        #[serde(default)]
+        always_on_top: Option<bool>,
+        #[serde(default)]
+        sticky: Option<bool>,
+    },
+    /// Set the opacity of a window, between 0.1 and 1.0
+    SetWindowOpacity {
+        id: u64,
+        opacity: f32,
+    },
+    /// Set the mode of an output, written as in the config (e.g. `"1920x1080@60"`)
+    SetOutputMode {
+        output: String,
+        mode: String,
+    },
+    SetKeyRepeat {
+        delay: i32,
+        rate: i32,
+    },
+    /// Render an output offscreen and save it as a PNG image at `path`, only supported by the
+    /// headless backend
+    CaptureOutput {
+        output: String,
+        path: PathBuf,
+    },
+    ReloadConfig,
+    /// End the programs still running from the `exec_once` and `exec` lists and run them again
+    RerunAutostart,
+    /// Choose the debug overlays drawn by the udev backend, until the config is reloaded
+    SetDebugOverlay {
+        damage: bool,
+        fps: bool,
+        repaint: bool,
+        #[serde(default)]
+        full_redraw: bool,
+    },
+    LockState,
+    /// The active keyboard layout out of the configured ones
+    KeyboardLayout,
+    /// Name of the output under the pointer, e.g. for the output chooser of a screencast portal
+    FocusedOutput,
+    /// The `[[window_rules]]` of the config, in the order they are applied
+    WindowRules,
+    /// Change a libinput setting of `device`, `"mouse"`, `"touchpad"` or the name of a device, as
+    /// written in the `[input.mouse]` config section. With `persist` the setting of mice or
+    /// touchpads is saved to the config file as well.
+    ConfigureInput {
+        device: String,
+        setting: String,
+        value: String,
+        #[serde(default)]
+        persist: bool,
+    },
+    /// Recent log events, oldest first
+    Logs {
+        /// Number of events, all kept ones if unset
+        #[serde(default)]
+        limit: Option<usize>,
+        /// Only events of this level or more severe, e.g. `"warn"`
+        #[serde(default)]
+        level: Option<String>,
+    },
+    /// Connectors of VR headsets and other non-desktop displays, on the udev backend
+    LeaseConnectors,
+    /// End the drm lease of a connector, e.g. of a VR session manager that hangs
+    RevokeLease {
+        connector: String,
+    },
+    /// Keep the connection open and receive the given kinds of events, all of them if empty
+    Subscribe {
+        #[serde(default)]
+        events: Vec<EventType>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "kebab-case")]
+pub enum Response {
+    Ok,
+    Error { message: String },
+    Windows { windows: Vec<WindowInfo> },
+    Outputs { outputs: Vec<OutputInfo> },
+    Workspaces { workspaces: Vec<WorkspaceInfo> },
+    LockState { caps_lock: bool, num_lock: bool },
+    /// `index` into the configured layouts and its name, as written in the config
+    KeyboardLayout { index: usize, layout: String },
+    FocusedOutput { output: Option<String> },
+    WindowRules { rules: Vec<WindowRule> },
+    Logs { entries: Vec<LogEntry> },
+    LeaseConnectors { connectors: Vec<LeaseConnectorInfo> },
+}
+
+impl Response {
+    fn error(message: impl Into<String>) -> Self {
+        Response::Error {
+            message: message.into(),
+        }
+    }
+}
+
+/// Sent to subscribed clients, at most once per iteration of the event loop for each window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    WindowOpened { window: WindowInfo },
+    WindowClosed { id: u64 },
+    /// Anything but the focus of a window changed, e.g. its title, geometry or workspace
+    WindowChanged { window: WindowInfo },
+    /// The keyboard focus moved to another window, or away from all of them
+    FocusChanged { window: Option<WindowInfo> },
+    /// A non-desktop display got plugged in
+    LeaseConnectorConnected { connector: LeaseConnectorInfo },
+    /// A non-desktop display got unplugged, `leased` tells whether a client was using it
+    LeaseConnectorDisconnected { connector: LeaseConnectorInfo },
+    /// The lid of a laptop got closed or opened
+    LidSwitched { closed: bool },
+    /// A convertible got folded into or out of tablet mode
+    TabletModeSwitched { enabled: bool },
+    /// The keyboard switched to layout `index` of the configured ones
+    KeyboardLayoutChanged { index: usize, layout: String },
+}
+
+impl Event {
+    pub fn event_type(&self) -> EventType {
+        match self {
+            Event::FocusChanged { .. } => EventType::Focus,
+            Event::LeaseConnectorConnected { .. } | Event::LeaseConnectorDisconnected { .. } => EventType::Lease,
+            Event::LidSwitched { .. } | Event::TabletModeSwitched { .. } => EventType::Switch,
+            Event::KeyboardLayoutChanged { .. } => EventType::Keyboard,
+            _ => EventType::Window,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventType {
+    /// Windows opening, closing and changing
+    Window,
+    Focus,
+    /// Non-desktop displays being plugged in and out
+    Lease,
+    /// Lid and tablet mode switches toggling
+    Switch,
+    /// Keyboard layout changes
+    Keyboard,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowInfo {
+    pub id: u64,
+    pub title: Option<String>,
+    pub app_id: Option<String>,
+    /// Process of the client, for X11 windows as given by the window itself
+    pub pid: Option<i32>,
+    /// Position and size as `[x, y, width, height]` in the global space, unset while the window
+    /// is not shown
+    pub geometry: Option<[i32; 4]>,
+    pub output: Option<String>,
+    /// Workspace the window is on, starting at 1
+    pub workspace: Option<usize>,
+    pub focused: bool,
+    pub fullscreen: bool,
+    /// The window asked for attention without being allowed to take the focus
+    pub urgent: bool,
+    /// The client missed a ping, `kill` ends it
+    pub unresponsive: bool,
+    pub xwayland: bool,
+    pub opacity: f32,
+    /// Indices of the window rules that matched the window when it was mapped
+    pub rules: Vec<usize>,
+    /// The window is toggled by the `toggle-scratchpad` action, it has no output while hidden
+    pub scratchpad: bool,
+    pub always_on_top: bool,
+    /// The window is shown on all workspaces of its output
+    pub sticky: bool,
+    /// The last buffer of the window was synchronized with a syncobj acquire point instead of the
+    /// implicit fence of its dmabuf
+    pub explicit_sync: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputInfo {
+    pub name: String,
+    pub make: String,
+    pub model: String,
+    pub position: [i32; 2],
+    /// Current mode as `<width>x<height>@<refresh>`
+    pub mode: Option<String>,
+    pub modes: Vec<String>,
+    pub scale: f64,
+    pub transform: String,
+    /// Gpu rendering the output, if it differs from `scanout_gpu` the frames get copied over
+    pub render_gpu: Option<String>,
+    /// Gpu the output is connected to
+    pub scanout_gpu: Option<String>,
+    /// Frames presented since the output was set up, for the udev and headless backends
+    pub frames: Option<FrameStats>,
+    /// Whether clients can synchronize their buffers explicitly on this output, for the udev
+    /// backend
+    pub explicit_sync: Option<bool>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct FrameStats {
+    /// Frames showing a client buffer directly on the primary plane
+    pub scanned_out: u64,
+    /// Frames composited into a buffer of the compositor
+    pub composited: u64,
+    /// Frames showing the pointer on the cursor plane instead of compositing it
+    #[serde(default)]
+    pub cursor_plane: u64,
+    /// Pixels that changed in the frames rendered while the damage overlay was shown
+    #[serde(default)]
+    pub damaged_pixels: u64,
+    /// All pixels of those frames, what rendering without damage tracking would repaint
+    #[serde(default)]
+    pub frame_pixels: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaseConnectorInfo {
+    pub name: String,
+    /// Make and model of the display
+    pub description: String,
+    /// Drm device the connector belongs to
+    pub gpu: String,
+    /// Whether clients can lease it, see the `[drm_lease]` config section
+    pub offered: bool,
+    pub leased: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// Seconds since the unix epoch
+    pub time: f64,
+    pub level: String,
+    pub target: String,
+    /// The message followed by the other fields as `name=value`
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceInfo {
+    pub output: String,
+    /// Index of the workspace, starting at 1
+    pub index: usize,
+    pub active: bool,
+    pub windows: usize,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum IpcError {
+    #[error("XDG_RUNTIME_DIR is not set")]
+    NoRuntimeDir,
+    #[error("Error creating ipc socket: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Error registering ipc socket: {0}")]
+    EventLoop(#[from] calloop::Error),
+}
+
+/// Location of the socket for the compositor listening on wayland socket `socket_name`
+pub fn socket_path(socket_name: &str) -> Result<PathBuf, IpcError> {
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").ok_or(IpcError::NoRuntimeDir)?;
+    Ok(socket_path_in(Path::new(&runtime_dir), socket_name))
+}
+
+/// Location of the socket for the compositor listening on wayland socket `socket_name`, given
+/// its runtime directory
+pub fn socket_path_in(runtime_dir: &Path, socket_name: &str) -> PathBuf {
+    runtime_dir.join(format!("luxo-{}.sock", socket_name))
+}
+
+/// The listening socket, removed from the file system once dropped
+#[derive(Debug)]
+pub struct IpcSocket {
+    path: PathBuf,
+    subscribers: Vec<Subscriber>,
+    /// Windows as last sent to the subscribers, to find out what changed since
+    windows: Vec<WindowInfo>,
+}
+
+/// A client that sent [`Request::Subscribe`]
+#[derive(Debug)]
+struct Subscriber {
+    stream: UnixStream,
+    events: Vec<EventType>,
+}
+
+impl IpcSocket {
+    /// Send `events` to the subscribers, dropping the ones gone meanwhile
+    fn broadcast(&mut self, events: &[Event]) {
+        self.subscribers.retain_mut(|subscriber| subscriber.send(events));
+    }
+}
+
+impl Subscriber {
+    /// Write the events the client is interested in, returns false once it can't keep up or is gone
+    fn send(&mut self, events: &[Event]) -> bool {
+        let mut lines = Vec::new();
+        for event in events {
+            if self.events.is_empty() || self.events.contains(&event.event_type()) {
+                serde_json::to_writer(&mut lines, event).unwrap();
+                lines.push(b'\n');
+            }
+        }
+        if lines.is_empty() {
+            return true;
+        }
+        // a non-blocking write, a client not reading its events must not hang us
+        match self.stream.write_all(&lines) {
+            Ok(()) => true,
+            Err(err) => {
+                debug!("Dropping ipc subscriber: {}", err);
+                false
+            }
+        }
+    }
+}
+
+impl IpcSocket {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for IpcSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Start accepting ipc clients on the event loop of the compositor
+pub fn listen<BackendData: Backend + 'static>(
+    handle: &LoopHandle<'static, LuxoState<BackendData>>,
+    socket_name: &str,
+) -> Result<IpcSocket, IpcError> {
+    let path = socket_path(socket_name)?;
+    // a previous instance with the same wayland socket is gone, otherwise we would not have its name
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    listener.set_nonblocking(true)?;
+    let socket = IpcSocket {
+        path,
+        subscribers: Vec::new(),
+        windows: Vec::new(),
+    };
+
+    let client_handle = handle.clone();
+    handle
+        .insert_source(
+            Generic::new(listener, Interest::READ, Mode::Level),
+            move |_, listener, _| {
+                // Safety: we don't drop the listener
+                let listener = unsafe { listener.get_mut() };
+                loop {
+                    match listener.accept() {
+                        Ok((stream, _)) => add_client(&client_handle, stream),
+                        Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                        Err(err) => {
+                            warn!("Failed to accept ipc client: {}", err);
+                            break;
+                        }
+                    }
+                }
+                Ok(PostAction::Continue)
+            },
+        )
+        .map_err(|err| err.error)?;
+
+    info!(path = %socket.path.display(), "Listening on ipc socket");
+    Ok(socket)
+}
+
+fn add_client<BackendData: Backend + 'static>(
+    handle: &LoopHandle<'static, LuxoState<BackendData>>,
+    stream: UnixStream,
+) {
+    if let Err(err) = stream.set_nonblocking(true) {
+        warn!("Failed to set up ipc client: {}", err);
+        return;
+    }
+
+    let mut buffer = Vec::new();
+    let res = handle.insert_source(
+        Generic::new(stream, Interest::READ, Mode::Level),
+        move |_, stream, state| {
+            // Safety: we don't drop the stream
+            let stream = unsafe { stream.get_mut() };
+
+            let mut closed = false;
+            let mut chunk = [0u8; 4096];
+            loop {
+                match stream.read(&mut chunk) {
+                    Ok(0) => {
+                        closed = true;
+                        break;
+                    }
+                    Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(err) => {
+                        debug!("Ipc client error: {}", err);
+                        return Ok(PostAction::Remove);
+                    }
+                }
+            }
+
+            let request = match buffer.iter().position(|byte| *byte == b'\n') {
+                Some(end) => &buffer[..end],
+                None if closed => &buffer[..],
+                None if buffer.len() > MAX_REQUEST_SIZE => return Ok(PostAction::Remove),
+                None => return Ok(PostAction::Continue),
+            };
+            if request.is_empty() {
+                return Ok(PostAction::Remove);
+            }
+
+            let response = match serde_json::from_slice::<Request>(request) {
+                Ok(Request::Subscribe { events }) => {
+                    debug!(?events, "Ipc client subscribed");
+                    // the clone stays open once this source is removed
+                    match stream.try_clone() {
+                        Ok(stream) => state.add_ipc_subscriber(stream, events),
+                        Err(err) => warn!("Failed to set up ipc subscriber: {}", err),
+                    }
+                    return Ok(PostAction::Remove);
+                }
+                Ok(request) => {
+                    debug!(?request, "Ipc request");
+                    state.handle_ipc_request(request)
+                }
+                Err(err) => Response::error(format!("Invalid request: {}", err)),
+            };
+
+            let mut reply = serde_json::to_vec(&response).unwrap();
+            reply.push(b'\n');
+            // the reply is small, a blocking write with a timeout keeps a stuck client from hanging us
+            let _ = stream.set_nonblocking(false);
+            let _ = stream.set_write_timeout(Some(Duration::from_secs(1)));
+            if let Err(err) = stream.write_all(&reply) {
+                debug!("Failed to send ipc response: {}", err);
+            }
+            Ok(PostAction::Remove)
+        },
+    );
+    if let Err(err) = res {
+        warn!("Failed to add ipc client: {}", err.error);
+    }
+}
+
+fn format_mode(mode: smithay::output::Mode) -> String {
+    format!(
+        "{}x{}@{:.3}",
+        mode.size.w,
+        mode.size.h,
+        mode.refresh as f64 / 1000.0
+    )
+}
+
+impl<BackendData: Backend + 'static> LuxoState<BackendData> {
+    pub fn handle_ipc_request(&mut self, request: Request) -> Response {
+        match request {
+            Request::Windows => Response::Windows {
+                windows: self.ipc_windows(),
+            },
+            Request::Outputs => Response::Outputs {
+                outputs: self
+                    .space
+                    .outputs()
+                    .map(|output| self.ipc_output(output))
+                    .collect(),
+            },
+            Request::Workspaces => Response::Workspaces {
+                workspaces: self.ipc_workspaces(),
+            },
+            Request::Action { action } => match action.parse::<KeyAction>() {
+                Ok(action) if action.is_common() => {
+                    self.process_common_key_action(action);
+                    Response::Ok
+                }
+                Ok(action) => Response::error(format!("Action {:?} is not supported over ipc", action)),
+                Err(err) => Response::error(err.to_string()),
+            },
+            Request::FocusWindow { id } => self.ipc_focus_window(id),
+            Request::MoveWindowToWorkspace { id, workspace } => {
+                let Some(window) = self.space.elements().find(|window| window.id() == id).cloned() else {
+                    return Response::error(format!("No visible window with id {}", id));
+                };
+                match workspace.checked_sub(1) {
+                    Some(index) => {
+                        self.move_to_workspace(&window, index);
+                        Response::Ok
+                    }
+                    None => Response::error("Workspaces start at 1"),
+                }
+            }
+            Request::CloseWindow { id } => {
+                let Some(window) = self.all_windows().into_iter().find(|window| window.id() == id) else {
+                    return Response::error(format!("No window with id {}", id));
+                };
+                self.close_window(&window);
+                Response::Ok
+            }
+            Request::KillWindow { id } => {
+                let Some(window) = self.all_windows().into_iter().find(|window| window.id() == id) else {
+                    return Response::error(format!("No window with id {}", id));
+                };
+                self.kill_window(&window);
+                Response::Ok
+            }
+            Request::SetScratchpad { id } => {
+                let Some(window) = self.space.elements().find(|window| window.id() == id).cloned() else {
+                    return Response::error(format!("No visible window with id {}", id));
+                };
+                self.set_scratchpad(&window);
+                Response::Ok
+            }
+            Request::SetWindowFlags {
+                id,
+                always_on_top,
+                sticky,
+            } => {
+                let Some(window) = self.all_windows().into_iter().find(|window| window.id() == id) else {
+                    return Response::error(format!("No window with id {}", id));
+                };
+                if let Some(on_top) = always_on_top {
+                    window.set_always_on_top(on_top);
+                }
+                if let Some(sticky) = sticky {
+                    window.set_sticky(sticky);
+                }
+                Response::Ok
+            }
+            Request::SetWindowOpacity { id, opacity } => {
+                let Some(window) = self.all_windows().into_iter().find(|window| window.id() == id) else {
+                    return Response::error(format!("No window with id {}", id));
+                };
+                window.set_opacity(opacity);
+                Response::Ok
+            }
+            Request::SetOutputMode { output, mode } => self.ipc_set_output_mode(&output, mode),
+            Request::CaptureOutput { output, path } => {
+                let Some(output) = self.space.outputs().find(|o| o.name() == output).cloned() else {
+                    return Response::error(format!("No output named {}", output));
+                };
+                match BackendData::capture_output(self, &output, &path) {
+                    Ok(()) => Response::Ok,
+                    Err(err) => Response::error(err),
+                }
+            }
+            Request::SetKeyRepeat { delay, rate } => {
+                if delay < 0 || rate < 0 {
+                    return Response::error("Key repeat delay and rate must not be negative");
+                }
+                self.set_key_repeat(delay, rate);
+                Response::Ok
+            }
+            Request::ReloadConfig => match self.reload_config() {
+                Ok(()) => Response::Ok,
+                Err(err) => Response::error(err.to_string()),
+            },
+            Request::RerunAutostart => {
+                self.rerun_autostart();
+                Response::Ok
+            }
+            Request::SetDebugOverlay {
+                damage,
+                fps,
+                repaint,
+                full_redraw,
+            } => {
+                self.debug_overlay = DebugOverlayConfig {
+                    damage,
+                    fps,
+                    repaint,
+                    full_redraw,
+                };
+                Response::Ok
+            }
+            Request::LockState => {
+                let modifiers = self.seat.get_keyboard().unwrap().modifier_state();
+                Response::LockState {
+                    caps_lock: modifiers.caps_lock,
+                    num_lock: modifiers.num_lock,
+                }
+            }
+            Request::KeyboardLayout => Response::KeyboardLayout {
+                index: self.keyboard_layout,
+                layout: self.config.input.keyboard.layout_name(self.keyboard_layout).to_owned(),
+            },
+            Request::FocusedOutput => Response::FocusedOutput {
+                output: self.workspace_output().map(|output| output.name()),
+            },
+            Request::WindowRules => Response::WindowRules {
+                rules: self.config.window_rules.clone(),
+            },
+            Request::ConfigureInput {
+                device,
+                setting,
+                value,
+                persist,
+            } => self.ipc_configure_input(&device, &setting, &value, persist),
+            Request::Logs { limit, level } => {
+                let level = match level.map(|level| level.parse::<tracing::Level>()).transpose() {
+                    Ok(level) => level,
+                    Err(err) => return Response::error(err.to_string()),
+                };
+                Response::Logs {
+                    entries: crate::logging::recent(limit, level),
+                }
+            }
+            Request::LeaseConnectors => Response::LeaseConnectors {
+                connectors: self.backend_data.lease_connectors(),
+            },
+            Request::RevokeLease { connector } => match BackendData::revoke_lease(self, &connector) {
+                Ok(()) => Response::Ok,
+                Err(err) => Response::error(err),
+            },
+            // only reached when not coming from a connection, which handles it itself
+            Request::Subscribe { .. } => Response::error("Subscribing needs an ipc connection"),
+        }
+    }
+
+    fn add_ipc_subscriber(&mut self, stream: UnixStream, events: Vec<EventType>) {
+        let windows = self.ipc_windows();
+        let Some(socket) = self.ipc_socket.as_mut() else {
+            return;
+        };
+        // events only describe what changes from now on
+        if socket.subscribers.is_empty() {
+            socket.windows = windows;
+        }
+        let mut subscriber = Subscriber { stream, events };
+        let mut reply = serde_json::to_vec(&Response::Ok).unwrap();
+        reply.push(b'\n');
+        if let Err(err) = subscriber.stream.write_all(&reply) {
+            debug!("Failed to send ipc response: {}", err);
+            return;
+        }
+        socket.subscribers.push(subscriber);
+    }
+
+    /// Send events about the windows that changed since the last call to the subscribed ipc
+    /// clients, runs once per iteration of the event loop
+    pub fn notify_ipc_subscribers(&mut self) {
+        if self
+            .ipc_socket
+            .as_ref()
+            .is_none_or(|socket| socket.subscribers.is_empty())
+        {
+            return;
+        }
+        let windows = self.ipc_windows();
+        let socket = self.ipc_socket.as_mut().unwrap();
+
+        let mut events = Vec::new();
+        for window in &windows {
+            match socket.windows.iter().find(|old| old.id == window.id) {
+                None => events.push(Event::WindowOpened { window: window.clone() }),
+                // focus changes are events of their own
+                Some(old) if *old != WindowInfo { focused: old.focused, ..window.clone() } => {
+                    events.push(Event::WindowChanged { window: window.clone() })
+                }
+                Some(_) => {}
+            }
+        }
+        for old in &socket.windows {
+            if !windows.iter().any(|window| window.id == old.id) {
+                events.push(Event::WindowClosed { id: old.id });
+            }
+        }
+        let focused = windows.iter().find(|window| window.focused);
+        if focused.map(|window| window.id) != socket.windows.iter().find(|old| old.focused).map(|old| old.id) {
+            events.push(Event::FocusChanged {
+                window: focused.cloned(),
+            });
+        }
+
+        if !events.is_empty() {
+            socket.broadcast(&events);
+        }
+        socket.windows = windows;
+    }
+
+    /// Send an event to the subscribed ipc clients right away
+    pub fn send_ipc_event(&mut self, event: Event) {
+        if let Some(socket) = self.ipc_socket.as_mut() {
+            socket.broadcast(&[event]);
+        }
+    }
+
+    fn ipc_window(&self, window: &WindowElement, output: Option<&Output>, workspace: usize) -> WindowInfo {
+        let focused = self.focused_window().as_ref() == Some(window);
+        let fullscreen = output
+            .and_then(|output| output.user_data().get::<FullscreenSurface>())
+            .and_then(|fullscreen| fullscreen.get())
+            .as_ref()
+            == Some(window);
+        WindowInfo {
+            id: window.id(),
+            title: window.title(),
+            app_id: window.app_id(),
+            pid: window.pid(),
+            geometry: self
+                .space
+                .element_geometry(window)
+                .map(|geometry| [geometry.loc.x, geometry.loc.y, geometry.size.w, geometry.size.h]),
+            output: output.map(|output| output.name()),
+            workspace: output.map(|_| workspace + 1),
+            focused,
+            fullscreen,
+            urgent: window.is_urgent(),
+            unresponsive: window.is_unresponsive(),
+            #[cfg(feature = "xwayland")]
+            xwayland: window.is_x11(),
+            #[cfg(not(feature = "xwayland"))]
+            xwayland: false,
+            opacity: window.opacity(),
+            rules: window.matched_rules(),
+            scratchpad: self.scratchpad.window() == Some(window),
+            always_on_top: window.is_always_on_top(),
+            sticky: window.is_sticky(),
+            #[cfg(feature = "udev")]
+            explicit_sync: window
+                .wl_surface()
+                .is_some_and(|surface| crate::shell::uses_explicit_sync(&surface)),
+            #[cfg(not(feature = "udev"))]
+            explicit_sync: false,
+        }
+    }
+
+    fn ipc_windows(&self) -> Vec<WindowInfo> {
+        let mut windows = self
+            .space
+            .elements()
+            .map(|window| {
+                let output = self.space.outputs_for_element(window).into_iter().next();
+                let workspace = output
+                    .as_ref()
+                    .map(|output| self.active_workspace(output))
+                    .unwrap_or_default();
+                self.ipc_window(window, output.as_ref(), workspace)
+            })
+            .collect::<Vec<_>>();
+        for output in self.space.outputs() {
+            windows.extend(
+                hidden_windows(output)
+                    .iter()
+                    .map(|(workspace, window)| self.ipc_window(window, Some(output), *workspace)),
+            );
+        }
+        windows.extend(
+            self.scratchpad
+                .hidden_window()
+                .map(|window| self.ipc_window(window, None, 0)),
+        );
+        windows
+    }
+
+    fn ipc_output(&self, output: &Output) -> OutputInfo {
+        let geometry = self.space.output_geometry(output).unwrap_or_default();
+        let properties = output.physical_properties();
+        let (render_gpu, scanout_gpu) = self.backend_data.output_gpus(output).unzip();
+        OutputInfo {
+            name: output.name(),
+            make: properties.make,
+            model: properties.model,
+            position: [geometry.loc.x, geometry.loc.y],
+            mode: output.current_mode().map(format_mode),
+            modes: output.modes().into_iter().map(format_mode).collect(),
+            scale: output.current_scale().fractional_scale(),
+            transform: format!("{:?}", output.current_transform()),
+            render_gpu,
+            scanout_gpu,
+            frames: self.backend_data.frame_stats(output),
+            explicit_sync: self.backend_data.explicit_sync(output),
+        }
+    }
+
+    fn ipc_workspaces(&self) -> Vec<WorkspaceInfo> {
+        let mut workspaces = Vec::new();
+        for output in self.space.outputs() {
+            let active = self.active_workspace(output);
+            let hidden = hidden_windows(output);
+            for index in 0..crate::shell::WORKSPACE_COUNT {
+                let windows = if index == active {
+                    self.space.elements_for_output(output).count()
+                } else {
+                    hidden.iter().filter(|(workspace, _)| *workspace == index).count()
+                };
+                workspaces.push(WorkspaceInfo {
+                    output: output.name(),
+                    index: index + 1,
+                    active: index == active,
+                    windows,
+                });
+            }
+        }
+        workspaces
+    }
+
+    fn ipc_focus_window(&mut self, id: u64) -> Response {
+        if self.focus_window_by_id(id) {
+            Response::Ok
+        } else {
+            Response::error(format!("No window with id {}", id))
+        }
+    }
+
+    fn ipc_configure_input(&mut self, device: &str, setting: &str, value: &str, persist: bool) -> Response {
+        let settings = match PointerDeviceConfig::parse_setting(setting, value) {
+            Ok(settings) => settings,
+            Err(err) => return Response::error(err.to_string()),
+        };
+        if persist && !matches!(device, "mouse" | "touchpad") {
+            return Response::error("Only mouse and touchpad settings can be saved to the config");
+        }
+        if let Err(err) = BackendData::configure_input(self, device, &settings) {
+            return Response::error(err);
+        }
+
+        if persist {
+            let saved = self
+                .config_path
+                .as_deref()
+                .ok_or(ConfigError::NoConfigDir)
+                .and_then(|path| save_input_setting(path, device, setting, value));
+            if let Err(err) = saved {
+                return Response::error(format!("The setting was applied but not saved: {}", err));
+            }
+        }
+        Response::Ok
+    }
+
+    fn ipc_set_output_mode(&mut self, name: &str, mode: String) -> Response {
+        let Some(output) = self.space.outputs().find(|output| output.name() == name).cloned() else {
+            return Response::error(format!("No output named {}", name));
+        };
+        let mode = match ModeConfig::try_from(mode) {
+            Ok(mode) => mode,
+            Err(err) => return Response::error(err),
+        };
+        let modes = output.modes();
+        let Some(mode) = mode.best_match(&modes) else {
+            return Response::error(format!("Output {} has no such mode", name));
+        };
+
+        let configuration = OutputConfiguration::Enabled(HeadConfiguration {
+            mode: Some(ModeConfiguration::Mode(*mode)),
+            ..Default::default()
+        });
+        if BackendData::configure_outputs(self, vec![(output, configuration)]) {
+            Response::Ok
+        } else {
+            Response::error("Failed to change the output mode")
+        }
+    }
+}
@@ -0,0 +1,653 @@
+use std::{collections::HashMap, str::FromStr};
+
+use smithay::input::keyboard::{Keysym, KeysymHandle, ModifiersState};
+use tracing::warn;
+use xkbcommon::xkb;
+
+bitflags::bitflags! {
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct Modifiers: u8 {
+        const CTRL = 1;
+        const ALT = 2;
+        const SHIFT = 4;
+        const LOGO = 8;
+    }
+}
+
+impl From<&ModifiersState> for Modifiers {
+    fn from(state: &ModifiersState) -> Self {
+        let mut modifiers = Modifiers::empty();
+        modifiers.set(Modifiers::CTRL, state.ctrl);
+        modifiers.set(Modifiers::ALT, state.alt);
+        modifiers.set(Modifiers::SHIFT, state.shift);
+        modifiers.set(Modifiers::LOGO, state.logo);
+        modifiers
+    }
+}
+
+/// A key combination, e.g. `Logo+Shift+M`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyCombo {
+    pub modifiers: Modifiers,
+    pub keysym: Keysym,
+}
+
+impl KeyCombo {
+    pub fn new(modifiers: Modifiers, keysym: Keysym) -> Self {
+        // Shift changes the produced keysym (e.g. `m` -> `M`), so always match on the lowercase variant
+        KeyCombo {
+            modifiers,
+            keysym: xkb::keysym_to_lower(keysym),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum KeybindingError {
+    #[error("Unknown modifier `{0}`")]
+    UnknownModifier(String),
+    #[error("Unknown key `{0}`")]
+    UnknownKey(String),
+    #[error("Unknown gesture `{0}`")]
+    UnknownGesture(String),
+    #[error("Unknown pointer button or scroll direction `{0}`")]
+    UnknownPointerTrigger(String),
+    #[error("Unknown action `{0}`")]
+    UnknownAction(String),
+    #[error("Action `{0}` is missing an argument")]
+    MissingArgument(String),
+    #[error("Invalid argument for action `{0}`")]
+    InvalidArgument(String),
+}
+
+impl FromStr for Modifiers {
+    type Err = KeybindingError;
+
+    /// Parse modifiers joined by `+`, e.g. `Logo+Shift`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = Modifiers::empty();
+        for part in s.split('+').map(str::trim).filter(|part| !part.is_empty()) {
+            modifiers |= match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => Modifiers::CTRL,
+                "alt" | "mod1" => Modifiers::ALT,
+                "shift" => Modifiers::SHIFT,
+                "logo" | "super" | "mod4" => Modifiers::LOGO,
+                _ => return Err(KeybindingError::UnknownModifier(part.into())),
+            };
+        }
+        Ok(modifiers)
+    }
+}
+
+impl FromStr for KeyCombo {
+    type Err = KeybindingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (modifiers, key) = match s.rsplit_once('+') {
+            Some((modifiers, key)) => (modifiers.parse::<Modifiers>()?, key.trim()),
+            None => (Modifiers::empty(), s.trim()),
+        };
+
+        let keysym = xkb::keysym_from_name(key, xkb::KEYSYM_CASE_INSENSITIVE);
+        if keysym.raw() == xkb::keysyms::KEY_NoSymbol {
+            return Err(KeybindingError::UnknownKey(key.into()));
+        }
+
+        Ok(KeyCombo::new(modifiers, keysym))
+    }
+}
+
+/// Direction of a swipe or of directional window actions
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl FromStr for Direction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "left" => Direction::Left,
+            "right" => Direction::Right,
+            "up" => Direction::Up,
+            "down" => Direction::Down,
+            _ => return Err(()),
+        })
+    }
+}
+
+/// Output relative to the one of the focused window
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OutputTarget {
+    /// The nearest output in the direction
+    Direction(Direction),
+    /// The next output from left to right, wrapping around
+    Next,
+    /// The previous output from left to right, wrapping around
+    Prev,
+}
+
+impl FromStr for OutputTarget {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "next" => OutputTarget::Next,
+            "prev" => OutputTarget::Prev,
+            direction => OutputTarget::Direction(direction.parse()?),
+        })
+    }
+}
+
+/// A touchpad swipe, e.g. `3-finger-swipe-left`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SwipeGesture {
+    pub fingers: u32,
+    pub direction: Direction,
+}
+
+impl FromStr for SwipeGesture {
+    type Err = KeybindingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unknown = || KeybindingError::UnknownGesture(s.into());
+        let gesture = s.trim().to_ascii_lowercase();
+        let (fingers, direction) = gesture.split_once("-finger-swipe-").ok_or_else(unknown)?;
+
+        // one and two finger swipes are pointer motion and scrolling
+        let fingers = fingers
+            .parse::<u32>()
+            .ok()
+            .filter(|n| *n >= 3)
+            .ok_or_else(unknown)?;
+        let direction = direction.parse::<Direction>().map_err(|_| unknown())?;
+
+        Ok(SwipeGesture { fingers, direction })
+    }
+}
+
+/// Mouse button or scroll direction of a pointer binding
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PointerTrigger {
+    /// Pressing the button with the given evdev code
+    Button(u32),
+    ScrollUp,
+    ScrollDown,
+}
+
+impl FromStr for PointerTrigger {
+    type Err = KeybindingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "button-left" => PointerTrigger::Button(BTN_LEFT),
+            "button-right" => PointerTrigger::Button(BTN_RIGHT),
+            "button-middle" => PointerTrigger::Button(BTN_MIDDLE),
+            "button-side" => PointerTrigger::Button(BTN_SIDE),
+            "button-extra" => PointerTrigger::Button(BTN_EXTRA),
+            "scroll-up" => PointerTrigger::ScrollUp,
+            "scroll-down" => PointerTrigger::ScrollDown,
+            _ => return Err(KeybindingError::UnknownPointerTrigger(s.into())),
+        })
+    }
+}
+
+// evdev codes of the bindable mouse buttons
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+const BTN_SIDE: u32 = 0x113;
+const BTN_EXTRA: u32 = 0x114;
+
+/// A mouse button or scroll direction with modifiers, e.g. `Logo+button-left`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PointerCombo {
+    pub modifiers: Modifiers,
+    pub trigger: PointerTrigger,
+}
+
+impl FromStr for PointerCombo {
+    type Err = KeybindingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (modifiers, trigger) = match s.rsplit_once('+') {
+            Some((modifiers, trigger)) => (modifiers.parse::<Modifiers>()?, trigger),
+            None => (Modifiers::empty(), s),
+        };
+        Ok(PointerCombo {
+            modifiers,
+            trigger: trigger.parse()?,
+        })
+    }
+}
+
+/// Action of a pointer binding
+#[derive(Debug, Clone, PartialEq)]
+pub enum PointerAction {
+    /// Move the window under the pointer while the button is held
+    Move,
+    /// Resize the window under the pointer at the edges nearest to it while the button is held
+    Resize,
+    /// Any keyboard action, run once per click or scroll step
+    Key(KeyAction),
+}
+
+impl FromStr for PointerAction {
+    type Err = KeybindingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim() {
+            "drag-move" => PointerAction::Move,
+            "drag-resize" => PointerAction::Resize,
+            _ => PointerAction::Key(s.parse()?),
+        })
+    }
+}
+
+/// Possible results of a keyboard action
+#[allow(dead_code)] // some of these are only read if udev is enabled
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyAction {
+    /// Quit the compositor
+    Quit,
+    /// Trigger a vt-switch
+    VtSwitch(i32),
+    /// Spawn a command through `sh -c`
+    Spawn(String),
+    /// Switch the current screen
+    Screen(usize),
+    /// Switch to the given workspace
+    Workspace(usize),
+    /// Move the focused window to the given workspace
+    MoveToWorkspace(usize),
+    /// Move the focused window to another output, keeping its position and size relative to the output
+    MoveToOutput(OutputTarget),
+    /// Switch to the workspace after the active one
+    NextWorkspace,
+    /// Switch to the workspace before the active one
+    PrevWorkspace,
+    /// Switch to the next of the configured keyboard layouts
+    NextLayout,
+    /// Close the focused window
+    CloseWindow,
+    /// Kill the client of the focused window, for windows that don't react to being closed
+    KillWindow,
+    /// Toggle fullscreen of the focused window
+    ToggleFullscreen,
+    /// Maximize the focused window, or restore its previous geometry if it is maximized
+    ToggleMaximize,
+    /// Focus the nearest window in the given direction
+    FocusDirection(Direction),
+    /// Focus the window that was focused before the current one, repeating this while holding
+    /// the modifiers cycles through all windows
+    FocusNext,
+    /// Focus the least recently focused window, cycling backwards like `FocusNext`
+    FocusPrev,
+    /// Move the focused window by a step in the given direction
+    MoveWindow(Direction),
+    /// Push the edge of the focused window on the given side outwards by a step
+    GrowWindow(Direction),
+    /// Pull the edge of the focused window on the given side inwards by a step
+    ShrinkWindow(Direction),
+    /// Center the focused window on its output
+    CenterWindow,
+    /// Minimize the focused window
+    MinimizeWindow,
+    /// Hide all windows of the current workspace, or bring them back if they are hidden
+    ToggleShowDesktop,
+    /// Restore the most recently minimized window of the current output
+    UnminimizeWindow,
+    ScaleUp,
+    ScaleDown,
+    TogglePreview,
+    RotateOutput,
+    ToggleTint,
+    ToggleDecorations,
+    /// Change the opacity of the focused window by the given amount, e.g. `-0.1`
+    ChangeOpacity(f32),
+    /// Show the scratchpad window on the current output, or hide it if it is shown there
+    ToggleScratchpad,
+    /// Keep the focused window above all others, or stop doing so
+    ToggleAlwaysOnTop,
+    /// Show the focused window on all workspaces of its output, or only on the active one again
+    ToggleSticky,
+    /// Magnify the output under the pointer by another step
+    ZoomIn,
+    /// Magnify the output under the pointer by a step less
+    ZoomOut,
+    /// Stop magnifying the output under the pointer
+    ZoomReset,
+    /// Allow the keyboard shortcuts inhibitor of the focused window, or suspend it if it is active.
+    /// Bindings of this action work while shortcuts are inhibited.
+    ToggleShortcutsInhibit,
+    /// Do nothing more
+    None,
+}
+
+impl KeyAction {
+    /// Whether the action works the same on every backend and does not depend on an output
+    pub fn is_common(&self) -> bool {
+        matches!(
+            self,
+            KeyAction::None
+                | KeyAction::Quit
+                | KeyAction::Spawn(_)
+                | KeyAction::TogglePreview
+                | KeyAction::ToggleDecorations
+                | KeyAction::ChangeOpacity(_)
+                | KeyAction::CloseWindow
+                | KeyAction::KillWindow
+                | KeyAction::ToggleFullscreen
+                | KeyAction::ToggleMaximize
+                | KeyAction::FocusDirection(_)
+                | KeyAction::FocusNext
+                | KeyAction::FocusPrev
+                | KeyAction::MoveWindow(_)
+                | KeyAction::GrowWindow(_)
+                | KeyAction::ShrinkWindow(_)
+                | KeyAction::CenterWindow
+                | KeyAction::MinimizeWindow
+                | KeyAction::UnminimizeWindow
+                | KeyAction::ToggleShowDesktop
+                | KeyAction::Workspace(_)
+                | KeyAction::MoveToWorkspace(_)
+                | KeyAction::MoveToOutput(_)
+                | KeyAction::NextWorkspace
+                | KeyAction::PrevWorkspace
+                | KeyAction::NextLayout
+                | KeyAction::ToggleShortcutsInhibit
+                | KeyAction::ToggleScratchpad
+                | KeyAction::ToggleAlwaysOnTop
+                | KeyAction::ToggleSticky
+                | KeyAction::ZoomIn
+                | KeyAction::ZoomOut
+                | KeyAction::ZoomReset
+        )
+    }
+}
+
+impl FromStr for KeyAction {
+    type Err = KeybindingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, arg) = match s.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, Some(arg.trim())),
+            None => (s, None),
+        };
+        let arg = || arg.ok_or_else(|| KeybindingError::MissingArgument(name.into()));
+        let index = || {
+            arg()?
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| n.checked_sub(1))
+                .ok_or_else(|| KeybindingError::InvalidArgument(name.into()))
+        };
+        let direction = || {
+            arg()?
+                .parse::<Direction>()
+                .map_err(|_| KeybindingError::InvalidArgument(name.into()))
+        };
+
+        Ok(match name {
+            "quit" => KeyAction::Quit,
+            "spawn" => KeyAction::Spawn(arg()?.into()),
+            "screen" => KeyAction::Screen(index()?),
+            "workspace" => KeyAction::Workspace(index()?),
+            "move-to-workspace" => KeyAction::MoveToWorkspace(index()?),
+            "move-to-output" => KeyAction::MoveToOutput(
+                arg()?
+                    .parse::<OutputTarget>()
+                    .map_err(|_| KeybindingError::InvalidArgument(name.into()))?,
+            ),
+            "next-workspace" => KeyAction::NextWorkspace,
+            "prev-workspace" => KeyAction::PrevWorkspace,
+            "next-layout" => KeyAction::NextLayout,
+            "close-window" => KeyAction::CloseWindow,
+            "kill-window" => KeyAction::KillWindow,
+            "toggle-fullscreen" => KeyAction::ToggleFullscreen,
+            "toggle-maximize" => KeyAction::ToggleMaximize,
+            "focus" => KeyAction::FocusDirection(direction()?),
+            "focus-next" => KeyAction::FocusNext,
+            "focus-prev" => KeyAction::FocusPrev,
+            "move-window" => KeyAction::MoveWindow(direction()?),
+            "grow-window" => KeyAction::GrowWindow(direction()?),
+            "shrink-window" => KeyAction::ShrinkWindow(direction()?),
+            "center-window" => KeyAction::CenterWindow,
+            "minimize-window" => KeyAction::MinimizeWindow,
+            "unminimize-window" => KeyAction::UnminimizeWindow,
+            "toggle-show-desktop" => KeyAction::ToggleShowDesktop,
+            "scale-up" => KeyAction::ScaleUp,
+            "scale-down" => KeyAction::ScaleDown,
+            "toggle-preview" => KeyAction::TogglePreview,
+            "rotate-output" => KeyAction::RotateOutput,
+            "toggle-tint" => KeyAction::ToggleTint,
+            "toggle-decorations" => KeyAction::ToggleDecorations,
+            "toggle-shortcuts-inhibit" => KeyAction::ToggleShortcutsInhibit,
+            "toggle-scratchpad" => KeyAction::ToggleScratchpad,
+            "toggle-always-on-top" => KeyAction::ToggleAlwaysOnTop,
+            "toggle-sticky" => KeyAction::ToggleSticky,
+            "zoom-in" => KeyAction::ZoomIn,
+            "zoom-out" => KeyAction::ZoomOut,
+            "zoom-reset" => KeyAction::ZoomReset,
+            "opacity" => KeyAction::ChangeOpacity(
+                arg()?
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|delta| delta.is_finite())
+                    .ok_or_else(|| KeybindingError::InvalidArgument(name.into()))?,
+            ),
+            "none" => KeyAction::None,
+            _ => return Err(KeybindingError::UnknownAction(name.into())),
+        })
+    }
+}
+
+/// Bindings used when the configuration does not override them
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("Ctrl+Alt+BackSpace", "quit"),
+    ("Logo+q", "quit"),
+    ("Logo+Return", "spawn weston-terminal"),
+    ("Logo+1", "workspace 1"),
+    ("Logo+2", "workspace 2"),
+    ("Logo+3", "workspace 3"),
+    ("Logo+4", "workspace 4"),
+    ("Logo+5", "workspace 5"),
+    ("Logo+6", "workspace 6"),
+    ("Logo+7", "workspace 7"),
+    ("Logo+8", "workspace 8"),
+    ("Logo+9", "workspace 9"),
+    ("Logo+Shift+1", "move-to-workspace 1"),
+    ("Logo+Shift+2", "move-to-workspace 2"),
+    ("Logo+Shift+3", "move-to-workspace 3"),
+    ("Logo+Shift+4", "move-to-workspace 4"),
+    ("Logo+Shift+5", "move-to-workspace 5"),
+    ("Logo+Shift+6", "move-to-workspace 6"),
+    ("Logo+Shift+7", "move-to-workspace 7"),
+    ("Logo+Shift+8", "move-to-workspace 8"),
+    ("Logo+Shift+9", "move-to-workspace 9"),
+    ("Logo+Ctrl+1", "screen 1"),
+    ("Logo+Ctrl+2", "screen 2"),
+    ("Logo+Ctrl+3", "screen 3"),
+    ("Logo+Ctrl+4", "screen 4"),
+    ("Logo+Ctrl+5", "screen 5"),
+    ("Logo+Ctrl+6", "screen 6"),
+    ("Logo+Ctrl+7", "screen 7"),
+    ("Logo+Ctrl+8", "screen 8"),
+    ("Logo+Ctrl+9", "screen 9"),
+    ("Logo+Shift+M", "scale-down"),
+    ("Logo+Shift+P", "scale-up"),
+    ("Logo+Shift+W", "toggle-preview"),
+    ("Logo+Shift+R", "rotate-output"),
+    ("Logo+Shift+T", "toggle-tint"),
+    ("Logo+Shift+D", "toggle-decorations"),
+    ("Logo+Left", "focus left"),
+    ("Logo+Right", "focus right"),
+    ("Logo+Up", "focus up"),
+    ("Logo+Down", "focus down"),
+    ("Alt+Tab", "focus-next"),
+    ("Alt+Shift+Tab", "focus-prev"),
+    ("Logo+Shift+Left", "move-window left"),
+    ("Logo+Shift+Right", "move-window right"),
+    ("Logo+Shift+Up", "move-window up"),
+    ("Logo+Shift+Down", "move-window down"),
+    ("Logo+Ctrl+Left", "grow-window left"),
+    ("Logo+Ctrl+Right", "grow-window right"),
+    ("Logo+Ctrl+Up", "grow-window up"),
+    ("Logo+Ctrl+Down", "grow-window down"),
+    ("Logo+Ctrl+Shift+Left", "shrink-window left"),
+    ("Logo+Ctrl+Shift+Right", "shrink-window right"),
+    ("Logo+Ctrl+Shift+Up", "shrink-window up"),
+    ("Logo+Ctrl+Shift+Down", "shrink-window down"),
+    ("Logo+Alt+Left", "move-to-output left"),
+    ("Logo+Alt+Right", "move-to-output right"),
+    ("Logo+Alt+Up", "move-to-output up"),
+    ("Logo+Alt+Down", "move-to-output down"),
+    ("Logo+c", "center-window"),
+    ("Logo+m", "toggle-maximize"),
+    ("Logo+n", "minimize-window"),
+    ("Logo+d", "toggle-show-desktop"),
+    ("Logo+Shift+N", "unminimize-window"),
+    ("Logo+Escape", "toggle-shortcuts-inhibit"),
+    ("Logo+grave", "toggle-scratchpad"),
+    ("Logo+t", "toggle-always-on-top"),
+    ("Logo+s", "toggle-sticky"),
+    ("Logo+equal", "zoom-in"),
+    ("Logo+minus", "zoom-out"),
+    ("Logo+0", "zoom-reset"),
+];
+
+/// Gesture bindings used when the configuration does not override them
+const DEFAULT_GESTURES: &[(&str, &str)] = &[
+    ("3-finger-swipe-left", "next-workspace"),
+    ("3-finger-swipe-right", "prev-workspace"),
+    ("4-finger-swipe-up", "toggle-preview"),
+];
+
+/// Pointer bindings used when the configuration does not override them
+const DEFAULT_POINTER_BINDINGS: &[(&str, &str)] = &[
+    ("Logo+button-left", "drag-move"),
+    ("Logo+button-right", "drag-resize"),
+    ("Logo+scroll-up", "prev-workspace"),
+    ("Logo+scroll-down", "next-workspace"),
+    ("Logo+Shift+scroll-up", "opacity 0.05"),
+    ("Logo+Shift+scroll-down", "opacity -0.05"),
+];
+
+/// Table mapping key combinations, touchpad swipes and mouse buttons to actions
+#[derive(Debug, Default, Clone)]
+pub struct Keybindings {
+    bindings: HashMap<KeyCombo, KeyAction>,
+    gestures: HashMap<SwipeGesture, KeyAction>,
+    pointer: HashMap<PointerCombo, PointerAction>,
+}
+
+impl Keybindings {
+    /// Build the table from the defaults, overridden by the `[keybindings]`, `[gestures]` and
+    /// `[pointer_bindings]` config sections.
+    ///
+    /// Binding a combination or gesture to `"none"` removes the default binding.
+    pub fn new<'a>(
+        config: impl IntoIterator<Item = (&'a String, &'a String)>,
+        gestures: impl IntoIterator<Item = (&'a String, &'a String)>,
+        pointer: impl IntoIterator<Item = (&'a String, &'a String)>,
+    ) -> Self {
+        let mut keybindings = Keybindings::default();
+
+        let defaults = DEFAULT_BINDINGS.iter().map(|(combo, action)| (*combo, *action));
+        let configured = config.into_iter().map(|(combo, action)| (combo.as_str(), action.as_str()));
+        for (combo, action) in defaults.chain(configured) {
+            match (combo.parse::<KeyCombo>(), action.parse::<KeyAction>()) {
+                (Ok(combo), Ok(KeyAction::None)) => {
+                    keybindings.bindings.remove(&combo);
+                }
+                (Ok(combo), Ok(action)) => {
+                    keybindings.bindings.insert(combo, action);
+                }
+                (Err(err), _) | (_, Err(err)) => {
+                    warn!(combo, action, "Ignoring keybinding: {}", err);
+                }
+            }
+        }
+
+        let defaults = DEFAULT_GESTURES
+            .iter()
+            .map(|(gesture, action)| (*gesture, *action));
+        let configured = gestures
+            .into_iter()
+            .map(|(gesture, action)| (gesture.as_str(), action.as_str()));
+        for (gesture, action) in defaults.chain(configured) {
+            match (gesture.parse::<SwipeGesture>(), action.parse::<KeyAction>()) {
+                (Ok(gesture), Ok(KeyAction::None)) => {
+                    keybindings.gestures.remove(&gesture);
+                }
+                (Ok(gesture), Ok(action)) => {
+                    keybindings.gestures.insert(gesture, action);
+                }
+                (Err(err), _) | (_, Err(err)) => {
+                    warn!(gesture, action, "Ignoring gesture binding: {}", err);
+                }
+            }
+        }
+
+        let defaults = DEFAULT_POINTER_BINDINGS
+            .iter()
+            .map(|(combo, action)| (*combo, *action));
+        let configured = pointer
+            .into_iter()
+            .map(|(combo, action)| (combo.as_str(), action.as_str()));
+        for (combo, action) in defaults.chain(configured) {
+            match (combo.parse::<PointerCombo>(), action.parse::<PointerAction>()) {
+                (Ok(combo), Ok(PointerAction::Key(KeyAction::None))) => {
+                    keybindings.pointer.remove(&combo);
+                }
+                (Ok(combo), Ok(action)) => {
+                    keybindings.pointer.insert(combo, action);
+                }
+                (Err(err), _) | (_, Err(err)) => {
+                    warn!(combo, action, "Ignoring pointer binding: {}", err);
+                }
+            }
+        }
+
+        keybindings
+    }
+
+    /// Whether swipes with this many fingers are handled by the compositor instead of clients
+    pub fn has_swipe(&self, fingers: u32) -> bool {
+        self.gestures.keys().any(|gesture| gesture.fingers == fingers)
+    }
+
+    pub fn swipe_action(&self, gesture: SwipeGesture) -> Option<KeyAction> {
+        self.gestures.get(&gesture).cloned()
+    }
+
+    pub fn pointer_action(&self, modifiers: &ModifiersState, trigger: PointerTrigger) -> Option<PointerAction> {
+        self.pointer
+            .get(&PointerCombo {
+                modifiers: modifiers.into(),
+                trigger,
+            })
+            .cloned()
+    }
+
+    pub fn action(&self, modifiers: &ModifiersState, handle: &KeysymHandle<'_>) -> Option<KeyAction> {
+        let keysym = handle.modified_sym();
+        if (xkb::keysyms::KEY_XF86Switch_VT_1..=xkb::keysyms::KEY_XF86Switch_VT_12).contains(&keysym.raw()) {
+            // VTSwitch is not configurable, it always has to work
+            return Some(KeyAction::VtSwitch(
+                (keysym.raw() - xkb::keysyms::KEY_XF86Switch_VT_1 + 1) as i32,
+            ));
+        }
+
+        // Match on the unmodified symbol, so e.g. `Logo+Shift+1` works regardless of what shift+1 produces
+        let keysym = handle.raw_latin_sym_or_raw_current_sym().unwrap_or(keysym);
+        self.bindings
+            .get(&KeyCombo::new(modifiers.into(), keysym))
+            .cloned()
+    }
+}
@@ -2,16 +2,31 @@
 // If no backend is enabled, a large portion of the codebase is unused.
 // So silence this useless warning for the CI.
 #![cfg_attr(
-    not(any(feature = "winit", feature = "x11", feature = "udev")),
+    not(any(feature = "winit", feature = "x11", feature = "udev", feature = "headless")),
     allow(dead_code, unused_imports)
 )]
 
+pub mod cli;
+pub mod config;
+pub mod crash;
 #[cfg(any(feature = "udev", feature = "xwayland"))]
 pub mod cursor;
 pub mod drawing;
 pub mod focus;
+#[cfg(feature = "headless")]
+pub mod headless;
 pub mod input_handler;
+pub mod ipc;
+pub mod keybindings;
+pub mod logging;
+pub mod magnifier;
+#[cfg(feature = "udev")]
+pub mod night_light;
+pub mod protocols;
 pub mod render;
+pub mod session;
+#[cfg(feature = "udev")]
+pub mod rotation;
 pub mod shell;
 pub mod state;
 #[cfg(feature = "udev")]
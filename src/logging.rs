@@ -0,0 +1,191 @@
+//! Log output
+//!
+//! Events go to stderr, as JSON lines to `$XDG_STATE_HOME/luxo/luxo.log` and into a ring of recent
+//! events that `luxoctl logs` reads over ipc. The log file is rotated when luxo starts and whenever
+//! it grows past [`MAX_LOG_SIZE`], keeping [`LOG_FILES`] older files around.
+
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, prelude::*, EnvFilter, Layer};
+
+use crate::ipc::LogEntry;
+
+/// The log file is rotated once it grows past this size
+const MAX_LOG_SIZE: u64 = 8 * 1024 * 1024;
+/// Number of rotated log files kept, as `luxo.log.1` (newest) to `luxo.log.<LOG_FILES>`
+const LOG_FILES: usize = 3;
+/// Number of events kept for `luxoctl logs`
+const RING_SIZE: usize = 1000;
+
+static RING: Mutex<VecDeque<LogEntry>> = Mutex::new(VecDeque::new());
+
+/// Directory for logs and crash reports, `$XDG_STATE_HOME/luxo` or `~/.local/state/luxo`
+pub fn state_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .map(|path| path.join("luxo"))
+}
+
+/// Set up the global subscriber, filtered by `RUST_LOG` or else by `debug`
+pub fn init(debug: bool) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::default().add_directive(if debug { Level::DEBUG } else { Level::INFO }.into())
+    });
+
+    let (file, file_error) = match state_dir().map(|dir| RotatingFile::open(dir.join("luxo.log"))) {
+        Some(Ok(file)) => (Some(file), None),
+        Some(Err(err)) => (None, Some(err)),
+        None => (None, None),
+    };
+    let file_layer = file.map(|file| {
+        tracing_subscriber::fmt::layer()
+            .json()
+            .with_writer(Mutex::new(file))
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer().compact())
+        .with(file_layer)
+        .with(RingLayer)
+        .init();
+
+    if let Some(err) = file_error {
+        tracing::warn!("Failed to open the log file: {}", err);
+    }
+}
+
+/// The last `limit` recorded events of at least `level`, oldest first
+pub fn recent(limit: Option<usize>, level: Option<Level>) -> Vec<LogEntry> {
+    let ring = RING.lock().unwrap();
+    let mut entries = ring
+        .iter()
+        .rev()
+        .filter(|entry| {
+            level.is_none_or(|level| entry.level.parse::<Level>().is_ok_and(|entry| entry <= level))
+        })
+        .take(limit.unwrap_or(RING_SIZE))
+        .cloned()
+        .collect::<Vec<_>>();
+    entries.reverse();
+    entries
+}
+
+/// Records events into the ring
+struct RingLayer;
+
+impl<S: Subscriber> Layer<S> for RingLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let metadata = event.metadata();
+        let entry = LogEntry {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs_f64())
+                .unwrap_or_default(),
+            level: metadata.level().to_string(),
+            target: metadata.target().to_owned(),
+            message: visitor.message,
+        };
+
+        let mut ring = RING.lock().unwrap();
+        if ring.len() == RING_SIZE {
+            ring.pop_front();
+        }
+        ring.push_back(entry);
+    }
+}
+
+/// Formats the message followed by the other fields as `name=value`
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let fields = std::mem::take(&mut self.message);
+            let _ = write!(self.message, "{:?}{}", value, fields);
+        } else {
+            let _ = write!(self.message, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.record_debug(field, &format_args!("{}", value));
+        } else {
+            let _ = write!(self.message, " {}={}", field.name(), value);
+        }
+    }
+}
+
+/// Log file moving itself to `<path>.1` once it gets too large
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Open a fresh log file at `path`, rotating the log of the previous run
+    fn open(path: PathBuf) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        if path.metadata().is_ok_and(|metadata| metadata.len() > 0) {
+            rotate(&path)?;
+        }
+        let file = File::create(&path)?;
+        Ok(RotatingFile { path, file, size: 0 })
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > MAX_LOG_SIZE {
+            rotate(&self.path)?;
+            self.file = File::create(&self.path)?;
+            self.size = 0;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Shift `<path>.1` to `<path>.2` and so on, then move `path` to `<path>.1`
+fn rotate(path: &Path) -> io::Result<()> {
+    let rotated = |index: usize| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    };
+    for index in (1..LOG_FILES).rev() {
+        let from = rotated(index);
+        if from.exists() {
+            std::fs::rename(from, rotated(index + 1))?;
+        }
+    }
+    std::fs::rename(path, rotated(1))
+}
@@ -0,0 +1,136 @@
+//! Screen magnifier, zooming the rendering of an output around the pointer
+//!
+//! The point under the pointer stays in place, so moving the pointer pans the magnified area
+//! smoothly and every part of the output can be reached. Input is not affected, the pointer
+//! still points at what is drawn under it.
+
+use std::cell::RefCell;
+
+use smithay::{
+    output::Output,
+    utils::{Logical, Physical, Point},
+};
+use tracing::debug;
+
+use crate::{
+    keybindings::Modifiers,
+    shell::{Animation, ANIMATION_DURATION},
+    state::{Backend, LuxoState},
+};
+
+/// Zoom factor of a step of the `zoom-in` and `zoom-out` actions
+pub const ZOOM_STEP: f64 = 1.25;
+/// Scroll distance doubling the zoom, about four clicks of a mouse wheel
+const SCROLL_PER_DOUBLING: f64 = 60.0;
+
+/// Magnifier state of an output, stored in its user data
+#[derive(Debug)]
+struct Magnifier {
+    zoom: f64,
+    /// Animation of the zoom changing from the given one, for the keyboard actions
+    transition: Option<(Animation, f64)>,
+    /// Pointer location relative to the output
+    focus: Point<f64, Logical>,
+}
+
+impl Default for Magnifier {
+    fn default() -> Self {
+        Magnifier {
+            zoom: 1.0,
+            transition: None,
+            focus: Point::default(),
+        }
+    }
+}
+
+fn magnifier(output: &Output) -> &RefCell<Magnifier> {
+    output
+        .user_data()
+        .insert_if_missing(|| RefCell::new(Magnifier::default()));
+    output.user_data().get::<RefCell<Magnifier>>().unwrap()
+}
+
+/// Zoom factor of `output` and the point to scale its elements around, unless it isn't magnified
+pub fn magnification(output: &Output) -> Option<(f64, Point<i32, Physical>)> {
+    let mut magnifier = magnifier(output).borrow_mut();
+    let zoom = match magnifier.transition {
+        Some((animation, from)) => match animation.progress() {
+            Some(progress) => from + (magnifier.zoom - from) * progress,
+            None => {
+                magnifier.transition = None;
+                magnifier.zoom
+            }
+        },
+        None => magnifier.zoom,
+    };
+    if zoom <= 1.0 {
+        return None;
+    }
+
+    let scale = output.current_scale().fractional_scale();
+    Some((zoom, magnifier.focus.to_physical(scale).to_i32_round()))
+}
+
+impl<BackendData: Backend> LuxoState<BackendData> {
+    /// Multiply the zoom of the output under the pointer by `factor`, between no magnification
+    /// and the configured maximum
+    pub fn zoom_by(&mut self, factor: f64, animate: bool) {
+        let Some(output) = self.workspace_output() else {
+            return;
+        };
+        let max_zoom = self.config.magnifier.max_zoom.max(1.0);
+        let mut magnifier = magnifier(&output).borrow_mut();
+        let zoom = (magnifier.zoom * factor).clamp(1.0, max_zoom);
+        if zoom == magnifier.zoom {
+            return;
+        }
+        debug!(output = ?output.name(), zoom, "Changing magnification");
+
+        let animate = animate && self.config.appearance.animations;
+        magnifier.transition = animate.then(|| (Animation::new(ANIMATION_DURATION), magnifier.zoom));
+        magnifier.zoom = zoom;
+        drop(magnifier);
+        self.update_magnifier();
+        self.backend_data.reset_buffers(&output);
+    }
+
+    /// Stop magnifying the output under the pointer
+    pub fn reset_zoom(&mut self) {
+        // clamped to no magnification
+        self.zoom_by(0.0, true);
+    }
+
+    /// Zoom with the scroll wheel or touchpad while the configured modifiers are held.
+    /// Returns whether the scroll event was used up.
+    pub fn zoom_by_scrolling(&mut self, amount: f64) -> bool {
+        let Ok(modifiers) = self.config.magnifier.scroll_modifiers.parse::<Modifiers>() else {
+            return false;
+        };
+        let keyboard = self.seat.get_keyboard().unwrap();
+        if modifiers.is_empty() || Modifiers::from(&keyboard.modifier_state()) != modifiers {
+            return false;
+        }
+
+        // scrolling up zooms in
+        self.zoom_by((-amount / SCROLL_PER_DOUBLING).exp2(), false);
+        true
+    }
+
+    /// Let the magnified area follow the pointer, runs once per iteration of the event loop
+    pub fn update_magnifier(&self) {
+        let location = self.pointer.current_location();
+        for output in self.space.outputs() {
+            let Some(geometry) = self.space.output_geometry(output) else {
+                continue;
+            };
+            let mut magnifier = magnifier(output).borrow_mut();
+            if magnifier.zoom <= 1.0 && magnifier.transition.is_none() {
+                continue;
+            }
+            // outputs the pointer is not on zoom around the nearest point to it
+            let size = geometry.size.to_f64();
+            let relative = location - geometry.loc.to_f64();
+            magnifier.focus = Point::from((relative.x.clamp(0.0, size.w), relative.y.clamp(0.0, size.h)));
+        }
+    }
+}
@@ -1,68 +1,96 @@
+use std::process::ExitCode;
 
-static POSSIBLE_BACKENDS: &[&str] = &[
-    #[cfg(feature = "winit")]
-    "--winit : Run anvil as a X11 or Wayland client using winit.",
-    #[cfg(feature = "udev")]
-    "--tty-udev : Run anvil as a tty udev client (requires root if without logind).",
-    #[cfg(feature = "x11")]
-    "--x11 : Run anvil as an X11 client.",
-];
+use luxo::cli::{BackendKind, Options, USAGE};
 
 #[cfg(feature = "profile-with-tracy-mem")]
 #[global_allocator]
 static GLOBAL: profiling::tracy_client::ProfiledAllocator<std::alloc::System> =
     profiling::tracy_client::ProfiledAllocator::new(std::alloc::System, 10);
 
-fn main() {
-    if let Ok(env_filter) = tracing_subscriber::EnvFilter::try_from_default_env() {
-        tracing_subscriber::fmt()
-            .compact()
-            .with_env_filter(env_filter)
-            .init();
-    } else {
-        tracing_subscriber::fmt().compact().init();
-    }
+fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            #[allow(clippy::disallowed_macros)]
+            {
+                eprintln!("{}", err);
+                eprintln!();
+                eprintln!("{}", USAGE);
+            }
+            return ExitCode::FAILURE;
+        }
+    };
+
+    luxo::logging::init(options.debug);
 
+    luxo::crash::install_panic_hook();
+
+    // tracy needs a running client for its scopes, it only sends data once a profiler connects
     #[cfg(feature = "profile-with-tracy")]
     profiling::tracy_client::Client::start();
 
     profiling::register_thread!("Main Thread");
 
     #[cfg(feature = "profile-with-puffin")]
-    let _server = puffin_http::Server::new(&format!("0.0.0.0:{}", puffin_http::DEFAULT_PORT)).unwrap();
-    #[cfg(feature = "profile-with-puffin")]
-    profiling::puffin::set_scopes_on(true);
+    let _server = start_puffin_server();
 
-    let arg = ::std::env::args().nth(1);
-    match arg.as_ref().map(|s| &s[..]) {
+    match options.backend {
         #[cfg(feature = "winit")]
-        Some("--winit") => {
-            tracing::info!("Starting anvil with winit backend");
-            luxo::winit::run_winit();
+        Some(BackendKind::Winit) => {
+            tracing::info!("Starting luxo with winit backend");
+            luxo::winit::run_winit(&options);
         }
         #[cfg(feature = "udev")]
-        Some("--tty-udev") => {
-            tracing::info!("Starting anvil on a tty using udev");
-            luxo::udev::run_udev();
+        Some(BackendKind::Udev) => {
+            tracing::info!("Starting luxo on a tty using udev");
+            luxo::udev::run_udev(&options);
         }
         #[cfg(feature = "x11")]
-        Some("--x11") => {
-            tracing::info!("Starting anvil with x11 backend");
-            luxo::x11::run_x11();
+        Some(BackendKind::X11) => {
+            tracing::info!("Starting luxo with x11 backend");
+            luxo::x11::run_x11(&options);
         }
-        Some(other) => {
-            tracing::error!("Unknown backend: {}", other);
+        #[cfg(feature = "headless")]
+        Some(BackendKind::Headless) => {
+            tracing::info!("Starting luxo with headless backend");
+            luxo::headless::run_headless(&options);
+        }
+        #[allow(unreachable_patterns)]
+        Some(backend) => {
+            tracing::error!(?backend, "Backend is not enabled in this build");
+            return ExitCode::FAILURE;
         }
         None => {
             #[allow(clippy::disallowed_macros)]
             {
-                println!("USAGE: anvil --backend");
-                println!();
-                println!("Possible backends are:");
-                for b in POSSIBLE_BACKENDS {
-                    println!("\t{}", b);
-                }
+                println!("{}", USAGE);
             }
+            return ExitCode::FAILURE;
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Serve puffin scopes if `LUXO_PROFILER` is set, to `127.0.0.1` or the address it holds.
+/// Scopes aren't recorded otherwise, so they cost next to nothing.
+#[cfg(feature = "profile-with-puffin")]
+fn start_puffin_server() -> Option<puffin_http::Server> {
+    let address = std::env::var("LUXO_PROFILER").ok()?;
+    let address = if address.contains(':') {
+        address
+    } else {
+        format!("127.0.0.1:{}", puffin_http::DEFAULT_PORT)
+    };
+    match puffin_http::Server::new(&address) {
+        Ok(server) => {
+            tracing::info!(%address, "Serving puffin profiler scopes");
+            profiling::puffin::set_scopes_on(true);
+            Some(server)
+        }
+        Err(err) => {
+            tracing::error!(%address, "Failed to start puffin server: {}", err);
+            None
         }
     }
 }
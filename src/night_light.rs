@@ -0,0 +1,62 @@
+//! Gamma ramps for the night light and the local time its schedule is based on
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::TimeOfDay;
+
+/// Color temperature leaving colors unchanged
+pub const NEUTRAL_TEMPERATURE: u32 = 6500;
+
+/// Relative intensity of red, green and blue of a black body at `kelvin`, using Tanner Helland's
+/// approximation
+fn black_body(kelvin: u32) -> [f64; 3] {
+    let t = kelvin.clamp(1000, 40000) as f64 / 100.0;
+    let red = if t <= 66.0 {
+        1.0
+    } else {
+        1.292_936_186 * (t - 60.0).powf(-0.133_204_759_2)
+    };
+    let green = if t <= 66.0 {
+        0.390_081_578_8 * t.ln() - 0.631_841_443_8
+    } else {
+        1.129_890_861 * (t - 60.0).powf(-0.075_514_849_2)
+    };
+    let blue = if t >= 66.0 {
+        1.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        0.543_206_789_1 * (t - 10.0).ln() - 1.196_254_089_1
+    };
+    [red, green, blue].map(|c| c.clamp(0.0, 1.0))
+}
+
+/// Linear gamma ramp with `size` entries per channel, scaled to the color of `kelvin` and laid
+/// out as all of red, then green, then blue
+pub fn gamma_ramp(kelvin: u32, size: u32) -> Vec<u16> {
+    let neutral = black_body(NEUTRAL_TEMPERATURE);
+    let color = black_body(kelvin);
+    let last = size.saturating_sub(1).max(1) as f64;
+    (0..3)
+        .flat_map(|channel| {
+            let factor = (color[channel] / neutral[channel]).min(1.0);
+            (0..size).map(move |i| (i as f64 / last * factor * u16::MAX as f64).round() as u16)
+        })
+        .collect()
+}
+
+/// Current local time of day
+pub fn local_time() -> TimeOfDay {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs() as libc::time_t)
+        .unwrap_or(0);
+    // SAFETY: an all-zero `tm` is valid, it only holds integers and a nullable pointer
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    // SAFETY: both pointers are valid for the duration of the call
+    if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+        // fall back to UTC
+        return TimeOfDay((now / 60 % (24 * 60)) as u32);
+    }
+    TimeOfDay((tm.tm_hour * 60 + tm.tm_min) as u32)
+}
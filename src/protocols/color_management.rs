@@ -0,0 +1,790 @@
+//! color-management-v1
+//!
+//! Lets clients tag their surfaces with parametric image descriptions (a named transfer function
+//! and primaries plus luminances) and tells them the image description of outputs and the one
+//! preferred for their surfaces. ICC profiles are not supported.
+//!
+//! The description set on a surface is double-buffered like the rest of its state and can be read
+//! with [`surface_image_description`]. Outputs are sRGB unless the backend stores another
+//! description with [`OutputImageDescription::set`] and calls [`output_changed`] afterwards.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+use smithay::{
+    output::Output,
+    reexports::{
+        wayland_protocols::wp::color_management::v1::server::{
+            wp_color_management_output_v1::{self, WpColorManagementOutputV1},
+            wp_color_management_surface_feedback_v1::{self, WpColorManagementSurfaceFeedbackV1},
+            wp_color_management_surface_v1::{self, WpColorManagementSurfaceV1},
+            wp_color_manager_v1::{self, Primaries, RenderIntent, TransferFunction, WpColorManagerV1},
+            wp_image_description_creator_icc_v1::{self, WpImageDescriptionCreatorIccV1},
+            wp_image_description_creator_params_v1::{self, WpImageDescriptionCreatorParamsV1},
+            wp_image_description_info_v1::{self, WpImageDescriptionInfoV1},
+            wp_image_description_v1::{self, WpImageDescriptionV1},
+        },
+        wayland_server::{
+            backend::{ClientId, GlobalId},
+            protocol::wl_surface::WlSurface,
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum, Weak,
+        },
+    },
+    wayland::compositor::{self, Cacheable},
+};
+
+const VERSION: u32 = 1;
+
+const TRANSFER_FUNCTIONS: [TransferFunction; 4] = [
+    TransferFunction::Srgb,
+    TransferFunction::Gamma22,
+    TransferFunction::ExtLinear,
+    TransferFunction::St2084Pq,
+];
+
+const PRIMARIES: [Primaries; 3] = [Primaries::Srgb, Primaries::DisplayP3, Primaries::Bt2020];
+
+/// CIE 1931 xy chromaticities of the red, green and blue primaries and the white point
+pub fn chromaticities(primaries: Primaries) -> [[f64; 2]; 4] {
+    const D65: [f64; 2] = [0.3127, 0.3290];
+    match primaries {
+        Primaries::DisplayP3 => [[0.680, 0.320], [0.265, 0.690], [0.150, 0.060], D65],
+        Primaries::Bt2020 => [[0.708, 0.292], [0.170, 0.797], [0.131, 0.046], D65],
+        _ => [[0.640, 0.330], [0.300, 0.600], [0.150, 0.060], D65],
+    }
+}
+
+/// Luminances in cd/m², except `min` which is in 0.0001 cd/m² like in the protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Luminances {
+    pub min: u32,
+    pub max: u32,
+    pub reference: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageDescription {
+    pub tf: TransferFunction,
+    pub primaries: Primaries,
+    /// `None` for the defaults of the transfer function
+    pub luminances: Option<Luminances>,
+    pub max_cll: Option<u32>,
+    pub max_fall: Option<u32>,
+}
+
+impl ImageDescription {
+    pub const SRGB: ImageDescription = ImageDescription {
+        tf: TransferFunction::Srgb,
+        primaries: Primaries::Srgb,
+        luminances: None,
+        max_cll: None,
+        max_fall: None,
+    };
+
+    /// BT.2100 with the PQ transfer function, as understood by HDR displays
+    pub const BT2100_PQ: ImageDescription = ImageDescription {
+        tf: TransferFunction::St2084Pq,
+        primaries: Primaries::Bt2020,
+        luminances: Some(Luminances {
+            min: 50,
+            max: 1000,
+            reference: 203,
+        }),
+        max_cll: None,
+        max_fall: None,
+    };
+
+    pub fn luminances(&self) -> Luminances {
+        self.luminances.unwrap_or(match self.tf {
+            TransferFunction::St2084Pq => Luminances {
+                min: 50,
+                max: 10000,
+                reference: 203,
+            },
+            _ => Luminances {
+                min: 2000,
+                max: 80,
+                reference: 80,
+            },
+        })
+    }
+}
+
+/// Image description of an output, outputs without one are sRGB
+#[derive(Debug)]
+pub struct OutputImageDescription(Mutex<ImageDescription>);
+
+impl OutputImageDescription {
+    pub fn get(output: &Output) -> ImageDescription {
+        output
+            .user_data()
+            .get::<OutputImageDescription>()
+            .map(|description| *description.0.lock().unwrap())
+            .unwrap_or(ImageDescription::SRGB)
+    }
+
+    pub fn set(output: &Output, description: ImageDescription) {
+        output
+            .user_data()
+            .insert_if_missing_threadsafe(|| OutputImageDescription(Mutex::new(ImageDescription::SRGB)));
+        *output
+            .user_data()
+            .get::<OutputImageDescription>()
+            .unwrap()
+            .0
+            .lock()
+            .unwrap() = description;
+    }
+}
+
+/// Image description of the current state of `surface`, `None` if the client didn't set one
+pub fn surface_image_description(surface: &WlSurface) -> Option<ImageDescription> {
+    compositor::with_states(surface, |states| {
+        states
+            .cached_state
+            .get::<SurfaceColorState>()
+            .current()
+            .description
+    })
+}
+
+pub trait ColorManagementHandler {
+    fn color_management_state(&mut self) -> &mut ColorManagementState;
+
+    /// Image description `surface` should ideally be rendered in, usually the one of its output
+    fn preferred_image_description(&mut self, surface: &WlSurface) -> ImageDescription;
+}
+
+#[derive(Debug)]
+pub struct ColorManagementState {
+    global: GlobalId,
+    outputs: Vec<WpColorManagementOutputV1>,
+    feedbacks: Vec<WpColorManagementSurfaceFeedbackV1>,
+    /// Identities handed out for compositor provided descriptions, so they stay the same
+    identities: Vec<(ImageDescription, u32)>,
+    next_identity: u32,
+}
+
+impl ColorManagementState {
+    pub fn new<D>(display: &DisplayHandle) -> Self
+    where
+        D: GlobalDispatch<WpColorManagerV1, ()> + Dispatch<WpColorManagerV1, ()> + 'static,
+    {
+        let global = display.create_global::<D, WpColorManagerV1, _>(VERSION, ());
+        ColorManagementState {
+            global,
+            outputs: Vec::new(),
+            feedbacks: Vec::new(),
+            identities: Vec::new(),
+            next_identity: 1,
+        }
+    }
+
+    pub fn global(&self) -> GlobalId {
+        self.global.clone()
+    }
+
+    fn new_identity(&mut self) -> u32 {
+        let identity = self.next_identity;
+        self.next_identity = self.next_identity.wrapping_add(1).max(1);
+        identity
+    }
+
+    fn identity(&mut self, description: ImageDescription) -> u32 {
+        if let Some((_, identity)) = self.identities.iter().find(|(d, _)| *d == description) {
+            return *identity;
+        }
+        let identity = self.new_identity();
+        self.identities.push((description, identity));
+        identity
+    }
+}
+
+/// Tell clients that the image description of `output` changed, and that the preferred
+/// description of their surfaces might have changed with it
+pub fn output_changed<D: ColorManagementHandler>(state: &mut D, output: &Output) {
+    let color_state = state.color_management_state();
+    for object in &color_state.outputs {
+        if object.data::<Option<Output>>().and_then(Option::as_ref) == Some(output) {
+            object.image_description_changed();
+        }
+    }
+
+    for feedback in color_state.feedbacks.clone() {
+        let Some(surface) = feedback
+            .data::<Weak<WlSurface>>()
+            .and_then(|surface| surface.upgrade().ok())
+        else {
+            continue;
+        };
+        let preferred = state.preferred_image_description(&surface);
+        let identity = state.color_management_state().identity(preferred);
+        feedback.preferred_changed(identity);
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct SurfaceColorState {
+    description: Option<ImageDescription>,
+}
+
+impl Cacheable for SurfaceColorState {
+    fn commit(&mut self, _dh: &DisplayHandle) -> Self {
+        *self
+    }
+
+    fn merge_into(self, into: &mut Self, _dh: &DisplayHandle) {
+        *into = self;
+    }
+}
+
+/// Set in the surface data while a color management surface object exists for it
+#[derive(Debug, Default)]
+struct ColorManagedSurface(AtomicBool);
+
+#[derive(Debug, Default)]
+struct PendingDescription {
+    tf: Option<TransferFunction>,
+    primaries: Option<Primaries>,
+    luminances: Option<Luminances>,
+    max_cll: Option<u32>,
+    max_fall: Option<u32>,
+}
+
+#[derive(Debug, Default)]
+pub struct CreatorData(Mutex<PendingDescription>);
+
+#[derive(Debug)]
+pub struct ImageDescriptionData {
+    /// `None` for descriptions that failed
+    description: Option<ImageDescription>,
+    /// Only descriptions made by the compositor may be queried
+    allow_information: bool,
+}
+
+impl<D> GlobalDispatch<WpColorManagerV1, (), D> for ColorManagementState
+where
+    D: GlobalDispatch<WpColorManagerV1, ()> + Dispatch<WpColorManagerV1, ()> + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        manager: New<WpColorManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        let manager = data_init.init(manager, ());
+        manager.supported_intent(RenderIntent::Perceptual);
+        manager.supported_feature(wp_color_manager_v1::Feature::Parametric);
+        manager.supported_feature(wp_color_manager_v1::Feature::SetLuminances);
+        for tf in TRANSFER_FUNCTIONS {
+            manager.supported_tf_named(tf);
+        }
+        for primaries in PRIMARIES {
+            manager.supported_primaries_named(primaries);
+        }
+        manager.done();
+    }
+}
+
+impl<D> Dispatch<WpColorManagerV1, (), D> for ColorManagementState
+where
+    D: Dispatch<WpColorManagerV1, ()>
+        + Dispatch<WpColorManagementOutputV1, Option<Output>>
+        + Dispatch<WpColorManagementSurfaceV1, Weak<WlSurface>>
+        + Dispatch<WpColorManagementSurfaceFeedbackV1, Weak<WlSurface>>
+        + Dispatch<WpImageDescriptionCreatorIccV1, ()>
+        + Dispatch<WpImageDescriptionCreatorParamsV1, CreatorData>
+        + Dispatch<WpImageDescriptionV1, ImageDescriptionData>
+        + ColorManagementHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        manager: &WpColorManagerV1,
+        request: wp_color_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            wp_color_manager_v1::Request::GetOutput { id, output } => {
+                let object = data_init.init(id, Output::from_resource(&output));
+                state.color_management_state().outputs.push(object);
+            }
+            wp_color_manager_v1::Request::GetSurface { id, surface } => {
+                data_init.init(id, surface.downgrade());
+                let exists = compositor::with_states(&surface, |states| {
+                    states
+                        .data_map
+                        .insert_if_missing_threadsafe(ColorManagedSurface::default);
+                    states
+                        .data_map
+                        .get::<ColorManagedSurface>()
+                        .unwrap()
+                        .0
+                        .swap(true, Ordering::SeqCst)
+                });
+                if exists {
+                    manager.post_error(
+                        wp_color_manager_v1::Error::SurfaceExists,
+                        "surface already has a color management surface",
+                    );
+                }
+            }
+            wp_color_manager_v1::Request::GetSurfaceFeedback { id, surface } => {
+                let feedback = data_init.init(id, surface.downgrade());
+                state.color_management_state().feedbacks.push(feedback);
+            }
+            wp_color_manager_v1::Request::CreateIccCreator { obj } => {
+                data_init.init(obj, ());
+                manager.post_error(
+                    wp_color_manager_v1::Error::UnsupportedFeature,
+                    "icc profiles are not supported",
+                );
+            }
+            wp_color_manager_v1::Request::CreateParametricCreator { obj } => {
+                data_init.init(obj, CreatorData::default());
+            }
+            wp_color_manager_v1::Request::CreateWindowsScrgb { image_description } => {
+                data_init.init(
+                    image_description,
+                    ImageDescriptionData {
+                        description: None,
+                        allow_information: false,
+                    },
+                );
+                manager.post_error(
+                    wp_color_manager_v1::Error::UnsupportedFeature,
+                    "windows scRGB is not supported",
+                );
+            }
+            wp_color_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<D> Dispatch<WpColorManagementOutputV1, Option<Output>, D> for ColorManagementState
+where
+    D: Dispatch<WpColorManagementOutputV1, Option<Output>>
+        + Dispatch<WpImageDescriptionV1, ImageDescriptionData>
+        + ColorManagementHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        _object: &WpColorManagementOutputV1,
+        request: wp_color_management_output_v1::Request,
+        data: &Option<Output>,
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            wp_color_management_output_v1::Request::GetImageDescription { image_description } => {
+                let description = data.as_ref().map(OutputImageDescription::get);
+                let image_description = data_init.init(
+                    image_description,
+                    ImageDescriptionData {
+                        description,
+                        allow_information: true,
+                    },
+                );
+                match description {
+                    Some(description) => {
+                        let identity = state.color_management_state().identity(description);
+                        image_description.ready(identity);
+                    }
+                    None => image_description.failed(
+                        wp_image_description_v1::Cause::NoOutput,
+                        "the output is gone".into(),
+                    ),
+                }
+            }
+            wp_color_management_output_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(
+        state: &mut D,
+        _client: ClientId,
+        object: &WpColorManagementOutputV1,
+        _data: &Option<Output>,
+    ) {
+        state.color_management_state().outputs.retain(|o| o != object);
+    }
+}
+
+impl<D> Dispatch<WpColorManagementSurfaceV1, Weak<WlSurface>, D> for ColorManagementState
+where
+    D: Dispatch<WpColorManagementSurfaceV1, Weak<WlSurface>> + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        object: &WpColorManagementSurfaceV1,
+        request: wp_color_management_surface_v1::Request,
+        data: &Weak<WlSurface>,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        let Ok(surface) = data.upgrade() else {
+            if !matches!(request, wp_color_management_surface_v1::Request::Destroy) {
+                object.post_error(
+                    wp_color_management_surface_v1::Error::Inert,
+                    "the surface was destroyed",
+                );
+            }
+            return;
+        };
+
+        let description = match request {
+            wp_color_management_surface_v1::Request::SetImageDescription {
+                image_description,
+                render_intent,
+            } => {
+                if render_intent != WEnum::Value(RenderIntent::Perceptual) {
+                    object.post_error(
+                        wp_color_management_surface_v1::Error::RenderIntent,
+                        "only the perceptual render intent is supported",
+                    );
+                    return;
+                }
+                let Some(description) = image_description
+                    .data::<ImageDescriptionData>()
+                    .and_then(|data| data.description)
+                else {
+                    object.post_error(
+                        wp_color_management_surface_v1::Error::ImageDescription,
+                        "the image description failed",
+                    );
+                    return;
+                };
+                Some(description)
+            }
+            wp_color_management_surface_v1::Request::UnsetImageDescription => None,
+            wp_color_management_surface_v1::Request::Destroy => {
+                compositor::with_states(&surface, |states| {
+                    if let Some(marker) = states.data_map.get::<ColorManagedSurface>() {
+                        marker.0.store(false, Ordering::SeqCst);
+                    }
+                });
+                None
+            }
+            _ => unreachable!(),
+        };
+
+        compositor::with_states(&surface, |states| {
+            states
+                .cached_state
+                .get::<SurfaceColorState>()
+                .pending()
+                .description = description;
+        });
+    }
+}
+
+impl<D> Dispatch<WpColorManagementSurfaceFeedbackV1, Weak<WlSurface>, D> for ColorManagementState
+where
+    D: Dispatch<WpColorManagementSurfaceFeedbackV1, Weak<WlSurface>>
+        + Dispatch<WpImageDescriptionV1, ImageDescriptionData>
+        + ColorManagementHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        feedback: &WpColorManagementSurfaceFeedbackV1,
+        request: wp_color_management_surface_feedback_v1::Request,
+        data: &Weak<WlSurface>,
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        let image_description = match request {
+            wp_color_management_surface_feedback_v1::Request::GetPreferred { image_description }
+            | wp_color_management_surface_feedback_v1::Request::GetPreferredParametric {
+                image_description,
+            } => image_description,
+            wp_color_management_surface_feedback_v1::Request::Destroy => return,
+            _ => unreachable!(),
+        };
+
+        let Ok(surface) = data.upgrade() else {
+            data_init.init(
+                image_description,
+                ImageDescriptionData {
+                    description: None,
+                    allow_information: false,
+                },
+            );
+            feedback.post_error(
+                wp_color_management_surface_feedback_v1::Error::Inert,
+                "the surface was destroyed",
+            );
+            return;
+        };
+        let description = state.preferred_image_description(&surface);
+        let identity = state.color_management_state().identity(description);
+        let image_description = data_init.init(
+            image_description,
+            ImageDescriptionData {
+                description: Some(description),
+                allow_information: true,
+            },
+        );
+        image_description.ready(identity);
+    }
+
+    fn destroyed(
+        state: &mut D,
+        _client: ClientId,
+        feedback: &WpColorManagementSurfaceFeedbackV1,
+        _data: &Weak<WlSurface>,
+    ) {
+        state.color_management_state().feedbacks.retain(|f| f != feedback);
+    }
+}
+
+impl<D> Dispatch<WpImageDescriptionCreatorIccV1, (), D> for ColorManagementState
+where
+    D: Dispatch<WpImageDescriptionCreatorIccV1, ()> + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        _creator: &WpImageDescriptionCreatorIccV1,
+        _request: wp_image_description_creator_icc_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        // never handed out successfully, creating it is a protocol error
+    }
+}
+
+impl<D> Dispatch<WpImageDescriptionCreatorParamsV1, CreatorData, D> for ColorManagementState
+where
+    D: Dispatch<WpImageDescriptionCreatorParamsV1, CreatorData>
+        + Dispatch<WpImageDescriptionV1, ImageDescriptionData>
+        + ColorManagementHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        creator: &WpImageDescriptionCreatorParamsV1,
+        request: wp_image_description_creator_params_v1::Request,
+        data: &CreatorData,
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        use wp_image_description_creator_params_v1::{Error, Request};
+
+        let mut pending = data.0.lock().unwrap();
+        let already_set = match request {
+            Request::Create { image_description } => {
+                let description = match (pending.tf, pending.primaries) {
+                    (Some(tf), Some(primaries)) => ImageDescription {
+                        tf,
+                        primaries,
+                        luminances: pending.luminances,
+                        max_cll: pending.max_cll,
+                        max_fall: pending.max_fall,
+                    },
+                    _ => {
+                        data_init.init(
+                            image_description,
+                            ImageDescriptionData {
+                                description: None,
+                                allow_information: false,
+                            },
+                        );
+                        creator.post_error(
+                            Error::IncompleteSet,
+                            "transfer function and primaries are required",
+                        );
+                        return;
+                    }
+                };
+                let image_description = data_init.init(
+                    image_description,
+                    ImageDescriptionData {
+                        description: Some(description),
+                        allow_information: false,
+                    },
+                );
+                image_description.ready(state.color_management_state().new_identity());
+                return;
+            }
+            Request::SetTfNamed { tf } => {
+                let tf = match tf {
+                    WEnum::Value(tf) if TRANSFER_FUNCTIONS.contains(&tf) => tf,
+                    _ => {
+                        creator.post_error(Error::InvalidTf, "unsupported transfer function");
+                        return;
+                    }
+                };
+                pending.tf.replace(tf).is_some()
+            }
+            Request::SetPrimariesNamed { primaries } => {
+                let primaries = match primaries {
+                    WEnum::Value(primaries) if PRIMARIES.contains(&primaries) => primaries,
+                    _ => {
+                        creator.post_error(Error::InvalidPrimariesNamed, "unsupported primaries");
+                        return;
+                    }
+                };
+                pending.primaries.replace(primaries).is_some()
+            }
+            Request::SetLuminances {
+                min_lum,
+                max_lum,
+                reference_lum,
+            } => {
+                if max_lum as u64 * 10000 <= min_lum as u64 || reference_lum as u64 * 10000 <= min_lum as u64
+                {
+                    creator.post_error(
+                        Error::InvalidLuminance,
+                        "maximum and reference luminance must be above the minimum",
+                    );
+                    return;
+                }
+                pending
+                    .luminances
+                    .replace(Luminances {
+                        min: min_lum,
+                        max: max_lum,
+                        reference: reference_lum,
+                    })
+                    .is_some()
+            }
+            Request::SetMaxCll { max_cll } => pending.max_cll.replace(max_cll).is_some(),
+            Request::SetMaxFall { max_fall } => pending.max_fall.replace(max_fall).is_some(),
+            // only informative, nothing to adapt to yet
+            Request::SetMasteringLuminance { .. } => false,
+            Request::SetTfPower { .. }
+            | Request::SetPrimaries { .. }
+            | Request::SetMasteringDisplayPrimaries { .. } => {
+                creator.post_error(Error::UnsupportedFeature, "only named parameters are supported");
+                return;
+            }
+            _ => unreachable!(),
+        };
+
+        if already_set {
+            creator.post_error(Error::AlreadySet, "parameter was already set");
+        }
+    }
+}
+
+impl<D> Dispatch<WpImageDescriptionV1, ImageDescriptionData, D> for ColorManagementState
+where
+    D: Dispatch<WpImageDescriptionV1, ImageDescriptionData>
+        + Dispatch<WpImageDescriptionInfoV1, ()>
+        + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        image_description: &WpImageDescriptionV1,
+        request: wp_image_description_v1::Request,
+        data: &ImageDescriptionData,
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            wp_image_description_v1::Request::GetInformation { information } => {
+                let information = data_init.init(information, ());
+                let Some(description) = data.description else {
+                    image_description.post_error(
+                        wp_image_description_v1::Error::NotReady,
+                        "the image description failed",
+                    );
+                    return;
+                };
+                if !data.allow_information {
+                    image_description.post_error(
+                        wp_image_description_v1::Error::NoInformation,
+                        "only compositor provided descriptions can be queried",
+                    );
+                    return;
+                }
+
+                let [r, g, b, w] = chromaticities(description.primaries)
+                    .map(|[x, y]| [(x * 1_000_000.0).round() as i32, (y * 1_000_000.0).round() as i32]);
+                information.primaries(r[0], r[1], g[0], g[1], b[0], b[1], w[0], w[1]);
+                information.primaries_named(description.primaries);
+                information.tf_named(description.tf);
+                let luminances = description.luminances();
+                information.luminances(luminances.min, luminances.max, luminances.reference);
+                information.target_primaries(r[0], r[1], g[0], g[1], b[0], b[1], w[0], w[1]);
+                information.target_luminance(luminances.min, luminances.max);
+                if let Some(max_cll) = description.max_cll {
+                    information.target_max_cll(max_cll);
+                }
+                if let Some(max_fall) = description.max_fall {
+                    information.target_max_fall(max_fall);
+                }
+                information.done();
+            }
+            wp_image_description_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<D> Dispatch<WpImageDescriptionInfoV1, (), D> for ColorManagementState
+where
+    D: Dispatch<WpImageDescriptionInfoV1, ()> + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        _information: &WpImageDescriptionInfoV1,
+        _request: wp_image_description_info_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        // has no requests
+    }
+}
+
+#[macro_export]
+macro_rules! delegate_color_management {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::wp::color_management::v1::server::wp_color_manager_v1::WpColorManagerV1: ()
+        ] => $crate::protocols::color_management::ColorManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::wp::color_management::v1::server::wp_color_manager_v1::WpColorManagerV1: ()
+        ] => $crate::protocols::color_management::ColorManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::wp::color_management::v1::server::wp_color_management_output_v1::WpColorManagementOutputV1: Option<smithay::output::Output>
+        ] => $crate::protocols::color_management::ColorManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::wp::color_management::v1::server::wp_color_management_surface_v1::WpColorManagementSurfaceV1: smithay::reexports::wayland_server::Weak<smithay::reexports::wayland_server::protocol::wl_surface::WlSurface>
+        ] => $crate::protocols::color_management::ColorManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::wp::color_management::v1::server::wp_color_management_surface_feedback_v1::WpColorManagementSurfaceFeedbackV1: smithay::reexports::wayland_server::Weak<smithay::reexports::wayland_server::protocol::wl_surface::WlSurface>
+        ] => $crate::protocols::color_management::ColorManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::wp::color_management::v1::server::wp_image_description_creator_icc_v1::WpImageDescriptionCreatorIccV1: ()
+        ] => $crate::protocols::color_management::ColorManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::wp::color_management::v1::server::wp_image_description_creator_params_v1::WpImageDescriptionCreatorParamsV1: $crate::protocols::color_management::CreatorData
+        ] => $crate::protocols::color_management::ColorManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::wp::color_management::v1::server::wp_image_description_v1::WpImageDescriptionV1: $crate::protocols::color_management::ImageDescriptionData
+        ] => $crate::protocols::color_management::ColorManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::wp::color_management::v1::server::wp_image_description_info_v1::WpImageDescriptionInfoV1: ()
+        ] => $crate::protocols::color_management::ColorManagementState);
+    };
+}
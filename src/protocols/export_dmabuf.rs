@@ -0,0 +1,232 @@
+//! wlr-export-dmabuf-unstable-v1
+//!
+//! Lets clients like OBS continuously capture an output without a readback into client memory.
+//! Every capture request becomes a [`DmabufExport`] handed to the [`ExportDmabufHandler`], which
+//! is expected to render the output into a compositor-owned dmabuf and export it on the next repaint.
+
+use std::{io::SeekFrom, time::Duration};
+
+use super::GlobalFilter;
+use smithay::{
+    backend::allocator::{dmabuf::Dmabuf, Buffer},
+    output::Output,
+    reexports::{
+        rustix,
+        wayland_protocols_wlr::export_dmabuf::v1::server::{
+            zwlr_export_dmabuf_frame_v1::{self, ZwlrExportDmabufFrameV1},
+            zwlr_export_dmabuf_manager_v1::{self, ZwlrExportDmabufManagerV1},
+        },
+        wayland_server::{backend::GlobalId, Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New},
+    },
+};
+
+const VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub struct ExportDmabufManagerState {
+    global: GlobalId,
+}
+
+impl ExportDmabufManagerState {
+    /// Create the global, only advertised to clients `filter` returns true for
+    pub fn new<D, F>(display: &DisplayHandle, filter: F) -> Self
+    where
+        D: GlobalDispatch<ZwlrExportDmabufManagerV1, GlobalFilter>
+            + Dispatch<ZwlrExportDmabufManagerV1, ()>
+            + Dispatch<ZwlrExportDmabufFrameV1, ()>
+            + ExportDmabufHandler
+            + 'static,
+        F: Fn(&Client) -> bool + Send + Sync + 'static,
+    {
+        let global =
+            display.create_global::<D, ZwlrExportDmabufManagerV1, _>(VERSION, GlobalFilter::new(filter));
+        ExportDmabufManagerState { global }
+    }
+
+    pub fn global(&self) -> GlobalId {
+        self.global.clone()
+    }
+}
+
+pub trait ExportDmabufHandler {
+    /// A client wants to capture [`DmabufExport::output`], export it on the next repaint
+    fn capture(&mut self, export: DmabufExport);
+}
+
+/// A pending capture of an output
+///
+/// Dropping it without calling [`DmabufExport::export`] cancels the frame, clients are
+/// expected to retry.
+#[derive(Debug)]
+pub struct DmabufExport {
+    frame: ZwlrExportDmabufFrameV1,
+    output: Output,
+    overlay_cursor: bool,
+    exported: bool,
+}
+
+impl DmabufExport {
+    pub fn output(&self) -> &Output {
+        &self.output
+    }
+
+    /// Whether the cursor should be part of the frame
+    pub fn overlay_cursor(&self) -> bool {
+        self.overlay_cursor
+    }
+
+    /// The frame the dmabuf is handed out with, the client destroys it once it is done with the
+    /// buffer
+    pub fn frame(&self) -> &ZwlrExportDmabufFrameV1 {
+        &self.frame
+    }
+
+    /// Hand `dmabuf`, containing the contents of the output at `timestamp`, to the client
+    ///
+    /// The compositor must not render into `dmabuf` again until the client destroyed the
+    /// [`DmabufExport::frame`]; as clients usually request the next frame right away, the next
+    /// capture needs another buffer.
+    pub fn export(mut self, dmabuf: &Dmabuf, timestamp: Duration) {
+        let size = dmabuf.size();
+        let format = dmabuf.format();
+        let modifier = u64::from(format.modifier);
+        self.frame.frame(
+            size.w as u32,
+            size.h as u32,
+            0,
+            0,
+            if dmabuf.y_inverted() { 1 } else { 0 },
+            zwlr_export_dmabuf_frame_v1::Flags::Transient,
+            format.code as u32,
+            (modifier >> 32) as u32,
+            modifier as u32,
+            dmabuf.num_planes() as u32,
+        );
+        for (index, ((fd, offset), stride)) in dmabuf
+            .handles()
+            .zip(dmabuf.offsets())
+            .zip(dmabuf.strides())
+            .enumerate()
+        {
+            let object_size = rustix::fs::seek(fd, SeekFrom::End(0)).unwrap_or(0) as u32;
+            self.frame
+                .object(index as u32, fd, object_size, offset, stride, index as u32);
+        }
+        let secs = timestamp.as_secs();
+        self.frame
+            .ready((secs >> 32) as u32, secs as u32, timestamp.subsec_nanos());
+        self.exported = true;
+    }
+}
+
+impl Drop for DmabufExport {
+    fn drop(&mut self) {
+        if !self.exported {
+            self.frame
+                .cancel(zwlr_export_dmabuf_frame_v1::CancelReason::Temporary);
+        }
+    }
+}
+
+impl<D> GlobalDispatch<ZwlrExportDmabufManagerV1, GlobalFilter, D> for ExportDmabufManagerState
+where
+    D: GlobalDispatch<ZwlrExportDmabufManagerV1, GlobalFilter>
+        + Dispatch<ZwlrExportDmabufManagerV1, ()>
+        + Dispatch<ZwlrExportDmabufFrameV1, ()>
+        + ExportDmabufHandler
+        + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        manager: New<ZwlrExportDmabufManagerV1>,
+        _global_data: &GlobalFilter,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(manager, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalFilter) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl<D> Dispatch<ZwlrExportDmabufManagerV1, (), D> for ExportDmabufManagerState
+where
+    D: GlobalDispatch<ZwlrExportDmabufManagerV1, GlobalFilter>
+        + Dispatch<ZwlrExportDmabufManagerV1, ()>
+        + Dispatch<ZwlrExportDmabufFrameV1, ()>
+        + ExportDmabufHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        _manager: &ZwlrExportDmabufManagerV1,
+        request: zwlr_export_dmabuf_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwlr_export_dmabuf_manager_v1::Request::CaptureOutput {
+                frame,
+                overlay_cursor,
+                output,
+            } => {
+                let frame = data_init.init(frame, ());
+                let Some(output) = Output::from_resource(&output).filter(|o| o.current_mode().is_some())
+                else {
+                    frame.cancel(zwlr_export_dmabuf_frame_v1::CancelReason::Permanent);
+                    return;
+                };
+
+                state.capture(DmabufExport {
+                    frame,
+                    output,
+                    overlay_cursor: overlay_cursor != 0,
+                    exported: false,
+                });
+            }
+            zwlr_export_dmabuf_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<D> Dispatch<ZwlrExportDmabufFrameV1, (), D> for ExportDmabufManagerState
+where
+    D: GlobalDispatch<ZwlrExportDmabufManagerV1, GlobalFilter>
+        + Dispatch<ZwlrExportDmabufManagerV1, ()>
+        + Dispatch<ZwlrExportDmabufFrameV1, ()>
+        + ExportDmabufHandler
+        + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        _frame: &ZwlrExportDmabufFrameV1,
+        _request: zwlr_export_dmabuf_frame_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        // the frame only has a destructor
+    }
+}
+
+#[macro_export]
+macro_rules! delegate_export_dmabuf {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::export_dmabuf::v1::server::zwlr_export_dmabuf_manager_v1::ZwlrExportDmabufManagerV1: $crate::protocols::GlobalFilter
+        ] => $crate::protocols::export_dmabuf::ExportDmabufManagerState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::export_dmabuf::v1::server::zwlr_export_dmabuf_manager_v1::ZwlrExportDmabufManagerV1: ()
+        ] => $crate::protocols::export_dmabuf::ExportDmabufManagerState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::export_dmabuf::v1::server::zwlr_export_dmabuf_frame_v1::ZwlrExportDmabufFrameV1: ()
+        ] => $crate::protocols::export_dmabuf::ExportDmabufManagerState);
+    };
+}
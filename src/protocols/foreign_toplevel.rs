@@ -0,0 +1,318 @@
+//! wlr-foreign-toplevel-management-unstable-v1
+//!
+//! Advertises every window to taskbars and docks with its title, app_id, outputs and state,
+//! and forwards their requests to activate, close, minimize, maximize or fullscreen a window
+//! to the [`ForeignToplevelHandler`]. Windows are identified by an opaque id, the compositor
+//! calls [`ForeignToplevelManagerState::update`] with the current list whenever it may have changed.
+
+use super::GlobalFilter;
+use smithay::{
+    output::Output,
+    reexports::{
+        wayland_protocols_wlr::foreign_toplevel::v1::server::{
+            zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+            zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+        },
+        wayland_server::{
+            backend::{ClientId, GlobalId},
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+        },
+    },
+};
+
+const VERSION: u32 = 3;
+
+/// Everything advertised about a single window
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ToplevelInfo {
+    pub title: String,
+    pub app_id: String,
+    pub outputs: Vec<Output>,
+    pub activated: bool,
+    pub maximized: bool,
+    pub minimized: bool,
+    pub fullscreen: bool,
+}
+
+impl ToplevelInfo {
+    fn states(&self, version: u32) -> Vec<u8> {
+        let states = [
+            (self.maximized, zwlr_foreign_toplevel_handle_v1::State::Maximized),
+            (self.minimized, zwlr_foreign_toplevel_handle_v1::State::Minimized),
+            (self.activated, zwlr_foreign_toplevel_handle_v1::State::Activated),
+            (
+                self.fullscreen && version >= 2,
+                zwlr_foreign_toplevel_handle_v1::State::Fullscreen,
+            ),
+        ];
+        states
+            .into_iter()
+            .filter(|(set, _)| *set)
+            .flat_map(|(_, state)| (state as u32).to_ne_bytes())
+            .collect()
+    }
+}
+
+pub trait ForeignToplevelHandler {
+    fn foreign_toplevel_manager_state(&mut self) -> &mut ForeignToplevelManagerState;
+
+    /// Focus the window, switching to its workspace if necessary
+    fn activate(&mut self, window: u64);
+    /// Ask the window to close itself
+    fn close(&mut self, window: u64);
+    fn set_maximized(&mut self, window: u64, maximized: bool);
+    fn set_minimized(&mut self, window: u64, minimized: bool);
+    /// `output` is the one the client would like the window to be fullscreen on, if any
+    fn set_fullscreen(&mut self, window: u64, fullscreen: bool, output: Option<Output>);
+}
+
+#[derive(Debug)]
+struct Toplevel {
+    id: u64,
+    info: ToplevelInfo,
+    handles: Vec<ZwlrForeignToplevelHandleV1>,
+}
+
+#[derive(Debug)]
+pub struct ForeignToplevelManagerState {
+    global: GlobalId,
+    managers: Vec<ZwlrForeignToplevelManagerV1>,
+    toplevels: Vec<Toplevel>,
+}
+
+impl ForeignToplevelManagerState {
+    /// Create the global, only advertised to clients `filter` returns true for
+    pub fn new<D, F>(display: &DisplayHandle, filter: F) -> Self
+    where
+        D: GlobalDispatch<ZwlrForeignToplevelManagerV1, GlobalFilter>
+            + Dispatch<ZwlrForeignToplevelManagerV1, ()>
+            + Dispatch<ZwlrForeignToplevelHandleV1, u64>
+            + ForeignToplevelHandler
+            + 'static,
+        F: Fn(&Client) -> bool + Send + Sync + 'static,
+    {
+        let global =
+            display.create_global::<D, ZwlrForeignToplevelManagerV1, _>(VERSION, GlobalFilter::new(filter));
+        ForeignToplevelManagerState {
+            global,
+            managers: Vec::new(),
+            toplevels: Vec::new(),
+        }
+    }
+
+    pub fn global(&self) -> GlobalId {
+        self.global.clone()
+    }
+
+    /// Set the current list of windows, announcing new ones, sending changes of existing ones
+    /// and closing the handles of windows that are gone.
+    pub fn update<D>(&mut self, dh: &DisplayHandle, toplevels: Vec<(u64, ToplevelInfo)>)
+    where
+        D: Dispatch<ZwlrForeignToplevelHandleV1, u64> + 'static,
+    {
+        self.toplevels.retain(|toplevel| {
+            let keep = toplevels.iter().any(|(id, _)| *id == toplevel.id);
+            if !keep {
+                for handle in &toplevel.handles {
+                    handle.closed();
+                }
+            }
+            keep
+        });
+
+        for (id, info) in toplevels {
+            match self.toplevels.iter_mut().find(|toplevel| toplevel.id == id) {
+                Some(toplevel) => {
+                    if toplevel.info == info {
+                        continue;
+                    }
+                    for handle in &toplevel.handles {
+                        send_changes(dh, handle, Some(&toplevel.info), &info);
+                    }
+                    toplevel.info = info;
+                }
+                None => {
+                    let handles = self
+                        .managers
+                        .iter()
+                        .filter_map(|manager| announce::<D>(dh, manager, id, &info))
+                        .collect();
+                    self.toplevels.push(Toplevel { id, info, handles });
+                }
+            }
+        }
+    }
+}
+
+/// Create a handle for a window on `manager` and send its initial state
+fn announce<D>(
+    dh: &DisplayHandle,
+    manager: &ZwlrForeignToplevelManagerV1,
+    id: u64,
+    info: &ToplevelInfo,
+) -> Option<ZwlrForeignToplevelHandleV1>
+where
+    D: Dispatch<ZwlrForeignToplevelHandleV1, u64> + 'static,
+{
+    let client = dh.get_client(manager.id()).ok()?;
+    let handle = client
+        .create_resource::<ZwlrForeignToplevelHandleV1, _, D>(dh, manager.version(), id)
+        .ok()?;
+    manager.toplevel(&handle);
+    send_changes(dh, &handle, None, info);
+    Some(handle)
+}
+
+/// Send everything that differs between `old` and `new`, or all of `new` for a fresh handle
+fn send_changes(
+    dh: &DisplayHandle,
+    handle: &ZwlrForeignToplevelHandleV1,
+    old: Option<&ToplevelInfo>,
+    new: &ToplevelInfo,
+) {
+    if old.is_none_or(|old| old.title != new.title) {
+        handle.title(new.title.clone());
+    }
+    if old.is_none_or(|old| old.app_id != new.app_id) {
+        handle.app_id(new.app_id.clone());
+    }
+
+    if let Ok(client) = dh.get_client(handle.id()) {
+        let old_outputs = old.map(|old| old.outputs.as_slice()).unwrap_or_default();
+        for output in old_outputs.iter().filter(|output| !new.outputs.contains(output)) {
+            for wl_output in output.client_outputs(&client) {
+                handle.output_leave(&wl_output);
+            }
+        }
+        for output in new.outputs.iter().filter(|output| !old_outputs.contains(output)) {
+            for wl_output in output.client_outputs(&client) {
+                handle.output_enter(&wl_output);
+            }
+        }
+    }
+
+    let version = handle.version();
+    if old.is_none_or(|old| old.states(version) != new.states(version)) {
+        handle.state(new.states(version));
+    }
+    handle.done();
+}
+
+impl<D> GlobalDispatch<ZwlrForeignToplevelManagerV1, GlobalFilter, D> for ForeignToplevelManagerState
+where
+    D: GlobalDispatch<ZwlrForeignToplevelManagerV1, GlobalFilter>
+        + Dispatch<ZwlrForeignToplevelManagerV1, ()>
+        + Dispatch<ZwlrForeignToplevelHandleV1, u64>
+        + ForeignToplevelHandler
+        + 'static,
+{
+    fn bind(
+        state: &mut D,
+        handle: &DisplayHandle,
+        _client: &Client,
+        manager: New<ZwlrForeignToplevelManagerV1>,
+        _global_data: &GlobalFilter,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        let manager = data_init.init(manager, ());
+        let state = state.foreign_toplevel_manager_state();
+        for toplevel in &mut state.toplevels {
+            if let Some(toplevel_handle) = announce::<D>(handle, &manager, toplevel.id, &toplevel.info) {
+                toplevel.handles.push(toplevel_handle);
+            }
+        }
+        state.managers.push(manager);
+    }
+
+    fn can_view(client: Client, global_data: &GlobalFilter) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl<D> Dispatch<ZwlrForeignToplevelManagerV1, (), D> for ForeignToplevelManagerState
+where
+    D: Dispatch<ZwlrForeignToplevelManagerV1, ()> + ForeignToplevelHandler + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        manager: &ZwlrForeignToplevelManagerV1,
+        request: zwlr_foreign_toplevel_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwlr_foreign_toplevel_manager_v1::Request::Stop => {
+                let state = state.foreign_toplevel_manager_state();
+                state.managers.retain(|m| m != manager);
+                manager.finished();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut D, _client: ClientId, manager: &ZwlrForeignToplevelManagerV1, _data: &()) {
+        state
+            .foreign_toplevel_manager_state()
+            .managers
+            .retain(|m| m != manager);
+    }
+}
+
+impl<D> Dispatch<ZwlrForeignToplevelHandleV1, u64, D> for ForeignToplevelManagerState
+where
+    D: Dispatch<ZwlrForeignToplevelHandleV1, u64> + ForeignToplevelHandler + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        _handle: &ZwlrForeignToplevelHandleV1,
+        request: zwlr_foreign_toplevel_handle_v1::Request,
+        data: &u64,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        let window = *data;
+        match request {
+            zwlr_foreign_toplevel_handle_v1::Request::SetMaximized => state.set_maximized(window, true),
+            zwlr_foreign_toplevel_handle_v1::Request::UnsetMaximized => state.set_maximized(window, false),
+            zwlr_foreign_toplevel_handle_v1::Request::SetMinimized => state.set_minimized(window, true),
+            zwlr_foreign_toplevel_handle_v1::Request::UnsetMinimized => state.set_minimized(window, false),
+            zwlr_foreign_toplevel_handle_v1::Request::Activate { .. } => state.activate(window),
+            zwlr_foreign_toplevel_handle_v1::Request::Close => state.close(window),
+            zwlr_foreign_toplevel_handle_v1::Request::SetFullscreen { output } => {
+                let output = output.as_ref().and_then(Output::from_resource);
+                state.set_fullscreen(window, true, output);
+            }
+            zwlr_foreign_toplevel_handle_v1::Request::UnsetFullscreen => {
+                state.set_fullscreen(window, false, None)
+            }
+            // only used for minimize animations
+            zwlr_foreign_toplevel_handle_v1::Request::SetRectangle { .. } => {}
+            zwlr_foreign_toplevel_handle_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut D, _client: ClientId, handle: &ZwlrForeignToplevelHandleV1, _data: &u64) {
+        for toplevel in &mut state.foreign_toplevel_manager_state().toplevels {
+            toplevel.handles.retain(|h| h != handle);
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! delegate_foreign_toplevel {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::foreign_toplevel::v1::server::zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1: $crate::protocols::GlobalFilter
+        ] => $crate::protocols::foreign_toplevel::ForeignToplevelManagerState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::foreign_toplevel::v1::server::zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1: ()
+        ] => $crate::protocols::foreign_toplevel::ForeignToplevelManagerState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::foreign_toplevel::v1::server::zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1: u64
+        ] => $crate::protocols::foreign_toplevel::ForeignToplevelManagerState);
+    };
+}
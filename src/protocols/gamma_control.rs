@@ -0,0 +1,254 @@
+//! wlr-gamma-control-unstable-v1
+//!
+//! Lets tools like gammastep set the gamma ramps of outputs. Only one client at a time can
+//! control an output, the ramps are handed to the [`GammaControlHandler`] and reset once the
+//! client is done. Backends call [`GammaControlManagerState::output_removed`] for outputs that
+//! go away.
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    os::fd::OwnedFd,
+};
+
+use super::GlobalFilter;
+use smithay::{
+    output::Output,
+    reexports::{
+        wayland_protocols_wlr::gamma_control::v1::server::{
+            zwlr_gamma_control_manager_v1::{self, ZwlrGammaControlManagerV1},
+            zwlr_gamma_control_v1::{self, ZwlrGammaControlV1},
+        },
+        rustix::fs::{fcntl_getfl, fcntl_setfl, OFlags},
+        wayland_server::{
+            backend::{ClientId, GlobalId},
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+        },
+    },
+};
+use tracing::debug;
+
+const VERSION: u32 = 1;
+
+pub trait GammaControlHandler {
+    fn gamma_control_state(&mut self) -> &mut GammaControlManagerState;
+
+    /// Number of entries per channel of the gamma ramp of `output`, `None` if it has none
+    fn gamma_size(&mut self, output: &Output) -> Option<u32>;
+    /// Set the gamma ramp of `output`, holding the red, green and blue channels after each other,
+    /// or restore the default one with `None`. Returns whether it succeeded.
+    fn set_gamma(&mut self, output: &Output, ramp: Option<Vec<u16>>) -> bool;
+}
+
+#[derive(Debug)]
+struct GammaControl {
+    control: ZwlrGammaControlV1,
+    output: Output,
+    size: u32,
+}
+
+#[derive(Debug)]
+pub struct GammaControlManagerState {
+    global: GlobalId,
+    controls: Vec<GammaControl>,
+}
+
+impl GammaControlManagerState {
+    /// Create the global, only advertised to clients `filter` returns true for
+    pub fn new<D, F>(display: &DisplayHandle, filter: F) -> Self
+    where
+        D: GlobalDispatch<ZwlrGammaControlManagerV1, GlobalFilter>
+            + Dispatch<ZwlrGammaControlManagerV1, ()>
+            + Dispatch<ZwlrGammaControlV1, ()>
+            + GammaControlHandler
+            + 'static,
+        F: Fn(&Client) -> bool + Send + Sync + 'static,
+    {
+        let global =
+            display.create_global::<D, ZwlrGammaControlManagerV1, _>(VERSION, GlobalFilter::new(filter));
+        GammaControlManagerState {
+            global,
+            controls: Vec::new(),
+        }
+    }
+
+    pub fn global(&self) -> GlobalId {
+        self.global.clone()
+    }
+
+    /// Whether a client currently controls the gamma ramp of `output`
+    pub fn is_controlled(&self, output: &Output) -> bool {
+        self.controls.iter().any(|control| &control.output == output)
+    }
+
+    /// Fail the control of `output`, which doesn't exist anymore
+    pub fn output_removed(&mut self, output: &Output) {
+        self.controls.retain(|control| {
+            let keep = &control.output != output;
+            if !keep {
+                control.control.failed();
+            }
+            keep
+        });
+    }
+}
+
+impl<D> GlobalDispatch<ZwlrGammaControlManagerV1, GlobalFilter, D> for GammaControlManagerState
+where
+    D: GlobalDispatch<ZwlrGammaControlManagerV1, GlobalFilter>
+        + Dispatch<ZwlrGammaControlManagerV1, ()>
+        + Dispatch<ZwlrGammaControlV1, ()>
+        + GammaControlHandler
+        + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        manager: New<ZwlrGammaControlManagerV1>,
+        _global_data: &GlobalFilter,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(manager, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalFilter) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl<D> Dispatch<ZwlrGammaControlManagerV1, (), D> for GammaControlManagerState
+where
+    D: Dispatch<ZwlrGammaControlManagerV1, ()>
+        + Dispatch<ZwlrGammaControlV1, ()>
+        + GammaControlHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        _manager: &ZwlrGammaControlManagerV1,
+        request: zwlr_gamma_control_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwlr_gamma_control_manager_v1::Request::GetGammaControl { id, output } => {
+                let control = data_init.init(id, ());
+                let Some(output) = Output::from_resource(&output) else {
+                    control.failed();
+                    return;
+                };
+                if state.gamma_control_state().is_controlled(&output) {
+                    debug!(
+                        output = output.name(),
+                        "Gamma ramp is already controlled by a client"
+                    );
+                    control.failed();
+                    return;
+                }
+                let Some(size) = state.gamma_size(&output) else {
+                    control.failed();
+                    return;
+                };
+
+                control.gamma_size(size);
+                state.gamma_control_state().controls.push(GammaControl {
+                    control,
+                    output,
+                    size,
+                });
+            }
+            zwlr_gamma_control_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<D> Dispatch<ZwlrGammaControlV1, (), D> for GammaControlManagerState
+where
+    D: Dispatch<ZwlrGammaControlV1, ()> + GammaControlHandler + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        control: &ZwlrGammaControlV1,
+        request: zwlr_gamma_control_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwlr_gamma_control_v1::Request::SetGamma { fd } => {
+                let Some((output, size)) = state
+                    .gamma_control_state()
+                    .controls
+                    .iter()
+                    .find(|c| &c.control == control)
+                    .map(|c| (c.output.clone(), c.size))
+                else {
+                    // the control already failed
+                    return;
+                };
+
+                let mut bytes = vec![0; size as usize * 3 * 2];
+                // a ramp that isn't written in full yet fails instead of blocking the compositor
+                let ramp = set_nonblocking(&fd).and_then(|()| File::from(fd).read_exact(&mut bytes));
+                let ramp = ramp.map(|()| {
+                    bytes
+                        .chunks_exact(2)
+                        .map(|chunk| u16::from_ne_bytes([chunk[0], chunk[1]]))
+                        .collect::<Vec<_>>()
+                });
+                let applied = match ramp {
+                    Ok(ramp) => state.set_gamma(&output, Some(ramp)),
+                    Err(err) => {
+                        debug!(output = output.name(), "Failed to read gamma ramp: {}", err);
+                        false
+                    }
+                };
+                if !applied {
+                    state
+                        .gamma_control_state()
+                        .controls
+                        .retain(|c| &c.control != control);
+                    state.set_gamma(&output, None);
+                    control.failed();
+                }
+            }
+            zwlr_gamma_control_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut D, _client: ClientId, control: &ZwlrGammaControlV1, _data: &()) {
+        let controls = &mut state.gamma_control_state().controls;
+        let Some(index) = controls.iter().position(|c| &c.control == control) else {
+            return;
+        };
+        let output = controls.remove(index).output;
+        state.set_gamma(&output, None);
+    }
+}
+
+fn set_nonblocking(fd: &OwnedFd) -> io::Result<()> {
+    let flags = fcntl_getfl(fd)?;
+    fcntl_setfl(fd, flags | OFlags::NONBLOCK)?;
+    Ok(())
+}
+
+#[macro_export]
+macro_rules! delegate_gamma_control {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::gamma_control::v1::server::zwlr_gamma_control_manager_v1::ZwlrGammaControlManagerV1: $crate::protocols::GlobalFilter
+        ] => $crate::protocols::gamma_control::GammaControlManagerState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::gamma_control::v1::server::zwlr_gamma_control_manager_v1::ZwlrGammaControlManagerV1: ()
+        ] => $crate::protocols::gamma_control::GammaControlManagerState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::gamma_control::v1::server::zwlr_gamma_control_v1::ZwlrGammaControlV1: ()
+        ] => $crate::protocols::gamma_control::GammaControlManagerState);
+    };
+}
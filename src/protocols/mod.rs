@@ -0,0 +1,27 @@
+//! Protocol implementations not provided by smithay
+
+use smithay::reexports::wayland_server::Client;
+
+pub mod color_management;
+pub mod export_dmabuf;
+pub mod foreign_toplevel;
+pub mod gamma_control;
+pub mod output_management;
+pub mod registry;
+pub mod screencopy;
+
+/// Global data of the privileged protocols, deciding which clients can see the global
+pub struct GlobalFilter(Box<dyn Fn(&Client) -> bool + Send + Sync>);
+
+impl GlobalFilter {
+    pub fn new<F>(filter: F) -> Self
+    where
+        F: Fn(&Client) -> bool + Send + Sync + 'static,
+    {
+        GlobalFilter(Box::new(filter))
+    }
+
+    pub fn can_view(&self, client: &Client) -> bool {
+        (self.0)(client)
+    }
+}
@@ -0,0 +1,627 @@
+//! wlr-output-management-unstable-v1
+//!
+//! Advertises every output (including disabled ones) as a head with its modes and current state,
+//! and lets clients like kanshi or wlr-randr change them. Applying a configuration is up to the
+//! [`OutputManagementHandler`], which has to call [`OutputManagementState::update`] afterwards
+//! and whenever outputs change on their own.
+//!
+//! Outputs only report adaptive sync as enabled if the backend keeps an [`AdaptiveSync`] in their
+//! user data.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+use super::GlobalFilter;
+use smithay::{
+    output::{Mode, Output},
+    reexports::{
+        wayland_protocols_wlr::output_management::v1::server::{
+            zwlr_output_configuration_head_v1::{self, ZwlrOutputConfigurationHeadV1},
+            zwlr_output_configuration_v1::{self, ZwlrOutputConfigurationV1},
+            zwlr_output_head_v1::{self, ZwlrOutputHeadV1},
+            zwlr_output_manager_v1::{self, ZwlrOutputManagerV1},
+            zwlr_output_mode_v1::{self, ZwlrOutputModeV1},
+        },
+        wayland_server::{
+            backend::{ClientId, GlobalId},
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+        },
+    },
+    utils::{Logical, Physical, Point, Size, Transform},
+};
+
+const VERSION: u32 = 4;
+
+/// Whether variable refresh rate is currently active on an output
+#[derive(Debug, Default)]
+pub struct AdaptiveSync(AtomicBool);
+
+impl AdaptiveSync {
+    pub fn get(output: &Output) -> bool {
+        output
+            .user_data()
+            .get::<AdaptiveSync>()
+            .is_some_and(|adaptive_sync| adaptive_sync.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(output: &Output, enabled: bool) {
+        output
+            .user_data()
+            .insert_if_missing_threadsafe(AdaptiveSync::default);
+        output
+            .user_data()
+            .get::<AdaptiveSync>()
+            .unwrap()
+            .0
+            .store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// Requested mode of a head
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModeConfiguration {
+    /// One of the modes advertised for the output
+    Mode(Mode),
+    /// An arbitrary mode, `refresh` is in mHz and `None` if the client doesn't care
+    Custom {
+        size: Size<i32, Physical>,
+        refresh: Option<i32>,
+    },
+}
+
+/// Changes requested for an enabled head, `None` fields keep their current value
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HeadConfiguration {
+    pub mode: Option<ModeConfiguration>,
+    pub position: Option<Point<i32, Logical>>,
+    pub transform: Option<Transform>,
+    pub scale: Option<f64>,
+    pub adaptive_sync: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputConfiguration {
+    Disabled,
+    Enabled(HeadConfiguration),
+}
+
+pub trait OutputManagementHandler {
+    fn output_management_state(&mut self) -> &mut OutputManagementState;
+
+    /// Apply `configuration`, containing an entry for every head, or only check whether
+    /// it could be applied if `test_only` is set. Returns whether it succeeded.
+    fn apply_output_configuration(
+        &mut self,
+        configuration: Vec<(Output, OutputConfiguration)>,
+        test_only: bool,
+    ) -> bool;
+}
+
+#[derive(Debug)]
+struct HeadInstance {
+    output: Output,
+    head: ZwlrOutputHeadV1,
+    modes: Vec<ZwlrOutputModeV1>,
+}
+
+#[derive(Debug)]
+struct ManagerInstance {
+    manager: ZwlrOutputManagerV1,
+    heads: Vec<HeadInstance>,
+}
+
+#[derive(Debug)]
+pub struct OutputManagementState {
+    global: GlobalId,
+    managers: Vec<ManagerInstance>,
+    /// Outputs and whether they are enabled
+    heads: Vec<(Output, bool)>,
+    serial: u32,
+}
+
+#[derive(Debug)]
+struct ConfigurationInner {
+    serial: u32,
+    used: bool,
+    /// Configured heads, `None` for disabled ones
+    heads: Vec<(Output, Option<ZwlrOutputConfigurationHeadV1>)>,
+}
+
+#[derive(Debug)]
+pub struct ConfigurationData(Mutex<ConfigurationInner>);
+
+#[derive(Debug, Default)]
+pub struct ConfigurationHeadData(Mutex<HeadConfiguration>);
+
+impl OutputManagementState {
+    /// Create the global, only advertised to clients `filter` returns true for
+    pub fn new<D, F>(display: &DisplayHandle, filter: F) -> Self
+    where
+        D: GlobalDispatch<ZwlrOutputManagerV1, GlobalFilter>
+            + Dispatch<ZwlrOutputManagerV1, ()>
+            + Dispatch<ZwlrOutputHeadV1, Output>
+            + Dispatch<ZwlrOutputModeV1, Mode>
+            + Dispatch<ZwlrOutputConfigurationV1, ConfigurationData>
+            + Dispatch<ZwlrOutputConfigurationHeadV1, ConfigurationHeadData>
+            + OutputManagementHandler
+            + 'static,
+        F: Fn(&Client) -> bool + Send + Sync + 'static,
+    {
+        let global = display.create_global::<D, ZwlrOutputManagerV1, _>(VERSION, GlobalFilter::new(filter));
+        OutputManagementState {
+            global,
+            managers: Vec::new(),
+            heads: Vec::new(),
+            serial: 0,
+        }
+    }
+
+    pub fn global(&self) -> GlobalId {
+        self.global.clone()
+    }
+
+    /// Outputs currently advertised and whether they are enabled
+    pub fn heads(&self) -> &[(Output, bool)] {
+        &self.heads
+    }
+
+    /// Set the current list of outputs and whether they are enabled, notifying all clients.
+    ///
+    /// Pending configurations created before this call get cancelled.
+    pub fn update<D>(&mut self, dh: &DisplayHandle, heads: Vec<(Output, bool)>)
+    where
+        D: Dispatch<ZwlrOutputHeadV1, Output> + Dispatch<ZwlrOutputModeV1, Mode> + 'static,
+    {
+        self.heads = heads;
+        self.serial = self.serial.wrapping_add(1);
+        for instance in &mut self.managers {
+            send_heads::<D>(dh, instance, &self.heads);
+            instance.manager.done(self.serial);
+        }
+    }
+}
+
+fn send_heads<D>(dh: &DisplayHandle, instance: &mut ManagerInstance, heads: &[(Output, bool)])
+where
+    D: Dispatch<ZwlrOutputHeadV1, Output> + Dispatch<ZwlrOutputModeV1, Mode> + 'static,
+{
+    let Ok(client) = dh.get_client(instance.manager.id()) else {
+        return;
+    };
+    let version = instance.manager.version();
+
+    instance.heads.retain(|head| {
+        let keep = heads.iter().any(|(output, _)| output == &head.output);
+        if !keep {
+            for mode in &head.modes {
+                mode.finished();
+            }
+            head.head.finished();
+        }
+        keep
+    });
+
+    for (output, enabled) in heads {
+        let index = match instance.heads.iter().position(|head| &head.output == output) {
+            Some(index) => index,
+            None => {
+                let Ok(head) = client.create_resource::<ZwlrOutputHeadV1, _, D>(dh, version, output.clone())
+                else {
+                    continue;
+                };
+                instance.manager.head(&head);
+                head.name(output.name());
+                head.description(output.description());
+                let physical = output.physical_properties();
+                if physical.size.w > 0 && physical.size.h > 0 {
+                    head.physical_size(physical.size.w, physical.size.h);
+                }
+                if version >= 2 {
+                    head.make(physical.make);
+                    head.model(physical.model);
+                }
+                instance.heads.push(HeadInstance {
+                    output: output.clone(),
+                    head,
+                    modes: Vec::new(),
+                });
+                instance.heads.len() - 1
+            }
+        };
+        let head = &mut instance.heads[index];
+
+        let preferred = output.preferred_mode();
+        for mode in output.modes() {
+            if head.modes.iter().any(|m| m.data::<Mode>() == Some(&mode)) {
+                continue;
+            }
+            let Ok(wl_mode) = client.create_resource::<ZwlrOutputModeV1, _, D>(dh, version, mode) else {
+                continue;
+            };
+            head.head.mode(&wl_mode);
+            wl_mode.size(mode.size.w, mode.size.h);
+            if mode.refresh > 0 {
+                wl_mode.refresh(mode.refresh);
+            }
+            if preferred == Some(mode) {
+                wl_mode.preferred();
+            }
+            head.modes.push(wl_mode);
+        }
+
+        head.head.enabled(*enabled as i32);
+        if *enabled {
+            if let Some(current) = output
+                .current_mode()
+                .and_then(|current| head.modes.iter().find(|m| m.data::<Mode>() == Some(&current)))
+            {
+                head.head.current_mode(current);
+            }
+            let position = output.current_location();
+            head.head.position(position.x, position.y);
+            head.head.transform(output.current_transform().into());
+            head.head.scale(output.current_scale().fractional_scale());
+            if version >= 4 {
+                head.head.adaptive_sync(if AdaptiveSync::get(output) {
+                    zwlr_output_head_v1::AdaptiveSyncState::Enabled
+                } else {
+                    zwlr_output_head_v1::AdaptiveSyncState::Disabled
+                });
+            }
+        }
+    }
+}
+
+impl<D> GlobalDispatch<ZwlrOutputManagerV1, GlobalFilter, D> for OutputManagementState
+where
+    D: GlobalDispatch<ZwlrOutputManagerV1, GlobalFilter>
+        + Dispatch<ZwlrOutputManagerV1, ()>
+        + Dispatch<ZwlrOutputHeadV1, Output>
+        + Dispatch<ZwlrOutputModeV1, Mode>
+        + Dispatch<ZwlrOutputConfigurationV1, ConfigurationData>
+        + Dispatch<ZwlrOutputConfigurationHeadV1, ConfigurationHeadData>
+        + OutputManagementHandler
+        + 'static,
+{
+    fn bind(
+        state: &mut D,
+        handle: &DisplayHandle,
+        _client: &Client,
+        manager: New<ZwlrOutputManagerV1>,
+        _global_data: &GlobalFilter,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        let manager = data_init.init(manager, ());
+        let state = state.output_management_state();
+        let mut instance = ManagerInstance {
+            manager,
+            heads: Vec::new(),
+        };
+        send_heads::<D>(handle, &mut instance, &state.heads);
+        instance.manager.done(state.serial);
+        state.managers.push(instance);
+    }
+
+    fn can_view(client: Client, global_data: &GlobalFilter) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl<D> Dispatch<ZwlrOutputManagerV1, (), D> for OutputManagementState
+where
+    D: GlobalDispatch<ZwlrOutputManagerV1, GlobalFilter>
+        + Dispatch<ZwlrOutputManagerV1, ()>
+        + Dispatch<ZwlrOutputHeadV1, Output>
+        + Dispatch<ZwlrOutputModeV1, Mode>
+        + Dispatch<ZwlrOutputConfigurationV1, ConfigurationData>
+        + Dispatch<ZwlrOutputConfigurationHeadV1, ConfigurationHeadData>
+        + OutputManagementHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        manager: &ZwlrOutputManagerV1,
+        request: zwlr_output_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwlr_output_manager_v1::Request::CreateConfiguration { id, serial } => {
+                data_init.init(
+                    id,
+                    ConfigurationData(Mutex::new(ConfigurationInner {
+                        serial,
+                        used: false,
+                        heads: Vec::new(),
+                    })),
+                );
+            }
+            zwlr_output_manager_v1::Request::Stop => {
+                let state = state.output_management_state();
+                state.managers.retain(|instance| &instance.manager != manager);
+                manager.finished();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut D, _client: ClientId, manager: &ZwlrOutputManagerV1, _data: &()) {
+        state
+            .output_management_state()
+            .managers
+            .retain(|instance| &instance.manager != manager);
+    }
+}
+
+impl<D> Dispatch<ZwlrOutputHeadV1, Output, D> for OutputManagementState
+where
+    D: Dispatch<ZwlrOutputHeadV1, Output> + OutputManagementHandler + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        head: &ZwlrOutputHeadV1,
+        request: zwlr_output_head_v1::Request,
+        _data: &Output,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwlr_output_head_v1::Request::Release => {
+                for instance in &mut state.output_management_state().managers {
+                    instance.heads.retain(|instance| &instance.head != head);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<D> Dispatch<ZwlrOutputModeV1, Mode, D> for OutputManagementState
+where
+    D: Dispatch<ZwlrOutputModeV1, Mode> + OutputManagementHandler + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        mode: &ZwlrOutputModeV1,
+        request: zwlr_output_mode_v1::Request,
+        _data: &Mode,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwlr_output_mode_v1::Request::Release => {
+                for instance in &mut state.output_management_state().managers {
+                    for head in &mut instance.heads {
+                        head.modes.retain(|m| m != mode);
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<D> Dispatch<ZwlrOutputConfigurationV1, ConfigurationData, D> for OutputManagementState
+where
+    D: Dispatch<ZwlrOutputConfigurationV1, ConfigurationData>
+        + Dispatch<ZwlrOutputConfigurationHeadV1, ConfigurationHeadData>
+        + OutputManagementHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        configuration: &ZwlrOutputConfigurationV1,
+        request: zwlr_output_configuration_v1::Request,
+        data: &ConfigurationData,
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        let mut inner = data.0.lock().unwrap();
+        let (head, enabled_head, test_only) = match request {
+            zwlr_output_configuration_v1::Request::EnableHead { id, head } => (Some(head), Some(id), false),
+            zwlr_output_configuration_v1::Request::DisableHead { head } => (Some(head), None, false),
+            zwlr_output_configuration_v1::Request::Apply => (None, None, false),
+            zwlr_output_configuration_v1::Request::Test => (None, None, true),
+            zwlr_output_configuration_v1::Request::Destroy => return,
+            _ => unreachable!(),
+        };
+
+        if let Some(head) = head {
+            let output = head.data::<Output>().unwrap().clone();
+            let config_head = enabled_head.map(|id| data_init.init(id, ConfigurationHeadData::default()));
+            if inner.used {
+                configuration.post_error(
+                    zwlr_output_configuration_v1::Error::AlreadyUsed,
+                    "configuration was already applied or tested",
+                );
+                return;
+            }
+            if inner.heads.iter().any(|(o, _)| o == &output) {
+                configuration.post_error(
+                    zwlr_output_configuration_v1::Error::AlreadyConfiguredHead,
+                    "head was already configured",
+                );
+                return;
+            }
+            inner.heads.push((output, config_head));
+            return;
+        }
+
+        if inner.used {
+            configuration.post_error(
+                zwlr_output_configuration_v1::Error::AlreadyUsed,
+                "configuration was already applied or tested",
+            );
+            return;
+        }
+        inner.used = true;
+
+        let management_state = state.output_management_state();
+        if inner.serial != management_state.serial {
+            configuration.cancelled();
+            return;
+        }
+        if management_state
+            .heads
+            .iter()
+            .any(|(output, _)| !inner.heads.iter().any(|(o, _)| o == output))
+        {
+            configuration.post_error(
+                zwlr_output_configuration_v1::Error::UnconfiguredHead,
+                "not all heads were configured",
+            );
+            return;
+        }
+
+        let requested = inner
+            .heads
+            .iter()
+            .map(|(output, head)| {
+                let config = match head {
+                    Some(head) => OutputConfiguration::Enabled(
+                        head.data::<ConfigurationHeadData>()
+                            .unwrap()
+                            .0
+                            .lock()
+                            .unwrap()
+                            .clone(),
+                    ),
+                    None => OutputConfiguration::Disabled,
+                };
+                (output.clone(), config)
+            })
+            .collect();
+        drop(inner);
+
+        if state.apply_output_configuration(requested, test_only) {
+            configuration.succeeded();
+        } else {
+            configuration.failed();
+        }
+    }
+}
+
+impl<D> Dispatch<ZwlrOutputConfigurationHeadV1, ConfigurationHeadData, D> for OutputManagementState
+where
+    D: Dispatch<ZwlrOutputConfigurationHeadV1, ConfigurationHeadData> + OutputManagementHandler + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        head: &ZwlrOutputConfigurationHeadV1,
+        request: zwlr_output_configuration_head_v1::Request,
+        data: &ConfigurationHeadData,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        let mut config = data.0.lock().unwrap();
+        let already_set = match request {
+            zwlr_output_configuration_head_v1::Request::SetMode { mode } => {
+                let mode = mode.data::<Mode>().copied();
+                let Some(mode) = mode else {
+                    head.post_error(
+                        zwlr_output_configuration_head_v1::Error::InvalidMode,
+                        "mode is not valid",
+                    );
+                    return;
+                };
+                config.mode.replace(ModeConfiguration::Mode(mode)).is_some()
+            }
+            zwlr_output_configuration_head_v1::Request::SetCustomMode {
+                width,
+                height,
+                refresh,
+            } => {
+                if width <= 0 || height <= 0 || refresh < 0 {
+                    head.post_error(
+                        zwlr_output_configuration_head_v1::Error::InvalidCustomMode,
+                        "invalid custom mode",
+                    );
+                    return;
+                }
+                config
+                    .mode
+                    .replace(ModeConfiguration::Custom {
+                        size: (width, height).into(),
+                        refresh: (refresh > 0).then_some(refresh),
+                    })
+                    .is_some()
+            }
+            zwlr_output_configuration_head_v1::Request::SetPosition { x, y } => {
+                config.position.replace((x, y).into()).is_some()
+            }
+            zwlr_output_configuration_head_v1::Request::SetTransform { transform } => {
+                let WEnum::Value(transform) = transform else {
+                    head.post_error(
+                        zwlr_output_configuration_head_v1::Error::InvalidTransform,
+                        "unknown transform",
+                    );
+                    return;
+                };
+                config.transform.replace(transform.into()).is_some()
+            }
+            zwlr_output_configuration_head_v1::Request::SetScale { scale } => {
+                if !(scale.is_finite() && scale > 0.0) {
+                    head.post_error(
+                        zwlr_output_configuration_head_v1::Error::InvalidScale,
+                        "scale must be positive",
+                    );
+                    return;
+                }
+                config.scale.replace(scale).is_some()
+            }
+            zwlr_output_configuration_head_v1::Request::SetAdaptiveSync { state } => {
+                let WEnum::Value(state) = state else {
+                    head.post_error(
+                        zwlr_output_configuration_head_v1::Error::InvalidAdaptiveSyncState,
+                        "unknown adaptive sync state",
+                    );
+                    return;
+                };
+                config
+                    .adaptive_sync
+                    .replace(state == zwlr_output_head_v1::AdaptiveSyncState::Enabled)
+                    .is_some()
+            }
+            _ => unreachable!(),
+        };
+
+        if already_set {
+            head.post_error(
+                zwlr_output_configuration_head_v1::Error::AlreadySet,
+                "property was already set",
+            );
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! delegate_output_management {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::output_management::v1::server::zwlr_output_manager_v1::ZwlrOutputManagerV1: $crate::protocols::GlobalFilter
+        ] => $crate::protocols::output_management::OutputManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::output_management::v1::server::zwlr_output_manager_v1::ZwlrOutputManagerV1: ()
+        ] => $crate::protocols::output_management::OutputManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::output_management::v1::server::zwlr_output_head_v1::ZwlrOutputHeadV1: smithay::output::Output
+        ] => $crate::protocols::output_management::OutputManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::output_management::v1::server::zwlr_output_mode_v1::ZwlrOutputModeV1: smithay::output::Mode
+        ] => $crate::protocols::output_management::OutputManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::output_management::v1::server::zwlr_output_configuration_v1::ZwlrOutputConfigurationV1: $crate::protocols::output_management::ConfigurationData
+        ] => $crate::protocols::output_management::OutputManagementState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::output_management::v1::server::zwlr_output_configuration_head_v1::ZwlrOutputConfigurationHeadV1: $crate::protocols::output_management::ConfigurationHeadData
+        ] => $crate::protocols::output_management::OutputManagementState);
+    };
+}
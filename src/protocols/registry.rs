@@ -0,0 +1,89 @@
+//! Registry of the globals the config can restrict or turn off
+//!
+//! Every privileged global is created with the filter of [`ProtocolRegistry::filter`] and every
+//! optional one only if [`ProtocolRegistry::enabled`] allows it, so the `[protocol_access]` and
+//! `[protocols]` config sections apply to all of them the same way.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::RwLock,
+};
+
+use smithay::reexports::wayland_server::Client;
+use tracing::warn;
+
+use crate::{
+    config::{ClientRule, Config, OptionalProtocol, PrivilegedProtocol, Protocol},
+    state::{is_privileged, ClientState},
+};
+
+/// The registry shared with the global filters, which can't reach the compositor state
+pub static PROTOCOLS: ProtocolRegistry = ProtocolRegistry {
+    inner: RwLock::new(Registry {
+        access: BTreeMap::new(),
+        disabled: BTreeSet::new(),
+        registered: BTreeSet::new(),
+    }),
+};
+
+#[derive(Debug)]
+pub struct ProtocolRegistry {
+    inner: RwLock<Registry>,
+}
+
+#[derive(Debug)]
+struct Registry {
+    /// The `[protocol_access]` section of the current config
+    access: BTreeMap<PrivilegedProtocol, Vec<ClientRule>>,
+    /// The protocols disabled in the current config
+    disabled: BTreeSet<Protocol>,
+    /// Protocols whose global was created or skipped
+    registered: BTreeSet<Protocol>,
+}
+
+impl ProtocolRegistry {
+    /// Take over the protocol settings of `config`, at startup and when the config is reloaded.
+    /// Optional protocols turned on or off keep their state until the next start.
+    pub fn configure(&self, config: &Config) {
+        let mut registry = self.inner.write().unwrap();
+        let disabled = config.protocols.disabled.iter().copied().collect::<BTreeSet<_>>();
+        for protocol in disabled.symmetric_difference(&registry.disabled) {
+            if matches!(protocol, Protocol::Optional(_)) && registry.registered.contains(protocol) {
+                warn!(?protocol, "Turning an optional protocol on or off only applies after a restart");
+            }
+        }
+        registry.access = config.protocol_access.clone();
+        registry.disabled = disabled;
+    }
+
+    /// Register the global of a privileged protocol, returning its filter. It shows the global to
+    /// the clients the config allows, sandboxed ones only through a rule naming their app id, or to
+    /// privileged ones if the config doesn't restrict it, and to none if the protocol is disabled.
+    /// Applies to clients binding the global after a reload.
+    pub fn filter(&self, protocol: PrivilegedProtocol) -> impl Fn(&Client) -> bool + Send + Sync + 'static {
+        self.register(Protocol::Privileged(protocol));
+        move |client| {
+            let registry = PROTOCOLS.inner.read().unwrap();
+            if registry.disabled.contains(&Protocol::Privileged(protocol)) {
+                return false;
+            }
+            match registry.access.get(&protocol) {
+                Some(rules) => client
+                    .get_data::<ClientState>()
+                    .is_some_and(|client_state| rules.iter().any(|rule| client_state.matches(rule))),
+                None => is_privileged(client),
+            }
+        }
+    }
+
+    /// Register the global of an optional protocol, returning whether to create it
+    pub fn enabled(&self, protocol: OptionalProtocol) -> bool {
+        let protocol = Protocol::Optional(protocol);
+        self.register(protocol);
+        !self.inner.read().unwrap().disabled.contains(&protocol)
+    }
+
+    fn register(&self, protocol: Protocol) {
+        self.inner.write().unwrap().registered.insert(protocol);
+    }
+}
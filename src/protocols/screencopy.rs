@@ -0,0 +1,379 @@
+//! wlr-screencopy-unstable-v1
+//!
+//! Clients request a frame for an output (or a region of it) and provide a shm or dmabuf
+//! buffer to copy it into. The resulting [`Screencopy`] is handed to the [`ScreencopyHandler`],
+//! which is expected to fill the buffer the next time the output is rendered.
+
+use std::{sync::Mutex, time::Duration};
+
+use super::GlobalFilter;
+use smithay::{
+    backend::allocator::{Buffer as _, Fourcc},
+    output::Output,
+    reexports::{
+        wayland_protocols_wlr::screencopy::v1::server::{
+            zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+            zwlr_screencopy_manager_v1::{self, ZwlrScreencopyManagerV1},
+        },
+        wayland_server::{
+            backend::GlobalId,
+            protocol::{wl_buffer::WlBuffer, wl_shm},
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+        },
+    },
+    utils::{Buffer, Logical, Rectangle},
+    wayland::{dmabuf::get_dmabuf, shm::with_buffer_contents},
+};
+
+const VERSION: u32 = 3;
+
+/// Format of shm buffers offered to clients
+pub const SHM_FORMAT: wl_shm::Format = wl_shm::Format::Argb8888;
+/// Format of dmabufs offered to clients
+pub const DMABUF_FORMAT: Fourcc = Fourcc::Argb8888;
+
+#[derive(Debug)]
+pub struct ScreencopyManagerState {
+    global: GlobalId,
+}
+
+impl ScreencopyManagerState {
+    /// Create the global, only advertised to clients `filter` returns true for
+    pub fn new<D, F>(display: &DisplayHandle, filter: F) -> Self
+    where
+        D: GlobalDispatch<ZwlrScreencopyManagerV1, GlobalFilter>
+            + Dispatch<ZwlrScreencopyManagerV1, ()>
+            + Dispatch<ZwlrScreencopyFrameV1, FrameData>
+            + ScreencopyHandler
+            + 'static,
+        F: Fn(&Client) -> bool + Send + Sync + 'static,
+    {
+        let global =
+            display.create_global::<D, ZwlrScreencopyManagerV1, _>(VERSION, GlobalFilter::new(filter));
+        ScreencopyManagerState { global }
+    }
+
+    pub fn global(&self) -> GlobalId {
+        self.global.clone()
+    }
+}
+
+pub trait ScreencopyHandler {
+    /// A client provided a buffer for a frame, fill it on the next repaint of [`Screencopy::output`]
+    fn frame(&mut self, screencopy: Screencopy);
+}
+
+#[derive(Debug)]
+struct FrameInner {
+    /// `None` if the frame already failed on creation
+    output: Option<Output>,
+    region: Rectangle<i32, Buffer>,
+    /// The region is the whole output, dmabufs are only offered then
+    full_output: bool,
+    overlay_cursor: bool,
+    copied: bool,
+}
+
+#[derive(Debug)]
+pub struct FrameData(Mutex<FrameInner>);
+
+/// A pending copy of an output into a client buffer
+///
+/// Dropping it without calling [`Screencopy::submit`] reports a failure to the client.
+#[derive(Debug)]
+pub struct Screencopy {
+    frame: ZwlrScreencopyFrameV1,
+    output: Output,
+    region: Rectangle<i32, Buffer>,
+    buffer: WlBuffer,
+    overlay_cursor: bool,
+    with_damage: bool,
+    submitted: bool,
+}
+
+impl Screencopy {
+    pub fn output(&self) -> &Output {
+        &self.output
+    }
+
+    /// Region of the output to copy, in buffer coordinates of the output
+    pub fn region(&self) -> Rectangle<i32, Buffer> {
+        self.region
+    }
+
+    pub fn buffer(&self) -> &WlBuffer {
+        &self.buffer
+    }
+
+    /// Whether the cursor should be part of the copy
+    pub fn overlay_cursor(&self) -> bool {
+        self.overlay_cursor
+    }
+
+    /// Whether the client only wants the frame once the output got damaged
+    pub fn with_damage(&self) -> bool {
+        self.with_damage
+    }
+
+    /// Report damaged regions, relative to [`Screencopy::region`]
+    pub fn damage(&self, damage: impl IntoIterator<Item = Rectangle<i32, Buffer>>) {
+        if !self.with_damage {
+            return;
+        }
+        for rect in damage {
+            self.frame.damage(
+                rect.loc.x as u32,
+                rect.loc.y as u32,
+                rect.size.w as u32,
+                rect.size.h as u32,
+            );
+        }
+    }
+
+    /// Notify the client that the buffer now contains the output contents, captured at `timestamp`
+    pub fn submit(mut self, y_invert: bool, timestamp: Duration) {
+        self.frame.flags(if y_invert {
+            zwlr_screencopy_frame_v1::Flags::YInvert
+        } else {
+            zwlr_screencopy_frame_v1::Flags::empty()
+        });
+        let secs = timestamp.as_secs();
+        self.frame
+            .ready((secs >> 32) as u32, secs as u32, timestamp.subsec_nanos());
+        self.submitted = true;
+    }
+}
+
+impl Drop for Screencopy {
+    fn drop(&mut self) {
+        if !self.submitted {
+            self.frame.failed();
+        }
+    }
+}
+
+impl<D> GlobalDispatch<ZwlrScreencopyManagerV1, GlobalFilter, D> for ScreencopyManagerState
+where
+    D: GlobalDispatch<ZwlrScreencopyManagerV1, GlobalFilter>
+        + Dispatch<ZwlrScreencopyManagerV1, ()>
+        + Dispatch<ZwlrScreencopyFrameV1, FrameData>
+        + ScreencopyHandler
+        + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        manager: New<ZwlrScreencopyManagerV1>,
+        _global_data: &GlobalFilter,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(manager, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalFilter) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl<D> Dispatch<ZwlrScreencopyManagerV1, (), D> for ScreencopyManagerState
+where
+    D: GlobalDispatch<ZwlrScreencopyManagerV1, GlobalFilter>
+        + Dispatch<ZwlrScreencopyManagerV1, ()>
+        + Dispatch<ZwlrScreencopyFrameV1, FrameData>
+        + ScreencopyHandler
+        + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        _manager: &ZwlrScreencopyManagerV1,
+        request: zwlr_screencopy_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        let (frame, overlay_cursor, output, region) = match request {
+            zwlr_screencopy_manager_v1::Request::CaptureOutput {
+                frame,
+                overlay_cursor,
+                output,
+            } => (frame, overlay_cursor, output, None),
+            zwlr_screencopy_manager_v1::Request::CaptureOutputRegion {
+                frame,
+                overlay_cursor,
+                output,
+                x,
+                y,
+                width,
+                height,
+            } => (
+                frame,
+                overlay_cursor,
+                output,
+                Some(Rectangle::<i32, Logical>::new(
+                    (x, y).into(),
+                    (width, height).into(),
+                )),
+            ),
+            zwlr_screencopy_manager_v1::Request::Destroy => return,
+            _ => unreachable!(),
+        };
+
+        let failed = |data_init: &mut DataInit<'_, D>, frame: New<ZwlrScreencopyFrameV1>| {
+            let frame = data_init.init(
+                frame,
+                FrameData(Mutex::new(FrameInner {
+                    output: None,
+                    region: Rectangle::default(),
+                    full_output: false,
+                    overlay_cursor: false,
+                    copied: false,
+                })),
+            );
+            frame.failed();
+        };
+
+        let Some((output, mode)) = Output::from_resource(&output).and_then(|output| {
+            let mode = output.current_mode()?;
+            Some((output, mode))
+        }) else {
+            failed(data_init, frame);
+            return;
+        };
+
+        let full = Rectangle::<i32, Buffer>::from_size((mode.size.w, mode.size.h).into());
+        let region = match region {
+            Some(region) => {
+                let scale = output.current_scale().fractional_scale();
+                let transform = output.current_transform();
+                let logical_size = transform.transform_size(mode.size).to_f64().to_logical(scale);
+                region
+                    .to_f64()
+                    .to_buffer(scale, transform, &logical_size)
+                    .to_i32_round()
+                    .intersection(full)
+            }
+            None => Some(full),
+        };
+        let Some(region) = region.filter(|region| !region.is_empty()) else {
+            failed(data_init, frame);
+            return;
+        };
+
+        let frame = data_init.init(
+            frame,
+            FrameData(Mutex::new(FrameInner {
+                output: Some(output),
+                region,
+                full_output: region == full,
+                overlay_cursor: overlay_cursor != 0,
+                copied: false,
+            })),
+        );
+
+        let (width, height) = (region.size.w as u32, region.size.h as u32);
+        frame.buffer(SHM_FORMAT, width, height, width * 4);
+        if frame.version() >= 3 {
+            // dmabufs are rendered into directly, which only works for the full output
+            if region == full {
+                frame.linux_dmabuf(DMABUF_FORMAT as u32, width, height);
+            }
+            frame.buffer_done();
+        }
+    }
+}
+
+impl<D> Dispatch<ZwlrScreencopyFrameV1, FrameData, D> for ScreencopyManagerState
+where
+    D: GlobalDispatch<ZwlrScreencopyManagerV1, GlobalFilter>
+        + Dispatch<ZwlrScreencopyManagerV1, ()>
+        + Dispatch<ZwlrScreencopyFrameV1, FrameData>
+        + ScreencopyHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        frame: &ZwlrScreencopyFrameV1,
+        request: zwlr_screencopy_frame_v1::Request,
+        data: &FrameData,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        let (buffer, with_damage) = match request {
+            zwlr_screencopy_frame_v1::Request::Copy { buffer } => (buffer, false),
+            zwlr_screencopy_frame_v1::Request::CopyWithDamage { buffer } => (buffer, true),
+            zwlr_screencopy_frame_v1::Request::Destroy => return,
+            _ => unreachable!(),
+        };
+
+        let mut inner = data.0.lock().unwrap();
+        if inner.copied {
+            frame.post_error(
+                zwlr_screencopy_frame_v1::Error::AlreadyUsed,
+                "copy was already requested",
+            );
+            return;
+        }
+        let Some(output) = inner.output.clone() else {
+            // the frame already failed
+            return;
+        };
+
+        let dmabuf = get_dmabuf(&buffer).ok();
+        if dmabuf.is_some() && !inner.full_output {
+            // the output is rendered straight into dmabufs, which only works for all of it
+            inner.copied = true;
+            frame.failed();
+            return;
+        }
+        let size = inner.region.size;
+        let valid = if let Some(dmabuf) = dmabuf {
+            dmabuf.size() == size && dmabuf.format().code == DMABUF_FORMAT
+        } else {
+            with_buffer_contents(&buffer, |_, _, data| {
+                data.format == SHM_FORMAT
+                    && data.width == size.w
+                    && data.height == size.h
+                    && data.stride == size.w * 4
+            })
+            .unwrap_or(false)
+        };
+        if !valid {
+            frame.post_error(
+                zwlr_screencopy_frame_v1::Error::InvalidBuffer,
+                "buffer does not match the advertised parameters",
+            );
+            return;
+        }
+        inner.copied = true;
+
+        let screencopy = Screencopy {
+            frame: frame.clone(),
+            output,
+            region: inner.region,
+            buffer,
+            overlay_cursor: inner.overlay_cursor,
+            with_damage,
+            submitted: false,
+        };
+        drop(inner);
+        state.frame(screencopy);
+    }
+}
+
+#[macro_export]
+macro_rules! delegate_screencopy {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::screencopy::v1::server::zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1: $crate::protocols::GlobalFilter
+        ] => $crate::protocols::screencopy::ScreencopyManagerState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::screencopy::v1::server::zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1: ()
+        ] => $crate::protocols::screencopy::ScreencopyManagerState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::screencopy::v1::server::zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1: $crate::protocols::screencopy::FrameData
+        ] => $crate::protocols::screencopy::ScreencopyManagerState);
+    };
+}
@@ -1,28 +1,42 @@
+use std::{cell::RefCell, time::Duration};
+
 use smithay::{
-    backend::renderer::{
-        damage::{Error as OutputDamageTrackerError, OutputDamageTracker, RenderOutputResult},
-        element::{
-            surface::WaylandSurfaceRenderElement,
-            utils::{
-                ConstrainAlign, ConstrainScaleBehavior, CropRenderElement, RelocateRenderElement,
-                RescaleRenderElement,
+    backend::{
+        allocator::{dmabuf::Dmabuf, Fourcc},
+        renderer::{
+            damage::{Error as OutputDamageTrackerError, OutputDamageTracker, RenderOutputResult},
+            element::{
+                solid::SolidColorRenderElement,
+                surface::WaylandSurfaceRenderElement,
+                utils::{
+                    ConstrainAlign, ConstrainScaleBehavior, CropRenderElement, Relocate,
+                    RelocateRenderElement, RescaleRenderElement,
+                },
+                AsRenderElements, Element, Kind, RenderElement, Wrap,
             },
-            AsRenderElements, RenderElement, Wrap,
+            Bind, Color32F, ExportMem, ImportAll, ImportMem, Offscreen, Renderer,
         },
-        Color32F, ImportAll, ImportMem, Renderer,
     },
     desktop::space::{
-        constrain_space_element, ConstrainBehavior, ConstrainReference, Space, SpaceRenderElements,
+        constrain_space_element, ConstrainBehavior, ConstrainReference, Space, SpaceElement,
+        SpaceRenderElements,
     },
     output::Output,
-    utils::{Point, Rectangle, Size},
+    utils::{Buffer, Point, Rectangle, Size},
+    wayland::{dmabuf::get_dmabuf, shm::with_buffer_contents_mut},
 };
+use tracing::warn;
 
 #[cfg(feature = "debug")]
 use crate::drawing::FpsElement;
 use crate::{
-    drawing::{PointerRenderElement, CLEAR_COLOR, CLEAR_COLOR_FULLSCREEN},
-    shell::{FullscreenSurface, WindowElement, WindowRenderElement},
+    drawing::{PointerRenderElement, CLEAR_COLOR_FULLSCREEN},
+    magnifier::magnification,
+    protocols::{export_dmabuf::DmabufExport, screencopy::Screencopy},
+    shell::{
+        closing_window_elements, workspace_slide_offset, FullscreenSurface, ResizeOutline, SnapPreview,
+        SnapshotElement, WindowElement, WindowRenderElement,
+    },
 };
 
 smithay::backend::renderer::element::render_elements! {
@@ -30,6 +44,12 @@ smithay::backend::renderer::element::render_elements! {
         R: ImportAll + ImportMem;
     Pointer=PointerRenderElement<R>,
     Surface=WaylandSurfaceRenderElement<R>,
+    SnapPreview=SolidColorRenderElement,
+    ResizeOutline=SolidColorRenderElement,
+    InhibitPrompt=SolidColorRenderElement,
+    Damage=SolidColorRenderElement,
+    RepaintStats=SolidColorRenderElement,
+    Closing=SnapshotElement<R::TextureId>,
     #[cfg(feature = "debug")]
     // Note: We would like to borrow this element instead, but that would introduce
     // a feature-dependent lifetime, which introduces a lot more feature bounds
@@ -43,6 +63,12 @@ impl<R: Renderer> std::fmt::Debug for CustomRenderElements<R> {
         match self {
             Self::Pointer(arg0) => f.debug_tuple("Pointer").field(arg0).finish(),
             Self::Surface(arg0) => f.debug_tuple("Surface").field(arg0).finish(),
+            Self::SnapPreview(arg0) => f.debug_tuple("SnapPreview").field(arg0).finish(),
+            Self::ResizeOutline(arg0) => f.debug_tuple("ResizeOutline").field(arg0).finish(),
+            Self::InhibitPrompt(arg0) => f.debug_tuple("InhibitPrompt").field(arg0).finish(),
+            Self::Damage(arg0) => f.debug_tuple("Damage").field(arg0).finish(),
+            Self::RepaintStats(arg0) => f.debug_tuple("RepaintStats").field(arg0).finish(),
+            Self::Closing(arg0) => f.debug_tuple("Closing").field(arg0).finish(),
             #[cfg(feature = "debug")]
             Self::Fps(arg0) => f.debug_tuple("Fps").field(arg0).finish(),
             Self::_GenericCatcher(arg0) => f.debug_tuple("_GenericCatcher").field(arg0).finish(),
@@ -53,9 +79,29 @@ impl<R: Renderer> std::fmt::Debug for CustomRenderElements<R> {
 smithay::backend::renderer::element::render_elements! {
     pub OutputRenderElements<R, E> where R: ImportAll + ImportMem;
     Space=SpaceRenderElements<R, E>,
-    Window=Wrap<E>,
+    Window=CropRenderElement<Wrap<E>>,
     Custom=CustomRenderElements<R>,
     Preview=CropRenderElement<RelocateRenderElement<RescaleRenderElement<WindowRenderElement<R>>>>,
+    Slide=RelocateRenderElement<Wrap<E>>,
+}
+
+// every element of a magnified output is scaled around the pointer
+smithay::backend::renderer::element::render_elements! {
+    pub MagnifiedRenderElements<R, E> where R: ImportAll + ImportMem;
+    Output=OutputRenderElements<R, E>,
+    Magnified=RescaleRenderElement<OutputRenderElements<R, E>>,
+}
+
+impl<R: Renderer + ImportAll + ImportMem, E: RenderElement<R> + std::fmt::Debug> std::fmt::Debug
+    for MagnifiedRenderElements<R, E>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Output(arg0) => f.debug_tuple("Output").field(arg0).finish(),
+            Self::Magnified(arg0) => f.debug_tuple("Magnified").field(arg0).finish(),
+            Self::_GenericCatcher(arg0) => f.debug_tuple("_GenericCatcher").field(arg0).finish(),
+        }
+    }
 }
 
 impl<R: Renderer + ImportAll + ImportMem, E: RenderElement<R> + std::fmt::Debug> std::fmt::Debug
@@ -67,6 +113,7 @@ impl<R: Renderer + ImportAll + ImportMem, E: RenderElement<R> + std::fmt::Debug>
             Self::Window(arg0) => f.debug_tuple("Window").field(arg0).finish(),
             Self::Custom(arg0) => f.debug_tuple("Custom").field(arg0).finish(),
             Self::Preview(arg0) => f.debug_tuple("Preview").field(arg0).finish(),
+            Self::Slide(arg0) => f.debug_tuple("Slide").field(arg0).finish(),
             Self::_GenericCatcher(arg0) => f.debug_tuple("_GenericCatcher").field(arg0).finish(),
         }
     }
@@ -142,27 +189,41 @@ pub fn output_elements<R>(
     custom_elements: impl IntoIterator<Item = CustomRenderElements<R>>,
     renderer: &mut R,
     show_window_preview: bool,
-) -> (Vec<OutputRenderElements<R, WindowRenderElement<R>>>, Color32F)
+    clear_color: Color32F,
+) -> (Vec<MagnifiedRenderElements<R, WindowRenderElement<R>>>, Color32F)
 where
     R: Renderer + ImportAll + ImportMem,
     R::TextureId: Clone + 'static,
 {
-    if let Some(window) = output
+    let (elements, clear_color) = if let Some(window) = output
         .user_data()
         .get::<FullscreenSurface>()
         .and_then(|f| f.get())
     {
         let scale = output.current_scale().fractional_scale().into();
+        // the window geometry, not the surface origin, goes to the corner of the output
+        let location = (-window.geometry().loc).to_physical_precise_round(scale);
         let window_render_elements: Vec<WindowRenderElement<R>> =
-            AsRenderElements::<R>::render_elements(&window, renderer, (0, 0).into(), scale, 1.0);
+            AsRenderElements::<R>::render_elements(&window, renderer, location, scale, 1.0);
+        // subsurfaces reaching past the output would show up on neighbouring outputs otherwise
+        let output_area = space
+            .output_geometry(output)
+            .map(|geometry| Rectangle::from_size(geometry.size.to_physical_precise_round(scale)))
+            .unwrap_or_default();
+        let prompt = window.shortcuts_inhibit_prompt_element((0, 0).into(), scale);
 
         let elements = custom_elements
             .into_iter()
             .map(OutputRenderElements::from)
+            .chain(
+                prompt
+                    .map(|prompt| OutputRenderElements::Custom(CustomRenderElements::InhibitPrompt(prompt))),
+            )
             .chain(
                 window_render_elements
                     .into_iter()
-                    .map(|e| OutputRenderElements::Window(Wrap::from(e))),
+                    .filter_map(|e| CropRenderElement::from_element(Wrap::from(e), scale, output_area))
+                    .map(OutputRenderElements::Window),
             )
             .collect::<Vec<_>>();
         (elements, CLEAR_COLOR_FULLSCREEN)
@@ -172,9 +233,37 @@ where
             .map(OutputRenderElements::from)
             .collect::<Vec<_>>();
 
+        if let Some(preview) = SnapPreview::render_element(output) {
+            output_render_elements.push(OutputRenderElements::Custom(CustomRenderElements::SnapPreview(
+                preview,
+            )));
+        }
+        output_render_elements.extend(
+            ResizeOutline::render_elements(output)
+                .into_iter()
+                .map(|edge| OutputRenderElements::Custom(CustomRenderElements::ResizeOutline(edge))),
+        );
+        if let Some(output_geometry) = space.output_geometry(output) {
+            let scale = output.current_scale().fractional_scale().into();
+            output_render_elements.extend(
+                space
+                    .elements_for_output(output)
+                    .filter_map(|window| {
+                        let location = space.element_geometry(window)?.loc - output_geometry.loc;
+                        window.shortcuts_inhibit_prompt_element(location, scale)
+                    })
+                    .map(|prompt| OutputRenderElements::Custom(CustomRenderElements::InhibitPrompt(prompt))),
+            );
+        }
+
         if show_window_preview && space.elements_for_output(output).count() > 0 {
             output_render_elements.extend(space_preview_elements(renderer, space, output));
         }
+        output_render_elements.extend(
+            closing_window_elements::<R>(output)
+                .into_iter()
+                .map(|element| OutputRenderElements::Custom(CustomRenderElements::Closing(element))),
+        );
 
         let space_elements = smithay::desktop::space::space_render_elements::<_, WindowElement, _>(
             renderer,
@@ -183,10 +272,30 @@ where
             1.0,
         )
         .expect("output without mode?");
-        output_render_elements.extend(space_elements.into_iter().map(OutputRenderElements::Space));
+        // only windows slide with their workspace, layer surfaces stay in place
+        let slide_offset = workspace_slide_offset(output);
+        output_render_elements.extend(space_elements.into_iter().map(|element| {
+            match (element, slide_offset) {
+                (SpaceRenderElements::Element(element), Some(offset)) => OutputRenderElements::Slide(
+                    RelocateRenderElement::from_element(element, offset, Relocate::Relative),
+                ),
+                (element, _) => OutputRenderElements::Space(element),
+            }
+        }));
 
-        (output_render_elements, CLEAR_COLOR)
-    }
+        (output_render_elements, clear_color)
+    };
+
+    let elements = match magnification(output) {
+        Some((zoom, origin)) => elements
+            .into_iter()
+            .map(|element| {
+                MagnifiedRenderElements::Magnified(RescaleRenderElement::from_element(element, origin, zoom))
+            })
+            .collect(),
+        None => elements.into_iter().map(MagnifiedRenderElements::Output).collect(),
+    };
+    (elements, clear_color)
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -199,12 +308,251 @@ pub fn render_output<'a, 'd, R>(
     damage_tracker: &'d mut OutputDamageTracker,
     age: usize,
     show_window_preview: bool,
+    clear_color: Color32F,
 ) -> Result<RenderOutputResult<'d>, OutputDamageTrackerError<R::Error>>
 where
     R: Renderer + ImportAll + ImportMem,
     R::TextureId: Clone + 'static,
 {
-    let (elements, clear_color) =
-        output_elements(output, space, custom_elements, renderer, show_window_preview);
+    let (elements, clear_color) = output_elements(
+        output,
+        space,
+        custom_elements,
+        renderer,
+        show_window_preview,
+        clear_color,
+    );
     damage_tracker.render_output(renderer, framebuffer, age, &elements, clear_color)
 }
+
+/// Tracks what changed on an output between its screencopies, in its user data
+#[derive(Default)]
+struct ScreencopyDamage(RefCell<Option<OutputDamageTracker>>);
+
+/// Damage of `output` since its last screencopy, in buffer coordinates of the output
+fn screencopy_damage<R, E>(output: &Output, elements: &[E]) -> Vec<Rectangle<i32, Buffer>>
+where
+    R: Renderer,
+    E: RenderElement<R>,
+{
+    let Some(mode) = output.current_mode() else {
+        return Vec::new();
+    };
+    let full = Rectangle::from_size((mode.size.w, mode.size.h).into());
+    output.user_data().insert_if_missing(ScreencopyDamage::default);
+    let mut tracker = output.user_data().get::<ScreencopyDamage>().unwrap().0.borrow_mut();
+    let tracker = tracker.get_or_insert_with(|| OutputDamageTracker::from_output(output));
+
+    let transform = output.current_transform();
+    let area = transform.transform_size(mode.size).to_logical(1);
+    match tracker.damage_output::<R, _>(1, elements) {
+        Ok((damage, _)) => damage
+            .into_iter()
+            .flatten()
+            .map(|rect| rect.to_logical(1).to_buffer(1, transform, &area))
+            .collect(),
+        Err(err) => {
+            warn!("Failed to track damage for screencopy: {:?}", err);
+            vec![full]
+        }
+    }
+}
+
+/// Fulfill `screencopies` of `output` with its `elements`, returning the ones that wait for
+/// damage within their region.
+///
+/// `elements` are expected to be the elements just used to render the output.
+pub fn render_screencopies<R, T, E>(
+    renderer: &mut R,
+    output: &Output,
+    screencopies: Vec<Screencopy>,
+    elements: &[E],
+    clear_color: Color32F,
+    timestamp: Duration,
+) -> Vec<Screencopy>
+where
+    R: Renderer + Offscreen<T> + Bind<T> + Bind<Dmabuf> + ExportMem,
+    E: RenderElement<R>,
+{
+    if screencopies.is_empty() {
+        return screencopies;
+    }
+    let damage = screencopy_damage(output, elements);
+
+    let mut waiting = Vec::new();
+    for screencopy in screencopies {
+        profiling::scope!("screencopy");
+        let region = screencopy.region();
+        let region_damage = damage
+            .iter()
+            .filter_map(|rect| rect.intersection(region))
+            .map(|rect| Rectangle::new(rect.loc - region.loc, rect.size))
+            .collect::<Vec<_>>();
+        if screencopy.with_damage() && region_damage.is_empty() {
+            waiting.push(screencopy);
+            continue;
+        }
+        render_screencopy::<_, T, _>(renderer, screencopy, elements, clear_color, &region_damage, timestamp);
+    }
+    waiting
+}
+
+/// Render `elements` of the output into the buffer of `screencopy` and submit it, reporting
+/// `damage` relative to its region
+fn render_screencopy<R, T, E>(
+    renderer: &mut R,
+    screencopy: Screencopy,
+    elements: &[E],
+    clear_color: Color32F,
+    damage: &[Rectangle<i32, Buffer>],
+    timestamp: Duration,
+) where
+    R: Renderer + Offscreen<T> + Bind<T> + Bind<Dmabuf> + ExportMem,
+    E: RenderElement<R>,
+{
+    let output = screencopy.output().clone();
+    let mut damage_tracker = OutputDamageTracker::from_output(&output);
+    let elements = elements
+        .iter()
+        .filter(|element| screencopy.overlay_cursor() || element.kind() != Kind::Cursor)
+        .collect::<Vec<_>>();
+
+    let res = if let Ok(dmabuf) = get_dmabuf(screencopy.buffer()) {
+        // render straight into the client buffer
+        let mut dmabuf = dmabuf.clone();
+        renderer
+            .bind(&mut dmabuf)
+            .map_err(|err| format!("{:?}", err))
+            .and_then(|mut framebuffer| {
+                damage_tracker
+                    .render_output(renderer, &mut framebuffer, 0, &elements, clear_color)
+                    .map(|_| ())
+                    .map_err(|err| format!("{:?}", err))
+            })
+    } else {
+        render_screencopy_shm(renderer, &screencopy, &mut damage_tracker, &elements, clear_color)
+    };
+
+    match res {
+        Ok(()) => {
+            screencopy.damage(damage.iter().copied());
+            screencopy.submit(false, timestamp);
+        }
+        Err(err) => warn!("Failed to copy output for screencopy: {}", err),
+    }
+}
+
+/// Render `elements` of the output into `dmabuf` and hand it to the client of `export`, returns
+/// whether it was handed out.
+///
+/// `elements` are expected to be the elements just used to render the output.
+pub fn render_dmabuf_export<R, E>(
+    renderer: &mut R,
+    export: DmabufExport,
+    dmabuf: &mut Dmabuf,
+    elements: &[E],
+    clear_color: Color32F,
+    timestamp: Duration,
+) -> bool
+where
+    R: Renderer + Bind<Dmabuf>,
+    E: RenderElement<R>,
+{
+    let mut damage_tracker = OutputDamageTracker::from_output(export.output());
+    let elements = elements
+        .iter()
+        .filter(|element| export.overlay_cursor() || element.kind() != Kind::Cursor)
+        .collect::<Vec<_>>();
+
+    let res = renderer
+        .bind(dmabuf)
+        .map_err(|err| format!("{:?}", err))
+        .and_then(|mut framebuffer| {
+            damage_tracker
+                .render_output(renderer, &mut framebuffer, 0, &elements, clear_color)
+                .map(|_| ())
+                .map_err(|err| format!("{:?}", err))
+        });
+
+    match res {
+        Ok(()) => {
+            export.export(dmabuf, timestamp);
+            true
+        }
+        Err(err) => {
+            warn!("Failed to render output for dmabuf export: {}", err);
+            false
+        }
+    }
+}
+
+fn render_screencopy_shm<R, T, E>(
+    renderer: &mut R,
+    screencopy: &Screencopy,
+    damage_tracker: &mut OutputDamageTracker,
+    elements: &[E],
+    clear_color: Color32F,
+) -> Result<(), String>
+where
+    R: Renderer + Offscreen<T> + Bind<T> + ExportMem,
+    E: RenderElement<R>,
+{
+    let output = screencopy.output();
+    let mode = output.current_mode().ok_or("output has no mode")?;
+    let region = screencopy.region();
+
+    let mut target = renderer
+        .create_buffer(Fourcc::Argb8888, (mode.size.w, mode.size.h).into())
+        .map_err(|err| format!("{:?}", err))?;
+    let mut framebuffer = renderer.bind(&mut target).map_err(|err| format!("{:?}", err))?;
+    damage_tracker
+        .render_output(renderer, &mut framebuffer, 0, elements, clear_color)
+        .map_err(|err| format!("{:?}", err))?;
+    let mapping = renderer
+        .copy_framebuffer(&framebuffer, region, Fourcc::Argb8888)
+        .map_err(|err| format!("{:?}", err))?;
+    let pixels = renderer
+        .map_texture(&mapping)
+        .map_err(|err| format!("{:?}", err))?;
+
+    with_buffer_contents_mut(screencopy.buffer(), |ptr, len, data| {
+        // Safety: the pointer and length are provided by the shm pool of the buffer
+        let dst = unsafe { std::slice::from_raw_parts_mut(ptr, len) };
+        let row_len = region.size.w as usize * 4;
+        for (row, src) in pixels.chunks_exact(row_len).enumerate() {
+            let offset = data.offset as usize + row * data.stride as usize;
+            dst[offset..offset + row_len].copy_from_slice(src);
+        }
+    })
+    .map_err(|err| format!("{:?}", err))
+}
+
+/// Fulfill the screencopies of `output` out of `pending` outside of the regular render path, e.g.
+/// for backends that don't keep the elements of the last frame around. Copies of other outputs
+/// and those waiting for damage stay in `pending`.
+pub fn render_pending_screencopies<R, T>(
+    renderer: &mut R,
+    output: &Output,
+    space: &Space<WindowElement>,
+    pending: &mut Vec<Screencopy>,
+    show_window_preview: bool,
+    clear_color: Color32F,
+    timestamp: Duration,
+) where
+    R: Renderer + ImportAll + ImportMem + Offscreen<T> + Bind<T> + Bind<Dmabuf> + ExportMem,
+    R::TextureId: Clone + 'static,
+{
+    let (screencopies, others): (Vec<_>, Vec<_>) = std::mem::take(pending)
+        .into_iter()
+        .partition(|screencopy| screencopy.output() == output);
+    *pending = others;
+    if screencopies.is_empty() {
+        return;
+    }
+
+    let (elements, clear_color) =
+        output_elements(output, space, [], renderer, show_window_preview, clear_color);
+    let waiting =
+        render_screencopies::<_, T, _>(renderer, output, screencopies, &elements, clear_color, timestamp);
+    pending.extend(waiting);
+}
@@ -0,0 +1,119 @@
+//! Rotation of the internal panel of convertibles, following the accelerometer exposed through the
+//! industrial I/O subsystem
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use smithay::utils::Transform;
+
+/// Tilt in degrees towards a side needed to switch to portrait, higher than for landscape so
+/// holding the device at an angle doesn't flip between the two
+const PORTRAIT_THRESHOLD: f64 = 60.0;
+const LANDSCAPE_THRESHOLD: f64 = 25.0;
+
+/// Whether the connector named `name` drives a built-in panel
+pub fn is_internal_panel(name: &str) -> bool {
+    ["eDP", "LVDS", "DSI"].iter().any(|prefix| name.starts_with(prefix))
+}
+
+/// `transform` turned by another quarter, staying flipped if it is
+pub fn rotate(transform: Transform) -> Transform {
+    match transform {
+        Transform::Normal => Transform::_90,
+        Transform::_90 => Transform::_180,
+        Transform::_180 => Transform::_270,
+        Transform::_270 => Transform::Normal,
+        Transform::Flipped => Transform::Flipped90,
+        Transform::Flipped90 => Transform::Flipped180,
+        Transform::Flipped180 => Transform::Flipped270,
+        Transform::Flipped270 => Transform::Flipped,
+    }
+}
+
+/// Accelerometer found in `/sys/bus/iio/devices`, read on demand
+#[derive(Debug)]
+pub struct Accelerometer {
+    path: PathBuf,
+    /// Rows of the matrix aligning the axes of the sensor with the panel, the identity if the
+    /// driver doesn't provide one
+    mount_matrix: [[f64; 3]; 3],
+}
+
+impl Accelerometer {
+    /// The first iio device measuring acceleration along all three axes
+    pub fn find() -> Option<Self> {
+        let path = fs::read_dir("/sys/bus/iio/devices")
+            .ok()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .find(|path| {
+                ["x", "y", "z"]
+                    .iter()
+                    .all(|axis| path.join(format!("in_accel_{}_raw", axis)).exists())
+            })?;
+        let mount_matrix = ["in_accel_mount_matrix", "mount_matrix"]
+            .iter()
+            .find_map(|name| fs::read_to_string(path.join(name)).ok())
+            .and_then(|matrix| parse_mount_matrix(&matrix))
+            .unwrap_or([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+        Some(Accelerometer { path, mount_matrix })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn axis(&self, axis: &str) -> io::Result<f64> {
+        let raw = fs::read_to_string(self.path.join(format!("in_accel_{}_raw", axis)))?;
+        raw.trim().parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid {} acceleration {:?}", axis, raw.trim()),
+            )
+        })
+    }
+
+    /// Transform keeping the content of the panel upright, `None` while the device lies flat or
+    /// is held at an angle in between
+    pub fn orientation(&self) -> io::Result<Option<Transform>> {
+        let raw = [self.axis("x")?, self.axis("y")?, self.axis("z")?];
+        let [x, y, z] = self
+            .mount_matrix
+            .map(|row| row.iter().zip(raw).map(|(factor, value)| factor * value).sum::<f64>());
+        Ok(orientation(x, y, z))
+    }
+}
+
+/// Parse a mount matrix written as `"x1, y1, z1; x2, y2, z2; x3, y3, z3"`
+fn parse_mount_matrix(matrix: &str) -> Option<[[f64; 3]; 3]> {
+    let rows = matrix
+        .trim()
+        .split(';')
+        .map(|row| {
+            let values = row
+                .split(',')
+                .map(|value| value.trim().parse::<f64>().ok())
+                .collect::<Option<Vec<_>>>()?;
+            values.try_into().ok()
+        })
+        .collect::<Option<Vec<[f64; 3]>>>()?;
+    rows.try_into().ok()
+}
+
+/// Orientation of the panel from the gravity measured along its axes, the way iio-sensor-proxy
+/// determines it. The scale of the values doesn't matter.
+fn orientation(x: f64, y: f64, z: f64) -> Option<Transform> {
+    let portrait = x.atan2((y * y + z * z).sqrt()).to_degrees();
+    let landscape = y.atan2((x * x + z * z).sqrt()).to_degrees();
+    if portrait.abs() > PORTRAIT_THRESHOLD {
+        // left or right side up
+        Some(if portrait > 0.0 { Transform::_270 } else { Transform::_90 })
+    } else if landscape.abs() > LANDSCAPE_THRESHOLD {
+        // bottom side up or upright
+        Some(if landscape > 0.0 { Transform::_180 } else { Transform::Normal })
+    } else {
+        None
+    }
+}
@@ -0,0 +1,187 @@
+//! Integration with the user session
+//!
+//! Once the outputs are set up, systemd is told the compositor is ready and, if enabled, the
+//! environment clients need is handed to systemd and dbus activated services before the configured
+//! services are started. The programs of the `exec_once` and `exec` lists follow as soon as
+//! XWayland is ready too, and are ended with the compositor.
+
+use std::{
+    collections::HashMap,
+    env, io,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::{
+            net::{SocketAddr, UnixDatagram},
+            process::CommandExt,
+        },
+    },
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+};
+
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    ipc::SOCKET_ENV,
+    state::{Backend, LuxoState},
+};
+
+/// Programs started from the `exec_once` and `exec` lists
+#[derive(Debug, Default)]
+pub struct Autostart {
+    /// Process groups of the programs still running, with their command
+    running: Arc<Mutex<HashMap<u32, String>>>,
+    /// Whether the lists ran at startup already
+    started: bool,
+}
+
+/// Tell the service manager that started the compositor that it is ready, if it asked for it
+fn notify_ready() -> io::Result<()> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let socket = UnixDatagram::unbound()?;
+    // abstract sockets are given with a leading `@`
+    match path.to_str().and_then(|path| path.strip_prefix('@')) {
+        Some(name) => {
+            socket.send_to_addr(b"READY=1", &SocketAddr::from_abstract_name(name)?)?;
+        }
+        None => {
+            socket.send_to(b"READY=1", &path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Run `command` without waiting for it, logging how it ended
+fn run_detached(mut command: Command) {
+    let program = format!("{:?}", command.get_program());
+    command.stdin(Stdio::null());
+    match command.spawn() {
+        Ok(mut child) => {
+            std::thread::spawn(move || match child.wait() {
+                Ok(status) if status.success() => debug!(program, "Session command finished"),
+                Ok(status) => warn!(program, %status, "Session command failed"),
+                Err(err) => warn!(program, %err, "Failed to wait for session command"),
+            });
+        }
+        Err(err) => warn!(program, %err, "Failed to run session command"),
+    }
+}
+
+impl<BackendData: Backend> LuxoState<BackendData> {
+    /// Start the session once the globals and the initial outputs are set up, runs once at startup
+    pub fn start_session(&mut self) {
+        if let Err(err) = notify_ready() {
+            warn!(%err, "Failed to notify systemd about being ready");
+        }
+
+        let session = self.config.session.clone();
+        if session.systemd {
+            let mut variables = Vec::new();
+            if let Some(socket_name) = self.socket_name.as_ref() {
+                variables.push(("WAYLAND_DISPLAY".to_owned(), socket_name.clone()));
+            }
+            if let Some(ipc_socket) = self.ipc_socket.as_ref() {
+                variables.push((SOCKET_ENV.to_owned(), ipc_socket.path().display().to_string()));
+            }
+            variables.extend(self.config.environment.clone());
+            export_environment(&variables);
+
+            for unit in &session.services {
+                info!(unit, "Starting user service");
+                let mut command = Command::new("systemctl");
+                command.args(["--user", "start", "--no-block", unit]);
+                run_detached(command);
+            }
+        }
+
+        // programs get DISPLAY if XWayland is about to be ready
+        #[cfg(feature = "xwayland")]
+        if self.xwayland.is_some() && self.xdisplay.is_none() {
+            return;
+        }
+        self.start_autostart();
+    }
+
+    /// Run the `exec_once` and `exec` programs unless they ran already
+    pub fn start_autostart(&mut self) {
+        if std::mem::replace(&mut self.autostart.started, true) {
+            return;
+        }
+        self.run_autostart(true);
+    }
+
+    /// End the programs still running from the autostart lists and run them all again
+    pub fn rerun_autostart(&mut self) {
+        self.stop_autostart();
+        self.autostart.started = true;
+        self.run_autostart(true);
+    }
+
+    /// Run the `exec` programs, and the `exec_once` ones with `once`
+    pub fn run_autostart(&mut self, once: bool) {
+        let session = &self.config.session;
+        let commands = once
+            .then_some(&session.exec_once)
+            .into_iter()
+            .chain([&session.exec])
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+        for cmd in commands {
+            info!(cmd, "Starting autostart program");
+            let mut command = self.program_command(&cmd);
+            // in a process group of its own, to end whatever the shell started along with it
+            command.process_group(0);
+            match command.spawn() {
+                Ok(mut child) => {
+                    let running = self.autostart.running.clone();
+                    let pid = child.id();
+                    running.lock().unwrap().insert(pid, cmd.clone());
+                    std::thread::spawn(move || {
+                        match child.wait() {
+                            Ok(status) => debug!(cmd, %status, "Autostart program exited"),
+                            Err(err) => warn!(cmd, %err, "Failed to wait for autostart program"),
+                        }
+                        running.lock().unwrap().remove(&pid);
+                    });
+                }
+                Err(err) => error!(cmd, %err, "Failed to start autostart program"),
+            }
+        }
+    }
+
+    /// Ask the programs still running from the autostart lists to terminate
+    pub fn stop_autostart(&mut self) {
+        for (pid, cmd) in self.autostart.running.lock().unwrap().iter() {
+            debug!(cmd, pid, "Terminating autostart program");
+            unsafe { libc::kill(-(*pid as i32), libc::SIGTERM) };
+        }
+    }
+
+    /// Hand the display of XWayland to services once it is ready, which is usually after the session
+    /// started
+    #[cfg(feature = "xwayland")]
+    pub fn export_xwayland_display(&self) {
+        if let (true, Some(xdisplay)) = (self.config.session.systemd, self.xdisplay) {
+            export_environment(&[("DISPLAY".to_owned(), format!(":{}", xdisplay))]);
+        }
+    }
+}
+
+/// Set `variables` in the environment of services started by systemd or through dbus activation
+fn export_environment(variables: &[(String, String)]) {
+    let assignments = variables
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>();
+    debug!(?assignments, "Exporting the environment to the session");
+
+    let mut systemctl = Command::new("systemctl");
+    systemctl.args(["--user", "set-environment"]).args(&assignments);
+    run_detached(systemctl);
+    let mut dbus = Command::new("dbus-update-activation-environment");
+    dbus.args(&assignments);
+    run_detached(dbus);
+}
@@ -0,0 +1,442 @@
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use smithay::{
+    backend::renderer::{
+        element::{Element, Id, RenderElement},
+        utils::{CommitCounter, RendererSurfaceStateUserData},
+        Frame, Renderer, Texture,
+    },
+    desktop::space::SpaceElement,
+    output::Output,
+    utils::{Buffer, IsAlive, Logical, Physical, Point, Rectangle, Scale, Size, Transform},
+    wayland::{
+        compositor::{with_surface_tree_downward, TraversalAction},
+        seat::WaylandFocus,
+    },
+};
+
+use crate::{state::Backend, LuxoState};
+
+use super::{ssd::HEADER_BAR_HEIGHT, WindowElement};
+
+/// How long window and workspace transitions take
+pub const ANIMATION_DURATION: Duration = Duration::from_millis(250);
+
+/// How long the contents of a window asked to close are kept to fade it out once it goes away.
+/// Windows asking to save changes first take longer and just disappear.
+const CLOSE_SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A transition started at a fixed point in time
+#[derive(Debug, Clone, Copy)]
+pub struct Animation {
+    start: Instant,
+    duration: Duration,
+}
+
+impl Animation {
+    pub fn new(duration: Duration) -> Self {
+        Animation {
+            start: Instant::now(),
+            duration,
+        }
+    }
+
+    /// Eased progress between 0 and 1, or `None` once the animation is over.
+    ///
+    /// Nothing drives animations except the render loops, which repaint every frame anyway.
+    pub fn progress(&self) -> Option<f64> {
+        let linear = self.start.elapsed().as_secs_f64() / self.duration.as_secs_f64();
+        // ease out cubic
+        (linear < 1.0).then(|| 1.0 - (1.0 - linear).powi(3))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+enum Fade {
+    #[default]
+    None,
+    /// Fade in as soon as the window has something to show
+    InPending,
+    In(Animation),
+}
+
+/// Running animations of a window, stored in its user data
+#[derive(Default)]
+struct WindowAnimations {
+    fade: Cell<Fade>,
+    /// Offset from the location the window moved away from
+    movement: Cell<Option<(Animation, Point<i32, Logical>)>>,
+    /// When the window was last asked to close
+    close_requested: Cell<Option<Instant>>,
+    /// Contents of the window as of its last repaint since it was asked to close
+    close_snapshot: RefCell<Option<CloseSnapshot>>,
+}
+
+/// Contents of a window asked to close, faded out once the client unmapped it
+struct CloseSnapshot {
+    /// `Vec<SnapshotSurface<T>>` for the texture type of the renderer that took the snapshot
+    surfaces: Rc<dyn Any>,
+    /// Location of the window geometry relative to the origin of the surfaces
+    geometry_loc: Point<i32, Logical>,
+    /// Area the surfaces cover, relative to their origin
+    bbox: Rectangle<i32, Logical>,
+}
+
+/// A surface of a window with the texture it was last rendered from
+struct SnapshotSurface<T> {
+    id: Id,
+    texture: T,
+    /// Location relative to the origin of the window's surfaces
+    location: Point<i32, Logical>,
+    src: Rectangle<f64, Buffer>,
+    size: Size<i32, Logical>,
+    transform: Transform,
+}
+
+/// A window fading out after its client unmapped it, kept in the user data of the outputs it
+/// was on
+struct ClosingWindow {
+    surfaces: Rc<dyn Any>,
+    /// Origin of the window's surfaces relative to the output
+    location: Point<i32, Logical>,
+    animation: Animation,
+    commit: CommitCounter,
+}
+
+#[derive(Default)]
+struct ClosingWindows(RefCell<Vec<ClosingWindow>>);
+
+/// A surface of a closed window, drawn while it fades out
+pub struct SnapshotElement<T> {
+    id: Id,
+    commit: CommitCounter,
+    texture: T,
+    geometry: Rectangle<i32, Physical>,
+    src: Rectangle<f64, Buffer>,
+    transform: Transform,
+    alpha: f32,
+}
+
+impl<T> std::fmt::Debug for SnapshotElement<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SnapshotElement")
+            .field("id", &self.id)
+            .field("geometry", &self.geometry)
+            .field("alpha", &self.alpha)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Element for SnapshotElement<T> {
+    fn id(&self) -> &Id {
+        &self.id
+    }
+
+    fn current_commit(&self) -> CommitCounter {
+        self.commit
+    }
+
+    fn src(&self) -> Rectangle<f64, Buffer> {
+        self.src
+    }
+
+    fn geometry(&self, _scale: Scale<f64>) -> Rectangle<i32, Physical> {
+        self.geometry
+    }
+
+    fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    fn alpha(&self) -> f32 {
+        self.alpha
+    }
+}
+
+impl<R> RenderElement<R> for SnapshotElement<R::TextureId>
+where
+    R: Renderer,
+    R::TextureId: 'static,
+{
+    fn draw(
+        &self,
+        frame: &mut R::Frame<'_, '_>,
+        src: Rectangle<f64, Buffer>,
+        dst: Rectangle<i32, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+        opaque_regions: &[Rectangle<i32, Physical>],
+    ) -> Result<(), R::Error> {
+        frame.render_texture_from_to(
+            &self.texture,
+            src,
+            dst,
+            damage,
+            opaque_regions,
+            self.transform,
+            self.alpha,
+        )
+    }
+}
+
+/// Elements of the windows fading out on `output` after they closed
+pub fn closing_window_elements<R>(output: &Output) -> Vec<SnapshotElement<R::TextureId>>
+where
+    R: Renderer,
+    R::TextureId: Clone + 'static,
+{
+    let Some(closing) = output.user_data().get::<ClosingWindows>() else {
+        return Vec::new();
+    };
+    let scale = Scale::from(output.current_scale().fractional_scale());
+    let mut closing = closing.0.borrow_mut();
+    closing.retain(|window| window.animation.progress().is_some());
+
+    let mut elements = Vec::new();
+    for window in closing.iter_mut() {
+        let (Some(progress), Some(surfaces)) = (
+            window.animation.progress(),
+            window.surfaces.downcast_ref::<Vec<SnapshotSurface<R::TextureId>>>(),
+        ) else {
+            continue;
+        };
+        // the alpha changes every frame
+        window.commit.increment();
+        elements.extend(surfaces.iter().map(|surface| SnapshotElement {
+            id: surface.id.clone(),
+            commit: window.commit,
+            texture: surface.texture.clone(),
+            geometry: Rectangle::new(
+                (window.location + surface.location).to_physical_precise_round(scale),
+                surface.size.to_physical_precise_round(scale),
+            ),
+            src: surface.src,
+            transform: surface.transform,
+            alpha: (1.0 - progress) as f32,
+        }));
+    }
+    elements
+}
+
+impl WindowElement {
+    fn animations(&self) -> &WindowAnimations {
+        self.user_data().insert_if_missing(WindowAnimations::default);
+        self.user_data().get::<WindowAnimations>().unwrap()
+    }
+
+    /// Opacity and location offset the window currently has to be rendered with
+    pub(super) fn animation_state(&self, has_content: bool) -> (f32, Point<f64, Logical>) {
+        let animations = self.animations();
+
+        let opacity = match animations.fade.get() {
+            Fade::None => 1.0,
+            Fade::InPending if has_content => {
+                animations.fade.set(Fade::In(Animation::new(ANIMATION_DURATION)));
+                0.0
+            }
+            Fade::InPending => 0.0,
+            Fade::In(animation) => animation.progress().unwrap_or_else(|| {
+                animations.fade.set(Fade::None);
+                1.0
+            }),
+        };
+
+        (opacity as f32, self.movement_offset())
+    }
+
+    /// Keep what the window shows from now on, to fade it out once the client unmaps it
+    pub(super) fn keep_close_snapshot(&self) {
+        self.animations().close_requested.set(Some(Instant::now()));
+    }
+
+    /// Take a snapshot of the window's surfaces if it was recently asked to close, called whenever
+    /// it is rendered. `ssd` is set if its surfaces are drawn below a header bar.
+    pub(super) fn update_close_snapshot<R>(&self, renderer: &R, ssd: bool)
+    where
+        R: Renderer,
+        R::TextureId: Clone + Texture + 'static,
+    {
+        let animations = self.animations();
+        let Some(requested) = animations.close_requested.get() else {
+            return;
+        };
+        if requested.elapsed() > CLOSE_SNAPSHOT_TIMEOUT {
+            animations.close_requested.set(None);
+            animations.close_snapshot.borrow_mut().take();
+            return;
+        }
+        let Some(surface) = self.0.wl_surface() else {
+            return;
+        };
+
+        let origin = Point::<i32, Logical>::from((0, if ssd { HEADER_BAR_HEIGHT } else { 0 }));
+        let mut surfaces = Vec::new();
+        with_surface_tree_downward(
+            &surface,
+            origin,
+            |_, states, location| {
+                let view = states
+                    .data_map
+                    .get::<RendererSurfaceStateUserData>()
+                    .and_then(|data| data.lock().unwrap().view());
+                match view {
+                    Some(view) => TraversalAction::DoChildren(*location + view.offset),
+                    None => TraversalAction::SkipChildren,
+                }
+            },
+            |_, states, location| {
+                let Some(data) = states.data_map.get::<RendererSurfaceStateUserData>() else {
+                    return;
+                };
+                let data = data.lock().unwrap();
+                let (Some(view), Some(buffer_size), Some(texture)) =
+                    (data.view(), data.buffer_size(), data.texture(&renderer.context_id()))
+                else {
+                    return;
+                };
+                surfaces.push(SnapshotSurface {
+                    id: Id::new(),
+                    texture: texture.clone(),
+                    location: *location + view.offset,
+                    src: view.src.to_buffer(
+                        data.buffer_scale() as f64,
+                        data.buffer_transform(),
+                        &buffer_size.to_f64(),
+                    ),
+                    size: view.dst,
+                    transform: data.buffer_transform(),
+                });
+            },
+            |_, _, _| true,
+        );
+
+        let snapshot = (!surfaces.is_empty()).then(|| CloseSnapshot {
+            bbox: surfaces
+                .iter()
+                .map(|surface| Rectangle::new(surface.location, surface.size))
+                .reduce(|bbox, rect| bbox.merge(rect))
+                .unwrap_or_default(),
+            geometry_loc: SpaceElement::geometry(self).loc,
+            surfaces: Rc::new(surfaces),
+        });
+        *animations.close_snapshot.borrow_mut() = snapshot;
+    }
+
+    fn movement_offset(&self) -> Point<f64, Logical> {
+        let movement = &self.animations().movement;
+        let Some((animation, from)) = movement.get() else {
+            return Point::default();
+        };
+        match animation.progress() {
+            Some(progress) => {
+                Point::from((from.x as f64 * (1.0 - progress), from.y as f64 * (1.0 - progress)))
+            }
+            None => {
+                movement.set(None);
+                Point::default()
+            }
+        }
+    }
+}
+
+impl<BackendData: Backend> LuxoState<BackendData> {
+    /// Fade in a newly mapped window
+    pub fn animate_open(&self, window: &WindowElement) {
+        if self.config.appearance.animations {
+            window.animations().fade.set(Fade::InPending);
+        }
+    }
+
+    /// Map `window` at `location`, sliding it there from where it was mapped before
+    pub fn map_window_animated(
+        &mut self,
+        window: &WindowElement,
+        location: Point<i32, Logical>,
+        activate: bool,
+    ) {
+        if self.config.appearance.animations {
+            if let Some(previous) = self.space.element_location(window) {
+                // continue from wherever a running animation currently shows the window
+                let from = previous - location + window.movement_offset().to_i32_round();
+                if from != Point::default() {
+                    window
+                        .animations()
+                        .movement
+                        .set(Some((Animation::new(ANIMATION_DURATION), from)));
+                }
+            }
+        }
+        self.space.map_element(window.clone(), location, activate);
+    }
+
+    /// Map `window`, which isn't in the space yet, at `location`, sliding in from `offset` away
+    pub fn map_window_sliding_in(
+        &mut self,
+        window: &WindowElement,
+        location: Point<i32, Logical>,
+        offset: Point<i32, Logical>,
+        activate: bool,
+    ) {
+        if self.config.appearance.animations {
+            window
+                .animations()
+                .movement
+                .set(Some((Animation::new(ANIMATION_DURATION), offset)));
+        }
+        self.space.map_element(window.clone(), location, activate);
+    }
+
+    /// Fade out `window`, which is about to leave the space, if it was asked to close before. Its
+    /// surfaces may be gone already, only what the space knows about it is used.
+    pub fn fade_out_closed(&self, window: &WindowElement) {
+        let animations = window.animations();
+        let Some(snapshot) = animations.close_snapshot.borrow_mut().take() else {
+            return;
+        };
+        let requested = animations.close_requested.take();
+        if !requested.is_some_and(|requested| requested.elapsed() <= CLOSE_SNAPSHOT_TIMEOUT) {
+            return;
+        }
+        let Some(location) = self.space.element_location(window) else {
+            return;
+        };
+        let origin = location - snapshot.geometry_loc;
+        let area = Rectangle::new(origin + snapshot.bbox.loc, snapshot.bbox.size);
+        for output in self.space.outputs() {
+            let Some(output_geometry) = self
+                .space
+                .output_geometry(output)
+                .filter(|geometry| geometry.overlaps(area))
+            else {
+                continue;
+            };
+            output.user_data().insert_if_missing(ClosingWindows::default);
+            let closing = output.user_data().get::<ClosingWindows>().unwrap();
+            closing.0.borrow_mut().push(ClosingWindow {
+                surfaces: snapshot.surfaces.clone(),
+                location: origin - output_geometry.loc,
+                animation: Animation::new(ANIMATION_DURATION),
+                commit: CommitCounter::default(),
+            });
+        }
+    }
+
+    /// Drop the windows of destroyed toplevels from the space, fading out the ones that were
+    /// asked to close
+    pub fn refresh_space(&mut self) {
+        let closed = self
+            .space
+            .elements()
+            .filter(|window| !window.alive())
+            .cloned()
+            .collect::<Vec<_>>();
+        for window in closed {
+            self.fade_out_closed(&window);
+        }
+        self.space.refresh();
+    }
+}
@@ -0,0 +1,270 @@
+use std::cell::RefCell;
+
+use smithay::{
+    desktop::{space::SpaceElement, WindowSurface},
+    output::Output,
+    utils::{Logical, Point, Rectangle},
+    wayland::compositor::with_states,
+};
+use tracing::debug;
+
+use crate::{
+    keybindings::{Direction, OutputTarget},
+    state::Backend,
+    LuxoState,
+};
+
+use super::{
+    constrain_size, usable_geometry, ResizeData, ResizeEdge, ResizeState, SurfaceData, WindowElement,
+};
+
+/// Distance in logical pixels a window is moved or resized by from the keyboard
+const KEYBOARD_STEP: i32 = 40;
+
+impl<BackendData: Backend + 'static> LuxoState<BackendData> {
+    /// The focused window, unless its geometry is managed by the compositor
+    fn arrangeable_window(&self) -> Option<WindowElement> {
+        self.focused_window()
+            .filter(|window| !window.is_fullscreen() && !window.is_maximized())
+    }
+
+    fn place_window(&mut self, window: &WindowElement, location: Point<i32, Logical>) {
+        self.map_window_animated(window, location, true);
+        #[cfg(feature = "xwayland")]
+        if let Some(surface) = window.0.x11_surface() {
+            let _ = surface.configure(Rectangle::new(location, window.geometry().size));
+        }
+    }
+
+    /// Move the focused window by a step in `direction`
+    pub fn move_focused_window(&mut self, direction: Direction) {
+        let Some(window) = self.arrangeable_window() else {
+            return;
+        };
+        let Some(mut location) = self.space.element_location(&window) else {
+            return;
+        };
+
+        match direction {
+            Direction::Left => location.x -= KEYBOARD_STEP,
+            Direction::Right => location.x += KEYBOARD_STEP,
+            Direction::Up => location.y -= KEYBOARD_STEP,
+            Direction::Down => location.y += KEYBOARD_STEP,
+        }
+
+        window.unsnap();
+        self.place_window(&window, location);
+    }
+
+    /// Center the focused window in the usable area of its output
+    pub fn center_focused_window(&mut self) {
+        let Some(window) = self.arrangeable_window() else {
+            return;
+        };
+        let Some(area) = self
+            .space
+            .outputs_for_element(&window)
+            .first()
+            .and_then(|output| usable_geometry(&self.space, output))
+        else {
+            return;
+        };
+
+        let size = window.geometry().size;
+        let location = Point::from((
+            area.loc.x + (area.size.w - size.w) / 2,
+            area.loc.y + (area.size.h - size.h) / 2,
+        ));
+
+        window.unsnap();
+        self.place_window(&window, location);
+    }
+
+    /// Push the edge of the focused window on the `direction` side outwards by a step
+    pub fn grow_focused_window(&mut self, direction: Direction) {
+        self.resize_focused_window(direction, KEYBOARD_STEP);
+    }
+
+    /// Pull the edge of the focused window on the `direction` side inwards by a step
+    pub fn shrink_focused_window(&mut self, direction: Direction) {
+        self.resize_focused_window(direction, -KEYBOARD_STEP);
+    }
+
+    fn resize_focused_window(&mut self, direction: Direction, step: i32) {
+        let Some(window) = self.arrangeable_window() else {
+            return;
+        };
+        let Some(location) = self.space.element_location(&window) else {
+            return;
+        };
+
+        let initial_size = window.geometry().size;
+        let (edges, size) = match direction {
+            Direction::Left => (
+                ResizeEdge::LEFT,
+                constrain_size(&window, initial_size.w + step, initial_size.h),
+            ),
+            Direction::Right => (
+                ResizeEdge::RIGHT,
+                constrain_size(&window, initial_size.w + step, initial_size.h),
+            ),
+            Direction::Up => (
+                ResizeEdge::TOP,
+                constrain_size(&window, initial_size.w, initial_size.h + step),
+            ),
+            Direction::Down => (
+                ResizeEdge::BOTTOM,
+                constrain_size(&window, initial_size.w, initial_size.h + step),
+            ),
+        };
+        if size == initial_size {
+            return;
+        }
+
+        window.unsnap();
+        match window.0.underlying_surface() {
+            WindowSurface::Wayland(xdg) => {
+                xdg.with_pending_state(|state| {
+                    state.size = Some(size);
+                });
+                let Some(serial) = xdg.send_pending_configure() else {
+                    return;
+                };
+
+                // The location is adjusted for the top and left edges once the client commits
+                // the new size, just like for a pointer driven resize that just got released.
+                with_states(xdg.wl_surface(), |states| {
+                    states
+                        .data_map
+                        .get::<RefCell<SurfaceData>>()
+                        .unwrap()
+                        .borrow_mut()
+                        .resize_state = ResizeState::WaitingForFinalAck(
+                        ResizeData {
+                            edges,
+                            initial_window_location: location,
+                            initial_window_size: initial_size,
+                        },
+                        serial,
+                    );
+                });
+            }
+            #[cfg(feature = "xwayland")]
+            WindowSurface::X11(x11) => {
+                let mut location = location;
+                if edges.intersects(ResizeEdge::LEFT) {
+                    location.x += initial_size.w - size.w;
+                }
+                if edges.intersects(ResizeEdge::TOP) {
+                    location.y += initial_size.h - size.h;
+                }
+
+                let _ = x11.configure(Rectangle::new(location, size));
+                self.map_window_animated(&window, location, true);
+            }
+        }
+    }
+
+    /// Move the focused window to the output `target` of its current one, keeping its position and
+    /// size relative to the usable area, so it covers the same part of outputs of another resolution
+    /// or scale. Maximized windows fill the usable area of the new output.
+    pub fn move_focused_window_to_output(&mut self, target: OutputTarget) {
+        let Some(window) = self.focused_window().filter(|window| !window.is_fullscreen()) else {
+            return;
+        };
+        let Some(from) = self.space.outputs_for_element(&window).first().cloned() else {
+            return;
+        };
+        let Some(to) = self.output_towards(&from, target) else {
+            return;
+        };
+        let (Some(from_area), Some(to_area), Some(location)) = (
+            usable_geometry(&self.space, &from),
+            usable_geometry(&self.space, &to),
+            self.space.element_location(&window),
+        ) else {
+            return;
+        };
+
+        let initial_size = window.geometry().size;
+        let (location, size) = if window.is_maximized() {
+            (to_area.loc, to_area.size)
+        } else {
+            let scale =
+                |value: i32, from: i32, to: i32| (value as i64 * to as i64 / from.max(1) as i64) as i32;
+            let size = constrain_size(
+                &window,
+                scale(initial_size.w, from_area.size.w, to_area.size.w).min(to_area.size.w),
+                scale(initial_size.h, from_area.size.h, to_area.size.h).min(to_area.size.h),
+            );
+            let relative = location - from_area.loc;
+            let x = to_area.loc.x + scale(relative.x, from_area.size.w, to_area.size.w);
+            let y = to_area.loc.y + scale(relative.y, from_area.size.h, to_area.size.h);
+            // keep it inside the new output in case the size could not shrink along
+            let location = Point::from((
+                x.min(to_area.loc.x + to_area.size.w - size.w).max(to_area.loc.x),
+                y.min(to_area.loc.y + to_area.size.h - size.h).max(to_area.loc.y),
+            ));
+            (location, size)
+        };
+        debug!(from = ?from.name(), to = ?to.name(), window = window.id(), "Moving window to output");
+
+        window.unsnap();
+        match window.0.underlying_surface() {
+            WindowSurface::Wayland(xdg) => {
+                if size != initial_size {
+                    xdg.with_pending_state(|state| state.size = Some(size));
+                    xdg.send_pending_configure();
+                }
+            }
+            #[cfg(feature = "xwayland")]
+            WindowSurface::X11(x11) => {
+                let _ = x11.configure(Rectangle::new(location, size));
+            }
+        }
+        self.map_window_animated(&window, location, true);
+    }
+
+    /// The output `target` of `from`
+    fn output_towards(&self, from: &Output, target: OutputTarget) -> Option<Output> {
+        let direction = match target {
+            OutputTarget::Direction(direction) => direction,
+            OutputTarget::Next | OutputTarget::Prev => {
+                let mut outputs = self
+                    .space
+                    .outputs()
+                    .filter_map(|output| Some((self.space.output_geometry(output)?.loc, output)))
+                    .collect::<Vec<_>>();
+                outputs.sort_by_key(|(location, _)| (location.x, location.y));
+                let index = outputs.iter().position(|(_, output)| *output == from)?;
+                let count = outputs.len();
+                let index = match target {
+                    OutputTarget::Next => (index + 1) % count,
+                    _ => (index + count - 1) % count,
+                };
+                return Some(outputs[index].1.clone()).filter(|output| output != from);
+            }
+        };
+
+        let center = |geometry: Rectangle<i32, Logical>| -> Point<f64, Logical> {
+            geometry.loc.to_f64() + geometry.size.to_f64().downscale(2.0).to_point()
+        };
+        let origin = center(self.space.output_geometry(from)?);
+        self.space
+            .outputs()
+            .filter(|output| *output != from)
+            .filter_map(|output| {
+                let offset = center(self.space.output_geometry(output)?) - origin;
+                // distance along the direction and across it, like for focusing windows
+                let (along, across) = match direction {
+                    Direction::Left => (-offset.x, offset.y),
+                    Direction::Right => (offset.x, offset.y),
+                    Direction::Up => (-offset.y, offset.x),
+                    Direction::Down => (offset.y, offset.x),
+                };
+                (along > 0.0).then(|| (output, along + across.abs() * 2.0))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(output, _)| output.clone())
+    }
+}
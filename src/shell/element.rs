@@ -1,4 +1,9 @@
-use std::{borrow::Cow, time::Duration};
+use std::{
+    borrow::Cow,
+    cell::{Cell, Ref, RefCell},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
 use smithay::{
     backend::renderer::{
@@ -19,20 +24,78 @@ use smithay::{
     },
     output::Output,
     reexports::{
-        wayland_protocols::wp::presentation_time::server::wp_presentation_feedback,
-        wayland_server::protocol::wl_surface::WlSurface,
+        wayland_protocols::{
+            wp::{
+                content_type::v1::server::wp_content_type_v1::Type as ContentType,
+                presentation_time::server::wp_presentation_feedback,
+            },
+            xdg::shell::server::xdg_toplevel,
+        },
+        wayland_server::{protocol::wl_surface::WlSurface, Resource},
     },
     render_elements,
     utils::{user_data::UserDataMap, IsAlive, Logical, Physical, Point, Rectangle, Scale, Serial},
-    wayland::{compositor::SurfaceData as WlSurfaceData, dmabuf::DmabufFeedback, seat::WaylandFocus},
+    wayland::{
+        compositor::{with_states, SurfaceData as WlSurfaceData},
+        content_type::ContentTypeSurfaceCachedState,
+        dmabuf::DmabufFeedback,
+        seat::WaylandFocus,
+        shell::xdg::XdgToplevelSurfaceData,
+    },
 };
 
-use super::ssd::HEADER_BAR_HEIGHT;
-use crate::{focus::PointerFocusTarget, state::Backend, LuxoState};
+use super::{ping::UNRESPONSIVE_ALPHA, ssd::HEADER_BAR_HEIGHT};
+use crate::{focus::PointerFocusTarget, state::Backend, ClientState, LuxoState};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct WindowElement(pub Window);
 
+static NEXT_WINDOW_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Identifier of a window, stored in its user data
+#[derive(Debug)]
+struct WindowId(u64);
+
+/// Title, app_id and process of a window, stored in its user data. Refreshed when the toplevel
+/// commits or the properties of the X11 window change, so they are consistent with what the
+/// client last committed.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct WindowMetadata {
+    title: Option<String>,
+    app_id: Option<String>,
+    pid: Option<i32>,
+}
+
+/// Set while a window wants attention but wasn't allowed to take the focus
+#[derive(Debug, Default)]
+struct Urgent(Cell<bool>);
+
+/// Lowest opacity a window can be set to, so it can't disappear entirely
+pub const MIN_OPACITY: f32 = 0.1;
+
+/// Opacity the window is drawn with, on top of its fade animation
+#[derive(Debug)]
+struct Opacity(Cell<f32>);
+
+impl Default for Opacity {
+    fn default() -> Self {
+        Opacity(Cell::new(1.0))
+    }
+}
+
+/// Set for windows stacked above all windows without it
+#[derive(Debug, Default)]
+struct AlwaysOnTop(Cell<bool>);
+
+/// Set for windows staying on screen when the workspace of their output is switched
+#[derive(Debug, Default)]
+struct Sticky(Cell<bool>);
+
+/// Set for windows that always have to be composited, e.g. because they misbehave when their
+/// buffers are scanned out directly
+#[derive(Debug, Default)]
+struct NoDirectScanout(Cell<bool>);
+
 impl WindowElement {
     pub fn surface_under(
         &self,
@@ -131,6 +194,188 @@ impl WindowElement {
     pub fn user_data(&self) -> &UserDataMap {
         self.0.user_data()
     }
+
+    /// Identifier of this window, unique for the lifetime of the compositor
+    pub fn id(&self) -> u64 {
+        self.user_data()
+            .insert_if_missing(|| WindowId(NEXT_WINDOW_ID.fetch_add(1, Ordering::Relaxed)));
+        self.user_data().get::<WindowId>().unwrap().0
+    }
+
+    /// Kind of content the client declared the window shows, `ContentType::None` if it didn't
+    pub fn content_type(&self) -> ContentType {
+        self.wl_surface().map_or(ContentType::None, |surface| {
+            with_states(&surface, |states| {
+                *states
+                    .cached_state
+                    .get::<ContentTypeSurfaceCachedState>()
+                    .current()
+                    .content_type()
+            })
+        })
+    }
+
+    /// Whether the window or any of its subsurfaces declared to show video
+    pub fn shows_video(&self) -> bool {
+        let mut video = false;
+        self.0.with_surfaces(|_, states| {
+            video |= *states
+                .cached_state
+                .get::<ContentTypeSurfaceCachedState>()
+                .current()
+                .content_type()
+                == ContentType::Video;
+        });
+        video
+    }
+
+    /// Ask the client to close the window
+    pub fn send_close(&self) {
+        #[allow(irrefutable_let_patterns)]
+        if let Some(toplevel) = self.0.toplevel() {
+            toplevel.send_close();
+        }
+        #[cfg(feature = "xwayland")]
+        if let Some(surface) = self.0.x11_surface() {
+            let _ = surface.close();
+        }
+    }
+
+    pub fn is_urgent(&self) -> bool {
+        self.user_data().get::<Urgent>().is_some_and(|urgent| urgent.0.get())
+    }
+
+    pub fn set_urgent(&self, urgent: bool) {
+        self.user_data().insert_if_missing(Urgent::default);
+        self.user_data().get::<Urgent>().unwrap().0.set(urgent);
+    }
+
+    pub fn opacity(&self) -> f32 {
+        self.user_data().get::<Opacity>().map_or(1.0, |opacity| opacity.0.get())
+    }
+
+    /// Set the opacity, clamped between [`MIN_OPACITY`] and 1.0
+    pub fn set_opacity(&self, opacity: f32) {
+        let opacity = if opacity.is_nan() {
+            1.0
+        } else {
+            opacity.clamp(MIN_OPACITY, 1.0)
+        };
+        self.user_data().insert_if_missing(Opacity::default);
+        self.user_data().get::<Opacity>().unwrap().0.set(opacity);
+    }
+
+    pub fn allows_direct_scanout(&self) -> bool {
+        !self
+            .user_data()
+            .get::<NoDirectScanout>()
+            .is_some_and(|no_scanout| no_scanout.0.get())
+    }
+
+    pub fn set_allows_direct_scanout(&self, allowed: bool) {
+        self.user_data().insert_if_missing(NoDirectScanout::default);
+        self.user_data().get::<NoDirectScanout>().unwrap().0.set(!allowed);
+    }
+
+    pub fn is_always_on_top(&self) -> bool {
+        self.user_data().get::<AlwaysOnTop>().is_some_and(|on_top| on_top.0.get())
+    }
+
+    pub fn set_always_on_top(&self, on_top: bool) {
+        self.user_data().insert_if_missing(AlwaysOnTop::default);
+        self.user_data().get::<AlwaysOnTop>().unwrap().0.set(on_top);
+    }
+
+    pub fn is_sticky(&self) -> bool {
+        self.user_data().get::<Sticky>().is_some_and(|sticky| sticky.0.get())
+    }
+
+    pub fn set_sticky(&self, sticky: bool) {
+        self.user_data().insert_if_missing(Sticky::default);
+        self.user_data().get::<Sticky>().unwrap().0.set(sticky);
+    }
+
+    /// Metadata as the client currently describes the window
+    fn read_metadata(&self) -> WindowMetadata {
+        match self.0.underlying_surface() {
+            WindowSurface::Wayland(toplevel) => {
+                let (title, app_id) = with_states(toplevel.wl_surface(), |states| {
+                    states
+                        .data_map
+                        .get::<XdgToplevelSurfaceData>()
+                        .map(|data| {
+                            let data = data.lock().unwrap();
+                            (data.title.clone(), data.app_id.clone())
+                        })
+                        .unwrap_or_default()
+                });
+                let pid = toplevel
+                    .wl_surface()
+                    .client()
+                    .and_then(|client| client.get_data::<ClientState>()?.pid.get().copied());
+                WindowMetadata { title, app_id, pid }
+            }
+            #[cfg(feature = "xwayland")]
+            WindowSurface::X11(surface) => WindowMetadata {
+                title: Some(surface.title()),
+                app_id: Some(surface.class()),
+                pid: surface.pid().map(|pid| pid as i32),
+            },
+        }
+    }
+
+    fn metadata(&self) -> Ref<'_, WindowMetadata> {
+        self.user_data()
+            .insert_if_missing(|| RefCell::new(self.read_metadata()));
+        self.user_data().get::<RefCell<WindowMetadata>>().unwrap().borrow()
+    }
+
+    /// Update the cached title, app_id and pid, returns whether any of them changed
+    pub fn refresh_metadata(&self) -> bool {
+        let metadata = self.read_metadata();
+        if *self.metadata() == metadata {
+            return false;
+        }
+        *self.user_data().get::<RefCell<WindowMetadata>>().unwrap().borrow_mut() = metadata;
+        true
+    }
+
+    pub fn title(&self) -> Option<String> {
+        self.metadata().title.clone()
+    }
+
+    /// The xdg app_id, or the window class for X11 windows
+    pub fn app_id(&self) -> Option<String> {
+        self.metadata().app_id.clone()
+    }
+
+    /// Process id of the client, from its socket credentials or the `_NET_WM_PID` of X11 windows,
+    /// which all share the connection of XWayland
+    pub fn pid(&self) -> Option<i32> {
+        self.metadata().pid
+    }
+
+    pub fn is_maximized(&self) -> bool {
+        match self.0.underlying_surface() {
+            WindowSurface::Wayland(toplevel) => toplevel
+                .current_state()
+                .states
+                .contains(xdg_toplevel::State::Maximized),
+            #[cfg(feature = "xwayland")]
+            WindowSurface::X11(surface) => surface.is_maximized(),
+        }
+    }
+
+    pub fn is_fullscreen(&self) -> bool {
+        match self.0.underlying_surface() {
+            WindowSurface::Wayland(toplevel) => toplevel
+                .current_state()
+                .states
+                .contains(xdg_toplevel::State::Fullscreen),
+            #[cfg(feature = "xwayland")]
+            WindowSurface::X11(surface) => surface.is_fullscreen(),
+        }
+    }
 }
 
 impl IsAlive for WindowElement {
@@ -195,7 +440,7 @@ impl<BackendData: Backend> PointerTarget<LuxoState<BackendData>> for SSD {
     ) {
         let mut state = self.0.decoration_state();
         if state.is_ssd {
-            state.header_bar.clicked(seat, data, &self.0, event.serial);
+            state.header_bar.clicked(seat, data, &self.0, event);
         }
     }
     fn axis(
@@ -428,6 +673,14 @@ where
         alpha: f32,
     ) -> Vec<C> {
         let window_bbox = SpaceElement::bbox(&self.0);
+        self.update_close_snapshot(renderer, self.decoration_state().is_ssd);
+
+        let (opacity, offset) = self.animation_state(!window_bbox.is_empty());
+        let mut alpha = alpha * opacity * self.opacity();
+        if self.is_unresponsive() {
+            alpha *= UNRESPONSIVE_ALPHA;
+        }
+        location += offset.to_physical_precise_round(scale);
 
         if self.decoration_state().is_ssd && !window_bbox.is_empty() {
             let window_geo = SpaceElement::geometry(&self.0);
@@ -0,0 +1,82 @@
+use std::mem;
+
+use smithay::{
+    desktop::{find_popup_root_surface, LayerSurface},
+    utils::{IsAlive, SERIAL_COUNTER},
+};
+
+use crate::{
+    focus::KeyboardFocusTarget,
+    state::{Backend, LuxoState},
+};
+
+/// Keyboard focus taken by layer surfaces, like launchers, to hand it back once they close
+#[derive(Debug, Default)]
+pub struct FocusReturn {
+    /// The keyboard focus as of the last change
+    current: Option<KeyboardFocusTarget>,
+    /// Layer surfaces that took the focus and the focus they took it from, the latest last
+    stack: Vec<(LayerSurface, Option<KeyboardFocusTarget>)>,
+}
+
+impl<BackendData: Backend> LuxoState<BackendData> {
+    /// Keep track of layer surfaces taking the keyboard focus, called on every focus change
+    pub fn track_focus_return(&mut self, target: Option<&KeyboardFocusTarget>) {
+        let previous = mem::replace(&mut self.focus_return.current, target.cloned());
+        let stack = &mut self.focus_return.stack;
+        match target {
+            Some(KeyboardFocusTarget::LayerSurface(layer)) => {
+                match stack.iter().position(|(taker, _)| taker == layer) {
+                    // the focus went back to a layer surface further down, like from its popup
+                    Some(index) => stack.truncate(index + 1),
+                    None => stack.push((layer.clone(), previous)),
+                }
+            }
+            // the user picked a window, closing the layer surfaces must not take it away again
+            Some(KeyboardFocusTarget::Window(_)) => stack.clear(),
+            Some(KeyboardFocusTarget::Popup(_)) | None => {}
+        }
+    }
+
+    /// `layer` got destroyed or can't take the keyboard focus anymore, hand the focus back to
+    /// what it took it from if it or one of its popups still has it
+    pub fn return_focus(&mut self, layer: &LayerSurface) {
+        let keyboard = self.seat.get_keyboard().unwrap();
+        let has_focus = match keyboard.current_focus() {
+            Some(KeyboardFocusTarget::LayerSurface(focus)) => focus == *layer,
+            Some(KeyboardFocusTarget::Popup(popup)) => {
+                find_popup_root_surface(&popup).is_ok_and(|root| root == *layer.wl_surface())
+            }
+            _ => false,
+        };
+
+        let stack = &mut self.focus_return.stack;
+        let previous = match stack.iter().position(|(taker, _)| taker == layer) {
+            Some(index) => {
+                let (_, previous) = stack.remove(index);
+                // a layer surface that took the focus from this one takes it from its predecessor
+                if let Some((_, above)) = stack.get_mut(index) {
+                    if matches!(above, Some(KeyboardFocusTarget::LayerSurface(taken)) if taken == layer) {
+                        *above = previous.clone();
+                    }
+                }
+                previous
+            }
+            None => None,
+        };
+        if !has_focus {
+            return;
+        }
+
+        let target = previous.filter(|target| match target {
+            KeyboardFocusTarget::Window(window) => self.space.elements().any(|element| element.0 == *window),
+            KeyboardFocusTarget::LayerSurface(other) => other.alive() && other.can_receive_keyboard_focus(),
+            KeyboardFocusTarget::Popup(popup) => popup.alive(),
+        });
+        // the grab of a popup of the layer surface would keep the focus on it
+        if matches!(keyboard.current_focus(), Some(KeyboardFocusTarget::Popup(_))) && keyboard.is_grabbed() {
+            keyboard.unset_grab(self);
+        }
+        keyboard.set_focus(self, target, SERIAL_COUNTER.next_serial());
+    }
+}
@@ -0,0 +1,126 @@
+use smithay::{
+    delegate_foreign_toplevel_list,
+    output::Output,
+    wayland::foreign_toplevel_list::{ForeignToplevelListHandler, ForeignToplevelListState},
+};
+
+use crate::{
+    delegate_foreign_toplevel,
+    protocols::foreign_toplevel::{ForeignToplevelHandler, ForeignToplevelManagerState, ToplevelInfo},
+    state::Backend,
+    LuxoState,
+};
+
+impl<BackendData: Backend> ForeignToplevelHandler for LuxoState<BackendData> {
+    fn foreign_toplevel_manager_state(&mut self) -> &mut ForeignToplevelManagerState {
+        &mut self.foreign_toplevel_state
+    }
+
+    fn activate(&mut self, window: u64) {
+        self.focus_window_by_id(window);
+    }
+
+    fn close(&mut self, window: u64) {
+        if let Some(window) = self.all_windows().into_iter().find(|w| w.id() == window) {
+            self.close_window(&window);
+        }
+    }
+
+    fn set_maximized(&mut self, window: u64, maximized: bool) {
+        // windows on hidden workspaces have no output to be maximized on
+        if let Some(window) = self.space.elements().find(|w| w.id() == window).cloned() {
+            self.set_window_maximized(&window, maximized);
+        }
+    }
+
+    fn set_minimized(&mut self, window: u64, minimized: bool) {
+        let Some(window) = self.all_windows().into_iter().find(|w| w.id() == window) else {
+            return;
+        };
+        if minimized {
+            self.minimize_window(&window);
+        } else {
+            self.unminimize_window(&window);
+        }
+    }
+
+    fn set_fullscreen(&mut self, window: u64, fullscreen: bool, _output: Option<Output>) {
+        // the window stays on its current output
+        if let Some(window) = self.space.elements().find(|w| w.id() == window).cloned() {
+            if window.is_fullscreen() != fullscreen {
+                self.toggle_fullscreen(&window);
+            }
+        }
+    }
+}
+delegate_foreign_toplevel!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
+
+impl<BackendData: Backend> ForeignToplevelListHandler for LuxoState<BackendData> {
+    fn foreign_toplevel_list_state(&mut self) -> &mut ForeignToplevelListState {
+        &mut self.foreign_toplevel_list_state
+    }
+}
+delegate_foreign_toplevel_list!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
+
+impl<BackendData: Backend + 'static> LuxoState<BackendData> {
+    /// Bring the foreign toplevel lists in line with the current windows and their state
+    pub fn update_foreign_toplevels(&mut self) {
+        let focused = self.focused_window();
+        let toplevels = self
+            .all_windows()
+            .into_iter()
+            .map(|window| {
+                let visible = self.space.elements().any(|w| w == &window);
+                let info = ToplevelInfo {
+                    title: window.title().unwrap_or_default(),
+                    app_id: window.app_id().unwrap_or_default(),
+                    outputs: if visible {
+                        self.space.outputs_for_element(&window)
+                    } else {
+                        Vec::new()
+                    },
+                    activated: focused.as_ref() == Some(&window),
+                    maximized: window.is_maximized(),
+                    minimized: self.is_minimized(&window),
+                    fullscreen: window.is_fullscreen(),
+                };
+                (window.id(), info)
+            })
+            .collect::<Vec<_>>();
+
+        self.foreign_toplevel_list_handles.retain(|id, handle| {
+            let keep = toplevels.iter().any(|(window, _)| window == id);
+            if !keep {
+                self.foreign_toplevel_list_state.remove_toplevel(handle);
+            }
+            keep
+        });
+        for (id, info) in &toplevels {
+            match self.foreign_toplevel_list_handles.get(id) {
+                Some(handle) => {
+                    let mut changed = false;
+                    if handle.title() != info.title {
+                        handle.send_title(&info.title);
+                        changed = true;
+                    }
+                    if handle.app_id() != info.app_id {
+                        handle.send_app_id(&info.app_id);
+                        changed = true;
+                    }
+                    if changed {
+                        handle.send_done();
+                    }
+                }
+                None => {
+                    let handle = self
+                        .foreign_toplevel_list_state
+                        .new_toplevel::<Self>(info.title.clone(), info.app_id.clone());
+                    self.foreign_toplevel_list_handles.insert(*id, handle);
+                }
+            }
+        }
+
+        let dh = self.display_handle.clone();
+        self.foreign_toplevel_state.update::<Self>(&dh, toplevels);
+    }
+}
@@ -6,19 +6,20 @@ use smithay::{
         pointer::{
             AxisFrame, ButtonEvent, GestureHoldBeginEvent, GestureHoldEndEvent, GesturePinchBeginEvent,
             GesturePinchEndEvent, GesturePinchUpdateEvent, GestureSwipeBeginEvent, GestureSwipeEndEvent,
-            GestureSwipeUpdateEvent, GrabStartData as PointerGrabStartData, MotionEvent, PointerGrab,
+            Focus, GestureSwipeUpdateEvent, GrabStartData as PointerGrabStartData, MotionEvent, PointerGrab,
             PointerInnerHandle, RelativeMotionEvent,
         },
         touch::{GrabStartData as TouchGrabStartData, TouchGrab},
     },
+    output::Output,
     reexports::wayland_protocols::xdg::shell::server::xdg_toplevel,
-    utils::{IsAlive, Logical, Point, Serial, Size},
+    utils::{IsAlive, Logical, Point, Rectangle, Serial, Size, SERIAL_COUNTER},
     wayland::{compositor::with_states, shell::xdg::SurfaceCachedState},
 };
 #[cfg(feature = "xwayland")]
-use smithay::{utils::Rectangle, xwayland::xwm::ResizeEdge as X11ResizeEdge};
+use smithay::xwayland::xwm::ResizeEdge as X11ResizeEdge;
 
-use super::{SurfaceData, WindowElement};
+use super::{usable_geometry, ResizeOutline, SnapPreview, SnapZone, SurfaceData, WindowElement};
 use crate::{
     focus::PointerFocusTarget,
     state::{LuxoState, Backend},
@@ -28,6 +29,41 @@ pub struct PointerMoveSurfaceGrab<BackendData: Backend + 'static> {
     pub start_data: PointerGrabStartData<LuxoState<BackendData>>,
     pub window: WindowElement,
     pub initial_window_location: Point<i32, Logical>,
+    /// Output and zone the window will be snapped to when released
+    pub snap: Option<(Output, SnapZone)>,
+}
+
+impl<BackendData: Backend> PointerMoveSurfaceGrab<BackendData> {
+    pub fn new(
+        start_data: PointerGrabStartData<LuxoState<BackendData>>,
+        window: WindowElement,
+        initial_window_location: Point<i32, Logical>,
+    ) -> Self {
+        PointerMoveSurfaceGrab {
+            start_data,
+            window,
+            initial_window_location,
+            snap: None,
+        }
+    }
+
+    fn update_snap(&mut self, data: &LuxoState<BackendData>, location: Point<f64, Logical>) {
+        let snap = data.space.output_under(location).next().and_then(|output| {
+            let output_geometry = data.space.output_geometry(output)?;
+            SnapZone::at(output_geometry, location).map(|zone| (output.clone(), zone))
+        });
+
+        if let Some((output, _)) = self.snap.as_ref() {
+            SnapPreview::clear(output);
+        }
+        if let Some((output, zone)) = snap.as_ref() {
+            let output_geometry = data.space.output_geometry(output).unwrap();
+            let mut preview = zone.geometry(usable_geometry(&data.space, output).unwrap());
+            preview.loc -= output_geometry.loc;
+            SnapPreview::set(output, preview);
+        }
+        self.snap = snap;
+    }
 }
 
 impl<BackendData: Backend> PointerGrab<LuxoState<BackendData>> for PointerMoveSurfaceGrab<BackendData> {
@@ -46,6 +82,7 @@ impl<BackendData: Backend> PointerGrab<LuxoState<BackendData>> for PointerMoveSu
 
         data.space
             .map_element(self.window.clone(), new_location.to_i32_round(), true);
+        self.update_snap(data, event.location);
     }
 
     fn relative_motion(
@@ -66,6 +103,17 @@ impl<BackendData: Backend> PointerGrab<LuxoState<BackendData>> for PointerMoveSu
     ) {
         handle.button(data, event);
         if handle.current_pressed().is_empty() {
+            match self.snap.as_ref() {
+                Some((output, zone)) => {
+                    let area = usable_geometry(&data.space, output).unwrap();
+                    let location = self.window.snap(*zone, area);
+                    data.map_window_animated(&self.window, location, true);
+                }
+                None => self.window.unsnap(),
+            }
+            if let Some(surface) = self.window.wl_surface() {
+                data.resync_popups(&surface);
+            }
             // No more buttons are pressed, release the grab.
             handle.unset_grab(self, data, event.serial, event.time, true);
         }
@@ -164,7 +212,11 @@ impl<BackendData: Backend> PointerGrab<LuxoState<BackendData>> for PointerMoveSu
         &self.start_data
     }
 
-    fn unset(&mut self, _data: &mut LuxoState<BackendData>) {}
+    fn unset(&mut self, _data: &mut LuxoState<BackendData>) {
+        if let Some((output, _)) = self.snap.take() {
+            SnapPreview::clear(&output);
+        }
+    }
 }
 
 pub struct TouchMoveSurfaceGrab<BackendData: Backend + 'static> {
@@ -199,6 +251,9 @@ impl<BackendData: Backend> TouchGrab<LuxoState<BackendData>> for TouchMoveSurfac
         }
 
         handle.up(data, event, seq);
+        if let Some(surface) = self.window.wl_surface() {
+            data.resync_popups(&surface);
+        }
         handle.unset_grab(self, data);
     }
 
@@ -297,6 +352,43 @@ impl From<ResizeEdge> for xdg_toplevel::ResizeEdge {
     }
 }
 
+impl ResizeEdge {
+    /// Edges of a window of `size` closest to `point`, relative to the window: a corner when the
+    /// point is in one of the outer thirds in both directions, otherwise the nearest side
+    pub fn nearest(size: Size<i32, Logical>, point: Point<f64, Logical>) -> Self {
+        let x = point.x / size.w.max(1) as f64;
+        let y = point.y / size.h.max(1) as f64;
+        let horizontal = if x < 1.0 / 3.0 {
+            ResizeEdge::LEFT
+        } else if x > 2.0 / 3.0 {
+            ResizeEdge::RIGHT
+        } else {
+            ResizeEdge::NONE
+        };
+        let vertical = if y < 1.0 / 3.0 {
+            ResizeEdge::TOP
+        } else if y > 2.0 / 3.0 {
+            ResizeEdge::BOTTOM
+        } else {
+            ResizeEdge::NONE
+        };
+
+        if !(horizontal | vertical).is_empty() {
+            horizontal | vertical
+        } else if (x - 0.5).abs() >= (y - 0.5).abs() {
+            if x < 0.5 {
+                ResizeEdge::LEFT
+            } else {
+                ResizeEdge::RIGHT
+            }
+        } else if y < 0.5 {
+            ResizeEdge::TOP
+        } else {
+            ResizeEdge::BOTTOM
+        }
+    }
+}
+
 #[cfg(feature = "xwayland")]
 impl From<X11ResizeEdge> for ResizeEdge {
     #[inline]
@@ -314,6 +406,30 @@ impl From<X11ResizeEdge> for ResizeEdge {
     }
 }
 
+/// Clamp the requested window size to the minimum and maximum size of the client
+pub(super) fn constrain_size(window: &WindowElement, width: i32, height: i32) -> Size<i32, Logical> {
+    let (min_size, max_size) = if let Some(surface) = window.wl_surface() {
+        with_states(&surface, |states| {
+            let mut guard = states.cached_state.get::<SurfaceCachedState>();
+            let data = guard.current();
+            (data.min_size, data.max_size)
+        })
+    } else {
+        ((0, 0).into(), (0, 0).into())
+    };
+
+    let min_width = min_size.w.max(1);
+    let min_height = min_size.h.max(1);
+    let max_width = if max_size.w == 0 { i32::MAX } else { max_size.w };
+    let max_height = if max_size.h == 0 { i32::MAX } else { max_size.h };
+
+    (
+        width.max(min_width).min(max_width),
+        height.max(min_height).min(max_height),
+    )
+        .into()
+}
+
 /// Information about the resize operation.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct ResizeData {
@@ -346,6 +462,22 @@ pub struct PointerResizeSurfaceGrab<BackendData: Backend + 'static> {
     pub initial_window_location: Point<i32, Logical>,
     pub initial_window_size: Size<i32, Logical>,
     pub last_window_size: Size<i32, Logical>,
+    /// Only draw an outline of `last_window_size` and configure the window once released
+    pub outline: bool,
+}
+
+impl<BackendData: Backend> PointerResizeSurfaceGrab<BackendData> {
+    /// Geometry the window ends up with at `last_window_size`, keeping the opposite edges in place
+    fn outline_geometry(&self) -> Rectangle<i32, Logical> {
+        let mut location = self.initial_window_location;
+        if self.edges.intersects(ResizeEdge::LEFT) {
+            location.x += self.initial_window_size.w - self.last_window_size.w;
+        }
+        if self.edges.intersects(ResizeEdge::TOP) {
+            location.y += self.initial_window_size.h - self.last_window_size.h;
+        }
+        Rectangle::new(location, self.last_window_size)
+    }
 }
 
 impl<BackendData: Backend> PointerGrab<LuxoState<BackendData>> for PointerResizeSurfaceGrab<BackendData> {
@@ -389,25 +521,12 @@ impl<BackendData: Backend> PointerGrab<LuxoState<BackendData>> for PointerResize
             new_window_height = (self.initial_window_size.h as f64 + dy) as i32;
         }
 
-        let (min_size, max_size) = if let Some(surface) = self.window.wl_surface() {
-            with_states(&surface, |states| {
-                let mut guard = states.cached_state.get::<SurfaceCachedState>();
-                let data = guard.current();
-                (data.min_size, data.max_size)
-            })
-        } else {
-            ((0, 0).into(), (0, 0).into())
-        };
-
-        let min_width = min_size.w.max(1);
-        let min_height = min_size.h.max(1);
-        let max_width = if max_size.w == 0 { i32::MAX } else { max_size.w };
-        let max_height = if max_size.h == 0 { i32::MAX } else { max_size.h };
-
-        new_window_width = new_window_width.max(min_width).min(max_width);
-        new_window_height = new_window_height.max(min_height).min(max_height);
+        self.last_window_size = constrain_size(&self.window, new_window_width, new_window_height);
 
-        self.last_window_size = (new_window_width, new_window_height).into();
+        if self.outline {
+            ResizeOutline::set(&data.space, self.outline_geometry());
+            return;
+        }
 
         match &self.window.0.underlying_surface() {
             WindowSurface::Wayland(xdg) => {
@@ -491,7 +610,11 @@ impl<BackendData: Backend> PointerGrab<LuxoState<BackendData>> for PointerResize
                 #[cfg(feature = "xwayland")]
                 WindowSurface::X11(x11) => {
                     let mut location = data.space.element_location(&self.window).unwrap();
-                    if self.edges.intersects(ResizeEdge::TOP_LEFT) {
+                    if self.outline {
+                        // the window still has its initial size
+                        location = self.outline_geometry().loc;
+                        data.space.map_element(self.window.clone(), location, true);
+                    } else if self.edges.intersects(ResizeEdge::TOP_LEFT) {
                         let geometry = self.window.geometry();
 
                         if self.edges.intersects(ResizeEdge::LEFT) {
@@ -622,7 +745,11 @@ impl<BackendData: Backend> PointerGrab<LuxoState<BackendData>> for PointerResize
         &self.start_data
     }
 
-    fn unset(&mut self, _data: &mut LuxoState<BackendData>) {}
+    fn unset(&mut self, data: &mut LuxoState<BackendData>) {
+        if self.outline {
+            ResizeOutline::clear(&data.space);
+        }
+    }
 }
 
 pub struct TouchResizeSurfaceGrab<BackendData: Backend + 'static> {
@@ -786,25 +913,7 @@ impl<BackendData: Backend> TouchGrab<LuxoState<BackendData>> for TouchResizeSurf
             new_window_height = (self.initial_window_size.h as f64 + dy) as i32;
         }
 
-        let (min_size, max_size) = if let Some(surface) = self.window.wl_surface() {
-            with_states(&surface, |states| {
-                let mut guard = states.cached_state.get::<SurfaceCachedState>();
-                let data = guard.current();
-                (data.min_size, data.max_size)
-            })
-        } else {
-            ((0, 0).into(), (0, 0).into())
-        };
-
-        let min_width = min_size.w.max(1);
-        let min_height = min_size.h.max(1);
-        let max_width = if max_size.w == 0 { i32::MAX } else { max_size.w };
-        let max_height = if max_size.h == 0 { i32::MAX } else { max_size.h };
-
-        new_window_width = new_window_width.max(min_width).min(max_width);
-        new_window_height = new_window_height.max(min_height).min(max_height);
-
-        self.last_window_size = (new_window_width, new_window_height).into();
+        self.last_window_size = constrain_size(&self.window, new_window_width, new_window_height);
 
         match self.window.0.underlying_surface() {
             WindowSurface::Wayland(xdg) => {
@@ -867,3 +976,75 @@ impl<BackendData: Backend> TouchGrab<LuxoState<BackendData>> for TouchResizeSurf
 
     fn unset(&mut self, _data: &mut LuxoState<BackendData>) {}
 }
+
+impl<BackendData: Backend> LuxoState<BackendData> {
+    /// Move `window` with the pointer until `button` is released, without the client asking for it
+    pub fn start_pointer_move(&mut self, window: &WindowElement, button: u32) {
+        let Some(mut initial_window_location) = self.space.element_location(window) else {
+            return;
+        };
+        if window.is_fullscreen() {
+            return;
+        }
+        let location = self.pointer.current_location();
+
+        // like a move started by the client, a maximized window is restored under the pointer
+        if window.is_maximized() {
+            self.toggle_maximize(window);
+            initial_window_location = location.to_i32_round();
+        }
+
+        let start_data = PointerGrabStartData {
+            focus: None,
+            button,
+            location,
+        };
+        let grab = PointerMoveSurfaceGrab::new(start_data, window.clone(), initial_window_location);
+        let pointer = self.pointer.clone();
+        pointer.set_grab(self, grab, SERIAL_COUNTER.next_serial(), Focus::Clear);
+    }
+
+    /// Resize `window` at the edges nearest to the pointer until `button` is released, without the
+    /// client asking for it
+    pub fn start_pointer_resize(&mut self, window: &WindowElement, button: u32) {
+        let Some(geometry) = self.space.element_geometry(window) else {
+            return;
+        };
+        if window.is_fullscreen() || window.is_maximized() {
+            return;
+        }
+        let location = self.pointer.current_location();
+        let edges = ResizeEdge::nearest(geometry.size, location - geometry.loc.to_f64());
+        let initial_window_location = self.space.element_location(window).unwrap();
+        let initial_window_size = window.geometry().size;
+
+        if let Some(surface) = window.wl_surface() {
+            with_states(&surface, |states| {
+                if let Some(data) = states.data_map.get::<RefCell<SurfaceData>>() {
+                    data.borrow_mut().resize_state = ResizeState::Resizing(ResizeData {
+                        edges,
+                        initial_window_location,
+                        initial_window_size,
+                    });
+                }
+            });
+        }
+
+        let start_data = PointerGrabStartData {
+            focus: None,
+            button,
+            location,
+        };
+        let grab = PointerResizeSurfaceGrab {
+            start_data,
+            window: window.clone(),
+            edges,
+            initial_window_location,
+            initial_window_size,
+            last_window_size: initial_window_size,
+            outline: self.config.appearance.outline_resize,
+        };
+        let pointer = self.pointer.clone();
+        pointer.set_grab(self, grab, SERIAL_COUNTER.next_serial(), Focus::Clear);
+    }
+}
@@ -0,0 +1,123 @@
+use std::cell::RefCell;
+
+use smithay::{
+    backend::renderer::element::{
+        solid::{SolidColorBuffer, SolidColorRenderElement},
+        Kind,
+    },
+    desktop::space::SpaceElement,
+    utils::{Logical, Point, Scale, Size},
+    wayland::keyboard_shortcuts_inhibit::{KeyboardShortcutsInhibitor, KeyboardShortcutsInhibitorSeat},
+};
+use tracing::info;
+
+use crate::state::{LuxoState, Backend};
+
+use super::WindowElement;
+
+/// Height of the bar over the top edge of a window waiting for confirmation, in logical pixels
+const PROMPT_HEIGHT: i32 = 4;
+const PROMPT_COLOR: [f32; 4] = [0.9, 0.6, 0.1, 1.0];
+
+/// Bar shown over a window whose shortcuts inhibitor waits for the user to allow it, stored in the
+/// window's user data
+#[derive(Debug, Default)]
+pub struct ShortcutsInhibitPrompt(RefCell<Option<SolidColorBuffer>>);
+
+impl WindowElement {
+    fn set_shortcuts_inhibit_prompt(&self, shown: bool) {
+        self.user_data()
+            .insert_if_missing(ShortcutsInhibitPrompt::default);
+        let mut prompt = self
+            .user_data()
+            .get::<ShortcutsInhibitPrompt>()
+            .unwrap()
+            .0
+            .borrow_mut();
+        match (shown, prompt.is_some()) {
+            (true, false) => *prompt = Some(SolidColorBuffer::new(Size::default(), PROMPT_COLOR)),
+            (false, true) => *prompt = None,
+            _ => {}
+        }
+    }
+
+    /// The prompt bar, if shown, for the window at `location` (relative to the output)
+    pub fn shortcuts_inhibit_prompt_element(
+        &self,
+        location: Point<i32, Logical>,
+        scale: Scale<f64>,
+    ) -> Option<SolidColorRenderElement> {
+        let prompt = self.user_data().get::<ShortcutsInhibitPrompt>()?;
+        let mut buffer = prompt.0.borrow_mut();
+        let buffer = buffer.as_mut()?;
+        let width = self.geometry().size.w;
+        buffer.resize((width, PROMPT_HEIGHT.min(self.geometry().size.h)));
+        Some(SolidColorRenderElement::from_buffer(
+            buffer,
+            location.to_physical_precise_round(scale),
+            scale,
+            1.0,
+            Kind::Unspecified,
+        ))
+    }
+}
+
+impl<BackendData: Backend> LuxoState<BackendData> {
+    /// Decide on a new shortcuts inhibitor: the `inhibit_shortcuts` window rules allow or deny it,
+    /// otherwise it stays inactive and its window shows a prompt until the user allows it with the
+    /// `toggle-shortcuts-inhibit` action
+    pub fn handle_shortcuts_inhibitor(&mut self, inhibitor: KeyboardShortcutsInhibitor) {
+        let window = self.window_for_surface(inhibitor.wl_surface());
+        let (app_id, title) = window
+            .as_ref()
+            .map(|window| (window.app_id(), window.title()))
+            .unwrap_or_default();
+        let allowed = self
+            .config
+            .window_rules
+            .iter()
+            .filter(|rule| rule.matches(app_id.as_deref(), title.as_deref()))
+            .filter_map(|rule| rule.inhibit_shortcuts)
+            .next_back();
+
+        match allowed {
+            Some(true) => inhibitor.activate(),
+            Some(false) => info!(?app_id, "Denied keyboard shortcuts inhibitor by window rule"),
+            None => {
+                info!(?app_id, "Keyboard shortcuts inhibitor waits for confirmation");
+                if let Some(window) = window {
+                    window.set_shortcuts_inhibit_prompt(true);
+                }
+            }
+        }
+    }
+
+    pub fn shortcuts_inhibitor_destroyed(&mut self, inhibitor: &KeyboardShortcutsInhibitor) {
+        if let Some(window) = self.window_for_surface(inhibitor.wl_surface()) {
+            window.set_shortcuts_inhibit_prompt(false);
+        }
+    }
+
+    /// Allow the pending or suspended shortcuts inhibitor of the focused window, or suspend the
+    /// active one. Bindings of this action work while shortcuts are inhibited.
+    pub fn toggle_shortcuts_inhibit(&mut self) {
+        let Some(window) = self.focused_window() else {
+            return;
+        };
+        let Some(inhibitor) = window
+            .wl_surface()
+            .and_then(|surface| self.seat.keyboard_shortcuts_inhibitor_for_surface(&surface))
+        else {
+            return;
+        };
+
+        if inhibitor.is_active() {
+            info!("Suspended keyboard shortcuts inhibitor");
+            inhibitor.inactivate();
+        } else {
+            info!("Allowed keyboard shortcuts inhibitor");
+            inhibitor.activate();
+            window.set_shortcuts_inhibit_prompt(false);
+        }
+    }
+}
@@ -1,10 +1,15 @@
 use std::cell::RefCell;
+#[cfg(feature = "udev")]
+use std::cell::Cell;
 
 #[cfg(feature = "xwayland")]
 use smithay::xwayland::XWaylandClientData;
 
 #[cfg(feature = "udev")]
-use smithay::wayland::drm_syncobj::DrmSyncobjCachedState;
+use smithay::{
+    reexports::wayland_server::{backend::protocol::ProtocolError, protocol::wl_display},
+    wayland::drm_syncobj::DrmSyncobjCachedState,
+};
 
 use smithay::{
     backend::renderer::utils::on_commit_buffer_handler,
@@ -16,12 +21,13 @@ use smithay::{
     output::Output,
     reexports::{
         calloop::Interest,
+        wayland_protocols::xdg::shell::server::xdg_toplevel,
         wayland_server::{
             protocol::{wl_buffer::WlBuffer, wl_output, wl_surface::WlSurface},
             Client, Resource,
         },
     },
-    utils::{IsAlive, Logical, Point, Rectangle, Size},
+    utils::{IsAlive, Logical, Point, Rectangle, Size, SERIAL_COUNTER},
     wayland::{
         buffer::BufferHandler,
         compositor::{
@@ -35,7 +41,7 @@ use smithay::{
                 Layer, LayerSurface as WlrLayerSurface, LayerSurfaceData, WlrLayerShellHandler,
                 WlrLayerShellState,
             },
-            xdg::XdgToplevelSurfaceData,
+            xdg::{PopupSurface, XdgToplevelSurfaceData},
         },
     },
 };
@@ -45,15 +51,36 @@ use crate::{
     ClientState,
 };
 
+mod animation;
+mod arrange;
 mod element;
+mod focus_return;
+mod foreign_toplevel;
 mod grabs;
+mod inhibit;
+mod navigation;
+mod outline;
+mod ping;
+mod rules;
+mod scratchpad;
 pub(crate) mod ssd;
 #[cfg(feature = "xwayland")]
 mod x11;
+mod snap;
+mod workspaces;
 mod xdg;
 
+pub use self::animation::*;
 pub use self::element::*;
+pub use self::focus_return::*;
 pub use self::grabs::*;
+pub use self::inhibit::*;
+pub use self::navigation::*;
+pub use self::outline::*;
+pub use self::rules::*;
+pub use self::scratchpad::*;
+pub use self::snap::*;
+pub use self::workspaces::*;
 
 fn fullscreen_output_geometry(
     wl_surface: &WlSurface,
@@ -95,6 +122,25 @@ impl FullscreenSurface {
     }
 }
 
+/// Geometry a window had before it was maximized, stored in the user data of the window, or of
+/// the X11 surface for X11 windows
+#[derive(Debug, Default)]
+struct OldGeometry(RefCell<Option<Rectangle<i32, Logical>>>);
+impl OldGeometry {
+    pub fn save(&self, geo: Rectangle<i32, Logical>) {
+        *self.0.borrow_mut() = Some(geo);
+    }
+
+    pub fn restore(&self) -> Option<Rectangle<i32, Logical>> {
+        self.0.borrow_mut().take()
+    }
+}
+
+/// Geometry of a window and the windows stacked above it before it went fullscreen, stored in its
+/// user data
+#[derive(Debug, Default)]
+struct FullscreenRestore(RefCell<Option<(Rectangle<i32, Logical>, Vec<WindowElement>)>>);
+
 impl<BackendData: Backend> BufferHandler for LuxoState<BackendData> {
     fn buffer_destroyed(&mut self, _buffer: &WlBuffer) {}
 }
@@ -139,17 +185,43 @@ impl<BackendData: Backend> CompositorHandler for LuxoState<BackendData> {
                     })
             });
             if let Some(dmabuf) = maybe_dmabuf {
+                #[cfg(feature = "udev")]
+                with_states(surface, |states| {
+                    states.data_map.insert_if_missing(ExplicitSync::default);
+                    let explicit_sync = states.data_map.get::<ExplicitSync>().unwrap();
+                    explicit_sync.used.set(acquire_point.is_some());
+                });
                 #[cfg(feature = "udev")]
                 if let Some(acquire_point) = acquire_point {
-                    if let Ok((blocker, source)) = acquire_point.generate_blocker() {
-                        let client = surface.client().unwrap();
-                        let res = state.handle.insert_source(source, move |_, _, data| {
-                            let dh = data.display_handle.clone();
-                            data.client_compositor_state(&client).blocker_cleared(data, &dh);
-                            Ok(())
-                        });
-                        if res.is_ok() {
-                            add_blocker(surface, blocker);
+                    let client = surface.client().unwrap();
+                    match acquire_point.generate_blocker() {
+                        Ok((blocker, source)) => {
+                            let res = state.handle.insert_source(source, move |_, _, data| {
+                                let dh = data.display_handle.clone();
+                                data.client_compositor_state(&client).blocker_cleared(data, &dh);
+                                Ok(())
+                            });
+                            if res.is_ok() {
+                                add_blocker(surface, blocker);
+                                return;
+                            }
+                        }
+                        // the implicit fence of the dmabuf may not cover what the client waits
+                        // for, showing the buffer anyway would tear or show garbage
+                        Err(err) => {
+                            tracing::warn!(
+                                ?err,
+                                "Failed to wait for an acquire point, disconnecting the client"
+                            );
+                            client.kill(
+                                &state.display_handle,
+                                ProtocolError {
+                                    code: wl_display::Error::Implementation as u32,
+                                    object_id: surface.id().protocol_id(),
+                                    object_interface: "wl_surface".into(),
+                                    message: format!("failed to wait for the acquire point: {}", err),
+                                },
+                            );
                             return;
                         }
                     }
@@ -183,6 +255,8 @@ impl<BackendData: Backend> CompositorHandler for LuxoState<BackendData> {
                 window.0.on_commit();
 
                 if &root == surface {
+                    window.refresh_metadata();
+
                     let buffer_offset = with_states(surface, |states| {
                         states
                             .cached_state
@@ -196,6 +270,9 @@ impl<BackendData: Backend> CompositorHandler for LuxoState<BackendData> {
                         let current_loc = self.space.element_location(&window).unwrap();
                         self.space.map_element(window, current_loc + buffer_offset, false);
                     }
+
+                    // the window might have been resized, its popups could be out of bounds now
+                    self.resync_popups(&root);
                 }
             }
         }
@@ -238,7 +315,33 @@ impl<BackendData: Backend> CompositorHandler for LuxoState<BackendData> {
             });
         }
 
-        ensure_initial_configure(surface, &self.space, &mut self.popups)
+        let layer_zone = self.space.outputs().find_map(|o| {
+            let map = layer_map_for_output(o);
+            map.layer_for_surface(surface, WindowSurfaceType::TOPLEVEL)
+                .map(|layer| (o.clone(), map.non_exclusive_zone(), layer.clone()))
+        });
+
+        // app_id and title are only known once the initial configure is requested
+        if let Some(window) = self.window_for_surface(surface) {
+            #[cfg_attr(not(feature = "xwayland"), allow(irrefutable_let_patterns))]
+            if let Some(toplevel) = window.0.toplevel() {
+                if !toplevel.is_initial_configure_sent() {
+                    self.apply_window_rules(&window);
+                }
+            }
+        }
+
+        ensure_initial_configure(surface, &self.space, &mut self.popups);
+
+        // the layer surface might have changed its exclusive zone or keyboard interactivity
+        if let Some((output, zone, layer)) = layer_zone {
+            if layer_map_for_output(&output).non_exclusive_zone() != zone {
+                self.refit_maximized_windows(&output);
+            }
+            if !layer.can_receive_keyboard_focus() {
+                self.return_focus(&layer);
+            }
+        }
     }
 }
 
@@ -262,16 +365,28 @@ impl<BackendData: Backend> WlrLayerShellHandler for LuxoState<BackendData> {
         map.map_layer(&LayerSurface::new(surface, namespace)).unwrap();
     }
 
+    fn new_popup(&mut self, _parent: WlrLayerSurface, popup: PopupSurface) {
+        // the popup got its parent only now, so it couldn't be placed when it was created
+        self.unconstrain_popup(&popup);
+    }
+
     fn layer_destroyed(&mut self, surface: WlrLayerSurface) {
-        if let Some((mut map, layer)) = self.space.outputs().find_map(|o| {
+        if let Some((output, mut map, layer)) = self.space.outputs().find_map(|o| {
             let map = layer_map_for_output(o);
             let layer = map
                 .layers()
                 .find(|&layer| layer.layer_surface() == &surface)
                 .cloned();
-            layer.map(|layer| (map, layer))
+            layer.map(|layer| (o.clone(), map, layer))
         }) {
+            let zone = map.non_exclusive_zone();
             map.unmap_layer(&layer);
+            let changed = map.non_exclusive_zone() != zone;
+            drop(map);
+            if changed {
+                self.refit_maximized_windows(&output);
+            }
+            self.return_focus(&layer);
         }
     }
 }
@@ -283,6 +398,298 @@ impl<BackendData: Backend> LuxoState<BackendData> {
             .find(|window| window.wl_surface().map(|s| &*s == surface).unwrap_or(false))
             .cloned()
     }
+
+    /// Remember where `window` is and what is stacked above it, unless it already went fullscreen
+    pub fn save_fullscreen_restore(&self, window: &WindowElement) {
+        let Some(location) = self.space.element_location(window) else {
+            return;
+        };
+        window.user_data().insert_if_missing(FullscreenRestore::default);
+        let mut restore = window
+            .user_data()
+            .get::<FullscreenRestore>()
+            .unwrap()
+            .0
+            .borrow_mut();
+        if restore.is_none() {
+            let above = self
+                .space
+                .elements()
+                .skip_while(|element| *element != window)
+                .skip(1)
+                .cloned()
+                .collect();
+            *restore = Some((Rectangle::new(location, window.0.geometry().size), above));
+        }
+    }
+
+    /// Move `window` back to where it was before it went fullscreen and restore the stacking order.
+    ///
+    /// Returns the geometry the window had, its size still has to be configured.
+    pub fn restore_fullscreen(&mut self, window: &WindowElement) -> Option<Rectangle<i32, Logical>> {
+        let (geometry, above) = window
+            .user_data()
+            .get::<FullscreenRestore>()
+            .and_then(|restore| restore.0.borrow_mut().take())?;
+        if self.space.element_location(window).is_none() {
+            // moved to another workspace or minimized in the meantime
+            return Some(geometry);
+        }
+
+        self.space.map_element(window.clone(), geometry.loc, false);
+        for element in above {
+            if self.space.element_location(&element).is_none() {
+                continue;
+            }
+            self.space.raise_element(&element, false);
+            #[cfg(feature = "xwayland")]
+            if let (Some(surface), Some(xwm)) = (element.0.x11_surface(), self.xwm.as_mut()) {
+                let _ = xwm.raise_window(surface);
+            }
+        }
+        Some(geometry)
+    }
+
+    /// Raise the always-on-top windows above all others and the scratchpad above them, keeping
+    /// their order among each other.
+    ///
+    /// Runs once per iteration of the event loop, as any window gets raised when it is focused
+    /// or moved.
+    pub fn restack_windows(&mut self) {
+        let scratchpad = self.scratchpad.window();
+        let top = self
+            .space
+            .elements()
+            .filter(|window| window.is_always_on_top() && Some(*window) != scratchpad)
+            .chain(self.space.elements().filter(|window| Some(*window) == scratchpad))
+            .cloned()
+            .collect::<Vec<_>>();
+        let stack = self.space.elements().collect::<Vec<_>>();
+        if stack[stack.len() - top.len()..].iter().copied().eq(top.iter()) {
+            return;
+        }
+
+        for window in top {
+            self.space.raise_element(&window, false);
+            #[cfg(feature = "xwayland")]
+            if let (Some(surface), Some(xwm)) = (window.0.x11_surface(), self.xwm.as_mut()) {
+                let _ = xwm.raise_window(surface);
+            }
+        }
+    }
+
+    /// All windows including those on hidden workspaces and minimized ones
+    pub fn all_windows(&self) -> Vec<WindowElement> {
+        let mut windows = self.space.elements().cloned().collect::<Vec<_>>();
+        for output in self.space.outputs() {
+            windows.extend(hidden_windows(output).into_iter().map(|(_, window)| window));
+            windows.extend(minimized_windows(output));
+        }
+        windows.extend(self.scratchpad.hidden_window().cloned());
+        // menus and tooltips of X11 clients are not toplevels
+        #[cfg(feature = "xwayland")]
+        windows.retain(|window| {
+            !window
+                .0
+                .x11_surface()
+                .is_some_and(|surface| surface.is_override_redirect())
+        });
+        windows
+    }
+
+    /// Focus the window with the given [`WindowElement::id`], switching to its workspace first
+    /// if it is hidden, restoring it if it is minimized or showing it if it is the hidden scratchpad.
+    /// Returns false if there is no such window.
+    pub fn focus_window_by_id(&mut self, id: u64) -> bool {
+        let minimized = self
+            .space
+            .outputs()
+            .flat_map(minimized_windows)
+            .find(|window| window.id() == id);
+        if let Some(window) = minimized {
+            return self.unminimize_window(&window);
+        }
+        if self.scratchpad.hidden_window().is_some_and(|window| window.id() == id) {
+            self.toggle_scratchpad();
+            return true;
+        }
+
+        let hidden = self.space.outputs().find_map(|output| {
+            hidden_windows(output)
+                .into_iter()
+                .find(|(_, window)| window.id() == id)
+                .map(|(workspace, _)| (output.clone(), workspace))
+        });
+        if let Some((output, workspace)) = hidden {
+            self.switch_workspace(&output, workspace);
+        }
+
+        let Some(window) = self.space.elements().find(|window| window.id() == id).cloned() else {
+            return false;
+        };
+        self.space.raise_element(&window, true);
+        #[cfg(feature = "xwayland")]
+        if let Some(surface) = window.0.x11_surface() {
+            if let Some(xwm) = self.xwm.as_mut() {
+                let _ = xwm.raise_window(surface);
+            }
+        }
+        let keyboard = self.seat.get_keyboard().unwrap();
+        keyboard.set_focus(self, Some(window.into()), SERIAL_COUNTER.next_serial());
+        true
+    }
+
+    /// Ask a window to close itself, it fades out once it actually goes away if animations are
+    /// enabled
+    pub fn close_window(&self, window: &WindowElement) {
+        if self.config.appearance.animations {
+            window.keep_close_snapshot();
+        }
+        window.send_close();
+    }
+
+    /// Terminate the client owning a window that doesn't react to being asked to close, the
+    /// window goes away with its connection
+    pub fn kill_window(&self, window: &WindowElement) {
+        // the pid of X11 windows comes from `_NET_WM_PID`, which the client sets to whatever it
+        // likes, so their connection to XWayland gets closed instead
+        #[cfg(feature = "xwayland")]
+        if let Some(surface) = window.0.x11_surface() {
+            let Some(xdisplay) = self.xdisplay else {
+                return;
+            };
+            let window_id = surface.window_id();
+            tracing::info!(window = window.id(), window_id, "Killing the X11 client of a window");
+            // XWayland answers on its own, but not from this thread, which it may be waiting on
+            std::thread::spawn(move || {
+                use x11rb::{connection::Connection, protocol::xproto::ConnectionExt};
+
+                let killed = x11rb::connect(Some(&format!(":{}", xdisplay)))
+                    .map_err(|err| err.to_string())
+                    .and_then(|(conn, _)| {
+                        conn.kill_client(window_id).map_err(|err| err.to_string())?;
+                        conn.flush().map_err(|err| err.to_string())
+                    });
+                if let Err(err) = killed {
+                    tracing::warn!(window_id, "Failed to kill an X11 client: {}", err);
+                }
+            });
+            return;
+        }
+
+        let Some(pid) = window.pid() else {
+            tracing::warn!(window = window.id(), "Unable to find the process of a window to kill");
+            return;
+        };
+        // 0 and -1 would signal whole process groups, 1 is init
+        if pid <= 1 || pid == std::process::id() as i32 {
+            tracing::warn!(window = window.id(), pid, "Refusing to kill the process of a window");
+            return;
+        }
+        #[cfg(feature = "xwayland")]
+        if self
+            .xwayland
+            .as_ref()
+            .and_then(|(_, client)| client.get_credentials(&self.display_handle).ok())
+            .is_some_and(|credentials| credentials.pid == pid)
+        {
+            tracing::warn!(window = window.id(), pid, "Refusing to kill XWayland");
+            return;
+        }
+        tracing::info!(window = window.id(), pid, "Killing the client of a window");
+        if unsafe { libc::kill(pid, libc::SIGKILL) } != 0 {
+            tracing::warn!(pid, "Failed to kill a client: {}", std::io::Error::last_os_error());
+        }
+    }
+
+    pub fn set_window_maximized(&mut self, window: &WindowElement, maximized: bool) {
+        if window.is_maximized() == maximized {
+            return;
+        }
+
+        #[allow(irrefutable_let_patterns)]
+        if let Some(toplevel) = window.0.toplevel() {
+            use smithay::wayland::shell::xdg::XdgShellHandler;
+
+            let toplevel = toplevel.clone();
+            if maximized {
+                self.maximize_request(toplevel);
+            } else {
+                self.unmaximize_request(toplevel);
+            }
+        }
+
+        #[cfg(feature = "xwayland")]
+        if let Some(surface) = window.0.x11_surface() {
+            use smithay::xwayland::XwmHandler;
+
+            let surface = surface.clone();
+            if maximized {
+                self.maximize_request_x11(&surface);
+            } else if let Some(xwm) = self.xwm.as_ref().map(|xwm| xwm.id()) {
+                self.unmaximize_request(xwm, surface);
+            }
+        }
+    }
+
+    /// Resize maximized windows on `output` to its current usable area
+    pub fn refit_maximized_windows(&mut self, output: &Output) {
+        let Some(geometry) = usable_geometry(&self.space, output) else {
+            return;
+        };
+        let windows = self
+            .space
+            .elements()
+            .filter(|window| self.space.outputs_for_element(window).first() == Some(output))
+            .cloned()
+            .collect::<Vec<_>>();
+        for window in windows {
+            #[allow(irrefutable_let_patterns)]
+            if let Some(toplevel) = window.0.toplevel() {
+                let maximized = toplevel
+                    .with_pending_state(|state| state.states.contains(xdg_toplevel::State::Maximized));
+                if !maximized {
+                    continue;
+                }
+                toplevel.with_pending_state(|state| state.size = Some(geometry.size));
+                toplevel.send_pending_configure();
+            }
+            #[cfg(feature = "xwayland")]
+            if let Some(surface) = window.0.x11_surface() {
+                if !surface.is_maximized() {
+                    continue;
+                }
+                let _ = surface.configure(geometry);
+            }
+            self.space.map_element(window, geometry.loc, false);
+        }
+    }
+}
+
+/// Synchronization of the last buffer of a surface, in its data map
+#[cfg(feature = "udev")]
+#[derive(Debug, Default)]
+struct ExplicitSync {
+    /// The buffer came with a syncobj acquire point
+    used: Cell<bool>,
+}
+
+/// Whether the last buffer of `surface` was synchronized explicitly with a syncobj acquire point
+#[cfg(feature = "udev")]
+pub fn uses_explicit_sync(surface: &WlSurface) -> bool {
+    with_states(surface, |states| {
+        states
+            .data_map
+            .get::<ExplicitSync>()
+            .is_some_and(|explicit_sync| explicit_sync.used.get())
+    })
+}
+
+/// Area of `output` in global coordinates that is not reserved by exclusive zones of layer surfaces
+pub fn usable_geometry(space: &Space<WindowElement>, output: &Output) -> Option<Rectangle<i32, Logical>> {
+    let geo = space.output_geometry(output)?;
+    let zone = layer_map_for_output(output).non_exclusive_zone();
+    Some(Rectangle::new(geo.loc + zone.loc, zone.size))
 }
 
 #[derive(Default)]
@@ -407,12 +814,7 @@ fn place_new_window(
         .or_else(|| space.outputs().next())
         .cloned();
     let output_geometry = output
-        .and_then(|o| {
-            let geo = space.output_geometry(&o)?;
-            let map = layer_map_for_output(&o);
-            let zone = map.non_exclusive_zone();
-            Some(Rectangle::new(geo.loc + zone.loc, zone.size))
-        })
+        .and_then(|o| usable_geometry(space, &o))
         .unwrap_or_else(|| Rectangle::from_size((800, 800).into()));
 
     // set the initial toplevel bounds
@@ -434,30 +836,74 @@ fn place_new_window(
     space.map_element(window.clone(), (x, y), activate);
 }
 
-pub fn fixup_positions(space: &mut Space<WindowElement>, pointer_location: Point<f64, Logical>) {
-    // fixup outputs
-    let mut offset = Point::<i32, Logical>::from((0, 0));
-    for output in space.outputs().cloned().collect::<Vec<_>>().into_iter() {
-        let size = space
-            .output_geometry(&output)
-            .map(|geo| geo.size)
-            .unwrap_or_else(|| Size::from((0, 0)));
-        space.map_output(&output, offset);
-        layer_map_for_output(&output).arrange();
-        offset.x += size.w;
+impl<BackendData: Backend> LuxoState<BackendData> {
+    /// Pack all outputs left-to-right and bring the windows and the pointer in line with the new layout
+    pub fn fixup_positions(&mut self) {
+        let mut offset = Point::<i32, Logical>::from((0, 0));
+        for output in self.space.outputs().cloned().collect::<Vec<_>>().into_iter() {
+            let size = self
+                .space
+                .output_geometry(&output)
+                .map(|geo| geo.size)
+                .unwrap_or_else(|| Size::from((0, 0)));
+            self.space.map_output(&output, offset);
+            offset.x += size.w;
+        }
+
+        self.output_layout_changed();
     }
 
-    // fixup windows
-    let mut orphaned_windows = Vec::new();
+    /// Re-arrange layer surfaces, refit maximized and fullscreen windows to their outputs, move
+    /// windows that are no longer on any output onto the nearest one and update the pointer focus,
+    /// after outputs moved or changed their size
+    pub fn output_layout_changed(&mut self) {
+        for output in self.space.outputs().cloned().collect::<Vec<_>>() {
+            layer_map_for_output(&output).arrange();
+            self.refit_maximized_windows(&output);
+            self.refit_fullscreen_window(&output);
+        }
+        fixup_window_positions(&mut self.space);
+
+        let location = self.pointer.current_location();
+        if self.space.output_under(location).next().is_some() {
+            // the surface under the pointer might have moved away
+            self.warp_pointer(location);
+        } else {
+            self.warp_pointer_onto_outputs();
+        }
+    }
+
+    fn refit_fullscreen_window(&mut self, output: &Output) {
+        let Some(window) = output
+            .user_data()
+            .get::<FullscreenSurface>()
+            .and_then(|fullscreen| fullscreen.get())
+        else {
+            return;
+        };
+        let Some(geometry) = self.space.output_geometry(output) else {
+            return;
+        };
+        #[allow(irrefutable_let_patterns)]
+        if let Some(toplevel) = window.0.toplevel() {
+            toplevel.with_pending_state(|state| state.size = Some(geometry.size));
+            toplevel.send_pending_configure();
+        }
+        #[cfg(feature = "xwayland")]
+        if let Some(surface) = window.0.x11_surface() {
+            let _ = surface.configure(geometry);
+        }
+    }
+}
+
+/// Move windows that are no longer on any output into the usable area of the nearest one,
+/// keeping the output layout as is
+fn fixup_window_positions(space: &mut Space<WindowElement>) {
     let outputs = space
         .outputs()
-        .flat_map(|o| {
-            let geo = space.output_geometry(o)?;
-            let map = layer_map_for_output(o);
-            let zone = map.non_exclusive_zone();
-            Some(Rectangle::new(geo.loc + zone.loc, zone.size))
-        })
+        .flat_map(|o| usable_geometry(space, o))
         .collect::<Vec<_>>();
+    let mut orphaned_windows = Vec::new();
     for window in space.elements() {
         let window_location = match space.element_location(window) {
             Some(loc) => loc,
@@ -466,10 +912,25 @@ pub fn fixup_positions(space: &mut Space<WindowElement>, pointer_location: Point
         let geo_loc = window.bbox().loc + window_location;
 
         if !outputs.iter().any(|o_geo| o_geo.contains(geo_loc)) {
-            orphaned_windows.push(window.clone());
+            orphaned_windows.push((window.clone(), window_location, geo_loc));
         }
     }
-    for window in orphaned_windows.into_iter() {
-        place_new_window(space, pointer_location, &window, false);
+
+    for (window, window_location, geo_loc) in orphaned_windows {
+        let distance = |area: &&Rectangle<i32, Logical>| {
+            let x = geo_loc.x.clamp(area.loc.x, area.loc.x + area.size.w - 1);
+            let y = geo_loc.y.clamp(area.loc.y, area.loc.y + area.size.h - 1);
+            (x - geo_loc.x).abs() as i64 + (y - geo_loc.y).abs() as i64
+        };
+        let Some(area) = outputs.iter().min_by_key(distance) else {
+            return;
+        };
+
+        // keep as much of the window as possible on the output, its top left corner at least
+        let size = window.geometry().size;
+        let x = geo_loc.x.min(area.loc.x + area.size.w - size.w).max(area.loc.x);
+        let y = geo_loc.y.min(area.loc.y + area.size.h - size.h).max(area.loc.y);
+        let location = window_location + Point::from((x, y)) - geo_loc;
+        space.map_element(window, location, false);
     }
 }
@@ -0,0 +1,171 @@
+use std::time::Duration;
+
+use smithay::{
+    reexports::calloop::{
+        timer::{TimeoutAction, Timer},
+        RegistrationToken,
+    },
+    utils::{IsAlive, Logical, Point},
+};
+use tracing::warn;
+
+use crate::{keybindings::Direction, state::Backend, LuxoState};
+
+use super::WindowElement;
+
+/// How long after the last step of `focus-next` or `focus-prev` the cycle ends, if the modifiers
+/// are not released before
+const FOCUS_CYCLE_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// Windows cycled through by `focus-next` and `focus-prev`, in the order of recent use from when
+/// the cycle started
+#[derive(Debug)]
+pub struct FocusCycle {
+    order: Vec<WindowElement>,
+    /// The window last focused by the cycle
+    index: usize,
+    timer: Option<RegistrationToken>,
+}
+
+impl<BackendData: Backend + 'static> LuxoState<BackendData> {
+    /// Remember `window` as the most recently focused one, unless focus is being cycled through
+    /// the windows, which only reorders them once it ends
+    pub fn push_focus_history(&mut self, window: WindowElement) {
+        if self.focus_cycle.is_some() {
+            return;
+        }
+        self.focus_history.retain(|w| w != &window && w.alive());
+        self.focus_history.insert(0, window);
+    }
+
+    /// All windows, most recently focused first
+    fn windows_by_recent_use(&self) -> Vec<WindowElement> {
+        let windows = self.all_windows();
+        let mut ordered = self
+            .focus_history
+            .iter()
+            .filter(|window| windows.contains(window))
+            .cloned()
+            .collect::<Vec<_>>();
+        ordered.extend(
+            windows
+                .into_iter()
+                .filter(|window| !self.focus_history.contains(window)),
+        );
+        ordered
+    }
+
+    /// Focus the window that was focused before the current one, further back in the order of
+    /// recent use on every step of a cycle
+    pub fn focus_next(&mut self) {
+        self.cycle_focus(1);
+    }
+
+    /// Focus the least recently focused window, going back towards the most recent one on every
+    /// step of a cycle
+    pub fn focus_prev(&mut self) {
+        self.cycle_focus(-1);
+    }
+
+    /// Move `step` windows through the order of recent use the cycle started with, starting a
+    /// cycle if none is running
+    fn cycle_focus(&mut self, step: isize) {
+        let mut cycle = match self.focus_cycle.take() {
+            Some(cycle) => cycle,
+            None => {
+                let order = self.windows_by_recent_use();
+                let focused = self.focused_window();
+                let index = order
+                    .iter()
+                    .position(|window| Some(window) == focused.as_ref())
+                    .unwrap_or(order.len());
+                FocusCycle {
+                    order,
+                    index,
+                    timer: None,
+                }
+            }
+        };
+        if let Some(timer) = cycle.timer.take() {
+            self.handle.remove(timer);
+        }
+
+        let windows = self.all_windows();
+        let current = cycle.order.get(cycle.index).cloned();
+        cycle.order.retain(|window| windows.contains(window));
+        let len = cycle.order.len() as isize;
+        if len == 0 {
+            return;
+        }
+        // the window last focused by the cycle may have closed in the meantime
+        let index = current
+            .and_then(|current| cycle.order.iter().position(|window| *window == current))
+            .map_or(if step > 0 { -1 } else { len }, |index| index as isize);
+        cycle.index = (index + step).rem_euclid(len) as usize;
+        let target = cycle.order[cycle.index].id();
+
+        cycle.timer = self
+            .handle
+            .insert_source(Timer::from_duration(FOCUS_CYCLE_TIMEOUT), |_, _, data| {
+                if let Some(cycle) = data.focus_cycle.as_mut() {
+                    cycle.timer = None;
+                }
+                data.end_focus_cycle();
+                TimeoutAction::Drop
+            })
+            .map_err(|err| warn!(error = ?err.error, "Failed to schedule the end of the focus cycle"))
+            .ok();
+        self.focus_cycle = Some(cycle);
+        self.focus_window_by_id(target);
+    }
+
+    /// End cycling through the windows, making the one it ended on the most recently focused
+    pub fn end_focus_cycle(&mut self) {
+        let Some(mut cycle) = self.focus_cycle.take() else {
+            return;
+        };
+        if let Some(timer) = cycle.timer.take() {
+            self.handle.remove(timer);
+        }
+        if let Some(window) = self.focused_window() {
+            self.push_focus_history(window);
+        }
+    }
+
+    /// Focus the closest visible window in `direction` of the focused one, or of the pointer
+    /// if no window is focused
+    pub fn focus_direction(&mut self, direction: Direction) {
+        let focused = self.focused_window();
+        let center = |window: &WindowElement| -> Option<Point<f64, Logical>> {
+            let geometry = self.space.element_geometry(window)?;
+            Some(geometry.loc.to_f64() + geometry.size.to_f64().downscale(2.0).to_point())
+        };
+        let origin = focused
+            .as_ref()
+            .and_then(center)
+            .unwrap_or_else(|| self.pointer.current_location());
+
+        let target = self
+            .space
+            .elements()
+            .filter(|window| Some(*window) != focused.as_ref())
+            .filter_map(|window| {
+                let offset = center(window)? - origin;
+                // distance along the direction and across it
+                let (along, across) = match direction {
+                    Direction::Left => (-offset.x, offset.y),
+                    Direction::Right => (offset.x, offset.y),
+                    Direction::Up => (-offset.y, offset.x),
+                    Direction::Down => (offset.y, offset.x),
+                };
+                // prefer windows that are lined up with the origin over closer ones that are not
+                (along > 0.0).then(|| (window.id(), along + across.abs() * 2.0))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(id, _)| id);
+
+        if let Some(id) = target {
+            self.focus_window_by_id(id);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::cell::RefCell;
+
+use smithay::{
+    backend::renderer::element::{
+        solid::{SolidColorBuffer, SolidColorRenderElement},
+        Kind,
+    },
+    desktop::Space,
+    output::Output,
+    utils::{Logical, Point, Rectangle, Scale, Size},
+};
+
+use super::WindowElement;
+
+/// Width of the outline in logical pixels
+const OUTLINE_WIDTH: i32 = 2;
+const OUTLINE_COLOR: [f32; 4] = [0.3, 0.45, 0.7, 1.0];
+
+/// Outline of the size a window gets once an outline resize is released, stored in the output's
+/// user data
+#[derive(Debug, Default)]
+pub struct ResizeOutline(RefCell<Option<(Rectangle<i32, Logical>, [SolidColorBuffer; 4])>>);
+
+impl ResizeOutline {
+    /// Show the outline of `geometry` (in global coordinates) on every output it overlaps
+    pub fn set(space: &Space<WindowElement>, geometry: Rectangle<i32, Logical>) {
+        for output in space.outputs() {
+            let Some(output_geometry) = space.output_geometry(output) else {
+                continue;
+            };
+            if !output_geometry.overlaps(geometry) {
+                ResizeOutline::clear_output(output);
+                continue;
+            }
+
+            let mut geometry = geometry;
+            geometry.loc -= output_geometry.loc;
+            output.user_data().insert_if_missing(ResizeOutline::default);
+            let mut outline = output.user_data().get::<ResizeOutline>().unwrap().0.borrow_mut();
+            match outline.as_mut() {
+                Some((current, buffers)) => {
+                    if *current != geometry {
+                        *current = geometry;
+                        for (buffer, edge) in buffers.iter_mut().zip(edges(geometry)) {
+                            buffer.resize(edge.size);
+                        }
+                    }
+                }
+                None => {
+                    let buffers = edges(geometry).map(|edge| SolidColorBuffer::new(edge.size, OUTLINE_COLOR));
+                    *outline = Some((geometry, buffers));
+                }
+            }
+        }
+    }
+
+    pub fn clear(space: &Space<WindowElement>) {
+        for output in space.outputs() {
+            ResizeOutline::clear_output(output);
+        }
+    }
+
+    fn clear_output(output: &Output) {
+        if let Some(outline) = output.user_data().get::<ResizeOutline>() {
+            outline.0.borrow_mut().take();
+        }
+    }
+
+    pub fn render_elements(output: &Output) -> Vec<SolidColorRenderElement> {
+        let Some(outline) = output.user_data().get::<ResizeOutline>() else {
+            return Vec::new();
+        };
+        let outline = outline.0.borrow();
+        let Some((geometry, buffers)) = outline.as_ref() else {
+            return Vec::new();
+        };
+        let scale = Scale::from(output.current_scale().fractional_scale());
+        buffers
+            .iter()
+            .zip(edges(*geometry))
+            .map(|(buffer, edge)| {
+                SolidColorRenderElement::from_buffer(
+                    buffer,
+                    edge.loc.to_physical_precise_round(scale),
+                    scale,
+                    1.0,
+                    Kind::Unspecified,
+                )
+            })
+            .collect()
+    }
+}
+
+/// Top, bottom, left and right edge of the outline of `geometry`
+fn edges(geometry: Rectangle<i32, Logical>) -> [Rectangle<i32, Logical>; 4] {
+    let Rectangle { loc, size } = geometry;
+    let width = OUTLINE_WIDTH.min(size.w).min(size.h).max(0);
+    let inner_height = (size.h - 2 * width).max(0);
+    [
+        Rectangle::new(loc, Size::from((size.w, width))),
+        Rectangle::new(
+            loc + Point::from((0, size.h - width)),
+            Size::from((size.w, width)),
+        ),
+        Rectangle::new(loc + Point::from((0, width)), Size::from((width, inner_height))),
+        Rectangle::new(
+            loc + Point::from((size.w - width, width)),
+            Size::from((width, inner_height)),
+        ),
+    ]
+}
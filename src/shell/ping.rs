@@ -0,0 +1,114 @@
+use std::{
+    cell::RefCell,
+    time::{Duration, Instant},
+};
+
+use smithay::{utils::SERIAL_COUNTER, wayland::shell::xdg::ShellClient};
+use tracing::{debug, info};
+
+use crate::state::{Backend, LuxoState};
+
+use super::WindowElement;
+
+/// Time between the answer to a ping and the next one
+const PING_INTERVAL: Duration = Duration::from_secs(2);
+/// Clients not answering a ping within this time are considered unresponsive
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+/// Alpha multiplier of the windows of unresponsive clients
+pub(super) const UNRESPONSIVE_ALPHA: f32 = 0.5;
+
+/// Ping state of an xdg_wm_base client, stored in the client's user data
+#[derive(Debug, Default)]
+struct PingState {
+    /// When the unanswered ping was sent
+    pending: Option<Instant>,
+    /// When to send the next ping, right away if unset
+    next: Option<Instant>,
+    unresponsive: bool,
+}
+
+fn with_ping_state<T>(client: &ShellClient, f: impl FnOnce(&mut PingState) -> T) -> Option<T> {
+    client
+        .with_data(|data| {
+            data.insert_if_missing(|| RefCell::new(PingState::default()));
+            f(&mut data.get::<RefCell<PingState>>().unwrap().borrow_mut())
+        })
+        .ok()
+}
+
+impl WindowElement {
+    /// Whether the client of the window missed a ping, X11 windows are never pinged
+    pub fn is_unresponsive(&self) -> bool {
+        #[allow(irrefutable_let_patterns)]
+        let Some(toplevel) = self.0.toplevel() else {
+            return false;
+        };
+        with_ping_state(&toplevel.client(), |state| state.unresponsive).unwrap_or(false)
+    }
+}
+
+impl<BackendData: Backend> LuxoState<BackendData> {
+    /// Ping the clients of mapped windows and mark the ones missing the answer as unresponsive,
+    /// runs once per iteration of the event loop
+    pub fn update_pings(&mut self) {
+        let now = Instant::now();
+        let mut changed = Vec::new();
+        for window in self.space.elements() {
+            #[allow(irrefutable_let_patterns)]
+            let Some(toplevel) = window.0.toplevel() else {
+                continue;
+            };
+            let client = toplevel.client();
+            let became_unresponsive = with_ping_state(&client, |state| match state.pending {
+                Some(sent) if !state.unresponsive && now.duration_since(sent) >= PING_TIMEOUT => {
+                    state.unresponsive = true;
+                    true
+                }
+                Some(_) => false,
+                None => {
+                    if state.next.is_none_or(|next| now >= next) {
+                        match client.send_ping(SERIAL_COUNTER.next_serial()) {
+                            Ok(()) => state.pending = Some(now),
+                            Err(err) => debug!(?err, "Failed to ping a client"),
+                        }
+                    }
+                    false
+                }
+            });
+            if became_unresponsive == Some(true) {
+                info!(app_id = ?window.app_id(), "Client stopped responding to pings");
+                changed.push(client);
+            }
+        }
+
+        for client in changed {
+            self.unresponsive_changed(&client);
+        }
+    }
+
+    /// The client answered a ping
+    pub fn client_answered_ping(&mut self, client: &ShellClient) {
+        let recovered = with_ping_state(client, |state| {
+            state.pending = None;
+            state.next = Some(Instant::now() + PING_INTERVAL);
+            std::mem::take(&mut state.unresponsive)
+        });
+        if recovered == Some(true) {
+            info!("Client is responding again");
+            self.unresponsive_changed(client);
+        }
+    }
+
+    /// Redraw the windows of `client` after they got dimmed or back to normal
+    fn unresponsive_changed(&mut self, client: &ShellClient) {
+        let outputs = self
+            .space
+            .elements()
+            .filter(|window| window.0.toplevel().is_some_and(|toplevel| toplevel.client() == *client))
+            .flat_map(|window| self.space.outputs_for_element(window))
+            .collect::<Vec<_>>();
+        for output in outputs {
+            self.backend_data.reset_buffers(&output);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+#[cfg(feature = "xwayland")]
+use smithay::xwayland::XwmHandler;
+use smithay::{
+    desktop::WindowSurface,
+    reexports::wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode,
+    utils::{Point, Rectangle, Size},
+    wayland::shell::xdg::XdgShellHandler,
+};
+use tracing::debug;
+
+use crate::{
+    config::{DecorationPolicy, SnapConfig, WindowRule},
+    state::{LuxoState, Backend},
+};
+
+use super::{usable_geometry, SnapZone, WindowElement};
+
+/// Indices of the window rules that matched a window when it was first mapped, stored in its
+/// user data
+#[derive(Debug)]
+pub struct MatchedRules(pub Vec<usize>);
+
+impl From<SnapConfig> for SnapZone {
+    fn from(snap: SnapConfig) -> Self {
+        match snap {
+            SnapConfig::Left => SnapZone::Left,
+            SnapConfig::Right => SnapZone::Right,
+            SnapConfig::TopLeft => SnapZone::TopLeft,
+            SnapConfig::TopRight => SnapZone::TopRight,
+            SnapConfig::BottomLeft => SnapZone::BottomLeft,
+            SnapConfig::BottomRight => SnapZone::BottomRight,
+        }
+    }
+}
+
+impl WindowElement {
+    /// Indices of the window rules applied to this window
+    pub fn matched_rules(&self) -> Vec<usize> {
+        self.user_data()
+            .get::<MatchedRules>()
+            .map(|matched| matched.0.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl<BackendData: Backend> LuxoState<BackendData> {
+    /// Apply the window rules matching `window`, which is about to be shown for the first time.
+    ///
+    /// Wayland windows only know their app_id and title once they ask for the initial configure,
+    /// so this has to run right before it is sent.
+    pub fn apply_window_rules(&mut self, window: &WindowElement) {
+        let (app_id, title) = (window.app_id(), window.title());
+        let mut rule = WindowRule::default();
+        let mut matched = Vec::new();
+        for (index, candidate) in self.config.window_rules.iter().enumerate() {
+            if candidate.matches(app_id.as_deref(), title.as_deref()) {
+                rule.merge(candidate);
+                matched.push(index);
+            }
+        }
+        if matched.is_empty() {
+            return;
+        }
+        debug!(window = window.id(), ?app_id, rules = ?matched, "Applying window rules");
+        window.user_data().insert_if_missing(|| MatchedRules(matched));
+
+        let Some(output) = self
+            .space
+            .outputs_for_element(window)
+            .first()
+            .or_else(|| self.space.outputs().next())
+            .cloned()
+        else {
+            return;
+        };
+        let Some(area) = usable_geometry(&self.space, &output) else {
+            return;
+        };
+
+        if let Some(opacity) = rule.opacity {
+            window.set_opacity(opacity);
+        }
+        if let Some(allowed) = rule.direct_scanout {
+            window.set_allows_direct_scanout(allowed);
+        }
+        if let Some(on_top) = rule.always_on_top {
+            window.set_always_on_top(on_top);
+        }
+        if let Some(sticky) = rule.sticky {
+            window.set_sticky(sticky);
+        }
+
+        if let Some(decorations) = rule.decorations {
+            match window.0.underlying_surface() {
+                WindowSurface::Wayland(toplevel) => toplevel.with_pending_state(|state| {
+                    state.decoration_mode = Some(match decorations {
+                        DecorationPolicy::Client => Mode::ClientSide,
+                        DecorationPolicy::Server => Mode::ServerSide,
+                    });
+                }),
+                #[cfg(feature = "xwayland")]
+                WindowSurface::X11(_) => window.set_ssd(decorations == DecorationPolicy::Server),
+            }
+        }
+
+        if rule.size.is_some() || rule.position.is_some() {
+            let mut geometry = self
+                .space
+                .element_geometry(window)
+                .unwrap_or_else(|| Rectangle::new(area.loc, Size::default()));
+            if let Some([x, y]) = rule.position {
+                geometry.loc = area.loc + Point::from((x, y));
+            }
+            if let Some([w, h]) = rule.size {
+                geometry.size = Size::from((w.max(1), h.max(1)));
+            }
+            match window.0.underlying_surface() {
+                WindowSurface::Wayland(toplevel) => {
+                    if rule.size.is_some() {
+                        toplevel.with_pending_state(|state| state.size = Some(geometry.size));
+                    }
+                }
+                #[cfg(feature = "xwayland")]
+                WindowSurface::X11(surface) => {
+                    let _ = surface.configure(geometry);
+                }
+            }
+            self.space.map_element(window.clone(), geometry.loc, true);
+        }
+
+        if let Some(snap) = rule.snap {
+            let location = window.snap(snap.into(), area);
+            self.space.map_element(window.clone(), location, true);
+        }
+
+        if rule.maximized == Some(true) {
+            match window.0.underlying_surface() {
+                WindowSurface::Wayland(toplevel) => XdgShellHandler::maximize_request(self, toplevel.clone()),
+                #[cfg(feature = "xwayland")]
+                WindowSurface::X11(surface) => self.maximize_request_x11(surface),
+            }
+        }
+
+        if rule.fullscreen == Some(true) {
+            match window.0.underlying_surface() {
+                WindowSurface::Wayland(toplevel) => {
+                    XdgShellHandler::fullscreen_request(self, toplevel.clone(), None)
+                }
+                #[cfg(feature = "xwayland")]
+                WindowSurface::X11(surface) => {
+                    if let Some(xwm) = self.xwm.as_ref().map(|xwm| xwm.id()) {
+                        XwmHandler::fullscreen_request(self, xwm, surface.clone());
+                    }
+                }
+            }
+        }
+
+        if rule.scratchpad == Some(true) {
+            self.set_scratchpad(window);
+        }
+
+        if let Some(index) = rule.workspace.and_then(|workspace| workspace.checked_sub(1)) {
+            // the initial configure is only sent to windows in the space, so get it out of the way
+            #[cfg_attr(not(feature = "xwayland"), allow(irrefutable_let_patterns))]
+            if let Some(toplevel) = window.0.toplevel() {
+                if !toplevel.is_initial_configure_sent() {
+                    toplevel.send_configure();
+                }
+            }
+            self.move_to_workspace(window, index);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use smithay::{
+    desktop::space::SpaceElement,
+    output::Output,
+    utils::{IsAlive, Point, SERIAL_COUNTER},
+};
+use tracing::debug;
+
+use crate::state::{Backend, LuxoState};
+
+use super::{usable_geometry, Animation, WindowElement, ANIMATION_DURATION};
+
+/// The window toggled onto the output under the pointer with `toggle-scratchpad`, dropping down from
+/// the top of the output above all other windows
+#[derive(Debug, Default)]
+pub struct Scratchpad {
+    window: Option<WindowElement>,
+    state: ScratchpadState,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+enum ScratchpadState {
+    #[default]
+    Shown,
+    /// Sliding out of view, unmapped once the animation is over
+    Hiding(Animation),
+    /// Unmapped until it is toggled again, the window keeps its size and state meanwhile
+    Hidden,
+}
+
+impl Scratchpad {
+    pub fn window(&self) -> Option<&WindowElement> {
+        self.window.as_ref().filter(|window| window.alive())
+    }
+
+    /// The scratchpad window while it is out of view and not in the space
+    pub fn hidden_window(&self) -> Option<&WindowElement> {
+        self.window().filter(|_| matches!(self.state, ScratchpadState::Hidden))
+    }
+}
+
+impl<BackendData: Backend> LuxoState<BackendData> {
+    /// Make `window`, which has to be in the space, the scratchpad.
+    ///
+    /// A previous scratchpad window is shown again, it would be lost otherwise.
+    pub fn set_scratchpad(&mut self, window: &WindowElement) {
+        if self.scratchpad.window() == Some(window) {
+            return;
+        }
+        if let Some(previous) = self.scratchpad.window().cloned() {
+            if !matches!(self.scratchpad.state, ScratchpadState::Shown) {
+                if let Some(output) = self.workspace_output() {
+                    self.show_scratchpad(&previous, &output);
+                }
+            }
+        }
+        debug!(window = window.id(), "Setting scratchpad window");
+        self.scratchpad = Scratchpad {
+            window: Some(window.clone()),
+            state: ScratchpadState::Shown,
+        };
+    }
+
+    /// Slide the scratchpad in on the output under the pointer, or out of view if it is already
+    /// shown there
+    pub fn toggle_scratchpad(&mut self) {
+        let Some(window) = self.scratchpad.window().cloned() else {
+            debug!("There is no scratchpad window to toggle");
+            return;
+        };
+        let Some(output) = self.workspace_output() else {
+            return;
+        };
+
+        let shown_here = self.space.outputs_for_element(&window).first() == Some(&output);
+        match self.scratchpad.state {
+            ScratchpadState::Shown if shown_here => self.hide_scratchpad(&window),
+            _ => self.show_scratchpad(&window, &output),
+        }
+    }
+
+    fn show_scratchpad(&mut self, window: &WindowElement, output: &Output) {
+        let (Some(area), Some(output_geometry)) =
+            (usable_geometry(&self.space, output), self.space.output_geometry(output))
+        else {
+            return;
+        };
+        debug!(output = ?output.name(), window = window.id(), "Showing scratchpad");
+
+        // it might have been left behind on another workspace or minimized
+        self.forget_window(window);
+        let size = window.geometry().size;
+        let location = Point::from((area.loc.x + (area.size.w - size.w) / 2, area.loc.y));
+        // starting with the bottom edge at the top of the output
+        let offset = Point::from((0, output_geometry.loc.y - location.y - size.h));
+        if self.space.element_location(window).is_some() {
+            self.map_window_animated(window, location, true);
+        } else {
+            self.map_window_sliding_in(window, location, offset, true);
+        }
+        #[cfg(feature = "xwayland")]
+        if let Some(surface) = window.0.x11_surface() {
+            let _ = surface.configure(smithay::utils::Rectangle::new(location, size));
+        }
+        self.scratchpad.state = ScratchpadState::Shown;
+
+        let keyboard = self.seat.get_keyboard().unwrap();
+        keyboard.set_focus(self, Some(window.clone().into()), SERIAL_COUNTER.next_serial());
+        self.backend_data.reset_buffers(output);
+    }
+
+    fn hide_scratchpad(&mut self, window: &WindowElement) {
+        debug!(window = window.id(), "Hiding scratchpad");
+        let keyboard = self.seat.get_keyboard().unwrap();
+        if keyboard
+            .current_focus()
+            .is_some_and(|focus| focus == window.clone().into())
+        {
+            keyboard.set_focus(self, None, SERIAL_COUNTER.next_serial());
+        }
+
+        if !self.config.appearance.animations {
+            self.unmap_scratchpad(window);
+            return;
+        }
+        let output_geometry = self
+            .space
+            .outputs_for_element(window)
+            .first()
+            .and_then(|output| self.space.output_geometry(output));
+        let (Some(output_geometry), Some(location), Some(geometry)) = (
+            output_geometry,
+            self.space.element_location(window),
+            self.space.element_geometry(window),
+        ) else {
+            self.unmap_scratchpad(window);
+            return;
+        };
+        // up until the bottom edge reaches the top of the output
+        let distance = geometry.loc.y + geometry.size.h - output_geometry.loc.y;
+        self.map_window_animated(window, location - Point::from((0, distance)), false);
+        self.scratchpad.state = ScratchpadState::Hiding(Animation::new(ANIMATION_DURATION));
+    }
+
+    fn unmap_scratchpad(&mut self, window: &WindowElement) {
+        let output = self.space.outputs_for_element(window).first().cloned();
+        self.dismiss_popup_grab(&window.clone().into());
+        self.space.unmap_elem(window);
+        self.scratchpad.state = ScratchpadState::Hidden;
+        if let Some(output) = output {
+            self.backend_data.reset_buffers(&output);
+        }
+    }
+
+    /// Unmap the scratchpad once it slid out of view, runs once per iteration of the event loop
+    pub fn update_scratchpad(&mut self) {
+        let Some(window) = self.scratchpad.window().cloned() else {
+            self.scratchpad.window = None;
+            return;
+        };
+        if let ScratchpadState::Hiding(animation) = self.scratchpad.state {
+            if animation.progress().is_none() {
+                self.unmap_scratchpad(&window);
+            }
+        }
+    }
+}
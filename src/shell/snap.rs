@@ -0,0 +1,183 @@
+use std::cell::RefCell;
+
+use smithay::{
+    backend::renderer::element::{
+        solid::{SolidColorBuffer, SolidColorRenderElement},
+        Kind,
+    },
+    output::Output,
+    reexports::wayland_protocols::xdg::shell::server::xdg_toplevel,
+    utils::{Logical, Point, Rectangle, Scale},
+};
+
+use super::{ssd::HEADER_BAR_HEIGHT, WindowElement};
+
+/// Distance in logical pixels from an output edge at which dragged windows snap
+const SNAP_THRESHOLD: f64 = 16.0;
+const PREVIEW_COLOR: [f32; 4] = [0.3, 0.45, 0.7, 0.4];
+
+/// Region of an output a dragged window can be snapped to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapZone {
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl SnapZone {
+    /// Zone the pointer at `location` is in, if it is close enough to an edge of `output_geometry`
+    pub fn at(output_geometry: Rectangle<i32, Logical>, location: Point<f64, Logical>) -> Option<SnapZone> {
+        let geo = output_geometry.to_f64();
+        if !geo.contains(location) {
+            return None;
+        }
+
+        let left = location.x - geo.loc.x < SNAP_THRESHOLD;
+        let right = geo.loc.x + geo.size.w - location.x < SNAP_THRESHOLD;
+        let top = location.y - geo.loc.y < SNAP_THRESHOLD;
+        let bottom = geo.loc.y + geo.size.h - location.y < SNAP_THRESHOLD;
+
+        match (left, right, top, bottom) {
+            (true, _, true, _) => Some(SnapZone::TopLeft),
+            (true, _, _, true) => Some(SnapZone::BottomLeft),
+            (_, true, true, _) => Some(SnapZone::TopRight),
+            (_, true, _, true) => Some(SnapZone::BottomRight),
+            (true, _, _, _) => Some(SnapZone::Left),
+            (_, true, _, _) => Some(SnapZone::Right),
+            _ => None,
+        }
+    }
+
+    /// Geometry of this zone inside the usable `area` of an output
+    pub fn geometry(self, area: Rectangle<i32, Logical>) -> Rectangle<i32, Logical> {
+        let half_w = area.size.w / 2;
+        let half_h = area.size.h / 2;
+        let (x, y, w, h) = match self {
+            SnapZone::Left => (0, 0, half_w, area.size.h),
+            SnapZone::Right => (half_w, 0, area.size.w - half_w, area.size.h),
+            SnapZone::TopLeft => (0, 0, half_w, half_h),
+            SnapZone::TopRight => (half_w, 0, area.size.w - half_w, half_h),
+            SnapZone::BottomLeft => (0, half_h, half_w, area.size.h - half_h),
+            SnapZone::BottomRight => (half_w, half_h, area.size.w - half_w, area.size.h - half_h),
+        };
+        Rectangle::new(area.loc + Point::from((x, y)), (w, h).into())
+    }
+
+    fn tiled_states(self) -> &'static [xdg_toplevel::State] {
+        use xdg_toplevel::State::*;
+        match self {
+            SnapZone::Left => &[TiledLeft, TiledTop, TiledBottom],
+            SnapZone::Right => &[TiledRight, TiledTop, TiledBottom],
+            SnapZone::TopLeft => &[TiledLeft, TiledTop],
+            SnapZone::TopRight => &[TiledRight, TiledTop],
+            SnapZone::BottomLeft => &[TiledLeft, TiledBottom],
+            SnapZone::BottomRight => &[TiledRight, TiledBottom],
+        }
+    }
+}
+
+/// Preview of the snap target of a window being dragged, stored in the output's user data
+#[derive(Debug, Default)]
+pub struct SnapPreview(RefCell<Option<(Rectangle<i32, Logical>, SolidColorBuffer)>>);
+
+impl SnapPreview {
+    /// Show a preview of `geometry` (relative to the output) on `output`
+    pub fn set(output: &Output, geometry: Rectangle<i32, Logical>) {
+        output.user_data().insert_if_missing(SnapPreview::default);
+        let mut preview = output.user_data().get::<SnapPreview>().unwrap().0.borrow_mut();
+        match preview.as_mut() {
+            Some((current, buffer)) => {
+                if *current != geometry {
+                    *current = geometry;
+                    buffer.resize(geometry.size);
+                }
+            }
+            None => *preview = Some((geometry, SolidColorBuffer::new(geometry.size, PREVIEW_COLOR))),
+        }
+    }
+
+    pub fn clear(output: &Output) {
+        if let Some(preview) = output.user_data().get::<SnapPreview>() {
+            preview.0.borrow_mut().take();
+        }
+    }
+
+    pub fn render_element(output: &Output) -> Option<SolidColorRenderElement> {
+        let preview = output.user_data().get::<SnapPreview>()?.0.borrow();
+        let (geometry, buffer) = preview.as_ref()?;
+        let scale = Scale::from(output.current_scale().fractional_scale());
+        Some(SolidColorRenderElement::from_buffer(
+            buffer,
+            geometry.loc.to_physical_precise_round(scale),
+            scale,
+            1.0,
+            Kind::Unspecified,
+        ))
+    }
+}
+
+impl WindowElement {
+    /// Resize and move the window to fill `zone` of the given usable `area`
+    ///
+    /// Returns the location the window has to be mapped at.
+    pub fn snap(&self, zone: SnapZone, area: Rectangle<i32, Logical>) -> Point<i32, Logical> {
+        let geometry = zone.geometry(area);
+        let mut size = geometry.size;
+        if self.decoration_state().is_ssd {
+            size.h -= HEADER_BAR_HEIGHT;
+        }
+
+        #[allow(irrefutable_let_patterns)]
+        if let Some(toplevel) = self.0.toplevel() {
+            toplevel.with_pending_state(|state| {
+                state.states.unset(xdg_toplevel::State::Maximized);
+                for tiled in zone.tiled_states() {
+                    state.states.set(*tiled);
+                }
+                state.size = Some(size);
+            });
+            toplevel.send_pending_configure();
+        }
+        #[cfg(feature = "xwayland")]
+        if let Some(surface) = self.0.x11_surface() {
+            let mut x11_geometry = geometry;
+            x11_geometry.size = size;
+            if self.decoration_state().is_ssd {
+                x11_geometry.loc.y += HEADER_BAR_HEIGHT;
+            }
+            let _ = surface.configure(x11_geometry);
+        }
+
+        geometry.loc
+    }
+
+    /// Drop the tiled states set by [`WindowElement::snap`] after the window got dragged out of its zone
+    pub fn unsnap(&self) {
+        #[allow(irrefutable_let_patterns)]
+        if let Some(toplevel) = self.0.toplevel() {
+            let was_tiled = toplevel.with_pending_state(|state| {
+                let tiled = [
+                    xdg_toplevel::State::TiledLeft,
+                    xdg_toplevel::State::TiledRight,
+                    xdg_toplevel::State::TiledTop,
+                    xdg_toplevel::State::TiledBottom,
+                ];
+                let was_tiled = tiled.iter().any(|s| state.states.contains(*s));
+                if was_tiled {
+                    for s in tiled {
+                        state.states.unset(s);
+                    }
+                    // let the client pick its size again
+                    state.size = None;
+                }
+                was_tiled
+            });
+            if was_tiled && toplevel.is_initial_configure_sent() {
+                toplevel.send_pending_configure();
+            }
+        }
+    }
+}
@@ -1,15 +1,17 @@
 use smithay::{
-    backend::renderer::{
-        element::{
-            solid::{SolidColorBuffer, SolidColorRenderElement},
-            AsRenderElements, Kind,
+    backend::{
+        input::ButtonState,
+        renderer::{
+            element::{
+                solid::{SolidColorBuffer, SolidColorRenderElement},
+                AsRenderElements, Kind,
+            },
+            Renderer,
         },
-        Renderer,
     },
     desktop::WindowSurface,
-    input::Seat,
+    input::{pointer::ButtonEvent, Seat},
     utils::{Logical, Point, Serial},
-    wayland::shell::xdg::XdgShellHandler,
 };
 
 use std::cell::{RefCell, RefMut};
@@ -32,6 +34,8 @@ pub struct HeaderBar {
     pub background: SolidColorBuffer,
     pub close_button: SolidColorBuffer,
     pub maximize_button: SolidColorBuffer,
+    pressed_button: Option<HeaderButton>,
+    last_click: Option<u32>,
 }
 
 const BG_COLOR: [f32; 4] = [0.75f32, 0.9f32, 0.78f32, 1f32];
@@ -44,6 +48,55 @@ pub const HEADER_BAR_HEIGHT: i32 = 32;
 const BUTTON_HEIGHT: u32 = HEADER_BAR_HEIGHT as u32;
 const BUTTON_WIDTH: u32 = 32;
 
+const BTN_LEFT: u32 = 0x110;
+/// Maximum time in milliseconds between two clicks on the header bar to count as a double-click
+const DOUBLE_CLICK_TIME: u32 = 400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HeaderButton {
+    Close,
+    Maximize,
+}
+
+impl HeaderButton {
+    fn activate<BackendData: Backend>(self, state: &mut LuxoState<BackendData>, window: &WindowElement) {
+        match (self, window.0.underlying_surface()) {
+            (HeaderButton::Close, _) => state.close_window(window),
+            (HeaderButton::Maximize, _) => {
+                // the decoration state is still borrowed by the caller, so defer remapping the window
+                let window = window.clone();
+                state
+                    .handle
+                    .insert_idle(move |data| data.toggle_maximize(&window));
+            }
+        }
+    }
+}
+
+fn start_move<BackendData: Backend>(
+    seat: &Seat<LuxoState<BackendData>>,
+    state: &mut LuxoState<BackendData>,
+    window: &WindowElement,
+    serial: Serial,
+) {
+    match window.0.underlying_surface() {
+        WindowSurface::Wayland(w) => {
+            let seat = seat.clone();
+            let toplevel = w.clone();
+            state
+                .handle
+                .insert_idle(move |data| data.move_request_xdg(&toplevel, &seat, serial));
+        }
+        #[cfg(feature = "xwayland")]
+        WindowSurface::X11(w) => {
+            let window = w.clone();
+            state
+                .handle
+                .insert_idle(move |data| data.move_request_x11(&window));
+        }
+    };
+}
+
 impl HeaderBar {
     pub fn pointer_enter(&mut self, loc: Point<f64, Logical>) {
         self.pointer_loc = Some(loc);
@@ -53,55 +106,52 @@ impl HeaderBar {
         self.pointer_loc = None;
     }
 
+    fn button_at(&self, loc: Point<f64, Logical>) -> Option<HeaderButton> {
+        if loc.x >= (self.width - BUTTON_WIDTH) as f64 {
+            Some(HeaderButton::Close)
+        } else if loc.x >= (self.width - (BUTTON_WIDTH * 2)) as f64 {
+            Some(HeaderButton::Maximize)
+        } else {
+            None
+        }
+    }
+
     pub fn clicked<BackendData: Backend>(
         &mut self,
         seat: &Seat<LuxoState<BackendData>>,
         state: &mut LuxoState<BackendData>,
         window: &WindowElement,
-        serial: Serial,
+        event: &ButtonEvent,
     ) {
-        match self.pointer_loc.as_ref() {
-            Some(loc) if loc.x >= (self.width - BUTTON_WIDTH) as f64 => {
-                match window.0.underlying_surface() {
-                    WindowSurface::Wayland(w) => w.send_close(),
-                    #[cfg(feature = "xwayland")]
-                    WindowSurface::X11(w) => {
-                        let _ = w.close();
-                    }
-                };
-            }
-            Some(loc) if loc.x >= (self.width - (BUTTON_WIDTH * 2)) as f64 => {
-                match window.0.underlying_surface() {
-                    WindowSurface::Wayland(w) => state.maximize_request(w.clone()),
-                    #[cfg(feature = "xwayland")]
-                    WindowSurface::X11(w) => {
-                        let surface = w.clone();
-                        state
-                            .handle
-                            .insert_idle(move |data| data.maximize_request_x11(&surface));
-                    }
-                };
+        if event.button != BTN_LEFT {
+            return;
+        }
+        let Some(loc) = self.pointer_loc else {
+            return;
+        };
+
+        match (self.button_at(loc), event.state) {
+            // buttons trigger on release, so the click can still be aborted by moving away
+            (Some(button), ButtonState::Pressed) => self.pressed_button = Some(button),
+            (Some(button), ButtonState::Released) => {
+                if self.pressed_button.take() == Some(button) {
+                    button.activate(state, window);
+                }
             }
-            Some(_) => {
-                match window.0.underlying_surface() {
-                    WindowSurface::Wayland(w) => {
-                        let seat = seat.clone();
-                        let toplevel = w.clone();
-                        state
-                            .handle
-                            .insert_idle(move |data| data.move_request_xdg(&toplevel, &seat, serial));
-                    }
-                    #[cfg(feature = "xwayland")]
-                    WindowSurface::X11(w) => {
-                        let window = w.clone();
-                        state
-                            .handle
-                            .insert_idle(move |data| data.move_request_x11(&window));
-                    }
-                };
+            (None, ButtonState::Pressed) => {
+                let double_click = self
+                    .last_click
+                    .is_some_and(|time| event.time.wrapping_sub(time) <= DOUBLE_CLICK_TIME);
+                if double_click {
+                    self.last_click = None;
+                    HeaderButton::Maximize.activate(state, window);
+                } else {
+                    self.last_click = Some(event.time);
+                    start_move(seat, state, window, event.serial);
+                }
             }
-            _ => {}
-        };
+            (None, ButtonState::Released) => self.pressed_button = None,
+        }
     }
 
     pub fn touch_down<BackendData: Backend>(
@@ -111,29 +161,11 @@ impl HeaderBar {
         window: &WindowElement,
         serial: Serial,
     ) {
-        match self.pointer_loc.as_ref() {
-            Some(loc) if loc.x >= (self.width - BUTTON_WIDTH) as f64 => {}
-            Some(loc) if loc.x >= (self.width - (BUTTON_WIDTH * 2)) as f64 => {}
-            Some(_) => {
-                match window.0.underlying_surface() {
-                    WindowSurface::Wayland(w) => {
-                        let seat = seat.clone();
-                        let toplevel = w.clone();
-                        state
-                            .handle
-                            .insert_idle(move |data| data.move_request_xdg(&toplevel, &seat, serial));
-                    }
-                    #[cfg(feature = "xwayland")]
-                    WindowSurface::X11(w) => {
-                        let window = w.clone();
-                        state
-                            .handle
-                            .insert_idle(move |data| data.move_request_x11(&window));
-                    }
-                };
+        if let Some(loc) = self.pointer_loc {
+            if self.button_at(loc).is_none() {
+                start_move(seat, state, window, serial);
             }
-            _ => {}
-        };
+        }
     }
 
     pub fn touch_up<BackendData: Backend>(
@@ -143,30 +175,9 @@ impl HeaderBar {
         window: &WindowElement,
         _serial: Serial,
     ) {
-        match self.pointer_loc.as_ref() {
-            Some(loc) if loc.x >= (self.width - BUTTON_WIDTH) as f64 => {
-                match window.0.underlying_surface() {
-                    WindowSurface::Wayland(w) => w.send_close(),
-                    #[cfg(feature = "xwayland")]
-                    WindowSurface::X11(w) => {
-                        let _ = w.close();
-                    }
-                };
-            }
-            Some(loc) if loc.x >= (self.width - (BUTTON_WIDTH * 2)) as f64 => {
-                match window.0.underlying_surface() {
-                    WindowSurface::Wayland(w) => state.maximize_request(w.clone()),
-                    #[cfg(feature = "xwayland")]
-                    WindowSurface::X11(w) => {
-                        let surface = w.clone();
-                        state
-                            .handle
-                            .insert_idle(move |data| data.maximize_request_x11(&surface));
-                    }
-                };
-            }
-            _ => {}
-        };
+        if let Some(button) = self.pointer_loc.and_then(|loc| self.button_at(loc)) {
+            button.activate(state, window);
+        }
     }
 
     pub fn redraw(&mut self, width: u32) {
@@ -279,6 +290,8 @@ impl WindowElement {
                     background: SolidColorBuffer::default(),
                     close_button: SolidColorBuffer::default(),
                     maximize_button: SolidColorBuffer::default(),
+                    pressed_button: None,
+                    last_click: None,
                 },
             })
         });
@@ -0,0 +1,473 @@
+use std::cell::RefCell;
+
+use smithay::{
+    output::Output,
+    utils::{IsAlive, Logical, Physical, Point, SERIAL_COUNTER},
+};
+use tracing::debug;
+#[cfg(feature = "xwayland")]
+use tracing::warn;
+
+use crate::{shell::FullscreenSurface, state::Backend, LuxoState};
+
+use super::{Animation, WindowElement, ANIMATION_DURATION};
+
+/// Number of workspaces available on every output
+pub const WORKSPACE_COUNT: usize = 9;
+
+/// Windows of a workspace that is currently not shown
+#[derive(Debug, Default)]
+struct HiddenWorkspace {
+    windows: Vec<(WindowElement, Point<i32, Logical>)>,
+    fullscreen: Option<WindowElement>,
+}
+
+/// Per-output workspace bookkeeping, stored in the output's user data.
+///
+/// The windows of the active workspace live in the `Space`, all others are unmapped
+/// and remembered here together with their location.
+#[derive(Debug, Default)]
+pub struct OutputWorkspaces {
+    active: usize,
+    hidden: [HiddenWorkspace; WORKSPACE_COUNT],
+    /// Minimized windows, restored onto whatever workspace is active at that time
+    minimized: Vec<(WindowElement, Point<i32, Logical>)>,
+    /// Windows of the active workspace hidden to show the desktop
+    desktop: Option<HiddenWorkspace>,
+    slide: Option<WorkspaceSlide>,
+}
+
+/// Animation of the active workspace sliding in after a switch
+#[derive(Debug, Clone, Copy)]
+struct WorkspaceSlide {
+    animation: Animation,
+    /// The new workspace comes after the old one and slides in from the right
+    forward: bool,
+}
+
+impl OutputWorkspaces {
+    pub fn active(&self) -> usize {
+        self.active
+    }
+}
+
+fn workspaces(output: &Output) -> &RefCell<OutputWorkspaces> {
+    output
+        .user_data()
+        .insert_if_missing(|| RefCell::new(OutputWorkspaces::default()));
+    output.user_data().get::<RefCell<OutputWorkspaces>>().unwrap()
+}
+
+/// Windows on the hidden workspaces of `output`, together with the index of their workspace.
+/// Windows hidden to show the desktop belong to the active one.
+pub fn hidden_windows(output: &Output) -> Vec<(usize, WindowElement)> {
+    let workspaces = workspaces(output).borrow();
+    let windows = workspaces
+        .hidden
+        .iter()
+        .enumerate()
+        .chain(workspaces.desktop.iter().map(|desktop| (workspaces.active, desktop)))
+        .flat_map(|(index, workspace)| {
+            workspace
+                .windows
+                .iter()
+                .filter(|(window, _)| window.alive())
+                .map(move |(window, _)| (index, window.clone()))
+        })
+        .collect::<Vec<_>>();
+    windows
+}
+
+/// Minimized windows of `output`, least recently minimized first
+pub fn minimized_windows(output: &Output) -> Vec<WindowElement> {
+    workspaces(output)
+        .borrow()
+        .minimized
+        .iter()
+        .filter(|(window, _)| window.alive())
+        .map(|(window, _)| window.clone())
+        .collect()
+}
+
+/// Offset the windows of `output` have to be rendered at while its active workspace slides in
+pub fn workspace_slide_offset(output: &Output) -> Option<Point<i32, Physical>> {
+    let mut workspaces = workspaces(output).borrow_mut();
+    let slide = workspaces.slide?;
+
+    let Some(progress) = slide.animation.progress() else {
+        workspaces.slide = None;
+        return None;
+    };
+
+    let width = output
+        .current_mode()
+        .map(|mode| output.current_transform().transform_size(mode.size).w)
+        .unwrap_or_default();
+    let x = (width as f64 * (1.0 - progress)).round() as i32;
+    Some(Point::from((if slide.forward { x } else { -x }, 0)))
+}
+
+impl<BackendData: Backend> LuxoState<BackendData> {
+    /// Index of the active workspace on `output`
+    pub fn active_workspace(&self, output: &Output) -> usize {
+        workspaces(output).borrow().active()
+    }
+
+    /// The output workspace actions apply to, the one under the pointer or the first one
+    pub fn workspace_output(&self) -> Option<Output> {
+        self.space
+            .output_under(self.pointer.current_location())
+            .next()
+            .or_else(|| self.space.outputs().next())
+            .cloned()
+    }
+
+    /// Make workspace `index` active on `output`, hiding the windows of the current one.
+    /// Windows hidden to show the desktop come back first, also when `index` is already active.
+    pub fn switch_workspace(&mut self, output: &Output, index: usize) {
+        if index >= WORKSPACE_COUNT {
+            return;
+        }
+        self.end_show_desktop(output);
+        let mut workspaces = workspaces(output).borrow_mut();
+        if workspaces.active == index {
+            return;
+        }
+        debug!(output = ?output.name(), from = workspaces.active, to = index, "Switching workspace");
+
+        // sticky windows stay where they are
+        let visible = self
+            .space
+            .elements()
+            .filter(|window| {
+                !window.is_sticky() && self.space.outputs_for_element(window).first() == Some(output)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        let active = workspaces.active;
+        let hidden = &mut workspaces.hidden[active];
+        for window in visible {
+            self.dismiss_popup_grab(&window.clone().into());
+            let location = self.space.element_location(&window).unwrap_or_default();
+            self.space.unmap_elem(&window);
+            hidden.windows.push((window, location));
+        }
+        if let Some(fullscreen) = output.user_data().get::<FullscreenSurface>() {
+            hidden.fullscreen = fullscreen.get();
+            fullscreen.clear();
+        }
+
+        let shown = std::mem::take(&mut workspaces.hidden[index]);
+        for (window, location) in shown.windows.into_iter().filter(|(window, _)| window.alive()) {
+            self.space.map_element(window, location, false);
+        }
+        if let Some(fullscreen) = shown.fullscreen.filter(|window| window.alive()) {
+            output.user_data().insert_if_missing(FullscreenSurface::default);
+            output.user_data().get::<FullscreenSurface>().unwrap().set(fullscreen);
+        }
+        workspaces.slide = self.config.appearance.animations.then(|| WorkspaceSlide {
+            animation: Animation::new(ANIMATION_DURATION),
+            forward: index > workspaces.active,
+        });
+        workspaces.active = index;
+        drop(workspaces);
+
+        let focus = output
+            .user_data()
+            .get::<FullscreenSurface>()
+            .and_then(|f| f.get())
+            .or_else(|| {
+                self.space
+                    .elements()
+                    .rev()
+                    .find(|window| self.space.outputs_for_element(window).first() == Some(output))
+                    .cloned()
+            });
+        let keyboard = self.seat.get_keyboard().unwrap();
+        keyboard.set_focus(self, focus.map(Into::into), SERIAL_COUNTER.next_serial());
+        self.backend_data.reset_buffers(output);
+    }
+
+    /// Move `window` to workspace `index` of the output it is currently shown on, sticky windows
+    /// stop being sticky.
+    pub fn move_to_workspace(&mut self, window: &WindowElement, index: usize) {
+        if index >= WORKSPACE_COUNT {
+            return;
+        }
+        window.set_sticky(false);
+        let Some(output) = self.space.outputs_for_element(window).first().cloned() else {
+            return;
+        };
+        let mut workspaces = workspaces(&output).borrow_mut();
+        if workspaces.active == index {
+            return;
+        }
+
+        let location = self.space.element_location(window).unwrap_or_default();
+        self.dismiss_popup_grab(&window.clone().into());
+        self.space.unmap_elem(window);
+        let hidden = &mut workspaces.hidden[index];
+        let fullscreen = output.user_data().get::<FullscreenSurface>();
+        if fullscreen.and_then(|f| f.get()).as_ref() == Some(window) {
+            fullscreen.unwrap().clear();
+            hidden.fullscreen = Some(window.clone());
+        }
+        hidden.windows.push((window.clone(), location));
+        drop(workspaces);
+
+        let keyboard = self.seat.get_keyboard().unwrap();
+        if keyboard
+            .current_focus()
+            .is_some_and(|focus| focus == window.clone().into())
+        {
+            keyboard.set_focus(self, None, SERIAL_COUNTER.next_serial());
+        }
+        self.backend_data.reset_buffers(&output);
+    }
+
+    /// Hide the windows of the active workspace of `output` to show the desktop, or bring them back
+    /// if they are hidden already
+    pub fn toggle_show_desktop(&mut self, output: &Output) {
+        if self.end_show_desktop(output) {
+            return;
+        }
+
+        // the scratchpad keeps being toggled on its own
+        let scratchpad = self.scratchpad.window().cloned();
+        let shown = self
+            .space
+            .elements()
+            .filter(|window| {
+                Some(*window) != scratchpad.as_ref()
+                    && self.space.outputs_for_element(window).first() == Some(output)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if shown.is_empty() {
+            return;
+        }
+        debug!(output = ?output.name(), "Showing the desktop");
+
+        let mut desktop = HiddenWorkspace::default();
+        for window in shown {
+            self.dismiss_popup_grab(&window.clone().into());
+            let location = self.space.element_location(&window).unwrap_or_default();
+            self.space.unmap_elem(&window);
+            desktop.windows.push((window, location));
+        }
+        if let Some(fullscreen) = output.user_data().get::<FullscreenSurface>() {
+            desktop.fullscreen = fullscreen.get();
+            fullscreen.clear();
+        }
+        let focused = self.focused_window();
+        let unfocus =
+            focused.is_some_and(|focused| desktop.windows.iter().any(|(window, _)| *window == focused));
+        workspaces(output).borrow_mut().desktop = Some(desktop);
+
+        if unfocus {
+            let keyboard = self.seat.get_keyboard().unwrap();
+            keyboard.set_focus(self, None, SERIAL_COUNTER.next_serial());
+        }
+        self.backend_data.reset_buffers(output);
+    }
+
+    /// Bring back the windows hidden to show the desktop on `output`, returning false if it isn't
+    /// shown
+    pub fn end_show_desktop(&mut self, output: &Output) -> bool {
+        let Some(desktop) = workspaces(output).borrow_mut().desktop.take() else {
+            return false;
+        };
+        debug!(output = ?output.name(), "Restoring the windows hidden to show the desktop");
+
+        for (window, location) in desktop.windows.into_iter().filter(|(window, _)| window.alive()) {
+            self.space.map_element(window, location, false);
+        }
+        if let Some(fullscreen) = desktop.fullscreen.filter(|window| window.alive()) {
+            output.user_data().insert_if_missing(FullscreenSurface::default);
+            output
+                .user_data()
+                .get::<FullscreenSurface>()
+                .unwrap()
+                .set(fullscreen);
+        }
+        self.backend_data.reset_buffers(output);
+        true
+    }
+
+    /// Hide `window` until it is restored with [`Self::unminimize_window`]
+    pub fn minimize_window(&mut self, window: &WindowElement) {
+        let Some(output) = self.space.outputs_for_element(window).first().cloned() else {
+            return;
+        };
+        debug!(output = ?output.name(), window = window.id(), "Minimizing window");
+
+        // it leaves fullscreen, to come back where it was before instead of covering the output
+        if window.is_fullscreen() {
+            self.toggle_fullscreen(window);
+        }
+        let location = self.space.element_location(window).unwrap_or_default();
+        self.dismiss_popup_grab(&window.clone().into());
+        self.space.unmap_elem(window);
+        let fullscreen = output.user_data().get::<FullscreenSurface>();
+        if fullscreen.and_then(|f| f.get()).as_ref() == Some(window) {
+            fullscreen.unwrap().clear();
+        }
+        // X11 clients and pagers see it iconified
+        #[cfg(feature = "xwayland")]
+        if let Some(surface) = window.0.x11_surface() {
+            if let Err(err) = surface.set_hidden(true) {
+                warn!(window = window.id(), ?err, "Failed to iconify X11 window");
+            }
+        }
+        workspaces(&output)
+            .borrow_mut()
+            .minimized
+            .push((window.clone(), location));
+
+        let keyboard = self.seat.get_keyboard().unwrap();
+        if keyboard
+            .current_focus()
+            .is_some_and(|focus| focus == window.clone().into())
+        {
+            keyboard.set_focus(self, None, SERIAL_COUNTER.next_serial());
+        }
+        self.backend_data.reset_buffers(&output);
+    }
+
+    /// Map a minimized window again on the active workspace of its output and focus it.
+    /// Returns false if `window` isn't minimized.
+    pub fn unminimize_window(&mut self, window: &WindowElement) -> bool {
+        let Some((output, location)) = self.space.outputs().find_map(|output| {
+            let mut workspaces = workspaces(output).borrow_mut();
+            let index = workspaces.minimized.iter().position(|(w, _)| w == window)?;
+            let (_, location) = workspaces.minimized.remove(index);
+            Some((output.clone(), location))
+        }) else {
+            return false;
+        };
+        debug!(output = ?output.name(), window = window.id(), "Restoring minimized window");
+
+        self.space.map_element(window.clone(), location, true);
+        #[cfg(feature = "xwayland")]
+        if let Some(surface) = window.0.x11_surface() {
+            if let Err(err) = surface.set_hidden(false) {
+                warn!(window = window.id(), ?err, "Failed to restore iconified X11 window");
+            }
+        }
+        let keyboard = self.seat.get_keyboard().unwrap();
+        keyboard.set_focus(self, Some(window.clone().into()), SERIAL_COUNTER.next_serial());
+        self.backend_data.reset_buffers(&output);
+        true
+    }
+
+    pub fn is_minimized(&self, window: &WindowElement) -> bool {
+        self.space.outputs().any(|output| {
+            workspaces(output)
+                .borrow()
+                .minimized
+                .iter()
+                .any(|(w, _)| w == window)
+        })
+    }
+
+    /// Drop every reference to `window` from the hidden workspaces, minimized windows and fullscreen
+    /// slots of all outputs.
+    ///
+    /// Needed for windows that are unmapped without being destroyed, which would otherwise
+    /// be brought back by the next workspace switch.
+    pub fn forget_window(&mut self, window: &WindowElement) {
+        for output in self.space.outputs() {
+            let mut workspaces = workspaces(output).borrow_mut();
+            let workspaces = &mut *workspaces;
+            workspaces.minimized.retain(|(minimized, _)| minimized != window);
+            for workspace in workspaces.hidden.iter_mut().chain(workspaces.desktop.as_mut()) {
+                workspace.windows.retain(|(hidden, _)| hidden != window);
+                if workspace.fullscreen.as_ref() == Some(window) {
+                    workspace.fullscreen = None;
+                }
+            }
+            if let Some(fullscreen) = output.user_data().get::<FullscreenSurface>() {
+                if fullscreen.get().as_ref() == Some(window) {
+                    fullscreen.clear();
+                }
+            }
+        }
+    }
+
+    /// Move all windows of `from`, including those on hidden workspaces, over to `to`.
+    ///
+    /// Windows keep their position relative to the output, scaled to the size of `to`.
+    /// Has to be called while `from` is still mapped.
+    pub fn migrate_windows(&mut self, from: &Output, to: &Output) {
+        let (Some(from_geo), Some(to_geo)) =
+            (self.space.output_geometry(from), self.space.output_geometry(to))
+        else {
+            return;
+        };
+        debug!(from = ?from.name(), to = ?to.name(), "Migrating windows");
+        self.end_show_desktop(from);
+        let relocate = |location: Point<i32, Logical>| -> Point<i32, Logical> {
+            let relative = location - from_geo.loc;
+            let x = relative.x as i64 * to_geo.size.w as i64 / from_geo.size.w.max(1) as i64;
+            let y = relative.y as i64 * to_geo.size.h as i64 / from_geo.size.h.max(1) as i64;
+            to_geo.loc + Point::from((x as i32, y as i32))
+        };
+
+        // a fullscreen window would keep the size of the old output
+        if let Some(fullscreen) = from.user_data().get::<FullscreenSurface>().and_then(|f| f.get()) {
+            self.toggle_fullscreen(&fullscreen);
+        }
+        // and so would those of the hidden workspaces, whose fullscreen slots don't move along
+        let hidden_fullscreen = workspaces(from)
+            .borrow()
+            .hidden
+            .iter()
+            .filter_map(|workspace| workspace.fullscreen.clone())
+            .filter(|window| window.alive())
+            .collect::<Vec<_>>();
+        for fullscreen in hidden_fullscreen {
+            self.toggle_fullscreen(&fullscreen);
+        }
+
+        let visible = self
+            .space
+            .elements()
+            .filter(|window| self.space.outputs_for_element(window).first() == Some(from))
+            .cloned()
+            .collect::<Vec<_>>();
+        for window in visible {
+            let location = self.space.element_location(&window).unwrap_or_default();
+            self.space.map_element(window, relocate(location), false);
+        }
+
+        let (hidden, minimized) = {
+            let mut workspaces = workspaces(from).borrow_mut();
+            (
+                std::mem::take(&mut workspaces.hidden),
+                std::mem::take(&mut workspaces.minimized),
+            )
+        };
+        let mut target = workspaces(to).borrow_mut();
+        target.minimized.extend(
+            minimized
+                .into_iter()
+                .filter(|(window, _)| window.alive())
+                .map(|(window, location)| (window, relocate(location))),
+        );
+        let active = target.active;
+        for (index, workspace) in hidden.into_iter().enumerate() {
+            let windows = workspace
+                .windows
+                .into_iter()
+                .filter(|(window, _)| window.alive())
+                .map(|(window, location)| (window, relocate(location)));
+            if index == active {
+                for (window, location) in windows {
+                    self.space.map_element(window, location, false);
+                }
+            } else {
+                target.hidden[index].windows.extend(windows);
+            }
+        }
+    }
+}
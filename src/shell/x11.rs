@@ -20,31 +20,20 @@ use smithay::{
         xwayland_shell::{XWaylandShellHandler, XWaylandShellState},
     },
     xwayland::{
-        xwm::{Reorder, ResizeEdge as X11ResizeEdge, XwmId},
+        xwm::{Reorder, ResizeEdge as X11ResizeEdge, WmWindowProperty, XwmId},
         X11Surface, X11Wm, XwmHandler,
     },
 };
-use tracing::{error, trace};
+use tracing::{error, trace, warn};
 
 use crate::{focus::KeyboardFocusTarget, state::Backend, LuxoState};
 
 use super::{
-    place_new_window, FullscreenSurface, PointerMoveSurfaceGrab, PointerResizeSurfaceGrab, ResizeData,
-    ResizeState, SurfaceData, TouchMoveSurfaceGrab, WindowElement,
+    hidden_windows, minimized_windows, place_new_window, usable_geometry, FullscreenSurface, OldGeometry,
+    PointerMoveSurfaceGrab, PointerResizeSurfaceGrab, ResizeData, ResizeState, SurfaceData,
+    TouchMoveSurfaceGrab, WindowElement,
 };
 
-#[derive(Debug, Default)]
-struct OldGeometry(RefCell<Option<Rectangle<i32, Logical>>>);
-impl OldGeometry {
-    pub fn save(&self, geo: Rectangle<i32, Logical>) {
-        *self.0.borrow_mut() = Some(geo);
-    }
-
-    pub fn restore(&self) -> Option<Rectangle<i32, Logical>> {
-        self.0.borrow_mut().take()
-    }
-}
-
 impl<BackendData: Backend> XWaylandShellHandler for LuxoState<BackendData> {
     fn xwayland_shell_state(&mut self) -> &mut XWaylandShellState {
         &mut self.xwayland_shell_state
@@ -59,16 +48,29 @@ impl<BackendData: Backend> XwmHandler for LuxoState<BackendData> {
     fn new_window(&mut self, _xwm: XwmId, _window: X11Surface) {}
     fn new_override_redirect_window(&mut self, _xwm: XwmId, _window: X11Surface) {}
 
-    fn map_window_request(&mut self, _xwm: XwmId, window: X11Surface) {
+    fn map_window_request(&mut self, xwm: XwmId, window: X11Surface) {
         window.set_mapped(true).unwrap();
-        let window = WindowElement(Window::new_x11_window(window));
-        place_new_window(&mut self.space, self.pointer.current_location(), &window, true);
-        let bbox = self.space.element_bbox(&window).unwrap();
-        let Some(xsurface) = window.0.x11_surface() else {
-            unreachable!()
-        };
-        xsurface.configure(Some(bbox)).unwrap();
-        window.set_ssd(!xsurface.is_decorated());
+        let element = WindowElement(Window::new_x11_window(window.clone()));
+        if let Some(output) = self.workspace_output() {
+            self.end_show_desktop(&output);
+        }
+        place_new_window(&mut self.space, self.pointer.current_location(), &element, true);
+        self.animate_open(&element);
+        let bbox = self.space.element_bbox(&element).unwrap();
+        window.configure(Some(bbox)).unwrap();
+        element.set_ssd(!window.is_decorated());
+        if let Err(err) = self.xwm.as_mut().unwrap().raise_window(&window) {
+            warn!(?err, "Failed to raise new X11 window");
+        }
+
+        // state requested before the window was mapped, see `fullscreen_request` and `maximize_request`
+        if window.is_fullscreen() {
+            self.fullscreen_request(xwm, window);
+        } else if window.is_maximized() {
+            self.maximize_request_x11(&window);
+        }
+
+        self.apply_window_rules(&element);
     }
 
     fn mapped_override_redirect_window(&mut self, _xwm: XwmId, window: X11Surface) {
@@ -78,40 +80,70 @@ impl<BackendData: Backend> XwmHandler for LuxoState<BackendData> {
     }
 
     fn unmapped_window(&mut self, _xwm: XwmId, window: X11Surface) {
-        let maybe = self
-            .space
-            .elements()
-            .find(|e| matches!(e.0.x11_surface(), Some(w) if w == &window))
-            .cloned();
-        if let Some(elem) = maybe {
-            self.space.unmap_elem(&elem)
-        }
+        self.remove_x11_window(&window);
         if !window.is_override_redirect() {
             window.set_mapped(false).unwrap();
         }
     }
 
-    fn destroyed_window(&mut self, _xwm: XwmId, _window: X11Surface) {}
+    fn destroyed_window(&mut self, _xwm: XwmId, window: X11Surface) {
+        self.remove_x11_window(&window);
+    }
+
+    fn property_notify(&mut self, _xwm: XwmId, window: X11Surface, _property: WmWindowProperty) {
+        if let Some(element) = self.x11_element(&window) {
+            element.refresh_metadata();
+        }
+    }
 
     fn configure_request(
         &mut self,
         _xwm: XwmId,
         window: X11Surface,
-        _x: Option<i32>,
-        _y: Option<i32>,
+        x: Option<i32>,
+        y: Option<i32>,
         w: Option<u32>,
         h: Option<u32>,
-        _reorder: Option<Reorder>,
+        reorder: Option<Reorder>,
     ) {
-        // we just set the new size, but don't let windows move themselves around freely
         let mut geo = window.geometry();
-        if let Some(w) = w {
-            geo.size.w = w as i32;
-        }
-        if let Some(h) = h {
-            geo.size.h = h as i32;
+        let Some(elem) = self.x11_element(&window) else {
+            // not managed yet, the client is free to pick its initial geometry
+            if let Some(x) = x {
+                geo.loc.x = x;
+            }
+            if let Some(y) = y {
+                geo.loc.y = y;
+            }
+            if let Some(w) = w {
+                geo.size.w = w as i32;
+            }
+            if let Some(h) = h {
+                geo.size.h = h as i32;
+            }
+            let _ = window.configure(geo);
+            return;
+        };
+
+        // we just set the new size, but don't let windows move themselves around freely.
+        // Fullscreen and maximized windows keep their size, but still get a synthetic configure
+        // as demanded by ICCCM.
+        if !window.is_fullscreen() && !window.is_maximized() {
+            if let Some(w) = w {
+                geo.size.w = w as i32;
+            }
+            if let Some(h) = h {
+                geo.size.h = h as i32;
+            }
         }
         let _ = window.configure(geo);
+
+        if matches!(reorder, Some(Reorder::Top)) && self.space.elements().any(|e| e == &elem) {
+            self.space.raise_element(&elem, false);
+            if let Err(err) = self.xwm.as_mut().unwrap().raise_window(&window) {
+                warn!(?err, "Failed to raise X11 window");
+            }
+        }
     }
 
     fn configure_notify(
@@ -135,7 +167,30 @@ impl<BackendData: Backend> XwmHandler for LuxoState<BackendData> {
     }
 
     fn maximize_request(&mut self, _xwm: XwmId, window: X11Surface) {
-        self.maximize_request_x11(&window);
+        if window.is_mapped() {
+            self.maximize_request_x11(&window);
+        } else {
+            // applied once the window gets mapped
+            let _ = window.set_maximized(true);
+        }
+    }
+
+    fn minimize_request(&mut self, _xwm: XwmId, window: X11Surface) {
+        let Some(elem) = self
+            .space
+            .elements()
+            .find(|e| matches!(e.0.x11_surface(), Some(w) if w == &window))
+            .cloned()
+        else {
+            return;
+        };
+        self.minimize_window(&elem);
+    }
+
+    fn unminimize_request(&mut self, _xwm: XwmId, window: X11Surface) {
+        if let Some(elem) = self.x11_element(&window) {
+            self.unminimize_window(&elem);
+        }
     }
 
     fn unmaximize_request(&mut self, _xwm: XwmId, window: X11Surface) {
@@ -155,7 +210,7 @@ impl<BackendData: Backend> XwmHandler for LuxoState<BackendData> {
             .and_then(|data| data.restore())
         {
             window.configure(old_geo).unwrap();
-            self.space.map_element(elem, old_geo.loc, false);
+            self.map_window_animated(&elem, old_geo.loc, false);
         }
     }
 
@@ -174,6 +229,7 @@ impl<BackendData: Backend> XwmHandler for LuxoState<BackendData> {
                 .expect("No outputs found");
             let geometry = self.space.output_geometry(output).unwrap();
 
+            self.save_fullscreen_restore(elem);
             window.set_fullscreen(true).unwrap();
             elem.set_ssd(false);
             window.configure(geometry).unwrap();
@@ -184,6 +240,9 @@ impl<BackendData: Backend> XwmHandler for LuxoState<BackendData> {
                 .unwrap()
                 .set(elem.clone());
             trace!("Fullscreening: {:?}", elem);
+        } else if !window.is_mapped() {
+            // applied once the window gets mapped
+            let _ = window.set_fullscreen(true);
         }
     }
 
@@ -192,20 +251,30 @@ impl<BackendData: Backend> XwmHandler for LuxoState<BackendData> {
             .space
             .elements()
             .find(|e| matches!(e.0.x11_surface(), Some(w) if w == &window))
+            .cloned()
         {
             window.set_fullscreen(false).unwrap();
             elem.set_ssd(!window.is_decorated());
-            if let Some(output) = self.space.outputs().find(|o| {
-                o.user_data()
-                    .get::<FullscreenSurface>()
-                    .and_then(|f| f.get())
-                    .map(|w| &w == elem)
-                    .unwrap_or(false)
-            }) {
+            if let Some(output) = self
+                .space
+                .outputs()
+                .find(|o| {
+                    o.user_data()
+                        .get::<FullscreenSurface>()
+                        .and_then(|f| f.get())
+                        .map(|w| w == elem)
+                        .unwrap_or(false)
+                })
+                .cloned()
+            {
                 trace!("Unfullscreening: {:?}", elem);
                 output.user_data().get::<FullscreenSurface>().unwrap().clear();
-                window.configure(self.space.element_bbox(elem)).unwrap();
-                self.backend_data.reset_buffers(output);
+                let geometry = self
+                    .restore_fullscreen(&elem)
+                    .filter(|geometry| !geometry.size.is_empty())
+                    .or_else(|| self.space.element_bbox(&elem));
+                window.configure(geometry).unwrap();
+                self.backend_data.reset_buffers(&output);
             }
         }
     }
@@ -246,6 +315,7 @@ impl<BackendData: Backend> XwmHandler for LuxoState<BackendData> {
             initial_window_location,
             initial_window_size,
             last_window_size: initial_window_size,
+            outline: self.config.appearance.outline_resize,
         };
 
         let pointer = self.pointer.clone();
@@ -256,7 +326,10 @@ impl<BackendData: Backend> XwmHandler for LuxoState<BackendData> {
         self.move_request_x11(&window)
     }
 
-    fn allow_selection_access(&mut self, xwm: XwmId, _selection: SelectionTarget) -> bool {
+    fn allow_selection_access(&mut self, xwm: XwmId, selection: SelectionTarget) -> bool {
+        if matches!(selection, SelectionTarget::Primary) && !self.config.primary_selection.to_x11() {
+            return false;
+        }
         if let Some(keyboard) = self.seat.get_keyboard() {
             // check that an X11 window is focused
             if let Some(KeyboardFocusTarget::Window(w)) = keyboard.current_focus() {
@@ -290,6 +363,9 @@ impl<BackendData: Backend> XwmHandler for LuxoState<BackendData> {
 
     fn new_selection(&mut self, _xwm: XwmId, selection: SelectionTarget, mime_types: Vec<String>) {
         trace!(?selection, ?mime_types, "Got Selection from X11",);
+        if matches!(selection, SelectionTarget::Primary) && !self.config.primary_selection.from_x11() {
+            return;
+        }
         // TODO check, that focused windows is X11 window before doing this
         match selection {
             SelectionTarget::Clipboard => {
@@ -302,6 +378,9 @@ impl<BackendData: Backend> XwmHandler for LuxoState<BackendData> {
     }
 
     fn cleared_selection(&mut self, _xwm: XwmId, selection: SelectionTarget) {
+        if matches!(selection, SelectionTarget::Primary) && !self.config.primary_selection.from_x11() {
+            return;
+        }
         match selection {
             SelectionTarget::Clipboard => {
                 if current_data_device_selection_userdata(&self.seat).is_some() {
@@ -318,6 +397,41 @@ impl<BackendData: Backend> XwmHandler for LuxoState<BackendData> {
 }
 
 impl<BackendData: Backend> LuxoState<BackendData> {
+    /// The element of an X11 window, whether it is on the active workspace, a hidden one or minimized
+    fn x11_element(&self, window: &X11Surface) -> Option<WindowElement> {
+        let is_window = |e: &WindowElement| matches!(e.0.x11_surface(), Some(w) if w == window);
+        self.space.elements().find(|e| is_window(e)).cloned().or_else(|| {
+            self.space
+                .outputs()
+                .flat_map(|output| {
+                    hidden_windows(output)
+                        .into_iter()
+                        .map(|(_, e)| e)
+                        .chain(minimized_windows(output))
+                })
+                .find(is_window)
+        })
+    }
+
+    /// Stop managing an X11 window that was unmapped or destroyed
+    fn remove_x11_window(&mut self, window: &X11Surface) {
+        let Some(elem) = self.x11_element(window) else {
+            return;
+        };
+        let output = self.space.outputs_for_element(&elem).first().cloned();
+        self.fade_out_closed(&elem);
+        self.space.unmap_elem(&elem);
+        self.forget_window(&elem);
+        if let Some(output) = output {
+            self.backend_data.reset_buffers(&output);
+        }
+
+        let keyboard = self.seat.get_keyboard().unwrap();
+        if keyboard.current_focus() == Some(elem.into()) {
+            keyboard.set_focus(self, None, SERIAL_COUNTER.next_serial());
+        }
+    }
+
     pub fn maximize_request_x11(&mut self, window: &X11Surface) {
         let Some(elem) = self
             .space
@@ -328,6 +442,7 @@ impl<BackendData: Backend> LuxoState<BackendData> {
             return;
         };
 
+        let was_maximized = window.is_maximized();
         let old_geo = self.space.element_bbox(&elem).unwrap();
         let outputs_for_window = self.space.outputs_for_element(&elem);
         let output = outputs_for_window
@@ -336,13 +451,16 @@ impl<BackendData: Backend> LuxoState<BackendData> {
             .or_else(|| self.space.outputs().next())
             // Assumes that at least one output exists
             .expect("No outputs found");
-        let geometry = self.space.output_geometry(output).unwrap();
+        let geometry = usable_geometry(&self.space, output).unwrap();
 
         window.set_maximized(true).unwrap();
         window.configure(geometry).unwrap();
-        window.user_data().insert_if_missing(OldGeometry::default);
-        window.user_data().get::<OldGeometry>().unwrap().save(old_geo);
-        self.space.map_element(elem, geometry.loc, false);
+        // keep the geometry from before the first of repeated maximize requests
+        if !was_maximized {
+            window.user_data().insert_if_missing(OldGeometry::default);
+            window.user_data().get::<OldGeometry>().unwrap().save(old_geo);
+        }
+        self.map_window_animated(&elem, geometry.loc, false);
     }
 
     pub fn move_request_x11(&mut self, window: &X11Surface) {
@@ -415,11 +533,7 @@ impl<BackendData: Backend> LuxoState<BackendData> {
             }
         }
 
-        let grab = PointerMoveSurfaceGrab {
-            start_data,
-            window: element.clone(),
-            initial_window_location,
-        };
+        let grab = PointerMoveSurfaceGrab::new(start_data, element.clone(), initial_window_location);
 
         let pointer = self.pointer.clone();
         pointer.set_grab(self, grab, SERIAL_COUNTER.next_serial(), Focus::Clear);
@@ -3,7 +3,7 @@ use std::cell::RefCell;
 use smithay::{
     desktop::{
         find_popup_root_surface, get_popup_toplevel_coords, layer_map_for_output, space::SpaceElement,
-        PopupKeyboardGrab, PopupKind, PopupPointerGrab, PopupUngrabStrategy, Space, Window,
+        PopupKeyboardGrab, PopupKind, PopupManager, PopupPointerGrab, PopupUngrabStrategy, Space, Window,
         WindowSurfaceType,
     },
     input::{pointer::Focus, Seat},
@@ -15,13 +15,13 @@ use smithay::{
             Resource,
         },
     },
-    utils::{Logical, Point, Serial},
+    utils::{Logical, Point, Rectangle, Serial},
     wayland::{
         compositor::{self, with_states},
         seat::WaylandFocus,
         shell::xdg::{
-            Configure, PopupSurface, PositionerState, ToplevelSurface, XdgShellHandler, XdgShellState,
-            XdgToplevelSurfaceData,
+            Configure, PopupSurface, PositionerState, ShellClient, ToplevelSurface, XdgShellHandler,
+            XdgShellState, XdgToplevelSurfaceData,
         },
     },
 };
@@ -34,8 +34,9 @@ use crate::{
 };
 
 use super::{
-    fullscreen_output_geometry, place_new_window, FullscreenSurface, PointerMoveSurfaceGrab,
-    PointerResizeSurfaceGrab, ResizeData, ResizeEdge, ResizeState, SurfaceData, WindowElement,
+    fullscreen_output_geometry, place_new_window, usable_geometry, FullscreenSurface, OldGeometry,
+    PointerMoveSurfaceGrab, PointerResizeSurfaceGrab, ResizeData, ResizeEdge, ResizeState, SurfaceData,
+    WindowElement,
 };
 
 impl<BackendData: Backend> XdgShellHandler for LuxoState<BackendData> {
@@ -43,12 +44,21 @@ impl<BackendData: Backend> XdgShellHandler for LuxoState<BackendData> {
         &mut self.xdg_shell_state
     }
 
+    fn client_pong(&mut self, client: ShellClient) {
+        self.client_answered_ping(&client);
+    }
+
     fn new_toplevel(&mut self, surface: ToplevelSurface) {
         // Do not send a configure here, the initial configure
         // of a xdg_surface has to be sent during the commit if
         // the surface is not already configured
         let window = WindowElement(Window::new_wayland_window(surface.clone()));
+        // a new window ends showing the desktop on the output it opens on
+        if let Some(output) = self.workspace_output() {
+            self.end_show_desktop(&output);
+        }
         place_new_window(&mut self.space, self.pointer.current_location(), &window, true);
+        self.animate_open(&window);
 
         compositor::add_post_commit_hook(surface.wl_surface(), |state: &mut Self, _, surface| {
             handle_toplevel_commit(&mut state.space, surface);
@@ -193,6 +203,7 @@ impl<BackendData: Backend> XdgShellHandler for LuxoState<BackendData> {
             initial_window_location,
             initial_window_size,
             last_window_size: initial_window_size,
+            outline: self.config.appearance.outline_resize,
         };
 
         pointer.set_grab(self, grab, serial, Focus::Clear);
@@ -218,7 +229,10 @@ impl<BackendData: Backend> XdgShellHandler for LuxoState<BackendData> {
                 // resizing. To check if the surface was resizing
                 // before sending the configure we need to use
                 // the current state as the received acknowledge
-                // will no longer have the resize state set
+                // will no longer have the resize state set.
+                // Keyboard driven resizes never set the resize
+                // state, so acknowledging the configure without
+                // it is enough for them.
                 let is_resizing = with_states(&surface, |states| {
                     states
                         .data_map
@@ -231,7 +245,8 @@ impl<BackendData: Backend> XdgShellHandler for LuxoState<BackendData> {
                         .contains(xdg_toplevel::State::Resizing)
                 });
 
-                if configure.serial >= serial && is_resizing {
+                let is_final = is_resizing || !configure.state.states.contains(xdg_toplevel::State::Resizing);
+                if configure.serial >= serial && is_final {
                     with_states(&surface, |states| {
                         let mut data = states
                             .data_map
@@ -292,7 +307,9 @@ impl<BackendData: Backend> XdgShellHandler for LuxoState<BackendData> {
                     .space
                     .elements()
                     .find(|window| window.wl_surface().map(|s| &*s == wl_surface).unwrap_or(false))
-                    .unwrap();
+                    .unwrap()
+                    .clone();
+                self.save_fullscreen_restore(&window);
 
                 surface.with_pending_state(|state| {
                     state.states.set(xdg_toplevel::State::Fullscreen);
@@ -341,6 +358,15 @@ impl<BackendData: Backend> XdgShellHandler for LuxoState<BackendData> {
             }
         }
 
+        let restored = self
+            .all_windows()
+            .into_iter()
+            .find(|window| window.0.toplevel() == Some(&surface))
+            .and_then(|window| self.restore_fullscreen(&window));
+        if let Some(geometry) = restored {
+            surface.with_pending_state(|state| state.size = Some(geometry.size));
+        }
+
         surface.send_pending_configure();
     }
 
@@ -352,21 +378,35 @@ impl<BackendData: Backend> XdgShellHandler for LuxoState<BackendData> {
             .capabilities
             .contains(xdg_toplevel::WmCapabilities::Maximize)
         {
-            let window = self.window_for_surface(surface.wl_surface()).unwrap();
-            let outputs_for_window = self.space.outputs_for_element(&window);
-            let output = outputs_for_window
-                .first()
-                // The window hasn't been mapped yet, use the primary output instead
-                .or_else(|| self.space.outputs().next())
-                // Assumes that at least one output exists
-                .expect("No outputs found");
-            let geometry = self.space.output_geometry(output).unwrap();
+            // Windows on hidden workspaces are not part of the space
+            if let Some(window) = self.window_for_surface(surface.wl_surface()) {
+                let outputs_for_window = self.space.outputs_for_element(&window);
+                let output = outputs_for_window
+                    .first()
+                    // The window hasn't been mapped yet, use the primary output instead
+                    .or_else(|| self.space.outputs().next())
+                    // Assumes that at least one output exists
+                    .expect("No outputs found");
+                let geometry = usable_geometry(&self.space, output).unwrap();
+
+                // keep the geometry from before the first of repeated maximize requests
+                if !window.is_maximized() {
+                    if let Some(location) = self.space.element_location(&window) {
+                        window.user_data().insert_if_missing(OldGeometry::default);
+                        window
+                            .user_data()
+                            .get::<OldGeometry>()
+                            .unwrap()
+                            .save(Rectangle::new(location, window.0.geometry().size));
+                    }
+                }
 
-            surface.with_pending_state(|state| {
-                state.states.set(xdg_toplevel::State::Maximized);
-                state.size = Some(geometry.size);
-            });
-            self.space.map_element(window, geometry.loc, true);
+                surface.with_pending_state(|state| {
+                    state.states.set(xdg_toplevel::State::Maximized);
+                    state.size = Some(geometry.size);
+                });
+                self.map_window_animated(&window, geometry.loc, true);
+            }
         }
 
         // The protocol demands us to always reply with a configure,
@@ -387,13 +427,25 @@ impl<BackendData: Backend> XdgShellHandler for LuxoState<BackendData> {
             return;
         }
 
+        let restored = self
+            .window_for_surface(surface.wl_surface())
+            .and_then(|window| take_old_geometry(&window).map(|old_geo| (window, old_geo)));
         surface.with_pending_state(|state| {
             state.states.unset(xdg_toplevel::State::Maximized);
-            state.size = None;
+            state.size = restored.as_ref().map(|(_, old_geo)| old_geo.size);
         });
+        if let Some((window, old_geo)) = restored {
+            self.map_window_animated(&window, old_geo.loc, true);
+        }
         surface.send_pending_configure();
     }
 
+    fn minimize_request(&mut self, surface: ToplevelSurface) {
+        if let Some(window) = self.window_for_surface(surface.wl_surface()) {
+            self.minimize_window(&window);
+        }
+    }
+
     fn grab(&mut self, surface: PopupSurface, seat: wl_seat::WlSeat, serial: Serial) {
         let seat: Seat<LuxoState<BackendData>> = Seat::from_resource(&seat).unwrap();
         let kind = PopupKind::Xdg(surface);
@@ -413,7 +465,7 @@ impl<BackendData: Backend> XdgShellHandler for LuxoState<BackendData> {
                         .map(KeyboardFocusTarget::LayerSurface)
                 })
         }) {
-            let ret = self.popups.grab_popup(root, kind, &seat, serial);
+            let ret = self.popups.grab_popup(root.clone(), kind, &seat, serial);
 
             if let Ok(mut grab) = ret {
                 if let Some(keyboard) = seat.get_keyboard() {
@@ -437,12 +489,45 @@ impl<BackendData: Backend> XdgShellHandler for LuxoState<BackendData> {
                     }
                     pointer.set_grab(self, PopupPointerGrab::new(&grab), serial, Focus::Keep);
                 }
+                self.popup_grab = Some((root, grab));
             }
         }
     }
 }
 
 impl<BackendData: Backend> LuxoState<BackendData> {
+    /// Dismiss the popups holding the popup grab if they belong to `root`, which is about to be
+    /// hidden. The keyboard and pointer grabs end with the next event.
+    pub fn dismiss_popup_grab(&mut self, root: &KeyboardFocusTarget) {
+        if let Some((_, mut grab)) = self.popup_grab.take_if(|(grab_root, _)| grab_root == root) {
+            if !grab.has_ended() {
+                grab.ungrab(PopupUngrabStrategy::All);
+            }
+        }
+    }
+
+    /// Dismiss the popup grab of a window or layer surface on `output`, which is being removed
+    pub fn dismiss_output_popup_grab(&mut self, output: &Output) {
+        let Some((root, _)) = self.popup_grab.as_ref() else {
+            return;
+        };
+        let on_output = match root {
+            KeyboardFocusTarget::Window(window) => self
+                .space
+                .elements()
+                .find(|element| element.0 == *window)
+                .is_some_and(|element| self.space.outputs_for_element(element).contains(output)),
+            KeyboardFocusTarget::LayerSurface(layer) => {
+                layer_map_for_output(output).layers().any(|mapped| mapped == layer)
+            }
+            KeyboardFocusTarget::Popup(_) => false,
+        };
+        if on_output {
+            let root = root.clone();
+            self.dismiss_popup_grab(&root);
+        }
+    }
+
     pub fn move_request_xdg(&mut self, surface: &ToplevelSurface, seat: &Seat<Self>, serial: Serial) {
         if let Some(touch) = seat.get_touch() {
             if touch.has_grab(serial) {
@@ -471,9 +556,10 @@ impl<BackendData: Backend> LuxoState<BackendData> {
                 // If surface is maximized then unmaximize it
                 let current_state = surface.current_state();
                 if current_state.states.contains(xdg_toplevel::State::Maximized) {
+                    let old_geo = take_old_geometry(&window);
                     surface.with_pending_state(|state| {
                         state.states.unset(xdg_toplevel::State::Maximized);
-                        state.size = None;
+                        state.size = old_geo.map(|old_geo| old_geo.size);
                     });
 
                     surface.send_configure();
@@ -535,9 +621,10 @@ impl<BackendData: Backend> LuxoState<BackendData> {
         // If surface is maximized then unmaximize it
         let current_state = surface.current_state();
         if current_state.states.contains(xdg_toplevel::State::Maximized) {
+            let old_geo = take_old_geometry(&window);
             surface.with_pending_state(|state| {
                 state.states.unset(xdg_toplevel::State::Maximized);
-                state.size = None;
+                state.size = old_geo.map(|old_geo| old_geo.size);
             });
 
             surface.send_configure();
@@ -557,20 +644,18 @@ impl<BackendData: Backend> LuxoState<BackendData> {
             initial_window_location = (pos.x as i32, pos.y as i32).into();
         }
 
-        let grab = PointerMoveSurfaceGrab {
-            start_data,
-            window,
-            initial_window_location,
-        };
+        let grab = PointerMoveSurfaceGrab::new(start_data, window, initial_window_location);
 
         pointer.set_grab(self, grab, serial, Focus::Clear);
     }
 
-    fn unconstrain_popup(&self, popup: &PopupSurface) {
+    /// Keep `popup` within the outputs of its window, or within the output of its layer surface
+    pub fn unconstrain_popup(&self, popup: &PopupSurface) {
         let Ok(root) = find_popup_root_surface(&PopupKind::Xdg(popup.clone())) else {
             return;
         };
         let Some(window) = self.window_for_surface(&root) else {
+            self.unconstrain_layer_popup(popup, &root);
             return;
         };
 
@@ -600,6 +685,56 @@ impl<BackendData: Backend> LuxoState<BackendData> {
             state.geometry = state.positioner.get_unconstrained_geometry(target);
         });
     }
+
+    /// Place the popups of `root` again after it moved or resized
+    ///
+    /// Popups are only positioned when they are created or repositioned by the client, so they
+    /// would otherwise keep the constraints of the old parent geometry.
+    pub fn resync_popups(&self, root: &WlSurface) {
+        for (popup, _) in PopupManager::popups_for_surface(root) {
+            let PopupKind::Xdg(popup) = popup else {
+                continue;
+            };
+            // the initial configure places the popup anyway
+            if !popup.is_initial_configure_sent() {
+                continue;
+            }
+
+            let old_geometry = popup.with_pending_state(|state| state.geometry);
+            self.unconstrain_popup(&popup);
+            if popup.with_pending_state(|state| state.geometry) != old_geometry {
+                if let Err(err) = popup.send_configure() {
+                    warn!("Failed to reposition popup: {}", err);
+                }
+            }
+        }
+    }
+
+    /// Keep `popup` of a layer surface (e.g. a panel menu) on the output of the layer surface
+    fn unconstrain_layer_popup(&self, popup: &PopupSurface, root: &WlSurface) {
+        let Some((output, layer)) = self.space.outputs().find_map(|output| {
+            let map = layer_map_for_output(output);
+            let layer = map.layer_for_surface(root, WindowSurfaceType::TOPLEVEL)?.clone();
+            Some((output.clone(), layer))
+        }) else {
+            return;
+        };
+        let Some(output_geo) = self.space.output_geometry(&output) else {
+            return;
+        };
+        let Some(layer_geo) = layer_map_for_output(&output).layer_geometry(&layer) else {
+            return;
+        };
+
+        // relative to the layer surface, like for windows
+        let mut target = Rectangle::from_size(output_geo.size);
+        target.loc -= get_popup_toplevel_coords(&PopupKind::Xdg(popup.clone()));
+        target.loc -= layer_geo.loc;
+
+        popup.with_pending_state(|state| {
+            state.geometry = state.positioner.get_unconstrained_geometry(target);
+        });
+    }
 }
 
 /// Should be called on `WlSurface::commit` of xdg toplevel
@@ -615,7 +750,10 @@ fn handle_toplevel_commit(space: &mut Space<WindowElement>, surface: &WlSurface)
     let new_loc: Point<Option<i32>, Logical> = with_states(window.wl_surface().as_deref()?, |states| {
         let data = states.data_map.get::<RefCell<SurfaceData>>()?.borrow_mut();
 
-        if let ResizeState::Resizing(resize_data) = data.resize_state {
+        if let ResizeState::Resizing(resize_data)
+        | ResizeState::WaitingForFinalAck(resize_data, _)
+        | ResizeState::WaitingForCommit(resize_data) = data.resize_state
+        {
             let edges = resize_data.edges;
             let loc = resize_data.initial_window_location;
             let size = resize_data.initial_window_size;
@@ -652,3 +790,12 @@ fn handle_toplevel_commit(space: &mut Space<WindowElement>, surface: &WlSurface)
 
     Some(())
 }
+
+/// Geometry `window` had before it was maximized, unless it was maximized before it had a size
+fn take_old_geometry(window: &WindowElement) -> Option<Rectangle<i32, Logical>> {
+    window
+        .user_data()
+        .get::<OldGeometry>()
+        .and_then(|data| data.restore())
+        .filter(|old_geo| !old_geo.size.is_empty())
+}
@@ -1,11 +1,15 @@
 use std::{
-    collections::HashMap,
-    os::unix::io::OwnedFd,
-    sync::{atomic::AtomicBool, Arc},
+    collections::{HashMap, HashSet},
+    os::unix::{io::OwnedFd, net::UnixStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
     time::Duration,
 };
 
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 
 use smithay::{
     backend::{
@@ -15,18 +19,19 @@ use smithay::{
         },
     },
     delegate_compositor, delegate_data_control, delegate_data_device, delegate_fractional_scale,
-    delegate_input_method_manager, delegate_keyboard_shortcuts_inhibit, delegate_layer_shell,
-    delegate_output, delegate_pointer_constraints, delegate_pointer_gestures, delegate_presentation,
-    delegate_primary_selection, delegate_relative_pointer, delegate_seat, delegate_security_context,
-    delegate_shm, delegate_tablet_manager, delegate_text_input_manager, delegate_viewporter,
-    delegate_virtual_keyboard_manager, delegate_xdg_activation, delegate_xdg_decoration, delegate_xdg_shell,
+    delegate_idle_inhibit, delegate_idle_notify, delegate_input_method_manager,
+    delegate_keyboard_shortcuts_inhibit, delegate_layer_shell, delegate_output, delegate_pointer_constraints,
+    delegate_pointer_gestures, delegate_presentation, delegate_primary_selection, delegate_relative_pointer,
+    delegate_seat, delegate_security_context, delegate_shm, delegate_tablet_manager,
+    delegate_text_input_manager, delegate_viewporter, delegate_virtual_keyboard_manager,
+    delegate_xdg_activation, delegate_xdg_decoration, delegate_xdg_shell,
     desktop::{
         space::SpaceElement,
         utils::{
             surface_presentation_feedback_flags_from_states, surface_primary_scanout_output,
             update_surface_primary_scanout_output, with_surfaces_surface_tree, OutputPresentationFeedback,
         },
-        PopupKind, PopupManager, Space,
+        PopupGrab, PopupKind, PopupManager, Space, WindowSurfaceType,
     },
     input::{
         keyboard::{Keysym, LedState, XkbConfig},
@@ -35,7 +40,11 @@ use smithay::{
     },
     output::Output,
     reexports::{
-        calloop::{generic::Generic, Interest, LoopHandle, Mode, PostAction},
+        calloop::{
+            generic::Generic,
+            signals::{Signal, Signals},
+            Interest, LoopHandle, Mode, PostAction, RegistrationToken,
+        },
         wayland_protocols::xdg::decoration::{
             self as xdg_decoration, zv1::server::zxdg_toplevel_decoration_v1::Mode as DecorationMode,
         },
@@ -47,11 +56,17 @@ use smithay::{
     },
     utils::{Clock, Logical, Monotonic, Point, Rectangle, Time},
     wayland::{
+        alpha_modifier::AlphaModifierState,
         commit_timing::{CommitTimerBarrierStateUserData, CommitTimingManagerState},
         compositor::{get_parent, with_states, CompositorClientState, CompositorHandler, CompositorState},
+        content_type::ContentTypeState,
+        cursor_shape::CursorShapeManagerState,
         dmabuf::DmabufFeedback,
         fifo::{FifoBarrierCachedState, FifoManagerState},
+        foreign_toplevel_list::{ForeignToplevelHandle, ForeignToplevelListState},
         fractional_scale::{with_fractional_scale, FractionalScaleHandler, FractionalScaleManagerState},
+        idle_inhibit::{IdleInhibitHandler, IdleInhibitManagerState},
+        idle_notify::{IdleNotifierHandler, IdleNotifierState},
         input_method::{InputMethodHandler, InputMethodManagerState, PopupSurface},
         keyboard_shortcuts_inhibit::{
             KeyboardShortcutsInhibitHandler, KeyboardShortcutsInhibitState, KeyboardShortcutsInhibitor,
@@ -70,6 +85,9 @@ use smithay::{
                 set_data_device_focus, ClientDndGrabHandler, DataDeviceHandler, DataDeviceState,
                 ServerDndGrabHandler,
             },
+            ext_data_control::{
+                DataControlHandler as ExtDataControlHandler, DataControlState as ExtDataControlState,
+            },
             primary_selection::{set_primary_focus, PrimarySelectionHandler, PrimarySelectionState},
             wlr_data_control::{DataControlHandler, DataControlState},
             SelectionHandler,
@@ -97,13 +115,35 @@ use smithay::{
 
 #[cfg(feature = "xwayland")]
 use crate::cursor::Cursor;
+#[cfg(feature = "udev")]
+use crate::input_handler::CompositorSwipe;
 use crate::{
+    cli::Options,
+    config::{
+        ClientRule, Config, ConfigError, DebugOverlayConfig, DecorationPolicy, OptionalProtocol,
+        OutputEdge, PointerDeviceConfig, PrivilegedProtocol,
+    },
+    delegate_color_management, delegate_screencopy,
     focus::{KeyboardFocusTarget, PointerFocusTarget},
-    shell::WindowElement,
+    ipc::{self, FrameStats, IpcSocket, LeaseConnectorInfo},
+    keybindings::Keybindings,
+    protocols::{
+        color_management::{
+            ColorManagementHandler, ColorManagementState, ImageDescription, OutputImageDescription,
+        },
+        foreign_toplevel::ForeignToplevelManagerState,
+        output_management::OutputConfiguration,
+        registry::PROTOCOLS,
+        screencopy::{Screencopy, ScreencopyHandler, ScreencopyManagerState},
+    },
+    session::Autostart,
+    shell::{FocusCycle, FocusReturn, Scratchpad, WindowElement},
 };
 #[cfg(feature = "xwayland")]
 use smithay::{
     delegate_xwayland_keyboard_grab, delegate_xwayland_shell,
+    input::pointer::CursorIcon,
+    reexports::wayland_server::backend::protocol::ProtocolError,
     utils::Size,
     wayland::selection::{SelectionSource, SelectionTarget},
     wayland::xwayland_keyboard_grab::{XWaylandKeyboardGrabHandler, XWaylandKeyboardGrabState},
@@ -111,10 +151,38 @@ use smithay::{
     xwayland::{X11Wm, XWayland, XWaylandEvent},
 };
 
+/// Activation tokens older than this only mark their window as urgent
+const ACTIVATION_TOKEN_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[derive(Debug, Default)]
 pub struct ClientState {
     pub compositor_state: CompositorClientState,
     pub security_context: Option<SecurityContext>,
+    /// Path of the executable, looked up once the client is inserted
+    pub executable: OnceLock<PathBuf>,
+    /// Process id from the socket credentials, looked up once the client is inserted
+    pub pid: OnceLock<i32>,
+}
+
+impl ClientState {
+    /// Whether every field given in `rule` matches this client. Clients connected through a
+    /// security context only match rules naming their app id, their executable is the one of the
+    /// sandbox and the engine alone would let in every app of it.
+    pub(crate) fn matches(&self, rule: &ClientRule) -> bool {
+        let context = self.security_context.as_ref();
+        if context.is_some() && rule.app_id.is_none() {
+            return false;
+        }
+        rule.executable
+            .as_ref()
+            .map_or(true, |executable| self.executable.get() == Some(executable))
+            && rule.sandbox_engine.as_ref().map_or(true, |engine| {
+                context.and_then(|context| context.sandbox_engine.as_ref()) == Some(engine)
+            })
+            && rule.app_id.as_ref().map_or(true, |app_id| {
+                context.and_then(|context| context.app_id.as_ref()) == Some(app_id)
+            })
+    }
 }
 impl ClientData for ClientState {
     /// Notification that a client was initialized
@@ -123,17 +191,34 @@ impl ClientData for ClientState {
     fn disconnected(&self, _client_id: ClientId, _reason: DisconnectReason) {}
 }
 
+/// Whether `client` may use privileged protocols like screencopy or data-control, which clients
+/// connected through a security context, e.g. flatpak apps, may not
+pub(crate) fn is_privileged(client: &Client) -> bool {
+    client
+        .get_data::<ClientState>()
+        .map_or(true, |client_state| client_state.security_context.is_none())
+}
+
 #[derive(Debug)]
 pub struct LuxoState<BackendData: Backend + 'static> {
     pub backend_data: BackendData,
     pub socket_name: Option<String>,
+    pub ipc_socket: Option<IpcSocket>,
     pub display_handle: DisplayHandle,
+    /// Source dispatching the clients, removing it drops the display
+    display_token: RegistrationToken,
     pub running: Arc<AtomicBool>,
     pub handle: LoopHandle<'static, LuxoState<BackendData>>,
+    pub config: Config,
+    /// Where `config` is loaded from, `None` if no location was given and there is no config directory
+    pub config_path: Option<PathBuf>,
+    pub keybindings: Keybindings,
 
     // desktop
     pub space: Space<WindowElement>,
     pub popups: PopupManager,
+    /// Root of the popups that took the last popup grab, with the grab
+    pub popup_grab: Option<(KeyboardFocusTarget, PopupGrab<LuxoState<BackendData>>)>,
 
     // smithay state
     pub compositor_state: CompositorState,
@@ -142,6 +227,7 @@ pub struct LuxoState<BackendData: Backend + 'static> {
     pub output_manager_state: OutputManagerState,
     pub primary_selection_state: PrimarySelectionState,
     pub data_control_state: DataControlState,
+    pub ext_data_control_state: ExtDataControlState,
     pub seat_state: SeatState<LuxoState<BackendData>>,
     pub keyboard_shortcuts_inhibit_state: KeyboardShortcutsInhibitState,
     pub shm_state: ShmState,
@@ -157,21 +243,62 @@ pub struct LuxoState<BackendData: Backend + 'static> {
     pub single_pixel_buffer_state: SinglePixelBufferState,
     pub fifo_manager_state: FifoManagerState,
     pub commit_timing_manager_state: CommitTimingManagerState,
+    pub screencopy_state: ScreencopyManagerState,
+    pub color_management_state: ColorManagementState,
+    pub foreign_toplevel_state: ForeignToplevelManagerState,
+    pub foreign_toplevel_list_state: ForeignToplevelListState,
+    /// ext-foreign-toplevel-list handles by window id
+    pub foreign_toplevel_list_handles: HashMap<u64, ForeignToplevelHandle>,
+    pub idle_notifier_state: IdleNotifierState<LuxoState<BackendData>>,
 
     pub dnd_icon: Option<DndIcon>,
+    /// Screencopy frames waiting for the next repaint of their output
+    pub pending_screencopies: Vec<Screencopy>,
+    /// Surfaces holding an idle inhibitor, only honoured while they are mapped
+    pub idle_inhibiting_surfaces: HashSet<WlSurface>,
 
     // input-related fields
     pub suppressed_keys: Vec<Keysym>,
+    /// Mouse buttons whose press ran a pointer binding, their release isn't sent to clients
+    pub suppressed_buttons: Vec<u32>,
+    /// Scroll distance towards the next step of a scroll binding, in 120ths of a wheel click
+    pub pointer_binding_scroll: f64,
+    /// Edge of the output under the pointer it is being pushed against, and how far
+    pub pointer_edge_push: Option<(OutputEdge, f64)>,
+    /// Index of the active layout out of the configured keyboard layouts
+    pub keyboard_layout: usize,
     pub cursor_status: CursorImageStatus,
+    /// Cursor images set by clients for each tablet tool
+    pub tablet_tool_cursors: HashMap<TabletToolDescriptor, CursorImageStatus>,
+    /// The tool currently in proximity and the pointer cursor it replaces
+    pub tablet_tool_in_proximity: Option<(TabletToolDescriptor, CursorImageStatus)>,
     pub seat_name: String,
     pub seat: Seat<LuxoState<BackendData>>,
     pub clock: Clock<Monotonic>,
     pub pointer: PointerHandle<LuxoState<BackendData>>,
+    /// Where the client of the locked pointer wants it to be once unlocked, in global coordinates
+    pub pointer_lock_hint: Option<Point<f64, Logical>>,
+    #[cfg(feature = "udev")]
+    pub compositor_swipe: Option<CompositorSwipe>,
+    /// Pending focus change of the focus-follows-mouse models
+    pub focus_timer: Option<RegistrationToken>,
+    /// Windows in the order they were last focused, most recent first
+    pub focus_history: Vec<WindowElement>,
+    /// Running cycle of `focus-next` and `focus-prev`, which freezes `focus_history`
+    pub focus_cycle: Option<FocusCycle>,
+    pub focus_return: FocusReturn,
+    pub autostart: Autostart,
+    pub scratchpad: Scratchpad,
+    /// Debug overlays currently drawn, initialized from the config and changeable over ipc
+    pub debug_overlay: DebugOverlayConfig,
 
     #[cfg(feature = "xwayland")]
     pub xwm: Option<X11Wm>,
     #[cfg(feature = "xwayland")]
     pub xdisplay: Option<u32>,
+    /// Event source and wayland client of the running XWayland server
+    #[cfg(feature = "xwayland")]
+    xwayland: Option<(RegistrationToken, Client)>,
 
     #[cfg(feature = "debug")]
     pub renderdoc: Option<renderdoc::RenderDoc<renderdoc::V141>>,
@@ -230,6 +357,9 @@ impl<BackendData: Backend> SelectionHandler for LuxoState<BackendData> {
 
     #[cfg(feature = "xwayland")]
     fn new_selection(&mut self, ty: SelectionTarget, source: Option<SelectionSource>, _seat: Seat<Self>) {
+        if matches!(ty, SelectionTarget::Primary) && !self.config.primary_selection.to_x11() {
+            return;
+        }
         if let Some(xwm) = self.xwm.as_mut() {
             if let Err(err) = xwm.new_selection(ty, source.map(|source| source.mime_types())) {
                 warn!(?err, ?ty, "Failed to set Xwayland selection");
@@ -269,6 +399,14 @@ impl<BackendData: Backend> DataControlHandler for LuxoState<BackendData> {
 
 delegate_data_control!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
 
+impl<BackendData: Backend> ExtDataControlHandler for LuxoState<BackendData> {
+    fn data_control_state(&self) -> &ExtDataControlState {
+        &self.ext_data_control_state
+    }
+}
+
+smithay::delegate_ext_data_control!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
+
 impl<BackendData: Backend> ShmHandler for LuxoState<BackendData> {
     fn shm_state(&self) -> &ShmState {
         &self.shm_state
@@ -286,16 +424,25 @@ impl<BackendData: Backend> SeatHandler for LuxoState<BackendData> {
     }
 
     fn focus_changed(&mut self, seat: &Seat<Self>, target: Option<&KeyboardFocusTarget>) {
+        if let Some(KeyboardFocusTarget::Window(window)) = target {
+            let window = WindowElement(window.clone());
+            window.set_urgent(false);
+            self.push_focus_history(window);
+        }
+        self.track_focus_return(target);
         let dh = &self.display_handle;
 
         let wl_surface = target.and_then(WaylandFocus::wl_surface);
 
         let focus = wl_surface.and_then(|s| dh.get_client(s.id()).ok());
         set_data_device_focus(dh, seat, focus.clone());
-        set_primary_focus(dh, seat, focus);
+        set_primary_focus(dh, seat, focus.filter(|_| self.config.primary_selection.enabled));
     }
     fn cursor_image(&mut self, _seat: &Seat<Self>, image: CursorImageStatus) {
-        self.cursor_status = image;
+        match self.tablet_tool_in_proximity.as_mut() {
+            Some((_, pointer_cursor)) => *pointer_cursor = image,
+            None => self.cursor_status = image,
+        }
     }
 
     fn led_state_changed(&mut self, _seat: &Seat<Self>, led_state: LedState) {
@@ -305,9 +452,11 @@ impl<BackendData: Backend> SeatHandler for LuxoState<BackendData> {
 delegate_seat!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
 
 impl<BackendData: Backend> TabletSeatHandler for LuxoState<BackendData> {
-    fn tablet_tool_image(&mut self, _tool: &TabletToolDescriptor, image: CursorImageStatus) {
-        // TODO: tablet tools should have their own cursors
-        self.cursor_status = image;
+    fn tablet_tool_image(&mut self, tool: &TabletToolDescriptor, image: CursorImageStatus) {
+        if matches!(&self.tablet_tool_in_proximity, Some((active, _)) if active == tool) {
+            self.cursor_status = image.clone();
+        }
+        self.tablet_tool_cursors.insert(tool.clone(), image);
     }
 }
 delegate_tablet_manager!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
@@ -345,8 +494,11 @@ impl<BackendData: Backend> KeyboardShortcutsInhibitHandler for LuxoState<Backend
     }
 
     fn new_inhibitor(&mut self, inhibitor: KeyboardShortcutsInhibitor) {
-        // Just grant the wish for everyone
-        inhibitor.activate();
+        self.handle_shortcuts_inhibitor(inhibitor);
+    }
+
+    fn inhibitor_destroyed(&mut self, inhibitor: KeyboardShortcutsInhibitor) {
+        self.shortcuts_inhibitor_destroyed(&inhibitor);
     }
 }
 
@@ -377,20 +529,18 @@ impl<BackendData: Backend> PointerConstraintsHandler for LuxoState<BackendData>
         pointer: &PointerHandle<Self>,
         location: Point<f64, Logical>,
     ) {
-        if with_pointer_constraint(surface, pointer, |constraint| {
+        if !with_pointer_constraint(surface, pointer, |constraint| {
             constraint.is_some_and(|c| c.is_active())
         }) {
-            let origin = self
-                .space
-                .elements()
-                .find_map(|window| {
-                    (window.wl_surface().as_deref() == Some(surface)).then(|| window.geometry())
-                })
-                .unwrap_or_default()
-                .loc
-                .to_f64();
+            return;
+        }
 
-            pointer.set_location(origin + location);
+        // an active lock keeps the pointer on the locked surface, so it is the one under it
+        let origin = self
+            .surface_under(pointer.current_location())
+            .and_then(|(target, loc)| (target.wl_surface().as_deref() == Some(surface)).then_some(loc));
+        if let Some(origin) = origin {
+            self.pointer_lock_hint = Some(origin + location);
         }
     }
 }
@@ -418,31 +568,50 @@ impl<BackendData: Backend> XdgActivationHandler for LuxoState<BackendData> {
 
     fn request_activation(
         &mut self,
-        _token: XdgActivationToken,
+        token: XdgActivationToken,
         token_data: XdgActivationTokenData,
         surface: WlSurface,
     ) {
-        if token_data.timestamp.elapsed().as_secs() < 10 {
-            // Just grant the wish
-            let w = self
-                .space
-                .elements()
-                .find(|window| window.wl_surface().map(|s| *s == surface).unwrap_or(false))
-                .cloned();
-            if let Some(window) = w {
-                self.space.raise_element(&window, true);
-            }
+        self.xdg_activation_state.remove_token(&token);
+        let Some(window) = self
+            .all_windows()
+            .into_iter()
+            .find(|window| window.wl_surface().is_some_and(|s| *s == surface))
+        else {
+            return;
+        };
+
+        // tokens are only handed out to clients that had the keyboard focus or minted by us when
+        // spawning a program, but they shouldn't be used to steal the focus long after that
+        if token_data.timestamp.elapsed() < ACTIVATION_TOKEN_TIMEOUT {
+            self.focus_window_by_id(window.id());
+        } else {
+            debug!(
+                window = window.id(),
+                "Activation token expired, marking window as urgent"
+            );
+            window.set_urgent(true);
         }
     }
 }
 delegate_xdg_activation!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
 
+impl<BackendData: Backend> LuxoState<BackendData> {
+    /// Decoration mode used for clients that didn't ask for a specific one
+    fn default_decoration_mode(&self) -> xdg_decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode {
+        use xdg_decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode;
+        match self.config.appearance.decorations {
+            DecorationPolicy::Client => Mode::ClientSide,
+            DecorationPolicy::Server => Mode::ServerSide,
+        }
+    }
+}
+
 impl<BackendData: Backend> XdgDecorationHandler for LuxoState<BackendData> {
     fn new_decoration(&mut self, toplevel: ToplevelSurface) {
-        use xdg_decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode;
-        // Set the default to client side
+        let mode = self.default_decoration_mode();
         toplevel.with_pending_state(|state| {
-            state.decoration_mode = Some(Mode::ClientSide);
+            state.decoration_mode = Some(mode);
         });
     }
     fn request_mode(&mut self, toplevel: ToplevelSurface, mode: DecorationMode) {
@@ -460,9 +629,9 @@ impl<BackendData: Backend> XdgDecorationHandler for LuxoState<BackendData> {
         }
     }
     fn unset_mode(&mut self, toplevel: ToplevelSurface) {
-        use xdg_decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode;
+        let mode = self.default_decoration_mode();
         toplevel.with_pending_state(|state| {
-            state.decoration_mode = Some(Mode::ClientSide);
+            state.decoration_mode = Some(mode);
         });
 
         if toplevel.is_initial_configure_sent() {
@@ -533,12 +702,7 @@ impl<BackendData: Backend + 'static> SecurityContextHandler for LuxoState<Backen
                     security_context: Some(security_context.clone()),
                     ..ClientState::default()
                 };
-                if let Err(err) = data
-                    .display_handle
-                    .insert_client(client_stream, Arc::new(client_state))
-                {
-                    warn!("Error adding wayland client: {}", err);
-                };
+                data.insert_client(client_stream, client_state);
             })
             .expect("Failed to init wayland socket source");
     }
@@ -570,33 +734,85 @@ smithay::delegate_xdg_foreign!(@<BackendData: Backend + 'static> LuxoState<Backe
 
 smithay::delegate_single_pixel_buffer!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
 
+smithay::delegate_content_type!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
+
+smithay::delegate_cursor_shape!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
+
 smithay::delegate_fifo!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
 
 smithay::delegate_commit_timing!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
 
+smithay::delegate_alpha_modifier!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
+
+impl<BackendData: Backend> ScreencopyHandler for LuxoState<BackendData> {
+    fn frame(&mut self, screencopy: Screencopy) {
+        self.pending_screencopies.push(screencopy);
+    }
+}
+delegate_screencopy!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
+
+impl<BackendData: Backend> ColorManagementHandler for LuxoState<BackendData> {
+    fn color_management_state(&mut self) -> &mut ColorManagementState {
+        &mut self.color_management_state
+    }
+
+    fn preferred_image_description(&mut self, surface: &WlSurface) -> ImageDescription {
+        with_states(surface, |states| surface_primary_scanout_output(surface, states))
+            .or_else(|| self.space.outputs().next().cloned())
+            .map(|output| OutputImageDescription::get(&output))
+            .unwrap_or(ImageDescription::SRGB)
+    }
+}
+delegate_color_management!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
+
+impl<BackendData: Backend> IdleNotifierHandler for LuxoState<BackendData> {
+    fn idle_notifier_state(&mut self) -> &mut IdleNotifierState<Self> {
+        &mut self.idle_notifier_state
+    }
+}
+delegate_idle_notify!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
+
+impl<BackendData: Backend> IdleInhibitHandler for LuxoState<BackendData> {
+    fn inhibit(&mut self, surface: WlSurface) {
+        self.idle_inhibiting_surfaces.insert(surface);
+        self.update_idle_inhibit();
+    }
+
+    fn uninhibit(&mut self, surface: WlSurface) {
+        self.idle_inhibiting_surfaces.remove(&surface);
+        self.update_idle_inhibit();
+    }
+}
+delegate_idle_inhibit!(@<BackendData: Backend + 'static> LuxoState<BackendData>);
+
 impl<BackendData: Backend + 'static> LuxoState<BackendData> {
     pub fn init(
         display: Display<LuxoState<BackendData>>,
         handle: LoopHandle<'static, LuxoState<BackendData>>,
         backend_data: BackendData,
         listen_on_socket: bool,
+        options: &Options,
     ) -> LuxoState<BackendData> {
         let dh = display.handle();
 
+        let config_path = options.config.clone().or_else(Config::path);
+        let config = Config::load(config_path.as_deref());
+        PROTOCOLS.configure(&config);
+        let debug_overlay = config.debug_overlay.with_env();
+
         let clock = Clock::new();
 
         // init wayland clients
         let socket_name = if listen_on_socket {
-            let source = ListeningSocketSource::new_auto().unwrap();
+            let source = match options.socket.as_deref() {
+                Some(name) => ListeningSocketSource::with_name(name),
+                None => ListeningSocketSource::new_auto(),
+            }
+            .expect("Failed to bind the wayland socket");
             let socket_name = source.socket_name().to_string_lossy().into_owned();
             handle
                 .insert_source(source, |client_stream, _, data| {
-                    if let Err(err) = data
-                        .display_handle
-                        .insert_client(client_stream, Arc::new(ClientState::default()))
-                    {
-                        warn!("Error adding wayland client: {}", err);
-                    };
+                    data.insert_client(client_stream, ClientState::default());
                 })
                 .expect("Failed to init wayland socket source");
             info!(name = socket_name, "Listening on wayland socket");
@@ -604,7 +820,23 @@ impl<BackendData: Backend + 'static> LuxoState<BackendData> {
         } else {
             None
         };
-        handle
+        let ipc_socket = socket_name.as_deref().and_then(|socket_name| {
+            ipc::listen(&handle, socket_name)
+                .map_err(|err| warn!("Failed to start ipc: {}", err))
+                .ok()
+        });
+        let watched = match config_path.clone() {
+            Some(path) => Config::watch(&handle, path, |state: &mut LuxoState<BackendData>| {
+                if let Err(err) = state.reload_config() {
+                    warn!("Failed to reload config: {}", err);
+                }
+            }),
+            None => Err(ConfigError::NoConfigDir),
+        };
+        if let Err(err) = watched {
+            warn!("Config hot-reload disabled: {}", err);
+        }
+        let display_token = handle
             .insert_source(
                 Generic::new(display, Interest::READ, Mode::Level),
                 |_, display, data| {
@@ -618,14 +850,38 @@ impl<BackendData: Backend + 'static> LuxoState<BackendData> {
             )
             .expect("Failed to init wayland server source");
 
+        let signals = Signals::new(&[Signal::SIGINT, Signal::SIGTERM]).and_then(|signals| {
+            handle
+                .insert_source(signals, |event, _, data: &mut LuxoState<BackendData>| {
+                    info!(signal = ?event.signal(), "Received signal, shutting down");
+                    data.running.store(false, Ordering::SeqCst);
+                })
+                .map_err(|err| err.error)
+        });
+        if let Err(err) = signals {
+            warn!("Unable to shut down on SIGINT or SIGTERM: {}", err);
+        }
+
         // init globals
         let compositor_state = CompositorState::new::<Self>(&dh);
         let data_device_state = DataDeviceState::new::<Self>(&dh);
-        let layer_shell_state = WlrLayerShellState::new::<Self>(&dh);
+        let layer_shell_state = WlrLayerShellState::new_with_filter::<Self, _>(
+            &dh,
+            PROTOCOLS.filter(PrivilegedProtocol::LayerShell),
+        );
         let output_manager_state = OutputManagerState::new_with_xdg_output::<Self>(&dh);
         let primary_selection_state = PrimarySelectionState::new::<Self>(&dh);
-        let data_control_state =
-            DataControlState::new::<Self, _>(&dh, Some(&primary_selection_state), |_| true);
+        let data_control_primary = config.primary_selection.enabled.then_some(&primary_selection_state);
+        let data_control_state = DataControlState::new::<Self, _>(
+            &dh,
+            data_control_primary,
+            PROTOCOLS.filter(PrivilegedProtocol::DataControl),
+        );
+        let ext_data_control_state = ExtDataControlState::new::<Self, _>(
+            &dh,
+            data_control_primary,
+            PROTOCOLS.filter(PrivilegedProtocol::DataControl),
+        );
         let mut seat_state = SeatState::new();
         let shm_state = ShmState::new::<Self>(&dh, vec![]);
         let viewporter_state = ViewporterState::new::<Self>(&dh);
@@ -636,33 +892,77 @@ impl<BackendData: Backend + 'static> LuxoState<BackendData> {
         let fractional_scale_manager_state = FractionalScaleManagerState::new::<Self>(&dh);
         let xdg_foreign_state = XdgForeignState::new::<Self>(&dh);
         let single_pixel_buffer_state = SinglePixelBufferState::new::<Self>(&dh);
+        if PROTOCOLS.enabled(OptionalProtocol::ContentType) {
+            ContentTypeState::new::<Self>(&dh);
+        }
+        // named shapes arrive through SeatHandler::cursor_image like any other cursor change
+        if PROTOCOLS.enabled(OptionalProtocol::CursorShape) {
+            CursorShapeManagerState::new::<Self>(&dh);
+        }
         let fifo_manager_state = FifoManagerState::new::<Self>(&dh);
         let commit_timing_manager_state = CommitTimingManagerState::new::<Self>(&dh);
-        TextInputManagerState::new::<Self>(&dh);
-        InputMethodManagerState::new::<Self, _>(&dh, |_client| true);
-        VirtualKeyboardManagerState::new::<Self, _>(&dh, |_client| true);
+        // the multiplier is applied by the surface render elements
+        if PROTOCOLS.enabled(OptionalProtocol::AlphaModifier) {
+            AlphaModifierState::new::<Self>(&dh);
+        }
+        let screencopy_state =
+            ScreencopyManagerState::new::<Self, _>(&dh, PROTOCOLS.filter(PrivilegedProtocol::Screencopy));
+        let color_management_state = ColorManagementState::new::<Self>(&dh);
+        let foreign_toplevel_state = ForeignToplevelManagerState::new::<Self, _>(
+            &dh,
+            PROTOCOLS.filter(PrivilegedProtocol::ForeignToplevel),
+        );
+        let foreign_toplevel_list_state = ForeignToplevelListState::new_with_filter::<Self>(
+            &dh,
+            PROTOCOLS.filter(PrivilegedProtocol::ForeignToplevel),
+        );
+        let idle_notifier_state = IdleNotifierState::new(&dh, handle.clone());
+        if PROTOCOLS.enabled(OptionalProtocol::IdleInhibit) {
+            IdleInhibitManagerState::new::<Self>(&dh);
+        }
+        if PROTOCOLS.enabled(OptionalProtocol::TextInput) {
+            TextInputManagerState::new::<Self>(&dh);
+        }
+        InputMethodManagerState::new::<Self, _>(&dh, PROTOCOLS.filter(PrivilegedProtocol::InputMethod));
+        VirtualKeyboardManagerState::new::<Self, _>(
+            &dh,
+            PROTOCOLS.filter(PrivilegedProtocol::VirtualKeyboard),
+        );
         // Expose global only if backend supports relative motion events
-        if BackendData::HAS_RELATIVE_MOTION {
+        if BackendData::HAS_RELATIVE_MOTION && PROTOCOLS.enabled(OptionalProtocol::RelativePointer) {
             RelativePointerManagerState::new::<Self>(&dh);
         }
-        PointerConstraintsState::new::<Self>(&dh);
-        if BackendData::HAS_GESTURES {
+        if PROTOCOLS.enabled(OptionalProtocol::PointerConstraints) {
+            PointerConstraintsState::new::<Self>(&dh);
+        }
+        if BackendData::HAS_GESTURES && PROTOCOLS.enabled(OptionalProtocol::PointerGestures) {
             PointerGesturesState::new::<Self>(&dh);
         }
-        TabletManagerState::new::<Self>(&dh);
-        SecurityContextState::new::<Self, _>(&dh, |client| {
-            client
-                .get_data::<ClientState>()
-                .map_or(true, |client_state| client_state.security_context.is_none())
-        });
+        if PROTOCOLS.enabled(OptionalProtocol::Tablet) {
+            TabletManagerState::new::<Self>(&dh);
+        }
+        // sandboxed clients can't create nested security contexts
+        SecurityContextState::new::<Self, _>(&dh, is_privileged);
 
         // init input
         let seat_name = backend_data.seat_name();
         let mut seat = seat_state.new_wl_seat(&dh, seat_name.clone());
 
         let pointer = seat.add_pointer();
-        seat.add_keyboard(XkbConfig::default(), 200, 25)
-            .expect("Failed to initialize the keyboard");
+        seat.add_keyboard(
+            config.input.keyboard.xkb_config(0),
+            config.input.repeat_delay,
+            config.input.repeat_rate,
+        )
+        .or_else(|err| {
+            warn!(error = ?err, "Failed to load the configured keymap, using the default");
+            seat.add_keyboard(
+                XkbConfig::default(),
+                config.input.repeat_delay,
+                config.input.repeat_rate,
+            )
+        })
+        .expect("Failed to initialize the keyboard");
 
         let keyboard_shortcuts_inhibit_state = KeyboardShortcutsInhibitState::new::<Self>(&dh);
 
@@ -670,22 +970,31 @@ impl<BackendData: Backend + 'static> LuxoState<BackendData> {
         let xwayland_shell_state = xwayland_shell::XWaylandShellState::new::<Self>(&dh.clone());
 
         #[cfg(feature = "xwayland")]
-        XWaylandKeyboardGrabState::new::<Self>(&dh.clone());
+        if PROTOCOLS.enabled(OptionalProtocol::XwaylandKeyboardGrab) {
+            XWaylandKeyboardGrabState::new::<Self>(&dh.clone());
+        }
 
-        LuxoState {
+        let mut state = LuxoState {
             backend_data,
             display_handle: dh,
+            display_token,
             socket_name,
+            ipc_socket,
             running: Arc::new(AtomicBool::new(true)),
             handle,
+            keybindings: Keybindings::new(&config.keybindings, &config.gestures, &config.pointer_bindings),
+            config,
+            config_path,
             space: Space::default(),
             popups: PopupManager::default(),
+            popup_grab: None,
             compositor_state,
             data_device_state,
             layer_shell_state,
             output_manager_state,
             primary_selection_state,
             data_control_state,
+            ext_data_control_state,
             seat_state,
             keyboard_shortcuts_inhibit_state,
             shm_state,
@@ -699,12 +1008,36 @@ impl<BackendData: Backend + 'static> LuxoState<BackendData> {
             single_pixel_buffer_state,
             fifo_manager_state,
             commit_timing_manager_state,
+            screencopy_state,
+            color_management_state,
+            foreign_toplevel_state,
+            foreign_toplevel_list_state,
+            foreign_toplevel_list_handles: HashMap::new(),
+            idle_notifier_state,
             dnd_icon: None,
+            pending_screencopies: Vec::new(),
+            idle_inhibiting_surfaces: HashSet::new(),
             suppressed_keys: Vec::new(),
+            suppressed_buttons: Vec::new(),
+            pointer_binding_scroll: 0.0,
+            pointer_edge_push: None,
+            keyboard_layout: 0,
             cursor_status: CursorImageStatus::default_named(),
+            tablet_tool_cursors: HashMap::new(),
+            tablet_tool_in_proximity: None,
             seat_name,
             seat,
             pointer,
+            pointer_lock_hint: None,
+            #[cfg(feature = "udev")]
+            compositor_swipe: None,
+            focus_timer: None,
+            focus_history: Vec::new(),
+            focus_cycle: None,
+            focus_return: FocusReturn::default(),
+            autostart: Autostart::default(),
+            scratchpad: Scratchpad::default(),
+            debug_overlay,
             clock,
 
             #[cfg(feature = "xwayland")]
@@ -713,10 +1046,17 @@ impl<BackendData: Backend + 'static> LuxoState<BackendData> {
             xwm: None,
             #[cfg(feature = "xwayland")]
             xdisplay: None,
+            #[cfg(feature = "xwayland")]
+            xwayland: None,
             #[cfg(feature = "debug")]
             renderdoc: renderdoc::RenderDoc::new().ok(),
             show_window_preview: false,
+        };
+
+        if state.config.input.numlock {
+            state.set_locks(false, true);
         }
+        state
     }
 
     #[cfg(feature = "xwayland")]
@@ -728,7 +1068,7 @@ impl<BackendData: Backend + 'static> LuxoState<BackendData> {
         let (xwayland, client) = XWayland::spawn(
             &self.display_handle,
             None,
-            std::iter::empty::<(String, String)>(),
+            self.config.environment.clone(),
             true,
             Stdio::null(),
             Stdio::null(),
@@ -736,6 +1076,7 @@ impl<BackendData: Backend + 'static> LuxoState<BackendData> {
         )
         .expect("failed to start XWayland");
 
+        let xwayland_client = client.clone();
         let ret = self
             .handle
             .insert_source(xwayland, move |event, _, data| match event {
@@ -749,31 +1090,90 @@ impl<BackendData: Backend + 'static> LuxoState<BackendData> {
                         .unwrap_or(1);
                     data.client_compositor_state(&client)
                         .set_client_scale(xwayland_scale);
-                    let mut wm = X11Wm::start_wm(data.handle.clone(), x11_socket, client.clone())
-                        .expect("Failed to attach X11 Window Manager");
-
-                    let cursor = Cursor::load();
-                    let image = cursor.get_image(1, Duration::ZERO);
-                    wm.set_cursor(
-                        &image.pixels_rgba,
-                        Size::from((image.width as u16, image.height as u16)),
-                        Point::from((image.xhot as u16, image.yhot as u16)),
-                    )
-                    .expect("Failed to set xwayland default cursor");
+                    let wm = match X11Wm::start_wm(data.handle.clone(), x11_socket, client.clone()) {
+                        Ok(wm) => wm,
+                        Err(err) => {
+                            tracing::error!("Failed to attach X11 Window Manager: {}", err);
+                            return;
+                        }
+                    };
+
                     data.xwm = Some(wm);
                     data.xdisplay = Some(display_number);
+                    data.update_xwayland_cursor();
+                    data.export_xwayland_display();
+                    data.start_autostart();
                 }
                 XWaylandEvent::Error => {
                     warn!("XWayland crashed on startup");
+                    data.start_autostart();
                 }
             });
-        if let Err(e) = ret {
-            tracing::error!("Failed to insert the XWaylandSource into the event loop: {}", e);
+        match ret {
+            Ok(token) => self.xwayland = Some((token, xwayland_client)),
+            Err(e) => tracing::error!("Failed to insert the XWaylandSource into the event loop: {}", e),
+        }
+    }
+
+    /// Show the configured cursor over the root window and windows that don't set a cursor themselves
+    #[cfg(feature = "xwayland")]
+    fn update_xwayland_cursor(&mut self) {
+        let Some(wm) = self.xwm.as_mut() else {
+            return;
+        };
+        let mut cursor = Cursor::load(&self.config.cursor);
+        let image = cursor.get_image(CursorIcon::Default, 1, Duration::ZERO);
+        if let Err(err) = wm.set_cursor(
+            &image.pixels_rgba,
+            Size::from((image.width as u16, image.height as u16)),
+            Point::from((image.xhot as u16, image.yhot as u16)),
+        ) {
+            warn!("Failed to set xwayland default cursor: {}", err);
+        }
+    }
+
+    /// Stop XWayland by dropping the window manager and disconnecting the server
+    #[cfg(feature = "xwayland")]
+    fn stop_xwayland(&mut self) {
+        self.xwm = None;
+        self.xdisplay = None;
+        if let Some((token, client)) = self.xwayland.take() {
+            self.handle.remove(token);
+            client.kill(
+                &self.display_handle,
+                ProtocolError {
+                    code: 0,
+                    object_id: 1,
+                    object_interface: "wl_display".into(),
+                    message: "compositor is shutting down".into(),
+                },
+            );
+        }
+    }
+
+    /// Tear everything down once the event loop stopped.
+    ///
+    /// The backend releases its resources first, while its session is still around to restore the
+    /// previous state. Dropping the display afterwards destroys all globals and hangs up on the
+    /// remaining clients, which is how they learn that the compositor went away.
+    pub fn shutdown(&mut self) {
+        info!("Shutting down");
+
+        BackendData::shutdown(self);
+        self.stop_autostart();
+        #[cfg(feature = "xwayland")]
+        self.stop_xwayland();
+        self.ipc_socket = None;
+
+        if let Err(err) = self.display_handle.flush_clients() {
+            warn!("Failed to flush clients: {}", err);
         }
+        self.handle.remove(self.display_token);
     }
 }
 
 impl<BackendData: Backend + 'static> LuxoState<BackendData> {
+    #[profiling::function]
     pub fn pre_repaint(&mut self, output: &Output, frame_target: impl Into<Time<Monotonic>>) {
         let frame_target = frame_target.into();
 
@@ -845,6 +1245,7 @@ impl<BackendData: Backend + 'static> LuxoState<BackendData> {
         }
     }
 
+    #[profiling::function]
     pub fn post_repaint(
         &mut self,
         output: &Output,
@@ -1015,6 +1416,109 @@ impl<BackendData: Backend + 'static> LuxoState<BackendData> {
         for client in clients.into_values() {
             self.client_compositor_state(&client).blocker_cleared(self, &dh);
         }
+
+        self.update_idle_inhibit();
+    }
+
+    /// Add a client connected on `stream`, looking up its executable for the protocol access rules
+    fn insert_client(&mut self, stream: UnixStream, client_state: ClientState) {
+        let client = match self.display_handle.insert_client(stream, Arc::new(client_state)) {
+            Ok(client) => client,
+            Err(err) => {
+                warn!("Error adding wayland client: {}", err);
+                return;
+            }
+        };
+        let client_state = client.get_data::<ClientState>().unwrap();
+        let executable = client
+            .get_credentials(&self.display_handle)
+            .and_then(|credentials| {
+                let _ = client_state.pid.set(credentials.pid);
+                std::fs::read_link(format!("/proc/{}/exe", credentials.pid))
+            });
+        match executable {
+            Ok(executable) => {
+                let _ = client_state.executable.set(executable);
+            }
+            Err(err) => debug!("Unable to look up the executable of a client: {}", err),
+        }
+    }
+
+    /// Re-read the config file and apply the keybindings, keyboard settings and backend specific
+    /// settings like the output layout from it
+    pub fn reload_config(&mut self) -> Result<(), ConfigError> {
+        let path = self.config_path.as_deref().ok_or(ConfigError::NoConfigDir)?;
+        let config = if path.exists() {
+            Config::from_path(path)?
+        } else {
+            Config::default()
+        };
+
+        self.keybindings = Keybindings::new(&config.keybindings, &config.gestures, &config.pointer_bindings);
+        self.debug_overlay = config.debug_overlay.with_env();
+        PROTOCOLS.configure(&config);
+        let keyboard_changed = config.input.keyboard != self.config.input.keyboard;
+        #[cfg(feature = "xwayland")]
+        let cursor_changed = config.cursor != self.config.cursor;
+        let (repeat_delay, repeat_rate) = (config.input.repeat_delay, config.input.repeat_rate);
+        self.config = config;
+
+        self.set_key_repeat(repeat_delay, repeat_rate);
+        if keyboard_changed {
+            self.set_keyboard_layout(0);
+        }
+        #[cfg(feature = "xwayland")]
+        if cursor_changed {
+            self.update_xwayland_cursor();
+        }
+        BackendData::apply_config(self);
+        self.run_autostart(false);
+        info!(path = %path.display(), "Reloaded configuration");
+        Ok(())
+    }
+
+    /// Inhibit idle notifications while any surface holding an inhibitor is mapped
+    pub fn update_idle_inhibit(&mut self) {
+        self.idle_inhibiting_surfaces.retain(|surface| surface.is_alive());
+
+        let is_inhibited = self.idle_inhibiting_surfaces.iter().any(|surface| {
+            let mut root = surface.clone();
+            while let Some(parent) = get_parent(&root) {
+                root = parent;
+            }
+            self.space
+                .elements()
+                .any(|window| window.wl_surface().as_deref() == Some(&root))
+                || self.space.outputs().any(|output| {
+                    smithay::desktop::layer_map_for_output(output)
+                        .layer_for_surface(&root, WindowSurfaceType::TOPLEVEL)
+                        .is_some()
+                })
+        });
+        self.idle_notifier_state.set_is_inhibited(is_inhibited);
+    }
+
+    /// Show the cursor of `tool` instead of the pointer cursor while it is in proximity
+    pub fn tablet_tool_proximity_in(&mut self, tool: &TabletToolDescriptor) {
+        let image = self
+            .tablet_tool_cursors
+            .get(tool)
+            .cloned()
+            .unwrap_or_else(CursorImageStatus::default_named);
+        let pointer_cursor = std::mem::replace(&mut self.cursor_status, image);
+        let pointer_cursor = match self.tablet_tool_in_proximity.take() {
+            Some((_, pointer_cursor)) => pointer_cursor,
+            None => pointer_cursor,
+        };
+        self.tablet_tool_in_proximity = Some((tool.clone(), pointer_cursor));
+    }
+
+    /// Restore the pointer cursor once `tool` left proximity
+    pub fn tablet_tool_proximity_out(&mut self, tool: &TabletToolDescriptor) {
+        if matches!(&self.tablet_tool_in_proximity, Some((active, _)) if active == tool) {
+            let (_, pointer_cursor) = self.tablet_tool_in_proximity.take().unwrap();
+            self.cursor_status = pointer_cursor;
+        }
     }
 }
 
@@ -1116,4 +1620,72 @@ pub trait Backend {
     fn reset_buffers(&mut self, output: &Output);
     fn early_import(&mut self, surface: &WlSurface);
     fn update_led_state(&mut self, led_state: LedState);
+    /// Apply a new configuration to some outputs, returns false if it was rejected or is unsupported
+    fn configure_outputs(
+        _state: &mut LuxoState<Self>,
+        _configuration: Vec<(Output, OutputConfiguration)>,
+    ) -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
+    /// Gpus rendering `output` and driving its display, for backends running on drm devices
+    fn output_gpus(&self, _output: &Output) -> Option<(String, String)> {
+        None
+    }
+    /// How many frames of `output` were scanned out directly or composited
+    fn frame_stats(&self, _output: &Output) -> Option<FrameStats> {
+        None
+    }
+    /// Whether clients can use explicit sync for surfaces on `output`, for backends running on drm
+    /// devices
+    fn explicit_sync(&self, _output: &Output) -> Option<bool> {
+        None
+    }
+    /// Render `output` offscreen and save it as a PNG image at `path`, for snapshot tests
+    fn capture_output(_state: &mut LuxoState<Self>, _output: &Output, _path: &Path) -> Result<(), String>
+    where
+        Self: Sized,
+    {
+        Err(String::from("Capturing outputs is not supported by this backend"))
+    }
+    /// Change libinput settings of the pointer devices `device` stands for, `"mouse"`, `"touchpad"`
+    /// or the name of a device. Mouse and touchpad settings last until the config is reloaded,
+    /// those of single devices until the compositor exits.
+    fn configure_input(
+        _state: &mut LuxoState<Self>,
+        _device: &str,
+        _settings: &PointerDeviceConfig,
+    ) -> Result<(), String>
+    where
+        Self: Sized,
+    {
+        Err(String::from(
+            "Input devices are only configured by the udev backend",
+        ))
+    }
+    /// Connectors of non-desktop displays that can be leased to clients
+    fn lease_connectors(&self) -> Vec<LeaseConnectorInfo> {
+        Vec::new()
+    }
+    /// Revoke the drm lease of `connector`
+    fn revoke_lease(_state: &mut LuxoState<Self>, _connector: &str) -> Result<(), String>
+    where
+        Self: Sized,
+    {
+        Err(String::from("Drm leases are only supported by the udev backend"))
+    }
+    /// Apply backend specific settings after the config was reloaded
+    fn apply_config(_state: &mut LuxoState<Self>)
+    where
+        Self: Sized,
+    {
+    }
+    /// Release backend resources, called once before the compositor exits
+    fn shutdown(_state: &mut LuxoState<Self>)
+    where
+        Self: Sized,
+    {
+    }
 }
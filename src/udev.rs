@@ -1,22 +1,43 @@
 use std::{
-    collections::hash_map::HashMap,
+    collections::{hash_map::HashMap, VecDeque},
     io,
-    path::Path,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
     sync::{atomic::Ordering, Mutex},
     time::{Duration, Instant},
 };
 
 use crate::{
+    cli::Options,
+    config::{
+        AccelProfileConfig, ClickMethodConfig, DebugOverlayConfig, InputConfig, LidAction, OutputConfig,
+        OutputsConfig, OverlayPlanesConfig, PointerDeviceConfig, PrivilegedProtocol, ScrollMethodConfig,
+        VrrConfig, MAX_OUTPUT_SCALE, MIN_OUTPUT_SCALE,
+    },
+    delegate_export_dmabuf, delegate_gamma_control, delegate_output_management,
     drawing::*,
+    ipc::{Event, FrameStats, LeaseConnectorInfo},
+    night_light::{self, NEUTRAL_TEMPERATURE},
+    rotation::{self, Accelerometer},
     render::*,
-    shell::WindowElement,
+    shell::{FullscreenSurface, WindowElement},
     state::{take_presentation_feedback, update_primary_scanout_output, LuxoState, Backend},
 };
 use crate::{
+    protocols::{
+        color_management::{self, chromaticities, ImageDescription, OutputImageDescription},
+        export_dmabuf::{DmabufExport, ExportDmabufHandler, ExportDmabufManagerState},
+        gamma_control::{GammaControlHandler, GammaControlManagerState},
+        output_management::{
+            AdaptiveSync, HeadConfiguration, ModeConfiguration, OutputConfiguration, OutputManagementHandler,
+            OutputManagementState,
+        },
+        registry::PROTOCOLS,
+        screencopy::Screencopy,
+    },
     shell::WindowRenderElement,
     state::{DndIcon, SurfaceDmabufFeedback},
 };
-#[cfg(feature = "renderer_sync")]
 use smithay::backend::drm::compositor::PrimaryPlaneElement;
 #[cfg(feature = "egl")]
 use smithay::backend::renderer::ImportEgl;
@@ -25,13 +46,13 @@ use smithay::backend::renderer::{multigpu::MultiTexture, ImportMem};
 use smithay::{
     backend::{
         allocator::{
-            dmabuf::Dmabuf,
+            dmabuf::{AsDmabuf, Dmabuf},
             format::FormatSet,
             gbm::{GbmAllocator, GbmBufferFlags, GbmDevice},
-            Fourcc,
+            Allocator, Buffer as _, Fourcc, Modifier,
         },
         drm::{
-            compositor::{DrmCompositor, FrameFlags},
+            compositor::{DrmCompositor, FrameFlags, VrrSupport},
             output::{DrmOutput, DrmOutputManager, DrmOutputRenderElements},
             CreateDrmNodeError, DrmAccessError, DrmDevice, DrmDeviceFd, DrmError, DrmEvent, DrmEventMetadata,
             DrmNode, DrmSurface, GbmBufferedSurface, NodeType,
@@ -40,11 +61,15 @@ use smithay::{
         input::InputEvent,
         libinput::{LibinputInputBackend, LibinputSessionInterface},
         renderer::{
-            damage::Error as OutputDamageTrackerError,
-            element::{memory::MemoryRenderBuffer, AsRenderElements, RenderElementStates},
-            gles::GlesRenderer,
-            multigpu::{gbm::GbmGlesBackend, GpuManager, MultiRenderer},
-            DebugFlags, ImportDma, ImportMemWl,
+            damage::{Error as OutputDamageTrackerError, OutputDamageTracker},
+            element::{
+                memory::MemoryRenderBuffer, utils::RescaleRenderElement, AsRenderElements, Element, Id,
+                RenderElement, RenderElementStates,
+            },
+            gles::{GlesFrame, GlesRenderbuffer, GlesRenderer, GlesTexture},
+            multigpu::{gbm::GbmGlesBackend, GpuManager, MultiError, MultiRenderer},
+            utils::CommitCounter,
+            Bind, Color32F, DebugFlags, Frame, ImportDma, ImportMemWl, Offscreen, Renderer, Texture,
         },
         session::{
             libseat::{self, LibSeatSession},
@@ -55,32 +80,40 @@ use smithay::{
     },
     delegate_dmabuf, delegate_drm_lease,
     desktop::{
+        layer_map_for_output,
         space::{Space, SurfaceTree},
         utils::OutputPresentationFeedback,
     },
     input::{
         keyboard::LedState,
-        pointer::{CursorImageAttributes, CursorImageStatus},
+        pointer::{CursorIcon, CursorImageAttributes, CursorImageStatus},
     },
-    output::{Mode as WlMode, Output, PhysicalProperties},
+    output::{Mode as WlMode, Output, PhysicalProperties, Scale as OutputScale},
     reexports::{
         calloop::{
             timer::{TimeoutAction, Timer},
-            EventLoop, RegistrationToken,
+            EventLoop, LoopHandle, RegistrationToken,
         },
         drm::{
-            control::{connector, crtc, Device, ModeTypeFlags},
+            control::{connector, crtc, property, Device, ModeTypeFlags},
             Device as _,
         },
-        input::{DeviceCapability, Libinput},
+        input::{self as libinput, AccelProfile, ClickMethod, DeviceCapability, Libinput, ScrollMethod},
         rustix::fs::OFlags,
         wayland_protocols::wp::{
+            content_type::v1::server::wp_content_type_v1::Type as ContentType,
             linux_dmabuf::zv1::server::zwp_linux_dmabuf_feedback_v1,
             presentation_time::server::wp_presentation_feedback,
         },
+        wayland_protocols_wlr::export_dmabuf::v1::server::{
+            zwlr_export_dmabuf_frame_v1::ZwlrExportDmabufFrameV1,
+        },
         wayland_server::{backend::GlobalId, protocol::wl_surface, Display, DisplayHandle},
     },
-    utils::{DeviceFd, IsAlive, Logical, Monotonic, Point, Scale, Time, Transform},
+    utils::{
+        Buffer as BufferCoords, DeviceFd, IsAlive, Logical, Monotonic, Physical, Point, Rectangle, Scale, Size,
+        Time, Transform,
+    },
     wayland::{
         compositor,
         dmabuf::{DmabufFeedbackBuilder, DmabufGlobal, DmabufHandler, DmabufState, ImportNotifier},
@@ -110,6 +143,10 @@ const SUPPORTED_FORMATS: &[Fourcc] = &[
     Fourcc::Argb8888,
 ];
 const SUPPORTED_FORMATS_8BIT_ONLY: &[Fourcc] = &[Fourcc::Abgr8888, Fourcc::Argb8888];
+/// Time for the session to get paused after switching the vt, before rendering is resumed
+const VT_SWITCH_TIMEOUT: Duration = Duration::from_secs(1);
+/// Interval the accelerometer is read at while auto-rotating
+const ORIENTATION_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
 type UdevRenderer<'a> = MultiRenderer<
     'a,
@@ -136,9 +173,31 @@ pub struct UdevData {
     pointer_element: PointerElement,
     #[cfg(feature = "debug")]
     fps_texture: Option<MultiTexture>,
-    pointer_image: crate::cursor::Cursor,
+    /// Loaded from the cursor config on first use
+    pointer_image: Option<crate::cursor::Cursor>,
     debug_flags: DebugFlags,
     keyboards: Vec<smithay::reexports::input::Device>,
+    pointer_devices: Vec<smithay::reexports::input::Device>,
+    /// Settings changed over ipc for single devices by name, on top of the mouse or touchpad config
+    device_overrides: HashMap<String, PointerDeviceConfig>,
+    pub export_dmabuf_state: ExportDmabufManagerState,
+    pending_dmabuf_exports: Vec<DmabufExport>,
+    pub output_management_state: OutputManagementState,
+    /// Outputs turned off through output management
+    disabled_outputs: Vec<Output>,
+    pub gamma_control_state: GammaControlManagerState,
+    /// Whether the night light is currently on
+    night_light: bool,
+    /// Set from a vt switch until the session is active again, nothing gets repainted meanwhile
+    rendering_paused: bool,
+    /// Transform of the internal panel last read from the accelerometer while auto-rotating
+    orientation: Option<Transform>,
+    /// Internal panels turned off by closing the lid, turned on again when it opens
+    lid_closed_outputs: Vec<Output>,
+    /// Render nodes whose context got lost, reset once the current dispatch is done
+    lost_gpus: Vec<DrmNode>,
+    /// Recent resets per render node, to give up on gpus that keep failing
+    gpu_resets: HashMap<DrmNode, VecDeque<Instant>>,
 }
 
 impl UdevData {
@@ -157,6 +216,11 @@ impl UdevData {
     pub fn debug_flags(&self) -> DebugFlags {
         self.debug_flags
     }
+
+    fn surface_mut(&mut self, output: &Output) -> Option<&mut SurfaceData> {
+        let id = output.user_data().get::<UdevOutputId>()?;
+        self.backends.get_mut(&id.device_id)?.surfaces.get_mut(&id.crtc)
+    }
 }
 
 impl DmabufHandler for LuxoState<UdevData> {
@@ -181,6 +245,45 @@ impl DmabufHandler for LuxoState<UdevData> {
 }
 delegate_dmabuf!(LuxoState<UdevData>);
 
+impl ExportDmabufHandler for LuxoState<UdevData> {
+    fn capture(&mut self, export: DmabufExport) {
+        self.backend_data.pending_dmabuf_exports.push(export);
+    }
+}
+delegate_export_dmabuf!(LuxoState<UdevData>);
+
+impl GammaControlHandler for LuxoState<UdevData> {
+    fn gamma_control_state(&mut self) -> &mut GammaControlManagerState {
+        &mut self.backend_data.gamma_control_state
+    }
+
+    fn gamma_size(&mut self, output: &Output) -> Option<u32> {
+        let id = output.user_data().get::<UdevOutputId>()?;
+        let device = self.backend_data.backends.get(&id.device_id)?;
+        if !device.surfaces.contains_key(&id.crtc) {
+            return None;
+        }
+        crtc_gamma_size(device.drm_output_manager.device(), id.crtc)
+    }
+
+    fn set_gamma(&mut self, output: &Output, ramp: Option<Vec<u16>>) -> bool {
+        let Some(surface) = self.backend_data.surface_mut(output) else {
+            return false;
+        };
+        surface.client_gamma = ramp;
+        self.apply_gamma(output)
+    }
+}
+delegate_gamma_control!(LuxoState<UdevData>);
+
+/// Number of entries per channel of the gamma ramp of `crtc`, `None` if it has none
+fn crtc_gamma_size(drm: &DrmDevice, crtc: crtc::Handle) -> Option<u32> {
+    drm.get_crtc(crtc)
+        .ok()
+        .map(|info| info.gamma_length())
+        .filter(|size| *size > 0)
+}
+
 impl Backend for UdevData {
     const HAS_RELATIVE_MOTION: bool = true;
     const HAS_GESTURES: bool = true;
@@ -210,9 +313,120 @@ impl Backend for UdevData {
             keyboard.led_update(led_state.into());
         }
     }
+
+    fn output_gpus(&self, output: &Output) -> Option<(String, String)> {
+        let id = output.user_data().get::<UdevOutputId>()?;
+        let surface = self.backends.get(&id.device_id)?.surfaces.get(&id.crtc)?;
+        Some((surface.render_gpu.to_string(), surface.render_node.to_string()))
+    }
+
+    fn frame_stats(&self, output: &Output) -> Option<FrameStats> {
+        let id = output.user_data().get::<UdevOutputId>()?;
+        let surface = self.backends.get(&id.device_id)?.surfaces.get(&id.crtc)?;
+        Some(surface.frame_stats)
+    }
+
+    fn explicit_sync(&self, output: &Output) -> Option<bool> {
+        let id = output.user_data().get::<UdevOutputId>()?;
+        let device = self.backends.get(&id.device_id)?;
+        Some(self.syncobj_state.is_some() && device.syncobj_eventfd)
+    }
+
+    fn configure_outputs(
+        state: &mut LuxoState<Self>,
+        configuration: Vec<(Output, OutputConfiguration)>,
+    ) -> bool {
+        state.apply_output_configuration(configuration, false)
+    }
+
+    fn apply_config(state: &mut LuxoState<Self>) {
+        for device in &mut state.backend_data.pointer_devices {
+            configure_pointer_device(device, &state.config.input, &state.backend_data.device_overrides);
+        }
+        state.apply_outputs_config();
+        state.update_night_light(true);
+        state.update_lease_offers();
+
+        // the cursor config might have changed
+        state.backend_data.pointer_image = None;
+        state.backend_data.pointer_images.clear();
+    }
+
+    fn configure_input(
+        state: &mut LuxoState<Self>,
+        device: &str,
+        settings: &PointerDeviceConfig,
+    ) -> Result<(), String> {
+        match device {
+            "mouse" => state.config.input.mouse.merge(settings),
+            "touchpad" => state.config.input.touchpad.merge(settings),
+            name => {
+                if !state
+                    .backend_data
+                    .pointer_devices
+                    .iter()
+                    .any(|d| d.name() == name)
+                {
+                    return Err(format!("No pointer device named {:?}", name));
+                }
+                state
+                    .backend_data
+                    .device_overrides
+                    .entry(name.to_owned())
+                    .or_default()
+                    .merge(settings);
+            }
+        }
+
+        for device in &mut state.backend_data.pointer_devices {
+            configure_pointer_device(device, &state.config.input, &state.backend_data.device_overrides);
+        }
+        Ok(())
+    }
+
+    fn lease_connectors(&self) -> Vec<LeaseConnectorInfo> {
+        self.backends
+            .iter()
+            .flat_map(|(node, device)| {
+                device
+                    .non_desktop_connectors
+                    .iter()
+                    .map(|connector| device.lease_connector_info(*node, connector))
+            })
+            .collect()
+    }
+
+    fn revoke_lease(state: &mut LuxoState<Self>, name: &str) -> Result<(), String> {
+        for device in state.backend_data.backends.values_mut() {
+            let Some(connector) = device.non_desktop_connectors.iter().find(|c| c.name == name) else {
+                continue;
+            };
+            let handle = connector.handle;
+            let leases = device.active_leases.len();
+            // dropping the lease revokes it
+            device
+                .active_leases
+                .retain(|(_, connectors)| !connectors.contains(&handle));
+            if device.active_leases.len() == leases {
+                return Err(format!("Connector {} is not leased", name));
+            }
+            info!(connector = name, "Revoked drm lease");
+            return Ok(());
+        }
+        Err(format!("No non-desktop connector named {}", name))
+    }
+
+    fn shutdown(state: &mut LuxoState<Self>) {
+        // The session is only closed when the backend data is dropped, so the drm devices still
+        // get to restore the previous mode before the vt is handed back.
+        let nodes: Vec<DrmNode> = state.backend_data.backends.keys().copied().collect();
+        for node in nodes {
+            state.device_removed(node);
+        }
+    }
 }
 
-pub fn run_udev() {
+pub fn run_udev(options: &Options) {
     let mut event_loop = EventLoop::try_new().unwrap();
     let display = Display::new().unwrap();
     let mut display_handle = display.handle();
@@ -257,15 +471,82 @@ pub fn run_udev() {
         primary_gpu,
         gpus,
         backends: HashMap::new(),
-        pointer_image: crate::cursor::Cursor::load(),
+        pointer_image: None,
         pointer_images: Vec::new(),
         pointer_element: PointerElement::default(),
         #[cfg(feature = "debug")]
         fps_texture: None,
         debug_flags: DebugFlags::empty(),
         keyboards: Vec::new(),
+        pointer_devices: Vec::new(),
+        device_overrides: HashMap::new(),
+        export_dmabuf_state: ExportDmabufManagerState::new::<LuxoState<UdevData>, _>(
+            &display_handle,
+            PROTOCOLS.filter(PrivilegedProtocol::ExportDmabuf),
+        ),
+        pending_dmabuf_exports: Vec::new(),
+        output_management_state: OutputManagementState::new::<LuxoState<UdevData>, _>(
+            &display_handle,
+            PROTOCOLS.filter(PrivilegedProtocol::OutputManagement),
+        ),
+        disabled_outputs: Vec::new(),
+        gamma_control_state: GammaControlManagerState::new::<LuxoState<UdevData>, _>(
+            &display_handle,
+            PROTOCOLS.filter(PrivilegedProtocol::GammaControl),
+        ),
+        night_light: false,
+        rendering_paused: false,
+        orientation: None,
+        lid_closed_outputs: Vec::new(),
+        lost_gpus: Vec::new(),
+        gpu_resets: HashMap::new(),
     };
-    let mut state = LuxoState::init(display, event_loop.handle(), data, true);
+    let mut state = LuxoState::init(display, event_loop.handle(), data, true, options);
+
+    /*
+     * Switch the night light on and off as scheduled
+     */
+    event_loop
+        .handle()
+        .insert_source(Timer::immediate(), |_, _, state| {
+            state.update_night_light(false);
+            TimeoutAction::ToDuration(Duration::from_secs(60))
+        })
+        .unwrap();
+
+    /*
+     * Follow the accelerometer of convertibles with the internal panel
+     */
+    if let Some(accelerometer) = Accelerometer::find() {
+        info!(path = ?accelerometer.path(), "Found accelerometer");
+        // only readings repeated by the next poll are followed, not every wobble
+        let mut last_reading = None;
+        event_loop
+            .handle()
+            .insert_source(Timer::immediate(), move |_, _, state| {
+                if !state.config.outputs.auto_rotate {
+                    state.backend_data.orientation = None;
+                    last_reading = None;
+                    return TimeoutAction::ToDuration(ORIENTATION_POLL_INTERVAL);
+                }
+                match accelerometer.orientation() {
+                    Ok(Some(transform)) => {
+                        if last_reading == Some(transform) {
+                            state.auto_rotate(transform);
+                        }
+                        last_reading = Some(transform);
+                    }
+                    // lying flat or held at an angle in between
+                    Ok(None) => last_reading = None,
+                    Err(err) => {
+                        warn!("Failed to read the accelerometer, stopping auto-rotation: {}", err);
+                        return TimeoutAction::Drop;
+                    }
+                }
+                TimeoutAction::ToDuration(ORIENTATION_POLL_INTERVAL)
+            })
+            .unwrap();
+    }
 
     /*
      * Initialize the udev backend
@@ -295,6 +576,10 @@ pub fn run_udev() {
         .insert_source(libinput_backend, move |mut event, _, data| {
             let dh = data.backend_data.dh.clone();
             if let InputEvent::DeviceAdded { device } = &mut event {
+                if device.has_capability(DeviceCapability::Pointer) {
+                    configure_pointer_device(device, &data.config.input, &data.backend_data.device_overrides);
+                    data.backend_data.pointer_devices.push(device.clone());
+                }
                 if device.has_capability(DeviceCapability::Keyboard) {
                     if let Some(led_state) = data.seat.get_keyboard().map(|keyboard| keyboard.led_state()) {
                         device.led_update(led_state.into());
@@ -305,6 +590,9 @@ pub fn run_udev() {
                 if device.has_capability(DeviceCapability::Keyboard) {
                     data.backend_data.keyboards.retain(|item| item != device);
                 }
+                if device.has_capability(DeviceCapability::Pointer) {
+                    data.backend_data.pointer_devices.retain(|item| item != device);
+                }
             }
 
             data.process_input_event(&dh, event)
@@ -317,6 +605,7 @@ pub fn run_udev() {
             SessionEvent::PauseSession => {
                 libinput_context.suspend();
                 info!("pausing session");
+                data.pause_rendering();
 
                 for backend in data.backend_data.backends.values_mut() {
                     backend.drm_output_manager.pause();
@@ -328,16 +617,12 @@ pub fn run_udev() {
             }
             SessionEvent::ActivateSession => {
                 info!("resuming session");
+                data.backend_data.rendering_paused = false;
 
                 if let Err(err) = libinput_context.resume() {
                     error!("Failed to resume libinput context: {:?}", err);
                 }
-                for (node, backend) in data
-                    .backend_data
-                    .backends
-                    .iter_mut()
-                    .map(|(handle, backend)| (*handle, backend))
-                {
+                for backend in data.backend_data.backends.values_mut() {
                     // if we do not care about flicking (caused by modesetting) we could just
                     // pass true for disable connectors here. this would make sure our drm
                     // device is in a known state (all connectors and planes disabled).
@@ -351,9 +636,14 @@ pub fn run_udev() {
                     if let Some(lease_global) = backend.leasing_global.as_mut() {
                         lease_global.resume::<LuxoState<UdevData>>();
                     }
-                    data.handle
-                        .insert_idle(move |data| data.render(node, None, data.clock.now()));
+                    // vblanks of frames queued before the pause never arrive
+                    for surface in backend.surfaces.values_mut() {
+                        surface.scheduler.reset();
+                        surface.scheduler.queue(data.clock.now());
+                    }
                 }
+                // another drm master might have changed the gamma ramps
+                data.update_night_light(true);
             }
         })
         .unwrap();
@@ -438,21 +728,32 @@ pub fn run_udev() {
         });
     });
 
-    // Expose syncobj protocol if supported by primary GPU
-    if let Some(primary_node) = state
+    // Expose syncobj protocol if supported by the primary GPU, or by another one otherwise. The
+    // acquire points are waited for before a commit is applied, so it works for surfaces on all GPUs.
+    let primary_node = state
         .backend_data
         .primary_gpu
         .node_with_type(NodeType::Primary)
-        .and_then(|x| x.ok())
-    {
-        if let Some(backend) = state.backend_data.backends.get(&primary_node) {
-            let import_device = backend.drm_output_manager.device().device_fd().clone();
-            if supports_syncobj_eventfd(&import_device) {
-                let syncobj_state =
-                    DrmSyncobjState::new::<LuxoState<UdevData>>(&display_handle, import_device);
-                state.backend_data.syncobj_state = Some(syncobj_state);
+        .and_then(|x| x.ok());
+    let backends = &state.backend_data.backends;
+    let syncobj_node = primary_node
+        .filter(|node| backends.get(node).is_some_and(|backend| backend.syncobj_eventfd))
+        .or_else(|| {
+            backends
+                .iter()
+                .find(|(_, backend)| backend.syncobj_eventfd)
+                .map(|(node, _)| *node)
+        });
+    match syncobj_node {
+        Some(node) => {
+            if Some(node) != primary_node {
+                info!("Primary gpu does not support explicit sync, using {} instead", node);
             }
+            let import_device = backends[&node].drm_output_manager.device().device_fd().clone();
+            let syncobj_state = DrmSyncobjState::new::<LuxoState<UdevData>>(&display_handle, import_device);
+            state.backend_data.syncobj_state = Some(syncobj_state);
         }
+        None => warn!("No gpu supports explicit sync, clients have to use implicit sync"),
     }
 
     event_loop
@@ -485,20 +786,49 @@ pub fn run_udev() {
     #[cfg(feature = "xwayland")]
     state.start_xwayland();
 
+    if let Some(command) = options.command.as_deref() {
+        state.spawn(command);
+    }
+    state.start_session();
+
     /*
      * And run our loop
      */
 
-    while state.running.load(Ordering::SeqCst) {
-        let result = event_loop.dispatch(Some(Duration::from_millis(16)), &mut state);
-        if result.is_err() {
-            state.running.store(false, Ordering::SeqCst);
-        } else {
-            state.space.refresh();
-            state.popups.cleanup();
-            display_handle.flush_clients().unwrap();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        while state.running.load(Ordering::SeqCst) {
+            let result = event_loop.dispatch(Some(Duration::from_millis(16)), &mut state);
+            if result.is_err() {
+                state.running.store(false, Ordering::SeqCst);
+            } else {
+                state.refresh_space();
+                state.update_scratchpad();
+                state.restack_windows();
+                state.update_magnifier();
+                state.update_pings();
+                state.popups.cleanup();
+                state.update_foreign_toplevels();
+                state.notify_ipc_subscribers();
+                display_handle.flush_clients().unwrap();
+            }
         }
+    }));
+
+    if let Err(payload) = result {
+        // A panic would otherwise leave the outputs in our modes and the vt in graphics mode, so the
+        // user ends up on a black tty. Tear down like a regular shutdown, restoring the previous
+        // crtc state while we are still drm master, then close the session to hand back the vt.
+        error!("Main loop panicked, restoring the tty");
+        if panic::catch_unwind(AssertUnwindSafe(|| state.shutdown())).is_err() {
+            error!("Shutdown panicked, the previous output state might not be restored");
+        }
+        // the event loop holds clones of the session as well
+        drop(state);
+        drop(event_loop);
+        panic::resume_unwind(payload);
     }
+
+    state.shutdown();
 }
 
 impl DrmLeaseHandler for LuxoState<UdevData> {
@@ -520,17 +850,23 @@ impl DrmLeaseHandler for LuxoState<UdevData> {
         let backend = self
             .backend_data
             .backends
-            .get(&node)
+            .get_mut(&node)
             .ok_or(LeaseRejected::default())?;
 
         let drm_device = backend.drm_output_manager.device();
         let mut builder = DrmLeaseBuilder::new(drm_device);
-        for conn in request.connectors {
-            if let Some((_, crtc)) = backend
+        for conn in request.connectors.iter().copied() {
+            if let Some(connector) = backend
                 .non_desktop_connectors
                 .iter()
-                .find(|(handle, _)| *handle == conn)
+                .find(|connector| connector.handle == conn)
             {
+                // the config might have changed since the connector was offered
+                if !self.config.drm_lease.allows(&connector.name) {
+                    warn!(connector = %connector.name, "Lease requested for a connector not offered");
+                    return Err(LeaseRejected::default());
+                }
+                let crtc = &connector.crtc;
                 builder.add_connector(conn);
                 builder.add_crtc(*crtc);
                 let planes = drm_device.planes(crtc).map_err(LeaseRejected::with_cause)?;
@@ -557,17 +893,19 @@ impl DrmLeaseHandler for LuxoState<UdevData> {
             }
         }
 
+        backend.requested_lease = request.connectors;
         Ok(builder)
     }
 
     fn new_active_lease(&mut self, node: DrmNode, lease: DrmLease) {
         let backend = self.backend_data.backends.get_mut(&node).unwrap();
-        backend.active_leases.push(lease);
+        let connectors = std::mem::take(&mut backend.requested_lease);
+        backend.active_leases.push((lease, connectors));
     }
 
     fn lease_destroyed(&mut self, node: DrmNode, lease: u32) {
         let backend = self.backend_data.backends.get_mut(&node).unwrap();
-        backend.active_leases.retain(|l| l.id() != lease);
+        backend.active_leases.retain(|(l, _)| l.id() != lease);
     }
 }
 
@@ -580,6 +918,652 @@ impl DrmSyncobjHandler for LuxoState<UdevData> {
 }
 smithay::delegate_drm_syncobj!(LuxoState<UdevData>);
 
+impl OutputManagementHandler for LuxoState<UdevData> {
+    fn output_management_state(&mut self) -> &mut OutputManagementState {
+        &mut self.backend_data.output_management_state
+    }
+
+    fn apply_output_configuration(
+        &mut self,
+        configuration: Vec<(Output, OutputConfiguration)>,
+        test_only: bool,
+    ) -> bool {
+        let mut resolved = Vec::with_capacity(configuration.len());
+        for (output, config) in configuration {
+            if !self.space.outputs().any(|o| o == &output)
+                && !self.backend_data.disabled_outputs.contains(&output)
+            {
+                debug!(
+                    output = output.name(),
+                    "Rejecting configuration of unknown output"
+                );
+                return false;
+            }
+            let config = match config {
+                OutputConfiguration::Disabled => None,
+                OutputConfiguration::Enabled(head) => {
+                    let mode = match head.mode {
+                        None => None,
+                        Some(ModeConfiguration::Mode(mode)) => Some(mode),
+                        Some(ModeConfiguration::Custom { size, refresh }) => output
+                            .modes()
+                            .into_iter()
+                            .filter(|mode| mode.size == size)
+                            .min_by_key(|mode| {
+                                refresh.map(|refresh| (mode.refresh - refresh).abs()).unwrap_or(0)
+                            }),
+                    };
+                    if head.mode.is_some() && !mode.is_some_and(|mode| output.modes().contains(&mode)) {
+                        debug!(output = output.name(), mode = ?head.mode, "Rejecting unsupported mode");
+                        return false;
+                    }
+                    if head.adaptive_sync == Some(true)
+                        && self
+                            .backend_data
+                            .surface_mut(&output)
+                            .is_some_and(|surface| !surface.vrr_supported)
+                    {
+                        debug!(output = output.name(), "Rejecting adaptive sync, not supported");
+                        return false;
+                    }
+                    if head
+                        .scale
+                        .is_some_and(|scale| !(MIN_OUTPUT_SCALE..=MAX_OUTPUT_SCALE).contains(&scale))
+                    {
+                        debug!(output = output.name(), scale = ?head.scale, "Rejecting unsupported scale");
+                        return false;
+                    }
+                    // the output has to fit into the coordinate space
+                    let scale = head
+                        .scale
+                        .unwrap_or_else(|| output.current_scale().fractional_scale());
+                    let extent = mode.or_else(|| output.current_mode()).map_or(0, |mode| {
+                        (mode.size.w.max(mode.size.h) as f64 / scale).ceil() as i32
+                    });
+                    if head.position.is_some_and(|position| {
+                        position.x.checked_add(extent).is_none() || position.y.checked_add(extent).is_none()
+                    }) {
+                        debug!(output = output.name(), position = ?head.position, "Rejecting position");
+                        return false;
+                    }
+                    Some((mode, head))
+                }
+            };
+            resolved.push((output, config));
+        }
+        if resolved.iter().all(|(_, config)| config.is_none()) {
+            debug!("Rejecting configuration disabling all outputs");
+            return false;
+        }
+        if test_only {
+            return true;
+        }
+
+        // modesets can still fail, the configuration is applied as a whole or not at all
+        let snapshots: Vec<_> = resolved
+            .iter()
+            .map(|(output, _)| self.output_snapshot(output))
+            .collect();
+        let mut applied = true;
+        for (index, (output, config)) in resolved.into_iter().enumerate() {
+            if !self.apply_head(output, config) {
+                warn!("Failed to apply an output configuration, rolling back");
+                for snapshot in snapshots.into_iter().take(index + 1).rev() {
+                    self.restore_output(snapshot);
+                }
+                applied = false;
+                break;
+            }
+        }
+
+        self.output_layout_changed();
+        self.update_output_heads();
+        applied
+    }
+}
+
+/// State of an output before an output configuration gets applied, to roll back to
+struct OutputSnapshot {
+    output: Output,
+    enabled: bool,
+    mode: Option<WlMode>,
+    transform: Transform,
+    scale: OutputScale,
+    location: Point<i32, Logical>,
+    vrr: Option<VrrConfig>,
+}
+
+impl LuxoState<UdevData> {
+    fn output_snapshot(&mut self, output: &Output) -> OutputSnapshot {
+        OutputSnapshot {
+            output: output.clone(),
+            enabled: !self.backend_data.disabled_outputs.contains(output),
+            mode: output.current_mode(),
+            transform: output.current_transform(),
+            scale: output.current_scale(),
+            location: output.current_location(),
+            vrr: self.backend_data.surface_mut(output).map(|surface| surface.vrr),
+        }
+    }
+
+    /// Apply the validated configuration of a single head, returns whether it succeeded
+    fn apply_head(&mut self, output: Output, config: Option<(Option<WlMode>, HeadConfiguration)>) -> bool {
+        let Some((mode, head)) = config else {
+            if !self.backend_data.disabled_outputs.contains(&output) {
+                self.disable_output(&output);
+            }
+            return true;
+        };
+        let output = if self.backend_data.disabled_outputs.contains(&output) {
+            match self.enable_output(&output) {
+                Some(output) => output,
+                None => return false,
+            }
+        } else {
+            output
+        };
+        if let Some(mode) = mode.filter(|mode| output.current_mode() != Some(*mode)) {
+            if !self.set_output_mode(&output, mode) {
+                return false;
+            }
+        }
+        let position = head.position.unwrap_or_else(|| output.current_location());
+        output.change_current_state(
+            None,
+            head.transform,
+            head.scale.map(OutputScale::Fractional),
+            Some(position),
+        );
+        self.space.map_output(&output, position);
+        layer_map_for_output(&output).arrange();
+        self.refit_maximized_windows(&output);
+        self.backend_data.reset_buffers(&output);
+        if let (Some(enabled), Some(surface)) = (head.adaptive_sync, self.backend_data.surface_mut(&output)) {
+            surface.vrr = if enabled { VrrConfig::On } else { VrrConfig::Off };
+        }
+        true
+    }
+
+    /// Bring an output back to the state of `snapshot`, as far as that still works
+    fn restore_output(&mut self, snapshot: OutputSnapshot) {
+        let disabled = self.backend_data.disabled_outputs.contains(&snapshot.output);
+        if !snapshot.enabled {
+            if !disabled {
+                // enabling it set up a new output with the same name
+                let output = self
+                    .space
+                    .outputs()
+                    .find(|o| o.name() == snapshot.output.name())
+                    .cloned();
+                if let Some(output) = output {
+                    self.disable_output(&output);
+                }
+            }
+            return;
+        }
+        let output = if disabled {
+            match self.enable_output(&snapshot.output) {
+                Some(output) => output,
+                None => return,
+            }
+        } else {
+            snapshot.output
+        };
+        if let Some(mode) = snapshot.mode.filter(|mode| output.current_mode() != Some(*mode)) {
+            self.set_output_mode(&output, mode);
+        }
+        output.change_current_state(
+            None,
+            Some(snapshot.transform),
+            Some(snapshot.scale),
+            Some(snapshot.location),
+        );
+        self.space.map_output(&output, snapshot.location);
+        layer_map_for_output(&output).arrange();
+        self.refit_maximized_windows(&output);
+        self.backend_data.reset_buffers(&output);
+        if let (Some(vrr), Some(surface)) = (snapshot.vrr, self.backend_data.surface_mut(&output)) {
+            surface.vrr = vrr;
+        }
+    }
+}
+delegate_output_management!(LuxoState<UdevData>);
+
+impl LuxoState<UdevData> {
+    /// Offer and withdraw non-desktop connectors for leasing as the `[drm_lease]` config says
+    fn update_lease_offers(&mut self) {
+        for device in self.backend_data.backends.values_mut() {
+            let Some(lease_state) = device.leasing_global.as_mut() else {
+                continue;
+            };
+            for connector in &mut device.non_desktop_connectors {
+                let offered = self.config.drm_lease.allows(&connector.name);
+                if offered && !connector.offered {
+                    info!(connector = %connector.name, "Offering connector for leasing");
+                    lease_state.add_connector::<LuxoState<UdevData>>(
+                        connector.handle,
+                        connector.name.clone(),
+                        connector.description.clone(),
+                    );
+                } else if !offered && connector.offered {
+                    info!(connector = %connector.name, "Withdrawing connector from leasing");
+                    lease_state.withdraw_connector(connector.handle);
+                }
+                connector.offered = offered;
+            }
+        }
+    }
+
+    /// Bring the connected outputs in line with the `[[outputs.output]]` sections of the config
+    fn apply_outputs_config(&mut self) {
+        for output in self.space.outputs() {
+            let physical = output.physical_properties();
+            let config = self
+                .config
+                .outputs
+                .find(&output.name(), &physical.make, &physical.model);
+            let Some(id) = output.user_data().get::<UdevOutputId>() else {
+                continue;
+            };
+            let UdevData {
+                backends,
+                gpus,
+                primary_gpu,
+                ..
+            } = &mut self.backend_data;
+            let Some(surface) = backends
+                .get_mut(&id.device_id)
+                .and_then(|device| device.surfaces.get_mut(&id.crtc))
+            else {
+                continue;
+            };
+
+            surface.vrr = config.and_then(|config| config.vrr).unwrap_or_default();
+            let render_gpu = render_gpu(
+                gpus,
+                *primary_gpu,
+                surface.render_node,
+                &self.config.outputs,
+                config,
+            );
+            if surface.render_gpu != render_gpu {
+                info!(output = output.name(), "Rendering on {}", render_gpu);
+                surface.render_gpu = render_gpu;
+                surface.scaled_frame = None;
+                surface.drm_output.reset_buffers();
+            }
+            let render_scale = config.map_or(1.0, |config| config.render_scale());
+            if surface.render_scale != render_scale {
+                info!(output = output.name(), render_scale, "Changing render scale");
+                surface.render_scale = render_scale;
+                surface.scaled_frame = None;
+                surface.drm_output.reset_buffers();
+            }
+        }
+
+        let configuration = self
+            .space
+            .outputs()
+            .chain(self.backend_data.disabled_outputs.iter())
+            // the lid decides about those
+            .filter(|output| !self.backend_data.lid_closed_outputs.contains(output))
+            .filter_map(|output| {
+                let physical = output.physical_properties();
+                let config = self
+                    .config
+                    .outputs
+                    .find(&output.name(), &physical.make, &physical.model)?;
+                let configuration = if config.enabled {
+                    OutputConfiguration::Enabled(HeadConfiguration {
+                        mode: config
+                            .mode
+                            .as_ref()
+                            .and_then(|mode| mode.best_match(&output.modes()).copied())
+                            .map(ModeConfiguration::Mode),
+                        position: config.position.map(|[x, y]| (x, y).into()),
+                        transform: self
+                            .backend_data
+                            .orientation
+                            .filter(|_| {
+                                self.config.outputs.auto_rotate && rotation::is_internal_panel(&output.name())
+                            })
+                            .or(config.transform.map(Into::into)),
+                        scale: config.scale(),
+                        adaptive_sync: None,
+                    })
+                } else {
+                    OutputConfiguration::Disabled
+                };
+                Some((output.clone(), configuration))
+            })
+            .collect::<Vec<_>>();
+
+        if !configuration.is_empty() && !self.apply_output_configuration(configuration, false) {
+            warn!("Failed to apply the output configuration from the config file");
+        }
+    }
+
+    /// Pack outputs without a configured position left-to-right after the others and bring the
+    /// windows and the pointer in line with the new layout
+    pub(crate) fn arrange_outputs(&mut self) {
+        let (fixed, auto): (Vec<_>, Vec<_>) = self.space.outputs().cloned().partition(|output| {
+            let physical = output.physical_properties();
+            self.config
+                .outputs
+                .find(&output.name(), &physical.make, &physical.model)
+                .is_some_and(|config| config.position.is_some())
+        });
+
+        let mut x = fixed
+            .iter()
+            .filter_map(|output| self.space.output_geometry(output))
+            .map(|geo| geo.loc.x + geo.size.w)
+            .max()
+            .unwrap_or(0);
+        for output in auto {
+            let width = self
+                .space
+                .output_geometry(&output)
+                .map(|geo| geo.size.w)
+                .unwrap_or(0);
+            output.change_current_state(None, None, None, Some((x, 0).into()));
+            self.space.map_output(&output, (x, 0));
+            x += width;
+        }
+
+        self.output_layout_changed();
+    }
+
+    /// Change the transform of `output`, e.g. to rotate it
+    pub(crate) fn set_output_transform(&mut self, output: &Output, transform: Transform) {
+        if output.current_transform() == transform {
+            return;
+        }
+        info!(output = output.name(), ?transform, "Changing output transform");
+        output.change_current_state(None, Some(transform), None, None);
+        self.arrange_outputs();
+        self.backend_data.reset_buffers(output);
+        self.update_output_heads();
+    }
+
+    /// Turn the internal panel off when the lid closes and on again when it opens, as the
+    /// `[switches]` config says
+    pub(crate) fn lid_switched(&mut self, closed: bool) {
+        if !closed {
+            let outputs = std::mem::take(&mut self.backend_data.lid_closed_outputs);
+            if outputs.is_empty() {
+                return;
+            }
+            for output in outputs {
+                if self.backend_data.disabled_outputs.contains(&output) {
+                    self.enable_output(&output);
+                }
+            }
+            self.arrange_outputs();
+            self.update_output_heads();
+            return;
+        }
+
+        let action = self.config.switches.lid_close;
+        if action == LidAction::None {
+            return;
+        }
+        let panels = self
+            .space
+            .outputs()
+            .filter(|output| rotation::is_internal_panel(&output.name()))
+            .cloned()
+            .collect::<Vec<_>>();
+        if panels.is_empty() {
+            return;
+        }
+        if panels.len() == self.space.outputs().count() {
+            // nothing would be left to show anything on
+            if action == LidAction::Suspend {
+                info!("Lid closed without another output, suspending");
+                self.spawn("systemctl suspend");
+            }
+            return;
+        }
+        for output in panels {
+            self.disable_output(&output);
+            self.backend_data.lid_closed_outputs.push(output);
+        }
+        self.arrange_outputs();
+        self.update_output_heads();
+    }
+
+    /// Turn the internal panel to the orientation read from the accelerometer
+    fn auto_rotate(&mut self, transform: Transform) {
+        if self.backend_data.orientation == Some(transform) {
+            return;
+        }
+        self.backend_data.orientation = Some(transform);
+        let panels = self
+            .space
+            .outputs()
+            .filter(|output| rotation::is_internal_panel(&output.name()))
+            .cloned()
+            .collect::<Vec<_>>();
+        for output in panels {
+            self.set_output_transform(&output, transform);
+        }
+    }
+
+    /// Advertise the current state of all outputs to output management clients
+    fn update_output_heads(&mut self) {
+        let mut heads = self
+            .space
+            .outputs()
+            .map(|output| (output.clone(), true))
+            .chain(
+                self.backend_data
+                    .disabled_outputs
+                    .iter()
+                    .map(|output| (output.clone(), false)),
+            )
+            .collect::<Vec<_>>();
+        heads.sort_by_key(|(output, _)| output.name());
+        self.backend_data
+            .output_management_state
+            .update::<Self>(&self.display_handle, heads);
+    }
+
+    /// Turn variable refresh rate on or off as the policy of `output` asks for
+    fn update_vrr(&mut self, output: &Output) {
+        let fullscreen = output
+            .user_data()
+            .get::<FullscreenSurface>()
+            .and_then(|fullscreen| fullscreen.get())
+            .is_some();
+        let Some(surface) = self.backend_data.surface_mut(output) else {
+            return;
+        };
+        let enable = surface.vrr_supported
+            && match surface.vrr {
+                VrrConfig::Off => false,
+                VrrConfig::On => true,
+                VrrConfig::Fullscreen => fullscreen,
+            };
+        if enable
+            == surface
+                .drm_output
+                .with_compositor(|compositor| compositor.vrr_enabled())
+        {
+            return;
+        }
+
+        match surface
+            .drm_output
+            .with_compositor(|compositor| compositor.use_vrr(enable))
+        {
+            Ok(()) => {
+                info!(output = output.name(), enable, "Changed variable refresh rate");
+                AdaptiveSync::set(output, enable);
+                self.update_output_heads();
+            }
+            Err(err) => {
+                // don't retry on every frame
+                warn!(
+                    output = output.name(),
+                    "Failed to change variable refresh rate: {}", err
+                );
+                surface.vrr_supported = false;
+            }
+        }
+    }
+
+    /// Load the gamma ramp set by a client, or else the one of the night light, into the crtc of
+    /// `output`. Returns whether it succeeded.
+    fn apply_gamma(&mut self, output: &Output) -> bool {
+        let Some(id) = output.user_data().get::<UdevOutputId>() else {
+            return false;
+        };
+        let Some(device) = self.backend_data.backends.get(&id.device_id) else {
+            return false;
+        };
+        let Some(surface) = device.surfaces.get(&id.crtc) else {
+            return false;
+        };
+        let drm = device.drm_output_manager.device();
+        let Some(size) = crtc_gamma_size(drm, id.crtc) else {
+            return false;
+        };
+
+        let night_ramp;
+        let ramp = match &surface.client_gamma {
+            Some(ramp) => ramp.as_slice(),
+            None => {
+                let temperature = if self.backend_data.night_light {
+                    self.config.night_light.temperature
+                } else {
+                    NEUTRAL_TEMPERATURE
+                };
+                night_ramp = night_light::gamma_ramp(temperature, size);
+                night_ramp.as_slice()
+            }
+        };
+        if ramp.len() != size as usize * 3 {
+            warn!(
+                output = output.name(),
+                "Gamma ramp doesn't match the size of the crtc"
+            );
+            return false;
+        }
+        let (red, rest) = ramp.split_at(size as usize);
+        let (green, blue) = rest.split_at(size as usize);
+        if let Err(err) = drm.set_gamma(id.crtc, red, green, blue) {
+            warn!(output = output.name(), "Failed to set gamma ramp: {}", err);
+            return false;
+        }
+        true
+    }
+
+    /// Switch the night light on or off as scheduled, loading the gamma ramps again if it changed
+    /// or `force` is set
+    fn update_night_light(&mut self, force: bool) {
+        let active = self.config.night_light.is_active(night_light::local_time());
+        if active != self.backend_data.night_light {
+            info!(active, "Switching night light");
+        } else if !force {
+            return;
+        }
+        self.backend_data.night_light = active;
+        for output in self.space.outputs().cloned().collect::<Vec<_>>() {
+            self.apply_gamma(&output);
+        }
+    }
+
+    /// Turn off `output`, keeping it around so it can be enabled again
+    fn disable_output(&mut self, output: &Output) {
+        let Some(id) = output.user_data().get::<UdevOutputId>() else {
+            return;
+        };
+        info!(output = output.name(), "Disabling output");
+        if let Some(device) = self.backend_data.backends.get_mut(&id.device_id) {
+            device.surfaces.remove(&id.crtc);
+        }
+        self.remove_output_from_space(output);
+        self.backend_data.disabled_outputs.push(output.clone());
+    }
+
+    /// Unmap `output`, moving its windows and the pointer over to a remaining output
+    fn remove_output_from_space(&mut self, output: &Output) {
+        self.backend_data.gamma_control_state.output_removed(output);
+        self.dismiss_output_popup_grab(output);
+        if let Some(target) = self.space.outputs().find(|o| *o != output).cloned() {
+            self.migrate_windows(output, &target);
+        }
+        self.space.unmap_output(output);
+        self.warp_pointer_onto_outputs();
+    }
+
+    /// Set up a previously disabled output again, returning the newly created output
+    fn enable_output(&mut self, output: &Output) -> Option<Output> {
+        self.backend_data.disabled_outputs.retain(|o| o != output);
+        let id = output.user_data().get::<UdevOutputId>()?;
+        let (node, crtc) = (id.device_id, id.crtc);
+        info!(output = output.name(), "Enabling output");
+
+        let connector = self
+            .backend_data
+            .backends
+            .get(&node)?
+            .drm_scanner
+            .crtcs()
+            .find(|(_, c)| *c == crtc)
+            .map(|(info, _)| info.clone())?;
+        self.setup_connector(node, connector, crtc, true);
+        self.space.outputs().find(|o| o.name() == output.name()).cloned()
+    }
+
+    /// Modeset `output` to `mode`, which has to be one of the modes of its connector
+    fn set_output_mode(&mut self, output: &Output, mode: WlMode) -> bool {
+        let Some(id) = output.user_data().get::<UdevOutputId>() else {
+            return false;
+        };
+        let (node, crtc) = (id.device_id, id.crtc);
+        let UdevData { backends, gpus, .. } = &mut self.backend_data;
+        let Some(device) = backends.get_mut(&node) else {
+            return false;
+        };
+        let Some(drm_mode) = device
+            .drm_scanner
+            .crtcs()
+            .find(|(_, c)| *c == crtc)
+            .and_then(|(info, _)| info.modes().iter().copied().find(|m| WlMode::from(*m) == mode))
+        else {
+            return false;
+        };
+        let Some(surface) = device.surfaces.get_mut(&crtc) else {
+            return false;
+        };
+
+        // the gpu may be gone after a reset or unplug, the caller rolls back then
+        let mut renderer = match gpus.single_renderer(&device.render_node) {
+            Ok(renderer) => renderer,
+            Err(err) => {
+                warn!(output = output.name(), "Failed to get a renderer to set the mode: {}", err);
+                return false;
+            }
+        };
+        let res = surface.drm_output.use_mode::<_, OutputRenderElements<
+            UdevRenderer<'_>,
+            WindowRenderElement<UdevRenderer<'_>>,
+        >>(drm_mode, &mut renderer, &DrmOutputRenderElements::default());
+        if let Err(err) = res {
+            warn!(output = output.name(), "Failed to set mode: {}", err);
+            return false;
+        }
+        output.change_current_state(Some(mode), None, None, None);
+
+        // the modeset dropped the frame waiting for its vblank
+        surface.scheduler.reset();
+        self.queue_repaint(node, crtc);
+        true
+    }
+}
+
 pub type RenderSurface = GbmBufferedSurface<GbmAllocator<DrmDeviceFd>, Option<OutputPresentationFeedback>>;
 
 pub type GbmDrmCompositor = DrmCompositor<
@@ -592,7 +1576,10 @@ pub type GbmDrmCompositor = DrmCompositor<
 struct SurfaceData {
     dh: DisplayHandle,
     device_id: DrmNode,
+    /// Render node of the gpu the output is connected to
     render_node: DrmNode,
+    /// Render node of the gpu rendering the output
+    render_gpu: DrmNode,
     global: Option<GlobalId>,
     drm_output: DrmOutput<
         GbmAllocator<DrmDeviceFd>,
@@ -601,11 +1588,253 @@ struct SurfaceData {
         DrmDeviceFd,
     >,
     disable_direct_scanout: bool,
+    frame_stats: FrameStats,
+    /// Whether the last frame was scanned out directly
+    scanned_out: bool,
     #[cfg(feature = "debug")]
     fps: fps_ticker::Fps,
     #[cfg(feature = "debug")]
     fps_element: Option<FpsElement<MultiTexture>>,
+    /// Only kept while the damage overlay is enabled
+    damage_overlay: Option<DamageOverlay>,
+    repaint_delay: RepaintDelay,
+    scheduler: FrameScheduler,
+    /// Variable refresh rate policy, only followed if the connector supports it
+    vrr: VrrConfig,
+    vrr_supported: bool,
+    /// Gamma ramp set through wlr-gamma-control, overriding the night light
+    client_gamma: Option<Vec<u16>>,
     dmabuf_feedback: Option<SurfaceDmabufFeedback>,
+    export_allocator: GbmAllocator<DrmDeviceFd>,
+    /// Buffers handed out to wlr-export-dmabuf clients, reused once the client is done with them
+    export_buffers: Vec<ExportBuffer>,
+    /// Scale the output is rendered at relative to its mode, only followed if the output is
+    /// rendered on the gpu it is connected to
+    render_scale: f64,
+    /// Only kept while the output is rendered at a render scale other than 1
+    scaled_frame: Option<ScaledFrame>,
+}
+
+/// Buffer wlr-export-dmabuf captures are rendered into
+#[derive(Debug)]
+struct ExportBuffer {
+    dmabuf: Dmabuf,
+    /// Frame the buffer is handed out with, it is free again once the client destroyed it
+    frame: Option<ZwlrExportDmabufFrameV1>,
+}
+
+/// Buffers per output for wlr-export-dmabuf, captures beyond that are cancelled until the client
+/// releases one
+const MAX_EXPORT_BUFFERS: usize = 4;
+
+/// Number of repaints the adaptive repaint delay is based on, about a second at 60 Hz
+const REPAINT_SAMPLES: usize = 60;
+/// Headroom left between the slowest recent repaint and the next vblank, for repaints that take
+/// longer than any recent one and for the atomic commit itself
+const REPAINT_MARGIN: Duration = Duration::from_millis(3);
+
+/// Delay between a vblank and the next repaint of a surface, derived from how long recent
+/// repaints took
+#[derive(Debug, Default)]
+struct RepaintDelay {
+    render_times: VecDeque<Duration>,
+    current: Duration,
+    /// Vblank the last submitted frame was meant for
+    target: Option<Time<Monotonic>>,
+}
+
+impl RepaintDelay {
+    fn record(&mut self, render_time: Duration) {
+        if self.render_times.len() == REPAINT_SAMPLES {
+            self.render_times.pop_front();
+        }
+        self.render_times.push_back(render_time);
+    }
+
+    /// A frame meant for the vblank at `frame_target` got submitted
+    fn submitted(&mut self, frame_target: Time<Monotonic>) {
+        self.target = Some(frame_target);
+    }
+
+    /// Whether the frame presented at `presented` landed on a later vblank than it was meant for
+    fn missed(&mut self, presented: Time<Monotonic>, frame_duration: Duration) -> bool {
+        self.target.take().is_some_and(|target| {
+            Duration::from(presented) > Duration::from(target) + frame_duration / 2
+        })
+    }
+
+    fn slowest(&self) -> Duration {
+        self.render_times.iter().max().copied().unwrap_or_default()
+    }
+
+    fn update(&mut self, frame_duration: Duration, config: &OutputsConfig) -> Duration {
+        self.current = if config.adaptive_repaint_delay && !self.render_times.is_empty() {
+            frame_duration.saturating_sub(self.slowest() + REPAINT_MARGIN)
+        } else {
+            frame_duration.mul_f64(config.repaint_delay)
+        };
+        self.current
+    }
+}
+
+/// Where a surface is in its repaint cycle
+#[derive(Debug, Default)]
+enum RepaintState {
+    /// Nothing is pending, the surface only repaints once queued again
+    #[default]
+    Idle,
+    /// A repaint is due once the timer fires
+    Queued(RegistrationToken),
+    /// A frame was submitted, the next repaint is scheduled on its vblank
+    WaitingForVblank,
+    /// Nothing was submitted, the timer fires around the vblank the frame would have hit
+    WaitingForEstimatedVblank(RegistrationToken),
+}
+
+/// Intermediate target an output with a render scale is rendered into before it gets stretched
+/// over the mode
+#[derive(Debug)]
+struct ScaledFrame {
+    texture: GlesTexture,
+    damage_tracker: OutputDamageTracker,
+    /// Output scale the damage tracker was created for
+    output_scale: f64,
+    id: Id,
+    commit: CommitCounter,
+}
+
+impl ScaledFrame {
+    fn element(&self, size: Size<i32, Physical>) -> ScaledFrameElement {
+        ScaledFrameElement {
+            id: self.id.clone(),
+            texture: self.texture.clone(),
+            size,
+            commit: self.commit,
+        }
+    }
+}
+
+/// The texture of a [`ScaledFrame`] covering the whole output
+#[derive(Debug)]
+struct ScaledFrameElement {
+    id: Id,
+    texture: GlesTexture,
+    /// Size of the output in the orientation of its content
+    size: Size<i32, Physical>,
+    commit: CommitCounter,
+}
+
+impl Element for ScaledFrameElement {
+    fn id(&self) -> &Id {
+        &self.id
+    }
+
+    fn current_commit(&self) -> CommitCounter {
+        self.commit
+    }
+
+    fn src(&self) -> Rectangle<f64, BufferCoords> {
+        Rectangle::from_size(self.texture.size()).to_f64()
+    }
+
+    fn geometry(&self, _scale: Scale<f64>) -> Rectangle<i32, Physical> {
+        Rectangle::from_size(self.size)
+    }
+}
+
+impl<'a> RenderElement<UdevRenderer<'a>> for ScaledFrameElement {
+    fn draw(
+        &self,
+        frame: &mut <UdevRenderer<'a> as Renderer>::Frame<'_, '_>,
+        src: Rectangle<f64, BufferCoords>,
+        dst: Rectangle<i32, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+        opaque_regions: &[Rectangle<i32, Physical>],
+    ) -> Result<(), <UdevRenderer<'a> as Renderer>::Error> {
+        // the texture lives on the gpu rendering the output
+        let frame: &mut GlesFrame<'_, '_> = frame.as_mut();
+        frame
+            .render_texture_from_to(&self.texture, src, dst, damage, opaque_regions, Transform::Normal, 1.0)
+            .map_err(MultiError::Render)
+    }
+}
+
+/// Repaint state machine of a surface, owning its timers so at most one repaint is pending
+#[derive(Debug)]
+struct FrameScheduler {
+    handle: LoopHandle<'static, LuxoState<UdevData>>,
+    node: DrmNode,
+    crtc: crtc::Handle,
+    state: RepaintState,
+}
+
+impl FrameScheduler {
+    fn new(handle: LoopHandle<'static, LuxoState<UdevData>>, node: DrmNode, crtc: crtc::Handle) -> Self {
+        FrameScheduler {
+            handle,
+            node,
+            crtc,
+            state: RepaintState::Idle,
+        }
+    }
+
+    /// Repaint as soon as possible, unless a repaint is already queued or follows the next vblank
+    fn queue(&mut self, frame_target: Time<Monotonic>) {
+        match self.state {
+            RepaintState::Queued(_) | RepaintState::WaitingForVblank => {}
+            RepaintState::Idle | RepaintState::WaitingForEstimatedVblank(_) => {
+                self.set_timer(Timer::immediate(), frame_target, false)
+            }
+        }
+    }
+
+    /// Forget pending repaints, e.g. when vblanks got lost while the session was inactive
+    fn reset(&mut self) {
+        if let RepaintState::Queued(token) | RepaintState::WaitingForEstimatedVblank(token) =
+            std::mem::take(&mut self.state)
+        {
+            self.handle.remove(token);
+        }
+    }
+
+    /// A frame got queued, the next repaint is scheduled once it is presented
+    fn frame_submitted(&mut self) {
+        self.reset();
+        self.state = RepaintState::WaitingForVblank;
+    }
+
+    /// The submitted frame was presented, repaint once `timer` fires
+    fn vblank(&mut self, timer: Timer, frame_target: Time<Monotonic>) {
+        self.set_timer(timer, frame_target, false);
+    }
+
+    /// Nothing got submitted, check for damage again once `timer` fires
+    fn estimated_vblank(&mut self, timer: Timer, frame_target: Time<Monotonic>) {
+        self.set_timer(timer, frame_target, true);
+    }
+
+    fn set_timer(&mut self, timer: Timer, frame_target: Time<Monotonic>, estimated: bool) {
+        self.reset();
+        let (node, crtc) = (self.node, self.crtc);
+        let token = self
+            .handle
+            .insert_source(timer, move |_, _, state| {
+                state.repaint_due(node, crtc, frame_target);
+                TimeoutAction::Drop
+            })
+            .expect("failed to schedule frame timer");
+        self.state = if estimated {
+            RepaintState::WaitingForEstimatedVblank(token)
+        } else {
+            RepaintState::Queued(token)
+        };
+    }
+}
+
+impl Drop for FrameScheduler {
+    fn drop(&mut self) {
+        self.reset();
+    }
 }
 
 impl Drop for SurfaceData {
@@ -616,11 +1845,27 @@ impl Drop for SurfaceData {
     }
 }
 
+/// Connector of a VR headset or another display that is not part of the desktop, clients can
+/// lease it instead
+struct LeaseConnector {
+    handle: connector::Handle,
+    crtc: crtc::Handle,
+    name: String,
+    description: String,
+    /// Advertised by the lease global, as allowed by the `[drm_lease]` config
+    offered: bool,
+}
+
 struct BackendData {
     surfaces: HashMap<crtc::Handle, SurfaceData>,
-    non_desktop_connectors: Vec<(connector::Handle, crtc::Handle)>,
+    non_desktop_connectors: Vec<LeaseConnector>,
     leasing_global: Option<DrmLeaseState>,
-    active_leases: Vec<DrmLease>,
+    /// The device can wait for syncobj timeline points, which explicit sync needs
+    syncobj_eventfd: bool,
+    /// Active leases with the connectors they hold
+    active_leases: Vec<(DrmLease, Vec<connector::Handle>)>,
+    /// Connectors of the last accepted lease request, until the lease becomes active
+    requested_lease: Vec<connector::Handle>,
     drm_output_manager: DrmOutputManager<
         GbmAllocator<DrmDeviceFd>,
         GbmDevice<DrmDeviceFd>,
@@ -629,9 +1874,149 @@ struct BackendData {
     >,
     drm_scanner: DrmScanner,
     render_node: DrmNode,
+    /// Device path, to open the device again when resetting it
+    path: PathBuf,
+    gbm: GbmDevice<DrmDeviceFd>,
     registration_token: RegistrationToken,
 }
 
+impl BackendData {
+    fn lease_connector_info(&self, node: DrmNode, connector: &LeaseConnector) -> LeaseConnectorInfo {
+        LeaseConnectorInfo {
+            name: connector.name.clone(),
+            description: connector.description.clone(),
+            gpu: node.to_string(),
+            offered: connector.offered,
+            leased: self
+                .active_leases
+                .iter()
+                .any(|(_, connectors)| connectors.contains(&connector.handle)),
+        }
+    }
+}
+
+/// `struct hdr_output_metadata` of the kernel uapi, holding HDMI static metadata type 1
+#[repr(C)]
+struct HdrOutputMetadata {
+    metadata_type: u32,
+    eotf: u8,
+    infoframe_type: u8,
+    /// In units of 0.00002
+    display_primaries: [[u16; 2]; 3],
+    white_point: [u16; 2],
+    /// In cd/m²
+    max_display_mastering_luminance: u16,
+    /// In 0.0001 cd/m²
+    min_display_mastering_luminance: u16,
+    max_cll: u16,
+    max_fall: u16,
+}
+
+impl HdrOutputMetadata {
+    const STATIC_METADATA_TYPE1: u8 = 0;
+    const EOTF_ST2084: u8 = 2;
+
+    fn new(description: &ImageDescription) -> Self {
+        let [r, g, b, w] = chromaticities(description.primaries)
+            .map(|[x, y]| [(x * 50000.0).round() as u16, (y * 50000.0).round() as u16]);
+        let luminances = description.luminances();
+        HdrOutputMetadata {
+            metadata_type: Self::STATIC_METADATA_TYPE1 as u32,
+            eotf: Self::EOTF_ST2084,
+            infoframe_type: Self::STATIC_METADATA_TYPE1,
+            display_primaries: [r, g, b],
+            white_point: w,
+            max_display_mastering_luminance: luminances.max.min(u16::MAX as u32) as u16,
+            min_display_mastering_luminance: luminances.min.min(u16::MAX as u32) as u16,
+            max_cll: description.max_cll.unwrap_or(0).min(u16::MAX as u32) as u16,
+            max_fall: description.max_fall.unwrap_or(0).min(u16::MAX as u32) as u16,
+        }
+    }
+}
+
+/// Switch a connector to BT.2020 with HDR metadata for the PQ transfer function, or back to
+/// the defaults. Returns whether HDR is on afterwards. Only enabled through `experimental_hdr`
+/// until composition encodes PQ.
+fn set_connector_hdr(drm: &DrmDevice, connector: connector::Handle, enable: bool) -> bool {
+    let find_property = |name: &str| {
+        let props = drm.get_properties(connector).ok()?;
+        let (handles, _) = props.as_props_and_values();
+        handles.iter().find_map(|handle| {
+            let info = drm.get_property(*handle).ok()?;
+            (info.name().to_str() == Ok(name)).then_some(info)
+        })
+    };
+    let (Some(metadata), Some(colorspace)) =
+        (find_property("HDR_OUTPUT_METADATA"), find_property("Colorspace"))
+    else {
+        if enable {
+            warn!(?connector, "Connector does not support HDR");
+        }
+        return false;
+    };
+    let colorspace_value = |name: &str| match colorspace.value_type() {
+        property::ValueType::Enum(values) => values
+            .values()
+            .1
+            .iter()
+            .find(|value| value.name().to_str() == Ok(name))
+            .map(|value| value.value()),
+        _ => None,
+    };
+    let (Some(bt2020), Some(default)) = (colorspace_value("BT2020_RGB"), colorspace_value("Default")) else {
+        if enable {
+            warn!(?connector, "Connector does not support the BT.2020 colorspace");
+        }
+        return false;
+    };
+
+    let result = if enable {
+        drm.create_property_blob(&HdrOutputMetadata::new(&ImageDescription::BT2100_PQ))
+            .and_then(|blob| {
+                drm.set_property(connector, metadata.handle(), blob.into())?;
+                drm.set_property(connector, colorspace.handle(), bt2020)
+            })
+    } else {
+        drm.set_property(connector, metadata.handle(), 0)
+            .and_then(|()| drm.set_property(connector, colorspace.handle(), default))
+    };
+    match result {
+        Ok(()) => enable,
+        Err(err) => {
+            warn!(?connector, "Failed to set the HDR properties: {}", err);
+            false
+        }
+    }
+}
+
+/// The gpu rendering an output connected to `target`: the configured render node if it is in use,
+/// otherwise the primary gpu, or `target` itself if copies between gpus are disabled
+fn render_gpu(
+    gpus: &mut GpuManager<GbmGlesBackend<GlesRenderer, DrmDeviceFd>>,
+    primary_gpu: DrmNode,
+    target: DrmNode,
+    config: &OutputsConfig,
+    output_config: Option<&OutputConfig>,
+) -> DrmNode {
+    if let Some(path) = output_config.and_then(|config| config.render_node.as_ref()) {
+        let node = DrmNode::from_path(path).map(|node| {
+            node.node_with_type(NodeType::Render)
+                .and_then(Result::ok)
+                .unwrap_or(node)
+        });
+        match node {
+            Ok(node) if gpus.single_renderer(&node).is_ok() => return node,
+            Ok(node) => warn!("Configured render node {} is not in use", node),
+            Err(err) => warn!("Invalid render node {}: {}", path.display(), err),
+        }
+    }
+    if config.disable_gpu_copy {
+        target
+    } else {
+        primary_gpu
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 enum DeviceAddError {
     #[error("Failed to open device using libseat: {0}")]
@@ -644,6 +2029,88 @@ enum DeviceAddError {
     DrmNode(CreateDrmNodeError),
     #[error("Failed to add device to GpuManager: {0}")]
     AddNode(egl::Error),
+    #[error("No device file found for {0}")]
+    NoDevicePath(DrmNode),
+}
+
+/// Settings of `device`, from the mouse or touchpad config and the overrides for its name
+fn pointer_settings(
+    device: &libinput::Device,
+    config: &InputConfig,
+    overrides: &HashMap<String, PointerDeviceConfig>,
+) -> PointerDeviceConfig {
+    let mut settings = if is_touchpad(device) {
+        config.touchpad.clone()
+    } else {
+        config.mouse.clone()
+    };
+    if let Some(overrides) = overrides.get(device.name()) {
+        settings.merge(overrides);
+    }
+    settings
+}
+
+fn is_touchpad(device: &libinput::Device) -> bool {
+    device.config_tap_finger_count() > 0
+}
+
+/// Apply the `[input.touchpad]` or `[input.mouse]` settings to a newly added pointer device
+fn configure_pointer_device(
+    device: &mut libinput::Device,
+    config: &InputConfig,
+    overrides: &HashMap<String, PointerDeviceConfig>,
+) {
+    let is_touchpad = is_touchpad(device);
+    let settings = pointer_settings(device, config, overrides);
+    let name = device.name().to_owned();
+    let check = |setting: &str, result: Result<(), libinput::DeviceConfigError>| {
+        if let Err(err) = result {
+            warn!(device = %name, setting, "Failed to configure input device: {:?}", err);
+        }
+    };
+
+    if is_touchpad {
+        check("tap", device.config_tap_set_enabled(settings.tap.unwrap_or(true)));
+    }
+    if let Some(natural_scroll) = settings.natural_scroll {
+        check(
+            "natural_scroll",
+            device.config_scroll_set_natural_scroll_enabled(natural_scroll),
+        );
+    }
+    if let Some(left_handed) = settings.left_handed {
+        check("left_handed", device.config_left_handed_set(left_handed));
+    }
+    if let Some(dwt) = settings.disable_while_typing {
+        check("disable_while_typing", device.config_dwt_set_enabled(dwt));
+    }
+    if let Some(profile) = settings.accel_profile {
+        let profile = match profile {
+            AccelProfileConfig::Flat => AccelProfile::Flat,
+            AccelProfileConfig::Adaptive => AccelProfile::Adaptive,
+        };
+        check("accel_profile", device.config_accel_set_profile(profile));
+    }
+    if let Some(speed) = settings.accel_speed {
+        let speed = speed.clamp(-1.0, 1.0);
+        check("accel_speed", device.config_accel_set_speed(speed));
+    }
+    if let Some(method) = settings.scroll_method {
+        let method = match method {
+            ScrollMethodConfig::None => ScrollMethod::NoScroll,
+            ScrollMethodConfig::TwoFinger => ScrollMethod::TwoFinger,
+            ScrollMethodConfig::Edge => ScrollMethod::Edge,
+            ScrollMethodConfig::OnButtonDown => ScrollMethod::OnButtonDown,
+        };
+        check("scroll_method", device.config_scroll_set_method(method));
+    }
+    if let Some(method) = settings.click_method {
+        let method = match method {
+            ClickMethodConfig::ButtonAreas => ClickMethod::ButtonAreas,
+            ClickMethodConfig::Clickfinger => ClickMethod::Clickfinger,
+        };
+        check("click_method", device.config_click_set_method(method));
+    }
 }
 
 fn get_surface_dmabuf_feedback(
@@ -700,7 +2167,40 @@ fn get_surface_dmabuf_feedback(
     })
 }
 
+/// Resets of a gpu within this window count towards [`MAX_GPU_RESETS`]
+const GPU_RESET_WINDOW: Duration = Duration::from_secs(60);
+/// Resets within [`GPU_RESET_WINDOW`] before a gpu is given up on
+const MAX_GPU_RESETS: usize = 3;
+
+/// Mark the gpu of `render_node` as lost, scheduling a reset once the current dispatch is done
+fn gpu_lost(
+    handle: &LoopHandle<'static, LuxoState<UdevData>>,
+    lost_gpus: &mut Vec<DrmNode>,
+    render_node: DrmNode,
+) {
+    if lost_gpus.contains(&render_node) {
+        return;
+    }
+    if lost_gpus.is_empty() {
+        handle.insert_idle(|state| state.reset_lost_gpus());
+    }
+    lost_gpus.push(render_node);
+}
+
 impl LuxoState<UdevData> {
+    /// Multiplier for scroll distances of the pointer device with the libinput `sysname`
+    pub fn scroll_factor(&self, sysname: &str) -> f64 {
+        self.backend_data
+            .pointer_devices
+            .iter()
+            .find(|device| device.sysname() == sysname)
+            .and_then(|device| {
+                pointer_settings(device, &self.config.input, &self.backend_data.device_overrides)
+                    .scroll_factor
+            })
+            .unwrap_or(1.0)
+    }
+
     fn device_added(&mut self, node: DrmNode, path: &Path) -> Result<(), DeviceAddError> {
         // Try to open the device
         let fd = self
@@ -713,6 +2213,10 @@ impl LuxoState<UdevData> {
             .map_err(DeviceAddError::DeviceOpen)?;
 
         let fd = DrmDeviceFd::new(DeviceFd::from(fd));
+        let syncobj_eventfd = supports_syncobj_eventfd(&fd);
+        if !syncobj_eventfd {
+            info!("{} does not support explicit sync", node);
+        }
 
         let (drm, notifier) = DrmDevice::new(fd.clone(), true).map_err(DeviceAddError::DrmDevice)?;
         let gbm = GbmDevice::new(fd).map_err(DeviceAddError::GbmDevice)?;
@@ -745,7 +2249,7 @@ impl LuxoState<UdevData> {
             .map_err(DeviceAddError::AddNode)?;
 
         let allocator = GbmAllocator::new(gbm.clone(), GbmBufferFlags::RENDERING | GbmBufferFlags::SCANOUT);
-        let color_formats = if std::env::var("ANVIL_DISABLE_10BIT").is_ok() {
+        let color_formats = if self.config.outputs.disable_10bit {
             SUPPORTED_FORMATS_8BIT_ONLY
         } else {
             SUPPORTED_FORMATS
@@ -757,7 +2261,7 @@ impl LuxoState<UdevData> {
             drm,
             allocator,
             gbm.clone(),
-            Some(gbm),
+            Some(gbm.clone()),
             color_formats.iter().copied(),
             render_formats,
         );
@@ -770,13 +2274,21 @@ impl LuxoState<UdevData> {
                 drm_scanner: DrmScanner::new(),
                 non_desktop_connectors: Vec::new(),
                 render_node,
+                path: path.to_owned(),
+                gbm,
                 surfaces: HashMap::new(),
-                leasing_global: DrmLeaseState::new::<LuxoState<UdevData>>(&self.display_handle, &node)
-                    .inspect_err(|err| {
-                        warn!(?err, "Failed to initialize drm lease global for: {}", node);
-                    })
-                    .ok(),
+                leasing_global: DrmLeaseState::new_with_filter::<LuxoState<UdevData>, _>(
+                    &self.display_handle,
+                    &node,
+                    PROTOCOLS.filter(PrivilegedProtocol::DrmLease),
+                )
+                .inspect_err(|err| {
+                    warn!(?err, "Failed to initialize drm lease global for: {}", node);
+                })
+                .ok(),
+                syncobj_eventfd,
                 active_leases: Vec::new(),
+                requested_lease: Vec::new(),
             },
         );
 
@@ -786,6 +2298,17 @@ impl LuxoState<UdevData> {
     }
 
     fn connector_connected(&mut self, node: DrmNode, connector: connector::Info, crtc: crtc::Handle) {
+        self.setup_connector(node, connector, crtc, false);
+    }
+
+    /// Set up a newly connected connector, `force_enable` overrides outputs disabled in the config
+    fn setup_connector(
+        &mut self,
+        node: DrmNode,
+        connector: connector::Info,
+        crtc: crtc::Handle,
+        force_enable: bool,
+    ) {
         let device = if let Some(device) = self.backend_data.backends.get_mut(&node) {
             device
         } else {
@@ -833,24 +2356,56 @@ impl LuxoState<UdevData> {
             .unwrap_or_else(|| "Unknown".into());
 
         if non_desktop {
-            info!("Connector {} is non-desktop, setting up for leasing", output_name);
-            device.non_desktop_connectors.push((connector.handle(), crtc));
-            if let Some(lease_state) = device.leasing_global.as_mut() {
-                lease_state.add_connector::<LuxoState<UdevData>>(
-                    connector.handle(),
-                    output_name,
-                    format!("{} {}", make, model),
-                );
+            let description = format!("{} {}", make, model);
+            let offered = self.config.drm_lease.allows(&output_name);
+            match device.leasing_global.as_mut() {
+                Some(lease_state) if offered => {
+                    info!("Connector {} is non-desktop, setting up for leasing", output_name);
+                    lease_state.add_connector::<LuxoState<UdevData>>(
+                        connector.handle(),
+                        output_name.clone(),
+                        description.clone(),
+                    );
+                }
+                _ => info!("Connector {} is non-desktop, not offering it for leasing", output_name),
             }
+            let lease_connector = LeaseConnector {
+                handle: connector.handle(),
+                crtc,
+                name: output_name,
+                description,
+                offered: offered && device.leasing_global.is_some(),
+            };
+            let event = Event::LeaseConnectorConnected {
+                connector: device.lease_connector_info(node, &lease_connector),
+            };
+            device.non_desktop_connectors.push(lease_connector);
+            self.send_ipc_event(event);
         } else {
+            let output_config = self.config.outputs.find(&output_name, &make, &model).cloned();
+
             let mode_id = connector
                 .modes()
                 .iter()
                 .position(|mode| mode.mode_type().contains(ModeTypeFlags::PREFERRED))
                 .unwrap_or(0);
-
-            let drm_mode = connector.modes()[mode_id];
-            let wl_mode = WlMode::from(drm_mode);
+            let preferred_mode = WlMode::from(connector.modes()[mode_id]);
+            let modes = connector
+                .modes()
+                .iter()
+                .map(|mode| WlMode::from(*mode))
+                .collect::<Vec<_>>();
+
+            let configured_mode = output_config.as_ref().and_then(|config| {
+                let mode = config.mode.as_ref()?;
+                let found = mode.best_match(&modes);
+                if found.is_none() {
+                    warn!(?mode, "Configured mode not supported by {}", output_name);
+                }
+                found
+            });
+            let wl_mode = configured_mode.copied().unwrap_or(preferred_mode);
+            let drm_mode = connector.modes()[modes.iter().position(|mode| *mode == wl_mode).unwrap()];
 
             let (phys_w, phys_h) = connector.size().unwrap_or((0, 0));
             let output = Output::new(
@@ -862,23 +2417,52 @@ impl LuxoState<UdevData> {
                     model,
                 },
             );
-            let global = output.create_global::<LuxoState<UdevData>>(&self.display_handle);
-
-            let x = self
-                .space
-                .outputs()
-                .fold(0, |acc, o| acc + self.space.output_geometry(o).unwrap().size.w);
-            let position = (x, 0).into();
-
-            output.set_preferred(wl_mode);
-            output.change_current_state(Some(wl_mode), None, None, Some(position));
-            self.space.map_output(&output, position);
-
+            for mode in &modes {
+                output.add_mode(*mode);
+            }
+            output.set_preferred(preferred_mode);
             output.user_data().insert_if_missing(|| UdevOutputId {
                 crtc,
                 device_id: node,
             });
 
+            if !force_enable && output_config.as_ref().is_some_and(|config| !config.enabled) {
+                info!("Connector {} is disabled in the configuration", output.name());
+                output.change_current_state(Some(wl_mode), None, None, None);
+                self.backend_data.disabled_outputs.push(output);
+                return;
+            }
+
+            let global = output.create_global::<LuxoState<UdevData>>(&self.display_handle);
+
+            let position = match output_config.as_ref().and_then(|config| config.position) {
+                Some([x, y]) => (x, y).into(),
+                None => {
+                    let x = self
+                        .space
+                        .outputs()
+                        .filter_map(|o| self.space.output_geometry(o))
+                        .map(|geo| geo.loc.x + geo.size.w)
+                        .max()
+                        .unwrap_or(0);
+                    (x, 0).into()
+                }
+            };
+
+            output.change_current_state(
+                Some(wl_mode),
+                output_config
+                    .as_ref()
+                    .and_then(|config| config.transform)
+                    .map(Into::into),
+                output_config
+                    .as_ref()
+                    .and_then(|config| config.scale())
+                    .map(OutputScale::Fractional),
+                Some(position),
+            );
+            self.space.map_output(&output, position);
+
             #[cfg(feature = "debug")]
             let fps_element = self.backend_data.fps_texture.clone().map(FpsElement::new);
 
@@ -898,17 +2482,43 @@ impl LuxoState<UdevData> {
                 }
             };
 
-            // Using an overlay plane on a nvidia card breaks
-            if driver.name().to_string_lossy().to_lowercase().contains("nvidia")
-                || driver
-                    .description()
-                    .to_string_lossy()
-                    .to_lowercase()
-                    .contains("nvidia")
+            // overlay planes are broken on some drivers, nvidia in particular
+            let driver_name = driver.name().to_string_lossy().to_lowercase();
+            let driver_description = driver.description().to_string_lossy().to_lowercase();
+            if let Some(disabled) = self
+                .config
+                .outputs
+                .disable_overlay_planes
+                .iter()
+                .find(|disabled| {
+                    let disabled = disabled.to_lowercase();
+                    driver_name.contains(&disabled) || driver_description.contains(&disabled)
+                })
             {
+                debug!(driver = %driver_name, "Overlay planes disabled for driver matching {:?}", disabled);
                 planes.overlay = vec![];
             }
 
+            let hdr = output_config
+                .as_ref()
+                .is_some_and(|config| config.experimental_hdr);
+            if hdr && self.config.outputs.disable_10bit {
+                warn!(
+                    "HDR on {} needs 10-bit color formats, which are disabled",
+                    output.name()
+                );
+            } else if hdr {
+                warn!(
+                    "Experimental HDR enabled on {}, frames are not converted to PQ yet",
+                    output.name()
+                );
+            }
+            let hdr = set_connector_hdr(
+                device.drm_output_manager.device(),
+                connector.handle(),
+                hdr && !self.config.outputs.disable_10bit,
+            );
+
             let drm_output = match device
                 .drm_output_manager
                 .initialize_output::<_, OutputRenderElements<UdevRenderer<'_>, WindowRenderElement<UdevRenderer<'_>>>>(
@@ -927,7 +2537,31 @@ impl LuxoState<UdevData> {
                 }
             };
 
-            let disable_direct_scanout = std::env::var("ANVIL_DISABLE_DIRECT_SCANOUT").is_ok();
+            let disable_direct_scanout = self.config.outputs.disable_direct_scanout;
+            let render_gpu = render_gpu(
+                &mut self.backend_data.gpus,
+                self.backend_data.primary_gpu,
+                device.render_node,
+                &self.config.outputs,
+                output_config.as_ref(),
+            );
+            if render_gpu != device.render_node {
+                info!("Rendering {} on {}", output.name(), render_gpu);
+            }
+
+            let vrr_supported = drm_output
+                .with_compositor(|compositor| compositor.vrr_supported(connector.handle()))
+                .is_ok_and(|support| support != VrrSupport::NotSupported);
+            let vrr = output_config
+                .as_ref()
+                .and_then(|config| config.vrr)
+                .unwrap_or_default();
+            if vrr != VrrConfig::Off && !vrr_supported {
+                info!(
+                    "Connector {} does not support variable refresh rate",
+                    output.name()
+                );
+            }
 
             let dmabuf_feedback = drm_output.with_compositor(|compositor| {
                 compositor.set_debug_flags(self.backend_data.debug_flags);
@@ -944,22 +2578,42 @@ impl LuxoState<UdevData> {
                 dh: self.display_handle.clone(),
                 device_id: node,
                 render_node: device.render_node,
+                render_gpu,
                 global: Some(global),
                 drm_output,
                 disable_direct_scanout,
+                frame_stats: FrameStats::default(),
+                scanned_out: false,
                 #[cfg(feature = "debug")]
                 fps: fps_ticker::Fps::default(),
                 #[cfg(feature = "debug")]
                 fps_element,
+                damage_overlay: None,
+                repaint_delay: RepaintDelay::default(),
+                scheduler: FrameScheduler::new(self.handle.clone(), node, crtc),
+                vrr,
+                vrr_supported,
+                client_gamma: None,
                 dmabuf_feedback,
+                export_allocator: GbmAllocator::new(device.gbm.clone(), GbmBufferFlags::RENDERING),
+                export_buffers: Vec::new(),
+                render_scale: output_config.as_ref().map_or(1.0, |config| config.render_scale()),
+                scaled_frame: None,
             };
 
             device.surfaces.insert(crtc, surface);
 
+            if hdr {
+                info!("Enabled HDR on {}", output.name());
+                OutputImageDescription::set(&output, ImageDescription::BT2100_PQ);
+                color_management::output_changed(self, &output);
+            }
+            if self.backend_data.night_light {
+                self.apply_gamma(&output);
+            }
+
             // kick-off rendering
-            self.handle.insert_idle(move |state| {
-                state.render_surface(node, crtc, state.clock.now());
-            });
+            self.queue_repaint(node, crtc);
         }
     }
 
@@ -973,14 +2627,25 @@ impl LuxoState<UdevData> {
         if let Some(pos) = device
             .non_desktop_connectors
             .iter()
-            .position(|(handle, _)| *handle == connector.handle())
+            .position(|lease_connector| lease_connector.handle == connector.handle())
         {
-            let _ = device.non_desktop_connectors.remove(pos);
-            if let Some(leasing_state) = device.leasing_global.as_mut() {
-                leasing_state.withdraw_connector(connector.handle());
+            let lease_connector = device.non_desktop_connectors.remove(pos);
+            let event = Event::LeaseConnectorDisconnected {
+                connector: device.lease_connector_info(node, &lease_connector),
+            };
+            if lease_connector.offered {
+                if let Some(leasing_state) = device.leasing_global.as_mut() {
+                    leasing_state.withdraw_connector(connector.handle());
+                }
             }
+            self.send_ipc_event(event);
         } else {
             device.surfaces.remove(&crtc);
+            self.backend_data.disabled_outputs.retain(|o| {
+                o.user_data()
+                    .get::<UdevOutputId>()
+                    .is_none_or(|id| id.device_id != node || id.crtc != crtc)
+            });
 
             let output = self
                 .space
@@ -994,10 +2659,13 @@ impl LuxoState<UdevData> {
                 .cloned();
 
             if let Some(output) = output {
-                self.space.unmap_output(&output);
+                self.remove_output_from_space(&output);
             }
         }
 
+        let Some(device) = self.backend_data.backends.get_mut(&node) else {
+            return;
+        };
         let mut renderer = self
             .backend_data
             .gpus
@@ -1050,8 +2718,9 @@ impl LuxoState<UdevData> {
             }
         }
 
-        // fixup window coordinates
-        crate::shell::fixup_positions(&mut self.space, self.pointer.current_location());
+        // fixup output and window coordinates
+        self.arrange_outputs();
+        self.update_output_heads();
     }
 
     fn device_removed(&mut self, node: DrmNode) {
@@ -1089,7 +2758,148 @@ impl LuxoState<UdevData> {
             debug!("Dropping device");
         }
 
-        crate::shell::fixup_positions(&mut self.space, self.pointer.current_location());
+        // outputs of other devices may have been rendered on this one
+        self.update_render_gpus();
+        self.arrange_outputs();
+        self.update_output_heads();
+    }
+
+    /// Pick the gpu rendering each output again, moving outputs off gpus that are gone
+    fn update_render_gpus(&mut self) {
+        let outputs: Vec<Output> = self
+            .space
+            .outputs()
+            .chain(self.backend_data.disabled_outputs.iter())
+            .cloned()
+            .collect();
+        for output in outputs {
+            let physical = output.physical_properties();
+            let config = self
+                .config
+                .outputs
+                .find(&output.name(), &physical.make, &physical.model);
+            let Some(id) = output.user_data().get::<UdevOutputId>() else {
+                continue;
+            };
+            let UdevData {
+                backends,
+                gpus,
+                primary_gpu,
+                ..
+            } = &mut self.backend_data;
+            let Some(surface) = backends
+                .get_mut(&id.device_id)
+                .and_then(|device| device.surfaces.get_mut(&id.crtc))
+            else {
+                continue;
+            };
+
+            let mut render_gpu = render_gpu(
+                gpus,
+                *primary_gpu,
+                surface.render_node,
+                &self.config.outputs,
+                config,
+            );
+            // the primary gpu itself may be gone
+            if render_gpu != surface.render_node && gpus.single_renderer(&render_gpu).is_err() {
+                render_gpu = surface.render_node;
+            }
+            if surface.render_gpu != render_gpu {
+                info!(output = output.name(), "Rendering on {}", render_gpu);
+                surface.render_gpu = render_gpu;
+                surface.scaled_frame = None;
+                surface.drm_output.reset_buffers();
+            }
+        }
+    }
+
+    /// Tear down and re-add the devices rendering with a lost gpu
+    ///
+    /// Client buffers and cursor images are imported into the new renderer on the next frame. A
+    /// gpu failing too often is given up on, which ends the compositor for the primary gpu.
+    fn reset_lost_gpus(&mut self) {
+        for render_node in std::mem::take(&mut self.backend_data.lost_gpus) {
+            let primary = render_node == self.backend_data.primary_gpu;
+            let resets = self.backend_data.gpu_resets.entry(render_node).or_default();
+            let now = Instant::now();
+            while resets
+                .front()
+                .is_some_and(|reset| now - *reset > GPU_RESET_WINDOW)
+            {
+                resets.pop_front();
+            }
+            resets.push_back(now);
+            let give_up = resets.len() > MAX_GPU_RESETS;
+            if give_up {
+                error!(
+                    "{} was lost {} times within a minute, giving up on it",
+                    render_node,
+                    MAX_GPU_RESETS + 1
+                );
+            }
+
+            let devices: Vec<(DrmNode, PathBuf)> = self
+                .backend_data
+                .backends
+                .iter()
+                .filter(|(_, device)| device.render_node == render_node)
+                .map(|(node, device)| (*node, device.path.clone()))
+                .collect();
+            let mut gone = give_up;
+
+            // a gpu only used for rendering other devices' outputs has no device of its own
+            if devices.is_empty() {
+                warn!("Resetting {} after it was lost", render_node);
+                self.backend_data.gpus.as_mut().remove_node(&render_node);
+                self.update_render_gpus();
+                if !give_up {
+                    if let Err(err) = self.add_render_node(render_node) {
+                        error!("Failed to reset {}: {}", render_node, err);
+                        gone = true;
+                    }
+                }
+            }
+
+            for (node, path) in devices {
+                warn!("Resetting {} after its gpu was lost", node);
+                self.device_removed(node);
+                if !give_up {
+                    if let Err(err) = self.device_added(node, &path) {
+                        error!("Failed to reset {}: {}", node, err);
+                        gone = true;
+                    }
+                }
+            }
+
+            if gone && primary {
+                error!("The primary gpu is gone, exiting");
+                self.running.store(false, Ordering::SeqCst);
+            }
+        }
+
+        // outputs moved off the lost gpus go back to them
+        self.update_render_gpus();
+        // drop textures of the lost renderers
+        self.backend_data.pointer_images.clear();
+    }
+
+    /// Open a gpu without a drm device in use, like an offload gpu only rendered on
+    fn add_render_node(&mut self, render_node: DrmNode) -> Result<(), DeviceAddError> {
+        let path = render_node
+            .dev_path()
+            .ok_or(DeviceAddError::NoDevicePath(render_node))?;
+        let fd = self
+            .backend_data
+            .session
+            .open(&path, OFlags::RDWR | OFlags::CLOEXEC | OFlags::NOCTTY | OFlags::NONBLOCK)
+            .map_err(DeviceAddError::DeviceOpen)?;
+        let gbm = GbmDevice::new(DrmDeviceFd::new(DeviceFd::from(fd))).map_err(DeviceAddError::GbmDevice)?;
+        self.backend_data
+            .gpus
+            .as_mut()
+            .add_node(render_node, gbm)
+            .map_err(DeviceAddError::AddNode)
     }
 
     fn frame_finish(&mut self, dev_id: DrmNode, crtc: crtc::Handle, metadata: &mut Option<DrmEventMetadata>) {
@@ -1180,7 +2990,15 @@ impl LuxoState<UdevData> {
                             ..
                         })) if source.kind() == io::ErrorKind::PermissionDenied
                     ),
-                    SwapBuffersError::ContextLost(err) => panic!("Rendering loop lost: {}", err),
+                    SwapBuffersError::ContextLost(err) => {
+                        error!("Rendering loop lost on {}: {}", dev_id, err);
+                        gpu_lost(
+                            &self.handle,
+                            &mut self.backend_data.lost_gpus,
+                            surface.render_node,
+                        );
+                        false
+                    }
                 }
             }
         };
@@ -1204,26 +3022,35 @@ impl LuxoState<UdevData> {
             // new buffer during the repaint delay that can hit the very next
             // VBlank, thus reducing the potential latency to below one frame.
             //
-            // Choosing a good delay is a topic on its own so we just implement
-            // a simple strategy here. We just split the duration between two
-            // VBlanks into two steps, one for the client repaint and one for the
-            // compositor repaint. Theoretically the repaint in the compositor should
-            // be faster so we give the client a bit more time to repaint. On a typical
-            // modern system the repaint in the compositor should not take more than 2ms
-            // so this should be safe for refresh rates up to at least 120 Hz. For 120 Hz
-            // this results in approx. 3.33ms time for repainting in the compositor.
-            // A too big delay could result in missing the next VBlank in the compositor.
-            //
-            // A more complete solution could work on a sliding window analyzing past repaints
-            // and do some prediction for the next repaint.
-            let repaint_delay = Duration::from_secs_f64(frame_duration.as_secs_f64() * 0.6f64);
-
-            let timer = if self.backend_data.primary_gpu != surface.render_node {
+            // A too big delay could result in missing the next VBlank in the compositor,
+            // so the delay is based on the slowest of the recent repaints of this surface,
+            // leaving the rest of the frame to the clients. Until there are samples, or
+            // with the adaptive delay disabled, the frame is split at a fixed fraction
+            // instead, giving the client a bit more time than the compositor.
+            let repaint_delay = surface.repaint_delay.update(frame_duration, &self.config.outputs);
+            let missed = surface.repaint_delay.missed(clock, frame_duration);
+
+            let timer = if missed {
+                // The last frame came a vblank late already, waiting would make the next one late
+                // as well.
+                trace!("scheduling repaint timer immediately after a missed vblank on {:?}", crtc);
+                Timer::immediate()
+            } else if surface.render_gpu != surface.render_node {
                 // However, if we need to do a copy, that might not be enough.
                 // (And without actual comparision to previous frames we cannot really know.)
                 // So lets ignore that in those cases to avoid thrashing performance.
                 trace!("scheduling repaint timer immediately on {:?}", crtc);
                 Timer::immediate()
+            } else if output
+                .user_data()
+                .get::<FullscreenSurface>()
+                .and_then(|fullscreen| fullscreen.get())
+                .is_some_and(|window| window.content_type() == ContentType::Game)
+            {
+                // Games render as fast as they can instead of waiting for frame callbacks, so the
+                // delay only adds latency for them.
+                trace!("scheduling repaint timer immediately for a game on {:?}", crtc);
+                Timer::immediate()
             } else {
                 trace!(
                     "scheduling repaint timer with delay {:?} on {:?}",
@@ -1233,33 +3060,91 @@ impl LuxoState<UdevData> {
                 Timer::from_duration(repaint_delay)
             };
 
-            self.handle
-                .insert_source(timer, move |_, _, data| {
-                    data.render(dev_id, Some(crtc), next_frame_target);
-                    TimeoutAction::Drop
-                })
-                .expect("failed to schedule frame timer");
+            surface.scheduler.vblank(timer, next_frame_target);
+        } else {
+            surface.scheduler.reset();
         }
     }
 
-    // If crtc is `Some()`, render it, else render all crtcs
-    fn render(&mut self, node: DrmNode, crtc: Option<crtc::Handle>, frame_target: Time<Monotonic>) {
-        let device_backend = match self.backend_data.backends.get_mut(&node) {
-            Some(backend) => backend,
-            None => {
-                error!("Trying to render on non-existent backend {}", node);
-                return;
+    /// Switch to another vt, getting ready for the session to be paused right away instead of
+    /// once the pause lands
+    pub(crate) fn switch_vt(&mut self, vt: i32) {
+        info!(to = vt, "Trying to switch vt");
+        // releases of the keys held now would never arrive, leaving them pressed for clients
+        self.release_all_keys();
+        self.suppressed_keys.clear();
+        // repaints would only fail until the session is paused
+        self.pause_rendering();
+        if let Err(err) = self.backend_data.session.change_vt(vt) {
+            error!(vt, "Error switching vt: {}", err);
+            self.resume_rendering();
+            return;
+        }
+        // switching to the current vt doesn't pause the session
+        self.handle
+            .insert_source(Timer::from_duration(VT_SWITCH_TIMEOUT), |_, _, state| {
+                if state.backend_data.rendering_paused && state.backend_data.session.is_active() {
+                    state.resume_rendering();
+                }
+                TimeoutAction::Drop
+            })
+            .unwrap();
+    }
+
+    /// Stop repainting, e.g. when the session is about to be paused
+    fn pause_rendering(&mut self) {
+        self.backend_data.rendering_paused = true;
+        for device in self.backend_data.backends.values_mut() {
+            for surface in device.surfaces.values_mut() {
+                surface.scheduler.reset();
             }
-        };
+        }
+    }
 
-        if let Some(crtc) = crtc {
-            self.render_surface(node, crtc, frame_target);
-        } else {
-            let crtcs: Vec<_> = device_backend.surfaces.keys().copied().collect();
-            for crtc in crtcs {
-                self.render_surface(node, crtc, frame_target);
+    /// Repaint again after [`Self::pause_rendering`], if the session didn't get paused after all
+    fn resume_rendering(&mut self) {
+        self.backend_data.rendering_paused = false;
+        let now = self.clock.now();
+        for device in self.backend_data.backends.values_mut() {
+            for surface in device.surfaces.values_mut() {
+                surface.scheduler.queue(now);
             }
+        }
+    }
+
+    /// Queue a repaint of a surface, unless one is already pending
+    fn queue_repaint(&mut self, node: DrmNode, crtc: crtc::Handle) {
+        if self.backend_data.rendering_paused {
+            return;
+        }
+        let now = self.clock.now();
+        if let Some(surface) = self
+            .backend_data
+            .backends
+            .get_mut(&node)
+            .and_then(|device| device.surfaces.get_mut(&crtc))
+        {
+            surface.scheduler.queue(now);
+        }
+    }
+
+    /// Called by the timer of a surface's [`FrameScheduler`]
+    fn repaint_due(&mut self, node: DrmNode, crtc: crtc::Handle, frame_target: Time<Monotonic>) {
+        let Some(surface) = self
+            .backend_data
+            .backends
+            .get_mut(&node)
+            .and_then(|device| device.surfaces.get_mut(&crtc))
+        else {
+            error!("Trying to render on non-existent crtc {:?}", crtc);
+            return;
         };
+        // the timer drops itself
+        surface.scheduler.state = RepaintState::Idle;
+        if self.backend_data.rendering_paused {
+            return;
+        }
+        self.render_surface(node, crtc, frame_target);
     }
 
     fn render_surface(&mut self, node: DrmNode, crtc: crtc::Handle, frame_target: Time<Monotonic>) {
@@ -1278,6 +3163,7 @@ impl LuxoState<UdevData> {
             return;
         };
 
+        self.update_vrr(&output);
         self.pre_repaint(&output, frame_target);
 
         let device = if let Some(device) = self.backend_data.backends.get_mut(&node) {
@@ -1294,23 +3180,37 @@ impl LuxoState<UdevData> {
 
         let start = Instant::now();
 
-        // TODO get scale from the rendersurface when supporting HiDPI
-        let frame = self
+        let cursor = self
             .backend_data
             .pointer_image
-            .get_image(1 /*scale*/, self.clock.now().into());
+            .get_or_insert_with(|| crate::cursor::Cursor::load(&self.config.cursor));
+        let icon = match &self.cursor_status {
+            CursorImageStatus::Named(icon) => *icon,
+            _ => CursorIcon::Default,
+        };
+        let cursor_scale = output.current_scale().fractional_scale().ceil() as u32;
+        let frame = cursor.get_image(icon, cursor_scale, self.clock.now().into());
+        let buffer_scale = cursor.buffer_scale(&frame);
+        let pointer_hotspot = Point::from((frame.xhot as i32, frame.yhot as i32)) / buffer_scale;
 
         let render_node = surface.render_node;
-        let primary_gpu = self.backend_data.primary_gpu;
-        let mut renderer = if primary_gpu == render_node {
+        let render_gpu = surface.render_gpu;
+        let renderer = if render_gpu == render_node {
             self.backend_data.gpus.single_renderer(&render_node)
         } else {
             let format = surface.drm_output.format();
-            self.backend_data
-                .gpus
-                .renderer(&primary_gpu, &render_node, format)
-        }
-        .unwrap();
+            self.backend_data.gpus.renderer(&render_gpu, &render_node, format)
+        };
+        let mut renderer = match renderer {
+            Ok(renderer) => renderer,
+            Err(err) => {
+                warn!(
+                    output = output.name(),
+                    "Skipping a frame without a renderer on {}: {}", render_gpu, err
+                );
+                return;
+            }
+        };
 
         let pointer_images = &mut self.backend_data.pointer_images;
         let pointer_image = pointer_images
@@ -1327,7 +3227,7 @@ impl LuxoState<UdevData> {
                     &frame.pixels_rgba,
                     Fourcc::Argb8888,
                     (frame.width as i32, frame.height as i32),
-                    1,
+                    buffer_scale,
                     Transform::Normal,
                     None,
                 );
@@ -1335,6 +3235,16 @@ impl LuxoState<UdevData> {
                 buffer
             });
 
+        let (mut screencopies, pending_screencopies) = std::mem::take(&mut self.pending_screencopies)
+            .into_iter()
+            .partition(|screencopy| screencopy.output() == &output);
+        self.pending_screencopies = pending_screencopies;
+        let (dmabuf_exports, pending_dmabuf_exports) =
+            std::mem::take(&mut self.backend_data.pending_dmabuf_exports)
+                .into_iter()
+                .partition(|export| export.output() == &output);
+        self.backend_data.pending_dmabuf_exports = pending_dmabuf_exports;
+
         let result = render_surface(
             surface,
             &mut renderer,
@@ -1342,11 +3252,22 @@ impl LuxoState<UdevData> {
             &output,
             self.pointer.current_location(),
             &pointer_image,
+            pointer_hotspot,
             &mut self.backend_data.pointer_element,
             &self.dnd_icon,
             &mut self.cursor_status,
             self.show_window_preview,
+            self.config.appearance.background_color(),
+            self.debug_overlay,
+            self.config.outputs.overlay_planes,
+            self.config.cursor.software(),
+            &mut screencopies,
+            dmabuf_exports,
+            self.clock.now().into(),
         );
+        // copies waiting for damage are retried on the next frame
+        self.pending_screencopies.extend(screencopies);
+        let submitted = matches!(result, Ok((true, _)));
         let reschedule = match result {
             Ok((has_rendered, states)) => {
                 let dmabuf_feedback = surface.dmabuf_feedback.clone();
@@ -1376,7 +3297,14 @@ impl LuxoState<UdevData> {
                                 .expect("failed to reset drm device");
                             true
                         }
-                        _ => panic!("Rendering loop lost: {}", err),
+                        _ => {
+                            error!("Rendering loop lost on {}: {}", node, err);
+                            // the context of either gpu might be gone
+                            for lost in [render_node, render_gpu] {
+                                gpu_lost(&self.handle, &mut self.backend_data.lost_gpus, lost);
+                            }
+                            false
+                        }
                     },
                 }
             }
@@ -1399,16 +3327,30 @@ impl LuxoState<UdevData> {
                 reschedule_timeout,
                 crtc,
             );
-            let timer = Timer::from_duration(reschedule_timeout);
-            self.handle
-                .insert_source(timer, move |_, _, data| {
-                    data.render(node, Some(crtc), next_frame_target);
-                    TimeoutAction::Drop
-                })
-                .expect("failed to schedule frame timer");
+            if let Some(surface) = self
+                .backend_data
+                .backends
+                .get_mut(&node)
+                .and_then(|device| device.surfaces.get_mut(&crtc))
+            {
+                let timer = Timer::from_duration(reschedule_timeout);
+                surface.scheduler.estimated_vblank(timer, next_frame_target);
+            }
         } else {
             let elapsed = start.elapsed();
             tracing::trace!(?elapsed, "rendered surface");
+            if let Some(surface) = self
+                .backend_data
+                .backends
+                .get_mut(&node)
+                .and_then(|device| device.surfaces.get_mut(&crtc))
+            {
+                surface.repaint_delay.record(elapsed);
+                if submitted {
+                    surface.repaint_delay.submitted(frame_target);
+                    surface.scheduler.frame_submitted();
+                }
+            }
         }
 
         profiling::finish_frame!();
@@ -1424,10 +3366,18 @@ fn render_surface<'a>(
     output: &Output,
     pointer_location: Point<f64, Logical>,
     pointer_image: &MemoryRenderBuffer,
+    pointer_hotspot: Point<i32, Logical>,
     pointer_element: &mut PointerElement,
     dnd_icon: &Option<DndIcon>,
     cursor_status: &mut CursorImageStatus,
     show_window_preview: bool,
+    clear_color: Color32F,
+    debug_overlay: DebugOverlayConfig,
+    overlay_planes: OverlayPlanesConfig,
+    software_cursor: bool,
+    screencopies: &mut Vec<Screencopy>,
+    dmabuf_exports: Vec<DmabufExport>,
+    timestamp: Duration,
 ) -> Result<(bool, RenderElementStates), SwapBuffersError> {
     let output_geometry = space.output_geometry(output).unwrap();
     let scale = Scale::from(output.current_scale().fractional_scale());
@@ -1435,6 +3385,7 @@ fn render_surface<'a>(
     let mut custom_elements: Vec<CustomRenderElements<_>> = Vec::new();
 
     if output_geometry.to_f64().contains(pointer_location) {
+        profiling::scope!("cursor_elements");
         let cursor_hotspot = if let CursorImageStatus::Surface(ref surface) = cursor_status {
             compositor::with_states(surface, |states| {
                 states
@@ -1446,7 +3397,7 @@ fn render_surface<'a>(
                     .hotspot
             })
         } else {
-            (0, 0).into()
+            pointer_hotspot
         };
         let cursor_pos = pointer_location - output_geometry.loc.to_f64();
 
@@ -1501,26 +3452,205 @@ fn render_surface<'a>(
     if let Some(element) = surface.fps_element.as_mut() {
         element.update_fps(surface.fps.avg().round() as u32);
         surface.fps.tick();
-        custom_elements.push(CustomRenderElements::Fps(element.clone()));
+        if debug_overlay.fps {
+            custom_elements.push(CustomRenderElements::Fps(element.clone()));
+        }
+    }
+
+    if debug_overlay.repaint {
+        if let Some(mode) = output.current_mode() {
+            let frame_duration = Duration::from_secs_f64(1_000f64 / mode.refresh as f64);
+            let width = output_geometry.size.to_physical_precise_round(scale).w;
+            let bars = repaint_bars(
+                width,
+                frame_duration,
+                surface.repaint_delay.current,
+                surface.repaint_delay.slowest(),
+            );
+            custom_elements.extend(bars.map(CustomRenderElements::RepaintStats));
+        }
+    }
+
+    let (mut elements, clear_color) = output_elements(
+        output,
+        space,
+        custom_elements,
+        renderer,
+        show_window_preview,
+        clear_color,
+    );
+
+    if debug_overlay.damage {
+        profiling::scope!("damage_overlay");
+        let overlay = surface
+            .damage_overlay
+            .get_or_insert_with(|| DamageOverlay::new(output));
+        let mut highlights = overlay.update::<UdevRenderer<'a>, _>(&elements);
+        let frame_pixels = output
+            .current_mode()
+            .map_or(0, |mode| mode.size.w as u64 * mode.size.h as u64);
+        if let Some(pixels) = overlay.frame_damage() {
+            surface.frame_stats.damaged_pixels += pixels;
+            surface.frame_stats.frame_pixels += frame_pixels;
+        }
+        let width = output_geometry.size.to_physical_precise_round(scale).w;
+        highlights.push(overlay.damage_bar(width, frame_pixels));
+        elements.splice(
+            0..0,
+            highlights
+                .into_iter()
+                .map(|highlight| OutputRenderElements::Custom(CustomRenderElements::Damage(highlight)))
+                .map(MagnifiedRenderElements::Output),
+        );
+    } else {
+        surface.damage_overlay = None;
     }
 
-    let (elements, clear_color) =
-        output_elements(output, space, custom_elements, renderer, show_window_preview);
+    // an output with a render scale is rendered into an intermediate target first, which is then
+    // stretched over the mode
+    let scaled_frame = if surface.render_scale != 1.0 && surface.render_gpu == surface.render_node {
+        profiling::scope!("scaled_frame");
+        match render_scaled_frame(surface, renderer, output, &elements, clear_color) {
+            Ok(scaled_frame) => Some(scaled_frame),
+            Err(err) => {
+                warn!(
+                    output = output.name(),
+                    "Failed to render at the render scale, using the mode: {}", err
+                );
+                surface.render_scale = 1.0;
+                surface.scaled_frame = None;
+                None
+            }
+        }
+    } else {
+        surface.scaled_frame = None;
+        None
+    };
 
-    let frame_mode = if surface.disable_direct_scanout {
+    let mut frame_mode = if surface.disable_direct_scanout {
         FrameFlags::empty()
+    } else if space
+        .elements_for_output(output)
+        .any(|window| !window.allows_direct_scanout())
+    {
+        // a window opted out through a window rule, only leave the cursor on its own plane
+        FrameFlags::ALLOW_CURSOR_PLANE_SCANOUT
     } else {
         FrameFlags::DEFAULT
     };
-    let (rendered, states) = surface
+    // elements failing the test commit for an overlay plane, e.g. because the plane can't scale
+    // them, are composited instead
+    let use_overlays = match overlay_planes {
+        OverlayPlanesConfig::Off => false,
+        OverlayPlanesConfig::Video => space
+            .elements_for_output(output)
+            .any(|window| window.shows_video()),
+        OverlayPlanesConfig::All => true,
+    };
+    if !use_overlays {
+        frame_mode.remove(FrameFlags::ALLOW_OVERLAY_PLANE_SCANOUT);
+    }
+    // otherwise the pointer is put on the cursor plane whenever the test commit for it succeeds,
+    // and composited if it fails
+    if software_cursor {
+        frame_mode.remove(FrameFlags::ALLOW_CURSOR_PLANE_SCANOUT);
+    }
+    if debug_overlay.full_redraw {
+        // buffers of unknown age get repainted completely
+        surface.drm_output.reset_buffers();
+    }
+    let (rendered, scanned_out, cursor_plane, mut states) = match &scaled_frame {
+        Some((element, _)) => render_frame(surface, renderer, std::slice::from_ref(element), clear_color, frame_mode)?,
+        None => render_frame(surface, renderer, &elements, clear_color, frame_mode)?,
+    };
+    if let Some((_, scaled_states)) = scaled_frame {
+        // only the intermediate frame knows which surfaces ended up visible
+        states = scaled_states;
+    }
+
+    update_primary_scanout_output(space, output, dnd_icon, cursor_status, &states);
+
+    *screencopies = render_screencopies::<_, GlesRenderbuffer, _>(
+        renderer,
+        output,
+        std::mem::take(screencopies),
+        &elements,
+        clear_color,
+        timestamp,
+    );
+
+    if !dmabuf_exports.is_empty() {
+        profiling::scope!("dmabuf_export");
+        for export in dmabuf_exports {
+            // without a free buffer the capture is cancelled and the client retries
+            let Some(buffer) = export_buffer(surface, output) else {
+                continue;
+            };
+            let frame = export.frame().clone();
+            let dmabuf = &mut buffer.dmabuf;
+            if render_dmabuf_export(renderer, export, dmabuf, &elements, clear_color, timestamp) {
+                buffer.frame = Some(frame);
+            }
+        }
+    }
+
+    if rendered {
+        profiling::scope!("queue_frame");
+        let output_presentation_feedback = take_presentation_feedback(output, space, &states);
+        surface
+            .drm_output
+            .queue_frame(Some(output_presentation_feedback))
+            .map_err(Into::<SwapBuffersError>::into)?;
+
+        if scanned_out {
+            surface.frame_stats.scanned_out += 1;
+        } else {
+            surface.frame_stats.composited += 1;
+        }
+        if cursor_plane {
+            surface.frame_stats.cursor_plane += 1;
+        }
+        if scanned_out != surface.scanned_out {
+            debug!(output = output.name(), scanned_out, "Direct scanout changed");
+            surface.scanned_out = scanned_out;
+        }
+    }
+
+    Ok((rendered, states))
+}
+
+/// Render `elements` on the drm output of `surface`, returning whether anything was rendered,
+/// whether the frame was scanned out directly and whether the cursor plane was used
+fn render_frame<'a, E>(
+    surface: &mut SurfaceData,
+    renderer: &mut UdevRenderer<'a>,
+    elements: &[E],
+    clear_color: Color32F,
+    frame_mode: FrameFlags,
+) -> Result<(bool, bool, bool, RenderElementStates), SwapBuffersError>
+where
+    E: RenderElement<UdevRenderer<'a>>,
+{
+    profiling::scope!("render_frame");
+    surface
         .drm_output
-        .render_frame(renderer, &elements, clear_color, frame_mode)
+        .render_frame(renderer, elements, clear_color, frame_mode)
         .map(|render_frame_result| {
+            let scanned_out = matches!(
+                render_frame_result.primary_element,
+                PrimaryPlaneElement::Element(_)
+            );
+            let cursor_plane = render_frame_result.cursor_element.is_some();
             #[cfg(feature = "renderer_sync")]
             if let PrimaryPlaneElement::Swapchain(element) = render_frame_result.primary_element {
                 element.sync.wait();
             }
-            (!render_frame_result.is_empty, render_frame_result.states)
+            (
+                !render_frame_result.is_empty,
+                scanned_out,
+                cursor_plane,
+                render_frame_result.states,
+            )
         })
         .map_err(|err| match err {
             smithay::backend::drm::compositor::RenderFrameError::PrepareFrame(err) => {
@@ -1530,17 +3660,103 @@ fn render_surface<'a>(
                 OutputDamageTrackerError::Rendering(err),
             ) => SwapBuffersError::from(err),
             _ => unreachable!(),
-        })?;
+        })
+}
 
-    update_primary_scanout_output(space, output, dnd_icon, cursor_status, &states);
+/// Render `elements` into the [`ScaledFrame`] of `surface` at its render scale, returning the
+/// element stretching it over the output and the states of the rendered elements
+fn render_scaled_frame<'a, E>(
+    surface: &mut SurfaceData,
+    renderer: &mut UdevRenderer<'a>,
+    output: &Output,
+    elements: &[E],
+    clear_color: Color32F,
+) -> Result<(ScaledFrameElement, RenderElementStates), String>
+where
+    E: RenderElement<UdevRenderer<'a>>,
+{
+    let mode = output.current_mode().ok_or("output has no mode")?;
+    // rendered upright, the output transform is applied when the frame gets stretched
+    let output_size = output.current_transform().transform_size(mode.size);
+    let size = output_size.to_f64().upscale(surface.render_scale).to_i32_round();
+    let output_scale = output.current_scale().fractional_scale();
+    if surface.scaled_frame.as_ref().is_some_and(|frame| {
+        frame.texture.size() != (size.w, size.h).into() || frame.output_scale != output_scale
+    }) {
+        surface.scaled_frame = None;
+    }
+    if surface.scaled_frame.is_none() {
+        let texture: GlesTexture = renderer
+            .create_buffer(Fourcc::Abgr8888, (size.w, size.h).into())
+            .map_err(|err| format!("{:?}", err))?;
+        surface.scaled_frame = Some(ScaledFrame {
+            texture,
+            damage_tracker: OutputDamageTracker::new(size, output_scale, Transform::Normal),
+            output_scale,
+            id: Id::new(),
+            commit: CommitCounter::default(),
+        });
+    }
+    let render_scale = surface.render_scale;
+    let frame = surface.scaled_frame.as_mut().unwrap();
 
-    if rendered {
-        let output_presentation_feedback = take_presentation_feedback(output, space, &states);
-        surface
-            .drm_output
-            .queue_frame(Some(output_presentation_feedback))
-            .map_err(Into::<SwapBuffersError>::into)?;
+    let elements = elements
+        .iter()
+        .map(|element| RescaleRenderElement::from_element(element, Point::default(), render_scale))
+        .collect::<Vec<_>>();
+    let mut framebuffer = renderer
+        .bind(&mut frame.texture)
+        .map_err(|err| format!("{:?}", err))?;
+    // the texture still holds the previous frame
+    let result = frame
+        .damage_tracker
+        .render_output(renderer, &mut framebuffer, 1, &elements, clear_color)
+        .map_err(|err| format!("{:?}", err))?;
+    if result.damage.is_some_and(|damage| !damage.is_empty()) {
+        frame.commit.increment();
     }
+    let states = result.states;
+    drop(framebuffer);
 
-    Ok((rendered, states))
+    Ok((frame.element(output_size), states))
+}
+
+/// A free buffer of the size of `output` to render wlr-export-dmabuf captures into, allocated if
+/// there are less than [`MAX_EXPORT_BUFFERS`]. `None` if all of them are still handed out.
+fn export_buffer<'a>(surface: &'a mut SurfaceData, output: &Output) -> Option<&'a mut ExportBuffer> {
+    let size = output.current_mode()?.size;
+    // the client keeps its own handles to buffers dropped while handed out
+    let buffers = &mut surface.export_buffers;
+    buffers.retain(|buffer| buffer.dmabuf.size() == (size.w, size.h).into());
+    for buffer in buffers.iter_mut() {
+        buffer.frame.take_if(|frame| !frame.alive());
+    }
+
+    if let Some(index) = buffers.iter().position(|buffer| buffer.frame.is_none()) {
+        return Some(&mut buffers[index]);
+    }
+    if buffers.len() >= MAX_EXPORT_BUFFERS {
+        debug!(output = output.name(), "All dmabuf export buffers are still in use");
+        return None;
+    }
+    let dmabuf = surface
+        .export_allocator
+        .create_buffer(
+            size.w as u32,
+            size.h as u32,
+            Fourcc::Argb8888,
+            &[Modifier::Invalid],
+        )
+        .map_err(|err| format!("{:?}", err))
+        .and_then(|buffer| buffer.export().map_err(|err| format!("{:?}", err)));
+    match dmabuf {
+        Ok(dmabuf) => {
+            buffers.push(ExportBuffer { dmabuf, frame: None });
+            buffers.last_mut()
+        }
+        Err(err) => {
+            warn!("Failed to allocate buffer for dmabuf export: {}", err);
+            None
+        }
+    }
 }
@@ -18,7 +18,7 @@ use smithay::{
         renderer::{
             damage::{Error as OutputDamageTrackerError, OutputDamageTracker},
             element::AsRenderElements,
-            gles::GlesRenderer,
+            gles::{GlesRenderbuffer, GlesRenderer},
             ImportDma, ImportMemWl,
         },
         winit::{self, WinitEvent, WinitGraphicsBackend},
@@ -48,7 +48,7 @@ use smithay::{
 use tracing::{error, info, warn};
 
 use crate::state::{take_presentation_feedback, LuxoState, Backend};
-use crate::{drawing::*, render::*};
+use crate::{cli::Options, drawing::*, render::*};
 
 pub const OUTPUT_NAME: &str = "winit";
 
@@ -93,7 +93,7 @@ impl Backend for WinitData {
     fn update_led_state(&mut self, _led_state: LedState) {}
 }
 
-pub fn run_winit() {
+pub fn run_winit(options: &Options) {
     let mut event_loop = EventLoop::try_new().unwrap();
     let display = Display::new().unwrap();
     let mut display_handle = display.handle();
@@ -199,7 +199,7 @@ pub fn run_winit() {
             fps: fps_ticker::Fps::default(),
         }
     };
-    let mut state = LuxoState::init(display, event_loop.handle(), data, true);
+    let mut state = LuxoState::init(display, event_loop.handle(), data, true, options);
     state
         .shm_state
         .update_formats(state.backend_data.backend.renderer().shm_formats());
@@ -208,6 +208,11 @@ pub fn run_winit() {
     #[cfg(feature = "xwayland")]
     state.start_xwayland();
 
+    if let Some(command) = options.command.as_deref() {
+        state.spawn(command);
+    }
+    state.start_session();
+
     info!("Initialization completed, starting the main loop.");
 
     let mut pointer_element = PointerElement::default();
@@ -224,7 +229,7 @@ pub fn run_winit() {
                 };
                 output.change_current_state(Some(mode), None, None, None);
                 output.set_preferred(mode);
-                crate::shell::fixup_positions(&mut state.space, state.pointer.current_location());
+                state.fixup_positions();
             }
             WinitEvent::Input(event) => state.process_input_event_windowed(event, OUTPUT_NAME),
             _ => (),
@@ -270,6 +275,7 @@ pub fn run_winit() {
             let space = &mut state.space;
             let damage_tracker = &mut state.backend_data.damage_tracker;
             let show_window_preview = state.show_window_preview;
+            let clear_color = state.config.appearance.background_color();
 
             let dnd_icon = state.dnd_icon.as_ref();
 
@@ -356,6 +362,7 @@ pub fn run_winit() {
                     damage_tracker,
                     age,
                     show_window_preview,
+                    clear_color,
                 )
                 .map_err(|err| match err {
                     OutputDamageTrackerError::Rendering(err) => err.into(),
@@ -413,6 +420,16 @@ pub fn run_winit() {
 
                     // Send frame events so that client start drawing their next frame
                     state.post_repaint(&output, frame_target, None, &states);
+
+                    render_pending_screencopies::<_, GlesRenderbuffer>(
+                        state.backend_data.backend.renderer(),
+                        &output,
+                        &state.space,
+                        &mut state.pending_screencopies,
+                        state.show_window_preview,
+                        state.config.appearance.background_color(),
+                        state.clock.now().into(),
+                    );
                 }
                 Err(SwapBuffersError::ContextLost(err)) => {
                     #[cfg(feature = "debug")]
@@ -444,12 +461,20 @@ pub fn run_winit() {
         if result.is_err() {
             state.running.store(false, Ordering::SeqCst);
         } else {
-            state.space.refresh();
+            state.refresh_space();
+            state.update_scratchpad();
+            state.restack_windows();
+            state.update_magnifier();
+            state.update_pings();
             state.popups.cleanup();
+            state.update_foreign_toplevels();
+            state.notify_ipc_subscribers();
             display_handle.flush_clients().unwrap();
         }
 
         #[cfg(feature = "debug")]
         state.backend_data.fps.tick();
     }
+
+    state.shutdown();
 }
@@ -4,6 +4,7 @@ use std::{
 };
 
 use crate::{
+    cli::Options,
     drawing::*,
     render::*,
     state::{take_presentation_feedback, LuxoState, Backend},
@@ -22,8 +23,10 @@ use smithay::{
         },
         egl::{EGLContext, EGLDisplay},
         renderer::{
-            damage::OutputDamageTracker, element::AsRenderElements, gles::GlesRenderer, Bind, ImportDma,
-            ImportMemWl,
+            damage::OutputDamageTracker,
+            element::AsRenderElements,
+            gles::{GlesRenderbuffer, GlesRenderer},
+            Bind, ImportDma, ImportMemWl,
         },
         vulkan::{version::Version, Instance, PhysicalDevice},
         x11::{WindowBuilder, X11Backend, X11Event, X11Surface},
@@ -96,7 +99,7 @@ impl Backend for X11Data {
     fn update_led_state(&mut self, _led_state: LedState) {}
 }
 
-pub fn run_x11() {
+pub fn run_x11(options: &Options) {
     let mut event_loop = EventLoop::try_new().unwrap();
     let display = Display::new().unwrap();
     let mut display_handle = display.handle();
@@ -249,7 +252,7 @@ pub fn run_x11() {
         fps: fps_ticker::Fps::default(),
     };
 
-    let mut state = LuxoState::init(display, event_loop.handle(), data, true);
+    let mut state = LuxoState::init(display, event_loop.handle(), data, true, options);
     state
         .shm_state
         .update_formats(state.backend_data.renderer.shm_formats());
@@ -273,7 +276,7 @@ pub fn run_x11() {
                 output.delete_mode(output.current_mode().unwrap());
                 output.change_current_state(Some(data.backend_data.mode), None, None, None);
                 output.set_preferred(data.backend_data.mode);
-                crate::shell::fixup_positions(&mut data.space, data.pointer.current_location());
+                data.fixup_positions();
 
                 data.backend_data.render = true;
             }
@@ -291,6 +294,11 @@ pub fn run_x11() {
     #[cfg(feature = "xwayland")]
     state.start_xwayland();
 
+    if let Some(command) = options.command.as_deref() {
+        state.spawn(command);
+    }
+    state.start_session();
+
     info!("Initialization completed, starting the main loop.");
 
     let mut pointer_element = PointerElement::default();
@@ -401,6 +409,7 @@ pub fn run_x11() {
                 &mut backend_data.damage_tracker,
                 age.into(),
                 state.show_window_preview,
+                state.config.appearance.background_color(),
             );
 
             match render_res {
@@ -452,6 +461,16 @@ pub fn run_x11() {
 
                     // Send frame events so that client start drawing their next frame
                     state.post_repaint(&output, frame_target, None, &states);
+
+                    render_pending_screencopies::<_, GlesRenderbuffer>(
+                        &mut state.backend_data.renderer,
+                        &output,
+                        &state.space,
+                        &mut state.pending_screencopies,
+                        state.show_window_preview,
+                        state.config.appearance.background_color(),
+                        state.clock.now().into(),
+                    );
                 }
                 Err(err) => {
                     #[cfg(feature = "debug")]
@@ -478,9 +497,17 @@ pub fn run_x11() {
         if result.is_err() {
             state.running.store(false, Ordering::SeqCst);
         } else {
-            state.space.refresh();
+            state.refresh_space();
+            state.update_scratchpad();
+            state.restack_windows();
+            state.update_magnifier();
+            state.update_pings();
             state.popups.cleanup();
+            state.update_foreign_toplevels();
+            state.notify_ipc_subscribers();
             display_handle.flush_clients().unwrap();
         }
     }
+
+    state.shutdown();
 }
@@ -0,0 +1,305 @@
+//! Minimal wayland client mapping xdg toplevels with shm buffers
+
+use std::{
+    fs::File,
+    io::Write,
+    os::{fd::AsFd, unix::net::UnixStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use wayland_client::{
+    delegate_noop,
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_compositor::WlCompositor,
+        wl_registry::{self, WlRegistry},
+        wl_seat::WlSeat,
+        wl_shm::{self, WlShm},
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, EventQueue, QueueHandle,
+};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::{self, XdgSurface},
+    xdg_toplevel::{self, XdgToplevel},
+    xdg_wm_base::{self, XdgWmBase},
+};
+
+use super::{Compositor, TIMEOUT};
+
+/// Size of the buffer attached when the compositor leaves the size to the client
+pub const DEFAULT_SIZE: (i32, i32) = (200, 150);
+
+/// Last configure of the toplevel, acked and answered with a buffer of its size
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Configure {
+    /// Size the compositor asked for, zero if left to the client
+    pub size: (i32, i32),
+    pub states: Vec<xdg_toplevel::State>,
+    /// Size of the buffer attached in response
+    pub buffer_size: (i32, i32),
+}
+
+impl Configure {
+    pub fn has_state(&self, state: xdg_toplevel::State) -> bool {
+        self.states.contains(&state)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ClientState {
+    compositor: Option<WlCompositor>,
+    shm: Option<WlShm>,
+    wm_base: Option<XdgWmBase>,
+    seat: Option<WlSeat>,
+    surface: Option<WlSurface>,
+    xdg_surface: Option<XdgSurface>,
+    toplevel: Option<XdgToplevel>,
+    buffer: Option<WlBuffer>,
+    /// Buffer size used regardless of the configured size
+    fixed_size: Option<(i32, i32)>,
+    /// Toplevel configure waiting for its xdg_surface configure
+    pending: Configure,
+    /// Every configure received, in order
+    pub configures: Vec<Configure>,
+    pub closed: bool,
+}
+
+pub struct TestClient {
+    connection: Connection,
+    queue: EventQueue<ClientState>,
+    pub state: ClientState,
+}
+
+impl TestClient {
+    pub fn connect(compositor: &Compositor) -> Self {
+        let stream = UnixStream::connect(compositor.wayland_socket()).expect("failed to connect to luxo");
+        let connection = Connection::from_socket(stream).unwrap();
+        let mut queue = connection.new_event_queue();
+        connection.display().get_registry(&queue.handle(), ());
+
+        let mut state = ClientState::default();
+        queue.roundtrip(&mut state).unwrap();
+        assert!(state.compositor.is_some(), "wl_compositor is missing");
+        assert!(state.shm.is_some(), "wl_shm is missing");
+        assert!(state.wm_base.is_some(), "xdg_wm_base is missing");
+
+        TestClient {
+            connection,
+            queue,
+            state,
+        }
+    }
+
+    pub fn roundtrip(&mut self) {
+        self.queue.roundtrip(&mut self.state).unwrap();
+    }
+
+    /// Create a toplevel and wait until it got its first buffer
+    pub fn map_toplevel(&mut self, title: &str, app_id: &str) {
+        let qh = self.queue.handle();
+        let state = &mut self.state;
+        let surface = state.compositor.as_ref().unwrap().create_surface(&qh, ());
+        let xdg_surface = state.wm_base.as_ref().unwrap().get_xdg_surface(&surface, &qh, ());
+        let toplevel = xdg_surface.get_toplevel(&qh, ());
+        toplevel.set_title(title.to_owned());
+        toplevel.set_app_id(app_id.to_owned());
+        surface.commit();
+
+        state.surface = Some(surface);
+        state.xdg_surface = Some(xdg_surface);
+        state.toplevel = Some(toplevel);
+        self.wait_for_configure("the toplevel is mapped", |_| true);
+    }
+
+    /// Always attach buffers of `size`, ignoring the size the compositor asks for
+    pub fn set_fixed_size(&mut self, size: (i32, i32)) {
+        self.state.fixed_size = Some(size);
+    }
+
+    pub fn toplevel(&self) -> &XdgToplevel {
+        self.state.toplevel.as_ref().expect("no toplevel mapped")
+    }
+
+    pub fn seat(&self) -> Option<&WlSeat> {
+        self.state.seat.as_ref()
+    }
+
+    pub fn last_configure(&self) -> Option<&Configure> {
+        self.state.configures.last()
+    }
+
+    /// Dispatch events until a new configure matches `check`, panicking after [`TIMEOUT`]
+    pub fn wait_for_configure(&mut self, what: &str, check: impl Fn(&Configure) -> bool) -> Configure {
+        let seen = self.state.configures.len();
+        let start = Instant::now();
+        loop {
+            self.roundtrip();
+            if let Some(configure) = self.state.configures[seen..].iter().find(|c| check(c)) {
+                return configure.clone();
+            }
+            assert!(start.elapsed() < TIMEOUT, "timed out waiting until {}", what);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Destroy the toplevel and its surfaces
+    pub fn unmap(&mut self) {
+        let state = &mut self.state;
+        if let Some(toplevel) = state.toplevel.take() {
+            toplevel.destroy();
+        }
+        if let Some(xdg_surface) = state.xdg_surface.take() {
+            xdg_surface.destroy();
+        }
+        if let Some(surface) = state.surface.take() {
+            surface.destroy();
+        }
+        self.connection.flush().unwrap();
+    }
+}
+
+impl ClientState {
+    /// Attach a freshly filled buffer of `size` to the surface and commit it
+    fn commit_buffer(&mut self, size: (i32, i32), qh: &QueueHandle<Self>) {
+        let (width, height) = size;
+        let stride = width * 4;
+        let len = (stride * height) as usize;
+
+        let mut file = tempfile();
+        file.write_all(&vec![0x80; len]).unwrap();
+        let shm = self.shm.as_ref().unwrap();
+        let pool = shm.create_pool(file.as_fd(), len as i32, qh, ());
+        let buffer = pool.create_buffer(0, width, height, stride, wl_shm::Format::Argb8888, qh, ());
+        pool.destroy();
+
+        let surface = self.surface.as_ref().unwrap();
+        surface.attach(Some(&buffer), 0, 0);
+        surface.damage_buffer(0, 0, width, height);
+        surface.commit();
+        if let Some(old) = self.buffer.replace(buffer) {
+            old.destroy();
+        }
+    }
+}
+
+/// Unlinked file to back an shm pool
+fn tempfile() -> File {
+    let path = std::env::temp_dir().join(format!(
+        "luxo-test-buffer-{}-{:?}",
+        std::process::id(),
+        thread::current().id()
+    ));
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    file
+}
+
+impl Dispatch<WlRegistry, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        registry: &WlRegistry,
+        event: wl_registry::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global {
+            name,
+            interface,
+            version,
+        } = event
+        {
+            match interface.as_str() {
+                "wl_compositor" => state.compositor = Some(registry.bind(name, version.min(4), qh, ())),
+                "wl_shm" => state.shm = Some(registry.bind(name, 1, qh, ())),
+                "xdg_wm_base" => state.wm_base = Some(registry.bind(name, version.min(5), qh, ())),
+                "wl_seat" if state.seat.is_none() => state.seat = Some(registry.bind(name, 1, qh, ())),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Dispatch<XdgWmBase, ()> for ClientState {
+    fn event(
+        _state: &mut Self,
+        wm_base: &XdgWmBase,
+        event: xdg_wm_base::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let xdg_wm_base::Event::Ping { serial } = event {
+            wm_base.pong(serial);
+        }
+    }
+}
+
+impl Dispatch<XdgSurface, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        xdg_surface: &XdgSurface,
+        event: xdg_surface::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let xdg_surface::Event::Configure { serial } = event {
+            xdg_surface.ack_configure(serial);
+            let mut configure = state.pending.clone();
+            configure.buffer_size = match (state.fixed_size, configure.size) {
+                (Some(size), _) => size,
+                (None, (0, _) | (_, 0)) => DEFAULT_SIZE,
+                (None, size) => size,
+            };
+            state.commit_buffer(configure.buffer_size, qh);
+            state.configures.push(configure);
+        }
+    }
+}
+
+impl Dispatch<XdgToplevel, ()> for ClientState {
+    fn event(
+        state: &mut Self,
+        _toplevel: &XdgToplevel,
+        event: xdg_toplevel::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            xdg_toplevel::Event::Configure {
+                width,
+                height,
+                states,
+            } => {
+                state.pending.size = (width, height);
+                state.pending.states = states
+                    .chunks_exact(4)
+                    .filter_map(|chunk| {
+                        let state = u32::from_ne_bytes(chunk.try_into().unwrap());
+                        xdg_toplevel::State::try_from(state).ok()
+                    })
+                    .collect();
+            }
+            xdg_toplevel::Event::Close => state.closed = true,
+            _ => {}
+        }
+    }
+}
+
+delegate_noop!(ClientState: ignore WlCompositor);
+delegate_noop!(ClientState: ignore WlShm);
+delegate_noop!(ClientState: ignore WlShmPool);
+delegate_noop!(ClientState: ignore WlBuffer);
+delegate_noop!(ClientState: ignore WlSurface);
+delegate_noop!(ClientState: ignore WlSeat);
@@ -0,0 +1,223 @@
+//! Harness running luxo on the headless backend
+//!
+//! Every [`Compositor`] gets its own runtime directory, wayland socket and config file, so tests
+//! can run in parallel. The compositor is killed once it is dropped. Set `LUXO_TEST_LOG` to see
+//! its log output.
+
+#![allow(dead_code)]
+
+pub mod client;
+pub mod snapshot;
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::{fs::PermissionsExt, net::UnixStream},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use luxo::ipc::{socket_path_in, Event, EventType, OutputInfo, Request, Response, WindowInfo};
+
+/// How long to wait for the compositor to reach an expected state
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Size of the virtual output
+pub const OUTPUT_SIZE: (i32, i32) = (1280, 720);
+
+static NEXT_INSTANCE: AtomicUsize = AtomicUsize::new(0);
+
+pub struct Compositor {
+    child: Child,
+    runtime_dir: PathBuf,
+    socket: String,
+}
+
+impl Compositor {
+    pub fn start() -> Self {
+        Self::with_config("")
+    }
+
+    /// Start the compositor with `config` as its config file
+    pub fn with_config(config: &str) -> Self {
+        let socket = format!(
+            "luxo-test-{}-{}",
+            std::process::id(),
+            NEXT_INSTANCE.fetch_add(1, Ordering::Relaxed)
+        );
+        let runtime_dir = std::env::temp_dir().join(&socket);
+        std::fs::create_dir_all(&runtime_dir).unwrap();
+        std::fs::set_permissions(&runtime_dir, std::fs::Permissions::from_mode(0o700)).unwrap();
+        let config_path = runtime_dir.join("config.toml");
+        std::fs::write(&config_path, config).unwrap();
+
+        let log = std::env::var_os("LUXO_TEST_LOG").is_some();
+        let output = || if log { Stdio::inherit() } else { Stdio::null() };
+        let child = Command::new(env!("CARGO_BIN_EXE_luxo"))
+            .arg("--headless")
+            .arg(format!("--output-size={}x{}", OUTPUT_SIZE.0, OUTPUT_SIZE.1))
+            .arg("--socket")
+            .arg(&socket)
+            .arg("--config")
+            .arg(&config_path)
+            .env("XDG_RUNTIME_DIR", &runtime_dir)
+            .env("XDG_STATE_HOME", &runtime_dir)
+            .env_remove("WAYLAND_DISPLAY")
+            .env_remove("WAYLAND_SOCKET")
+            .env_remove("DISPLAY")
+            .stdout(output())
+            .stderr(output())
+            .spawn()
+            .expect("failed to start luxo");
+
+        let mut compositor = Compositor {
+            child,
+            runtime_dir,
+            socket,
+        };
+        compositor.wait_for_ipc();
+        compositor
+    }
+
+    fn wait_for_ipc(&mut self) {
+        let path = self.ipc_socket();
+        let start = Instant::now();
+        while UnixStream::connect(&path).is_err() {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                panic!("luxo exited with {} before it was ready", status);
+            }
+            assert!(
+                start.elapsed() < TIMEOUT,
+                "luxo did not open its ipc socket in time"
+            );
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    pub fn runtime_dir(&self) -> &Path {
+        &self.runtime_dir
+    }
+
+    /// Path of the wayland socket clients connect to
+    pub fn wayland_socket(&self) -> PathBuf {
+        self.runtime_dir.join(&self.socket)
+    }
+
+    fn ipc_socket(&self) -> PathBuf {
+        socket_path_in(&self.runtime_dir, &self.socket)
+    }
+
+    pub fn request(&self, request: &Request) -> Response {
+        let mut stream = UnixStream::connect(self.ipc_socket()).expect("failed to connect to ipc socket");
+        let mut line = serde_json::to_vec(request).unwrap();
+        line.push(b'\n');
+        stream.write_all(&line).unwrap();
+
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply).unwrap();
+        serde_json::from_str(&reply).unwrap_or_else(|err| panic!("invalid reply {:?}: {}", reply, err))
+    }
+
+    /// Subscribe to `events`, read them with [`Subscription::next_event`]
+    pub fn subscribe(&self, events: Vec<EventType>) -> Subscription {
+        let mut stream = UnixStream::connect(self.ipc_socket()).expect("failed to connect to ipc socket");
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        let mut line = serde_json::to_vec(&Request::Subscribe { events }).unwrap();
+        line.push(b'\n');
+        stream.write_all(&line).unwrap();
+
+        let mut subscription = Subscription(BufReader::new(stream));
+        let reply = subscription.read_line();
+        assert!(
+            matches!(serde_json::from_str(&reply), Ok(Response::Ok)),
+            "unexpected reply {:?}",
+            reply
+        );
+        subscription
+    }
+
+    pub fn windows(&self) -> Vec<WindowInfo> {
+        match self.request(&Request::Windows) {
+            Response::Windows { windows } => windows,
+            response => panic!("unexpected response {:?}", response),
+        }
+    }
+
+    pub fn outputs(&self) -> Vec<OutputInfo> {
+        match self.request(&Request::Outputs) {
+            Response::Outputs { outputs } => outputs,
+            response => panic!("unexpected response {:?}", response),
+        }
+    }
+
+    /// Render the headless output offscreen and read it back
+    pub fn capture(&self) -> image::RgbaImage {
+        let path = self.runtime_dir.join("capture.png");
+        let request = Request::CaptureOutput {
+            output: String::from("headless"),
+            path: path.clone(),
+        };
+        match self.request(&request) {
+            Response::Ok => image::open(&path).unwrap().to_rgba8(),
+            response => panic!("failed to capture the output: {:?}", response),
+        }
+    }
+
+    /// Capture the output once two captures in a row are the same, e.g. after animations ended
+    pub fn capture_stable(&self) -> image::RgbaImage {
+        let mut last = self.capture();
+        self.wait_until("the output stopped changing", |luxo| {
+            let capture = luxo.capture();
+            if capture == last {
+                return Some(capture);
+            }
+            last = capture;
+            None
+        })
+    }
+
+    /// Window with `title`, if it is mapped
+    pub fn window(&self, title: &str) -> Option<WindowInfo> {
+        self.windows()
+            .into_iter()
+            .find(|window| window.title.as_deref() == Some(title))
+    }
+
+    /// Poll the compositor until `check` returns something, panicking after [`TIMEOUT`]
+    pub fn wait_until<T>(&self, what: &str, mut check: impl FnMut(&Self) -> Option<T>) -> T {
+        let start = Instant::now();
+        loop {
+            if let Some(value) = check(self) {
+                return value;
+            }
+            assert!(start.elapsed() < TIMEOUT, "timed out waiting until {}", what);
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+pub struct Subscription(BufReader<UnixStream>);
+
+impl Subscription {
+    fn read_line(&mut self) -> String {
+        let mut line = String::new();
+        self.0.read_line(&mut line).expect("no ipc event in time");
+        line
+    }
+
+    /// Wait for the next event, panicking after [`TIMEOUT`]
+    pub fn next_event(&mut self) -> Event {
+        let line = self.read_line();
+        serde_json::from_str(&line).unwrap_or_else(|err| panic!("invalid event {:?}: {}", line, err))
+    }
+}
+
+impl Drop for Compositor {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.runtime_dir);
+    }
+}
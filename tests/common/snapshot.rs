@@ -0,0 +1,81 @@
+//! Comparison of captured frames against the reference images in `tests/snapshots`
+//!
+//! Set `LUXO_UPDATE_SNAPSHOTS` to record missing references or overwrite them after intended
+//! changes, then review and commit them. Without it a missing reference fails the test. On a mismatch
+//! the capture is saved next to the reference as `<name>.actual.png`.
+
+use std::path::PathBuf;
+
+use image::RgbaImage;
+
+/// Largest difference of a channel that still counts as the same color, rendering on different
+/// gpus or drivers isn't bit exact
+const CHANNEL_TOLERANCE: u8 = 3;
+/// Fraction of pixels allowed to differ by more than `CHANNEL_TOLERANCE`, for antialiasing
+const PIXEL_TOLERANCE: f64 = 0.001;
+
+fn snapshot_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("snapshots")
+}
+
+/// Compare `capture` against the reference image `name`, panicking if they differ
+pub fn assert_snapshot(name: &str, capture: &RgbaImage) {
+    let dir = snapshot_dir();
+    let reference_path = dir.join(format!("{}.png", name));
+    let actual_path = dir.join(format!("{}.actual.png", name));
+
+    if std::env::var_os("LUXO_UPDATE_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(&dir).unwrap();
+        capture.save(&reference_path).unwrap();
+        let _ = std::fs::remove_file(&actual_path);
+        eprintln!("recorded snapshot {}", reference_path.display());
+        return;
+    }
+    if !reference_path.exists() {
+        capture.save(&actual_path).unwrap();
+        panic!(
+            "snapshot {} has no reference, record it with LUXO_UPDATE_SNAPSHOTS=1, see {}",
+            name,
+            actual_path.display()
+        );
+    }
+
+    let reference = image::open(&reference_path).unwrap().to_rgba8();
+    if let Err(mismatch) = compare(&reference, capture) {
+        capture.save(&actual_path).unwrap();
+        panic!(
+            "snapshot {} does not match: {}, see {}",
+            name,
+            mismatch,
+            actual_path.display()
+        );
+    }
+    let _ = std::fs::remove_file(&actual_path);
+}
+
+fn compare(reference: &RgbaImage, capture: &RgbaImage) -> Result<(), String> {
+    if reference.dimensions() != capture.dimensions() {
+        return Err(format!(
+            "size is {:?} instead of {:?}",
+            capture.dimensions(),
+            reference.dimensions()
+        ));
+    }
+
+    let differing = reference
+        .pixels()
+        .zip(capture.pixels())
+        .filter(|(a, b)| {
+            a.0.iter()
+                .zip(b.0)
+                .any(|(a, b)| a.abs_diff(b) > CHANNEL_TOLERANCE)
+        })
+        .count();
+    let allowed = (reference.len() / 4) as f64 * PIXEL_TOLERANCE;
+    if differing as f64 > allowed {
+        return Err(format!("{} pixels differ", differing));
+    }
+    Ok(())
+}
@@ -0,0 +1,67 @@
+//! Render tests comparing captured frames of the headless output against reference images
+
+mod common;
+
+use common::{client::TestClient, snapshot::assert_snapshot, Compositor};
+use wayland_protocols::xdg::shell::client::xdg_toplevel::State;
+
+/// Placed windows and animations depend on timing and randomness, so the tests pin them down
+const CONFIG: &str = r#"
+[appearance]
+background_color = [0.2, 0.3, 0.4, 1.0]
+animations = false
+
+[[window_rules]]
+app_id = "luxo.snapshot.*"
+position = [100, 100]
+
+[[window_rules]]
+app_id = "luxo.snapshot.ssd"
+decorations = "server"
+"#;
+
+#[test]
+#[ignore = "no reference images committed yet, record them with LUXO_UPDATE_SNAPSHOTS=1"]
+fn empty_output_with_cursor() {
+    let luxo = Compositor::with_config(CONFIG);
+    assert_snapshot("empty_output_with_cursor", &luxo.capture_stable());
+}
+
+#[test]
+#[ignore = "no reference images committed yet, record them with LUXO_UPDATE_SNAPSHOTS=1"]
+fn client_side_decorations() {
+    let luxo = Compositor::with_config(CONFIG);
+    let mut client = TestClient::connect(&luxo);
+    client.map_toplevel("csd", "luxo.snapshot.csd");
+    luxo.wait_until("the window is mapped", |luxo| luxo.window("csd"));
+
+    assert_snapshot("client_side_decorations", &luxo.capture_stable());
+}
+
+#[test]
+#[ignore = "no reference images committed yet, record them with LUXO_UPDATE_SNAPSHOTS=1"]
+fn server_side_decorations() {
+    let luxo = Compositor::with_config(CONFIG);
+    let mut client = TestClient::connect(&luxo);
+    client.map_toplevel("ssd", "luxo.snapshot.ssd");
+    luxo.wait_until("the window is mapped", |luxo| luxo.window("ssd"));
+
+    assert_snapshot("server_side_decorations", &luxo.capture_stable());
+}
+
+#[test]
+#[ignore = "no reference images committed yet, record them with LUXO_UPDATE_SNAPSHOTS=1"]
+fn fullscreen_clear_color() {
+    let luxo = Compositor::with_config(CONFIG);
+    let mut client = TestClient::connect(&luxo);
+    // smaller than the output, so the clear color shows around it
+    client.set_fixed_size((320, 240));
+    client.map_toplevel("fullscreen", "luxo.snapshot.fullscreen");
+    client.toplevel().set_fullscreen(None);
+    client.wait_for_configure("the window is fullscreen", |c| c.has_state(State::Fullscreen));
+    luxo.wait_until("ipc reports fullscreen", |luxo| {
+        luxo.window("fullscreen").filter(|w| w.fullscreen)
+    });
+
+    assert_snapshot("fullscreen_clear_color", &luxo.capture_stable());
+}
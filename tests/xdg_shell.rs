@@ -0,0 +1,235 @@
+//! Regression tests for the xdg-shell handling in `shell/xdg.rs`
+
+mod common;
+
+use common::{
+    client::{TestClient, DEFAULT_SIZE},
+    Compositor, OUTPUT_SIZE,
+};
+use luxo::ipc::{Event, EventType, Request, Response};
+use wayland_protocols::xdg::shell::client::xdg_toplevel::{ResizeEdge, State};
+
+#[test]
+fn toplevel_is_mapped_and_focused() {
+    let luxo = Compositor::start();
+    let mut client = TestClient::connect(&luxo);
+    client.map_toplevel("mapped", "luxo.test");
+
+    let window = luxo.wait_until("the window is mapped", |luxo| luxo.window("mapped"));
+    assert_eq!(window.app_id.as_deref(), Some("luxo.test"));
+    assert_eq!(window.output.as_deref(), Some("headless"));
+    assert_eq!(window.workspace, Some(1));
+    assert!(window.focused);
+    assert!(!window.fullscreen);
+    assert_eq!(client.last_configure().unwrap().buffer_size, DEFAULT_SIZE);
+}
+
+#[test]
+fn destroyed_toplevel_is_unmapped() {
+    let luxo = Compositor::start();
+    let mut client = TestClient::connect(&luxo);
+    client.map_toplevel("short-lived", "luxo.test");
+    luxo.wait_until("the window is mapped", |luxo| luxo.window("short-lived"));
+
+    client.unmap();
+    client.roundtrip();
+    luxo.wait_until("the window is gone", |luxo| {
+        luxo.window("short-lived").is_none().then_some(())
+    });
+}
+
+#[test]
+fn fullscreen_covers_output_and_restores_size() {
+    let luxo = Compositor::start();
+    let mut client = TestClient::connect(&luxo);
+    client.map_toplevel("fullscreen", "luxo.test");
+
+    client.toplevel().set_fullscreen(None);
+    let configure = client.wait_for_configure("the window is fullscreen", |c| c.has_state(State::Fullscreen));
+    assert_eq!(configure.size, OUTPUT_SIZE);
+    luxo.wait_until("ipc reports fullscreen", |luxo| {
+        luxo.window("fullscreen").filter(|w| w.fullscreen)
+    });
+
+    client.toplevel().unset_fullscreen();
+    let configure =
+        client.wait_for_configure("the window left fullscreen", |c| !c.has_state(State::Fullscreen));
+    assert_eq!(configure.buffer_size, DEFAULT_SIZE);
+    luxo.wait_until("ipc reports windowed", |luxo| {
+        luxo.window("fullscreen").filter(|w| !w.fullscreen)
+    });
+}
+
+#[test]
+fn maximize_and_unmaximize_restore_size() {
+    let luxo = Compositor::start();
+    let mut client = TestClient::connect(&luxo);
+    client.map_toplevel("maximized", "luxo.test");
+
+    client.toplevel().set_maximized();
+    let configure = client.wait_for_configure("the window is maximized", |c| c.has_state(State::Maximized));
+    assert!(configure.size.0 > DEFAULT_SIZE.0 && configure.size.1 > DEFAULT_SIZE.1);
+
+    // repeated requests must not overwrite the size to restore
+    client.toplevel().set_maximized();
+    client.roundtrip();
+
+    client.toplevel().unset_maximized();
+    let configure =
+        client.wait_for_configure("the window is unmaximized", |c| !c.has_state(State::Maximized));
+    assert_eq!(configure.buffer_size, DEFAULT_SIZE);
+}
+
+#[test]
+fn maximize_action_toggles() {
+    let luxo = Compositor::start();
+    let mut client = TestClient::connect(&luxo);
+    client.map_toplevel("toggled", "luxo.test");
+    luxo.wait_until("the window is focused", |luxo| {
+        luxo.window("toggled").filter(|w| w.focused)
+    });
+
+    let action = Request::Action {
+        action: "toggle-maximize".into(),
+    };
+    assert!(matches!(luxo.request(&action), Response::Ok));
+    client.wait_for_configure("the window is maximized", |c| c.has_state(State::Maximized));
+
+    assert!(matches!(luxo.request(&action), Response::Ok));
+    let configure =
+        client.wait_for_configure("the window is unmaximized", |c| !c.has_state(State::Maximized));
+    assert_eq!(configure.buffer_size, DEFAULT_SIZE);
+}
+
+#[test]
+fn resize_without_grab_is_ignored() {
+    let luxo = Compositor::start();
+    let mut client = TestClient::connect(&luxo);
+    client.map_toplevel("resized", "luxo.test");
+    luxo.wait_until("the window is mapped", |luxo| luxo.window("resized"));
+    let configures = client.state.configures.len();
+
+    // no button was pressed, so there is no grab the serial could belong to
+    let seat = client.seat().expect("wl_seat is missing").clone();
+    client.toplevel().resize(&seat, 0, ResizeEdge::BottomRight);
+    client.toplevel()._move(&seat, 0);
+    client.roundtrip();
+
+    assert!(client.state.configures[configures..]
+        .iter()
+        .all(|c| !c.has_state(State::Resizing)));
+    assert!(luxo.window("resized").is_some());
+}
+
+#[test]
+fn headless_output_renders_frames() {
+    let luxo = Compositor::start();
+    let outputs = luxo.outputs();
+    assert_eq!(outputs.len(), 1);
+    assert_eq!(outputs[0].name, "headless");
+    assert_eq!(
+        outputs[0].mode.as_deref(),
+        Some(&*format!("{}x{}@60.000", OUTPUT_SIZE.0, OUTPUT_SIZE.1))
+    );
+
+    let mut client = TestClient::connect(&luxo);
+    client.map_toplevel("frames", "luxo.test");
+    luxo.wait_until("a frame was composited", |luxo| {
+        luxo.outputs()[0].frames.filter(|frames| frames.composited > 0)
+    });
+}
+
+#[test]
+fn scratchpad_is_hidden_and_shown_again() {
+    let luxo = Compositor::with_config("[[window_rules]]\napp_id = \"luxo.dropdown\"\nscratchpad = true\n");
+    let mut client = TestClient::connect(&luxo);
+    client.map_toplevel("dropdown", "luxo.dropdown");
+    luxo.wait_until("the scratchpad is focused", |luxo| {
+        luxo.window("dropdown").filter(|w| w.scratchpad && w.focused)
+    });
+
+    let action = Request::Action {
+        action: "toggle-scratchpad".into(),
+    };
+    assert!(matches!(luxo.request(&action), Response::Ok));
+    luxo.wait_until("the scratchpad is hidden", |luxo| {
+        luxo.window("dropdown").filter(|w| w.output.is_none() && !w.focused)
+    });
+
+    assert!(matches!(luxo.request(&action), Response::Ok));
+    luxo.wait_until("the scratchpad is shown", |luxo| {
+        luxo.window("dropdown")
+            .filter(|w| w.output.as_deref() == Some("headless") && w.focused)
+    });
+    assert_eq!(client.last_configure().unwrap().buffer_size, DEFAULT_SIZE);
+}
+
+#[test]
+fn sticky_window_stays_on_workspace_switch() {
+    let luxo = Compositor::start();
+    let mut client = TestClient::connect(&luxo);
+    client.map_toplevel("sticky", "luxo.test");
+    let window = luxo.wait_until("the window is mapped", |luxo| luxo.window("sticky"));
+
+    let flags = Request::SetWindowFlags {
+        id: window.id,
+        always_on_top: None,
+        sticky: Some(true),
+    };
+    assert!(matches!(luxo.request(&flags), Response::Ok));
+    let action = Request::Action {
+        action: "workspace 2".into(),
+    };
+    assert!(matches!(luxo.request(&action), Response::Ok));
+
+    let window = luxo.wait_until("the window is on workspace 2", |luxo| {
+        luxo.window("sticky").filter(|w| w.workspace == Some(2))
+    });
+    assert!(window.sticky);
+    assert!(!window.always_on_top);
+}
+
+#[test]
+fn focus_changes_are_sent_to_subscribers() {
+    let luxo = Compositor::start();
+    let mut events = luxo.subscribe(vec![EventType::Focus]);
+    let mut client = TestClient::connect(&luxo);
+    client.map_toplevel("subscribed", "luxo.test");
+
+    match events.next_event() {
+        Event::FocusChanged { window: Some(window) } => {
+            assert_eq!(window.title.as_deref(), Some("subscribed"));
+            assert!(window.geometry.is_some());
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    client.unmap();
+    client.roundtrip();
+    assert!(matches!(events.next_event(), Event::FocusChanged { window: None }));
+}
+
+#[test]
+fn keyboard_layout_changes_are_sent_to_subscribers() {
+    let luxo = Compositor::with_config("[input.keyboard]\nlayout = \"us,de\"\n");
+    let mut events = luxo.subscribe(vec![EventType::Keyboard]);
+    let action = Request::Action {
+        action: "next-layout".into(),
+    };
+    assert!(matches!(luxo.request(&action), Response::Ok));
+
+    match events.next_event() {
+        Event::KeyboardLayoutChanged { index, layout } => {
+            assert_eq!(index, 1);
+            assert_eq!(layout, "de");
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+    match luxo.request(&Request::KeyboardLayout) {
+        Response::KeyboardLayout { index, layout } => {
+            assert_eq!(index, 1);
+            assert_eq!(layout, "de");
+        }
+        response => panic!("unexpected response {:?}", response),
+    }
+}